@@ -1,30 +1,172 @@
 //! A set of common appenders
 
+use std::collections::VecDeque;
 use std::convert::AsRef;
 use std::default::Default;
 use std::io;
 use std::error::Error;
 use std::io::prelude::*;
-use std::io::{BufWriter, Stdout};
-use std::fs::{File, OpenOptions};
+use std::io::BufWriter;
+use std::fs::{self, File, OpenOptions};
+use std::mem;
 use std::path::{Path, PathBuf};
-use log::LogRecord;
+use std::sync::{Arc, Condvar, Mutex, Weak};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use log::{LogLevel, LogRecord};
+use time::{self, Tm};
 
-use Append;
+use {Append, Encode, LogEvent, handle_error};
+use clock::{Clock, SystemClock};
 use pattern::PatternLayout;
 
+pub use self::syslog::{SyslogAppender, SyslogAppenderBuilder, SyslogTransport, Facility,
+                       ParseFacilityError};
+pub use self::tcp::{TcpAppender, TcpAppenderBuilder, OutagePolicy};
+#[cfg(unix)]
+pub use self::fifo::{FifoAppender, FifoAppenderBuilder};
+pub use self::memory::{MemoryAppender, MemoryAppenderBuilder, MemoryHandle};
+pub use self::process::{ProcessAppender, ProcessAppenderBuilder, RestartPolicy};
+pub use self::smtp::{SmtpAppender, SmtpAppenderBuilder, Tls};
+pub use self::udp::{UdpAppender, UdpAppenderBuilder, Oversize};
+pub use self::dedup::{DedupAppender, DedupAppenderBuilder};
+pub use self::eventlog::{EventLogAppender, EventLogAppenderBuilder};
+#[cfg(target_os = "linux")]
+pub use self::journald::{JournaldAppender, JournaldAppenderBuilder, JOURNALD_SOCKET};
+pub use self::leveled::{LeveledFileAppender, LeveledFileAppenderBuilder, LEVEL_TOKEN};
+pub use self::routing::{RoutingAppender, RoutingAppenderBuilder};
+pub use self::rolling::{RollingFileAppender, RollingFileAppenderBuilder, Policy, Roll, SizePolicy,
+                        DailyPolicy, RenameRoller, FixedWindowRoller};
+
+mod dedup;
+mod eventlog;
+#[cfg(unix)]
+mod fifo;
+#[cfg(target_os = "linux")]
+mod journald;
+mod leveled;
+mod memory;
+mod process;
+mod rolling;
+mod routing;
+mod smtp;
+mod syslog;
+mod tcp;
+mod udp;
+
 /// An appender which logs to a file.
+///
+/// By default the file is flushed after every log event. If buffering is
+/// enabled, output is instead held in memory until the buffer fills, a flush
+/// is requested, or the configured number of log events or amount of time
+/// has passed. Buffered output which has not yet been flushed is lost if the
+/// process crashes, but is written when the appender is flushed or dropped.
+///
+/// When the appender is reopened, as after `logrotate` has moved the file
+/// aside, the file at the configured path is opened in append mode, and
+/// created if it does not exist.
 pub struct FileAppender {
-    file: BufWriter<File>,
-    pattern: PatternLayout,
+    path: PathBuf,
+    mode: Option<u32>,
+    file: Arc<Mutex<BufWriter<File>>>,
+    encoder: Box<Encode>,
+    buffered: bool,
+    flush_records: Option<u64>,
+    flush_level: Option<LogLevel>,
+    unflushed: u64,
 }
 
 impl Append for FileAppender {
     fn append(&mut self, record: &LogRecord) -> Result<(), Box<Error>> {
-        try!(self.pattern.append(&mut self.file, record));
-        try!(self.file.flush());
+        self.write(record.level(), |encoder, w| encoder.encode(w, record))
+    }
+
+    fn append_event(&mut self, event: &LogEvent) -> Result<(), Box<Error>> {
+        self.write(event.level(), |encoder, w| encoder.encode_event(w, event))
+    }
+
+    fn flush(&mut self) -> Result<(), Box<Error>> {
+        self.unflushed = 0;
+        try!(self.file.lock().unwrap().flush());
         Ok(())
     }
+
+    fn append_text(&mut self, text: &str) -> Result<(), Box<Error>> {
+        let mut file = self.file.lock().unwrap();
+        try!(writeln!(file, "{}", text));
+        try!(maybe_flush(&mut file, self.buffered, self.flush_records, &mut self.unflushed));
+        Ok(())
+    }
+
+    fn reopen(&mut self) -> Result<(), Box<Error>> {
+        let mut options = OpenOptions::new();
+        options.append(true).create(true);
+        if let Some(mode) = self.mode {
+            set_mode(&mut options, mode);
+        }
+
+        let mut file = self.file.lock().unwrap();
+        self.unflushed = 0;
+        try!(file.flush());
+        let new = try!(open_file(&options, &self.path));
+        *file = BufWriter::with_capacity(file.capacity(), new);
+        Ok(())
+    }
+}
+
+impl FileAppender {
+    // Encodes a log event of the specified level into the file with `encode`.
+    fn write<F>(&mut self, level: LogLevel, encode: F) -> Result<(), Box<Error>>
+        where F: FnOnce(&Encode, &mut Write) -> io::Result<()>
+    {
+        let mut file = self.file.lock().unwrap();
+        try!(encode(&*self.encoder, &mut *file));
+        if self.flush_level.map_or(false, |flush_level| level <= flush_level) {
+            self.unflushed = 0;
+            try!(file.flush());
+        } else {
+            try!(maybe_flush(&mut file, self.buffered, self.flush_records, &mut self.unflushed));
+        }
+        Ok(())
+    }
+}
+
+fn maybe_flush(file: &mut BufWriter<File>,
+               buffered: bool,
+               flush_records: Option<u64>,
+               unflushed: &mut u64)
+               -> io::Result<()> {
+    *unflushed += 1;
+    match flush_records {
+        Some(records) if *unflushed < records => Ok(()),
+        None if buffered => Ok(()),
+        _ => {
+            *unflushed = 0;
+            file.flush()
+        }
+    }
+}
+
+// Periodically flushes a buffered file until its appender is dropped.
+fn spawn_flusher(file: Weak<Mutex<BufWriter<File>>>, interval: Duration) -> io::Result<()> {
+    let builder = thread::Builder::new().name("log4rs-flush".to_owned());
+    try!(builder.spawn(move || {
+        loop {
+            thread::sleep(interval);
+            match file.upgrade() {
+                Some(file) => {
+                    if let Err(err) = file.lock().unwrap().flush() {
+                        handle_error(&err);
+                    }
+                }
+                None => break,
+            }
+        }
+    }));
+    Ok(())
 }
 
 impl FileAppender {
@@ -33,8 +175,14 @@ impl FileAppender {
     pub fn builder<P: AsRef<Path>>(path: P) -> FileAppenderBuilder {
         FileAppenderBuilder {
             path: path.as_ref().to_path_buf(),
-            pattern: Default::default(),
+            encoder: Box::new(PatternLayout::default()),
             append: true,
+            create_dirs: false,
+            buffer_size: None,
+            flush_interval: None,
+            flush_records: None,
+            flush_level: None,
+            mode: None,
         }
     }
 }
@@ -42,51 +190,498 @@ impl FileAppender {
 /// A builder for `FileAppender`s.
 pub struct FileAppenderBuilder {
     path: PathBuf,
-    pattern: PatternLayout,
+    encoder: Box<Encode>,
     append: bool,
+    create_dirs: bool,
+    buffer_size: Option<usize>,
+    flush_interval: Option<Duration>,
+    flush_records: Option<u64>,
+    flush_level: Option<LogLevel>,
+    mode: Option<u32>,
 }
 
 impl FileAppenderBuilder {
     /// Sets the output pattern for the `FileAppender`.
-    pub fn pattern(mut self, pattern: PatternLayout) -> FileAppenderBuilder {
-        self.pattern = pattern;
+    ///
+    /// This is a shorthand for `encoder(Box::new(pattern))`.
+    pub fn pattern(self, pattern: PatternLayout) -> FileAppenderBuilder {
+        self.encoder(Box::new(pattern))
+    }
+
+    /// Sets the encoder used to format output for the `FileAppender`.
+    ///
+    /// Defaults to the default `PatternLayout`.
+    pub fn encoder(mut self, encoder: Box<Encode>) -> FileAppenderBuilder {
+        self.encoder = encoder;
         self
     }
 
     /// Determines if the appender will append to or truncate the output file.
     ///
-    /// Defaults to `true`.
+    /// If `false`, any existing contents of the file are discarded when the
+    /// appender is built. Defaults to `true`.
     pub fn append(mut self, append: bool) -> FileAppenderBuilder {
         self.append = append;
         self
     }
 
+    /// Determines if the appender will create any missing parent directories
+    /// of the output file.
+    ///
+    /// Defaults to `false`.
+    pub fn create_dirs(mut self, create_dirs: bool) -> FileAppenderBuilder {
+        self.create_dirs = create_dirs;
+        self
+    }
+
+    /// Enables buffering of output with a buffer of the specified size in
+    /// bytes.
+    ///
+    /// Buffered output is only flushed when the buffer fills or as specified
+    /// by `flush_interval`, `flush_records` and `flush_level`. Defaults to
+    /// unbuffered.
+    pub fn buffer_size(mut self, buffer_size: usize) -> FileAppenderBuilder {
+        self.buffer_size = Some(buffer_size);
+        self
+    }
+
+    /// Sets the interval at which a background thread flushes buffered
+    /// output.
+    ///
+    /// Has no effect unless buffering is enabled. Defaults to no interval.
+    pub fn flush_interval(mut self, flush_interval: Duration) -> FileAppenderBuilder {
+        self.flush_interval = Some(flush_interval);
+        self
+    }
+
+    /// Sets the number of log events after which buffered output is flushed.
+    ///
+    /// Has no effect unless buffering is enabled. Defaults to no limit.
+    pub fn flush_records(mut self, flush_records: u64) -> FileAppenderBuilder {
+        self.flush_records = Some(flush_records);
+        self
+    }
+
+    /// Sets the least severe level of log events which are flushed as soon as
+    /// they are written, along with any output buffered before them.
+    ///
+    /// For example, `LogLevel::Error` keeps other log events buffered while
+    /// making sure errors reach the file even if the process crashes soon
+    /// after. Has no effect unless buffering is enabled. Defaults to none.
+    pub fn flush_level(mut self, flush_level: LogLevel) -> FileAppenderBuilder {
+        self.flush_level = Some(flush_level);
+        self
+    }
+
+    /// Sets the permissions of the output file if the appender creates it,
+    /// for example `0o640` to keep it from being world-readable.
+    ///
+    /// The permissions of an existing file are not changed, and the process
+    /// umask still applies. This is ignored on platforms other than Unix.
+    /// Defaults to the platform's default permissions.
+    pub fn mode(mut self, mode: u32) -> FileAppenderBuilder {
+        self.mode = Some(mode);
+        self
+    }
+
     /// Consumes the `FileAppenderBuilder`, producing a `FileAppender`.
     pub fn build(self) -> io::Result<FileAppender> {
-        let file = try!(OpenOptions::new()
-            .write(true)
-            .append(self.append)
-            .create(true)
-            .open(&self.path));
+        if self.create_dirs {
+            if let Some(parent) = self.path.parent().filter(|p| !p.as_os_str().is_empty()) {
+                if let Err(err) = fs::create_dir_all(parent) {
+                    let msg = format!("Unable to create directory `{}`: {}", parent.display(), err);
+                    return Err(io::Error::new(err.kind(), msg));
+                }
+            }
+        }
+
+        let mut options = OpenOptions::new();
+        options.write(true).append(self.append).truncate(!self.append).create(true);
+        if let Some(mode) = self.mode {
+            set_mode(&mut options, mode);
+        }
+        let file = try!(open_file(&options, &self.path));
+
+        let buffered = self.buffer_size.is_some();
+        let file = BufWriter::with_capacity(self.buffer_size.unwrap_or(1024), file);
+        let file = Arc::new(Mutex::new(file));
+        if let (true, Some(interval)) = (buffered, self.flush_interval) {
+            try!(spawn_flusher(Arc::downgrade(&file), interval));
+        }
 
         Ok(FileAppender {
-            file: BufWriter::with_capacity(1024, file),
-            pattern: self.pattern
+            path: self.path,
+            mode: self.mode,
+            file: file,
+            encoder: self.encoder,
+            buffered: buffered,
+            flush_records: if buffered { self.flush_records } else { None },
+            flush_level: if buffered { self.flush_level } else { None },
+            unflushed: 0,
+        })
+    }
+}
+
+/// An appender which logs to a file whose name is derived from the current
+/// date, starting a new file whenever the day changes.
+///
+/// The path is a template in the syntax accepted by `Tm::strftime`, for
+/// example `log/app-%Y-%m-%d.log`. Files are always opened in append mode, so
+/// a process started partway through a day will continue writing to that
+/// day's file.
+///
+/// Days are calendar days in the local time zone, and both the roll and the
+/// path are based on the local time of the first log event written after
+/// midnight. Since the local date is compared rather than elapsed time, days
+/// which are 23 or 25 hours long due to daylight saving transitions are
+/// handled correctly.
+///
+/// If compression is enabled, the previous day's file is gzipped to a file
+/// with a `.gz` extension appended once the appender moves on to a new file.
+/// Compression happens on a background thread, and the original file is only
+/// removed once the compressed file is complete.
+pub struct DailyRollingFileAppender {
+    path: String,
+    current: PathBuf,
+    file: BufWriter<File>,
+    day: (i32, i32),
+    encoder: Box<Encode>,
+    compress: bool,
+    compression: Option<JoinHandle<()>>,
+    clock: Arc<Clock>,
+}
+
+impl Append for DailyRollingFileAppender {
+    fn append(&mut self, record: &LogRecord) -> Result<(), Box<Error>> {
+        let now = time::at(self.clock.now());
+        try!(self.roll_if_needed(&now));
+        try!(self.encoder.encode(&mut self.file, record));
+        try!(self.file.flush());
+        Ok(())
+    }
+
+    fn append_event(&mut self, event: &LogEvent) -> Result<(), Box<Error>> {
+        let now = time::at(self.clock.now());
+        try!(self.roll_if_needed(&now));
+        try!(self.encoder.encode_event(&mut self.file, event));
+        try!(self.file.flush());
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<Error>> {
+        try!(self.file.flush());
+        Ok(())
+    }
+
+    fn append_text(&mut self, text: &str) -> Result<(), Box<Error>> {
+        let now = time::at(self.clock.now());
+        try!(self.roll_if_needed(&now));
+        try!(writeln!(self.file, "{}", text));
+        try!(self.file.flush());
+        Ok(())
+    }
+
+    fn reopen(&mut self) -> Result<(), Box<Error>> {
+        try!(self.file.flush());
+        let file = try!(open_file(OpenOptions::new().write(true).append(true).create(true),
+                                  &self.current));
+        self.file = BufWriter::with_capacity(1024, file);
+        Ok(())
+    }
+}
+
+impl DailyRollingFileAppender {
+    /// Creates a new `DailyRollingFileAppender` builder for an appender which
+    /// will log to files at paths generated from the provided template.
+    pub fn builder(path: &str) -> DailyRollingFileAppenderBuilder {
+        DailyRollingFileAppenderBuilder {
+            path: path.to_owned(),
+            encoder: Box::new(PatternLayout::default()),
+            compress: false,
+            clock: Arc::new(SystemClock::new()),
+        }
+    }
+
+    fn roll_if_needed(&mut self, now: &Tm) -> io::Result<()> {
+        let day = day_of(now);
+        if day != self.day {
+            try!(self.file.flush());
+            let (path, file) = try!(open_daily_file(&self.path, now));
+            self.file = file;
+            self.day = day;
+            let old = mem::replace(&mut self.current, path);
+            if self.compress && old != self.current {
+                self.compress_in_background(old);
+            }
+        }
+        Ok(())
+    }
+
+    fn compress_in_background(&mut self, path: PathBuf) {
+        // only one file is compressed at a time
+        if let Some(compression) = self.compression.take() {
+            let _ = compression.join();
+        }
+
+        let thread = thread::Builder::new()
+            .name("log4rs compression".to_string())
+            .spawn(move || {
+                if let Err(err) = compress(&path) {
+                    handle_error(&err);
+                }
+            });
+        match thread {
+            Ok(thread) => self.compression = Some(thread),
+            Err(err) => handle_error(&err),
+        }
+    }
+}
+
+/// Gzips the file at `path` to a file at `path` with `.gz` appended, removing
+/// the original.
+///
+/// The compressed data is written to a temporary file which is renamed once
+/// it is complete, so an interruption never leaves a truncated `.gz` file.
+fn compress(path: &Path) -> io::Result<()> {
+    let mut gz_path = path.as_os_str().to_owned();
+    gz_path.push(".gz");
+    let mut tmp_path = gz_path.clone();
+    tmp_path.push(".tmp");
+
+    {
+        let mut input = try!(File::open(path));
+        let output = try!(File::create(&tmp_path));
+        let mut encoder = GzEncoder::new(output, Compression::default());
+        try!(io::copy(&mut input, &mut encoder));
+        let output = try!(encoder.finish());
+        try!(output.sync_all());
+    }
+
+    try!(fs::rename(&tmp_path, &gz_path));
+    fs::remove_file(path)
+}
+
+/// A builder for `DailyRollingFileAppender`s.
+pub struct DailyRollingFileAppenderBuilder {
+    path: String,
+    encoder: Box<Encode>,
+    compress: bool,
+    clock: Arc<Clock>,
+}
+
+impl DailyRollingFileAppenderBuilder {
+    /// Sets the output pattern for the `DailyRollingFileAppender`.
+    ///
+    /// This is a shorthand for `encoder(Box::new(pattern))`.
+    pub fn pattern(self, pattern: PatternLayout) -> DailyRollingFileAppenderBuilder {
+        self.encoder(Box::new(pattern))
+    }
+
+    /// Sets the encoder used to format output for the `DailyRollingFileAppender`.
+    ///
+    /// Defaults to the default `PatternLayout`.
+    pub fn encoder(mut self, encoder: Box<Encode>) -> DailyRollingFileAppenderBuilder {
+        self.encoder = encoder;
+        self
+    }
+
+    /// Determines if files will be gzipped once the appender has moved on
+    /// to the next day's file.
+    ///
+    /// Defaults to `false`.
+    pub fn compress(mut self, compress: bool) -> DailyRollingFileAppenderBuilder {
+        self.compress = compress;
+        self
+    }
+
+    /// Sets the clock which determines the current day.
+    ///
+    /// Defaults to `SystemClock`.
+    pub fn clock(mut self, clock: Arc<Clock>) -> DailyRollingFileAppenderBuilder {
+        self.clock = clock;
+        self
+    }
+
+    /// Consumes the `DailyRollingFileAppenderBuilder`, producing a
+    /// `DailyRollingFileAppender`.
+    ///
+    /// The file for the current day is opened immediately.
+    pub fn build(self) -> io::Result<DailyRollingFileAppender> {
+        let now = time::at(self.clock.now());
+        let (path, file) = try!(open_daily_file(&self.path, &now));
+
+        Ok(DailyRollingFileAppender {
+            path: self.path,
+            current: path,
+            file: file,
+            day: day_of(&now),
+            encoder: self.encoder,
+            compress: self.compress,
+            compression: None,
+            clock: self.clock,
         })
     }
 }
 
-/// An appender which logs to stdout.
+// The calendar day of `tm` in its own time zone, which is unaffected by the
+// length of the day.
+fn day_of(tm: &Tm) -> (i32, i32) {
+    (tm.tm_year, tm.tm_yday)
+}
+
+fn open_daily_file(path: &str, now: &Tm) -> io::Result<(PathBuf, BufWriter<File>)> {
+    let path = match now.strftime(path) {
+        Ok(path) => PathBuf::from(path.to_string()),
+        Err(err) => return Err(io::Error::new(io::ErrorKind::InvalidInput, err.to_string())),
+    };
+
+    let file = try!(open_file(OpenOptions::new().write(true).append(true).create(true), &path));
+    Ok((path, BufWriter::with_capacity(1024, file)))
+}
+
+// Sets the permissions of files created with `options`, where supported.
+#[cfg(unix)]
+fn set_mode(options: &mut OpenOptions, mode: u32) {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    options.mode(mode);
+}
+
+#[cfg(not(unix))]
+fn set_mode(_: &mut OpenOptions, _: u32) {}
+
+// Opens a file, including its path in any error so that a misconfigured
+// appender can be identified when the config is loaded.
+fn open_file(options: &OpenOptions, path: &Path) -> io::Result<File> {
+    options.open(path).map_err(|err| {
+        io::Error::new(err.kind(), format!("Unable to open `{}`: {}", path.display(), err))
+    })
+}
+
+/// The stream a `ConsoleAppender` writes to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Target {
+    /// Standard output.
+    Stdout,
+    /// Standard error.
+    Stderr,
+}
+
+// Held while any `ConsoleWriter` writes, whichever stream it targets.
+static CONSOLE: Mutex<()> = Mutex::new(());
+
+/// A writer to the standard output or standard error of the process.
+///
+/// All `ConsoleWriter`s share a single process-wide lock which is held while
+/// a buffer is written and flushed, so the output of different appenders is
+/// never interleaved mid-line, even when stdout and stderr are the same
+/// terminal. Appenders should encode a log event in full before writing it,
+/// so that the lock is not held while formatting.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ConsoleWriter {
+    target: Target,
+}
+
+impl ConsoleWriter {
+    /// Creates a new `ConsoleWriter` for the specified stream.
+    pub fn new(target: Target) -> ConsoleWriter {
+        ConsoleWriter { target: target }
+    }
+
+    /// Returns the stream the `ConsoleWriter` writes to.
+    pub fn target(&self) -> Target {
+        self.target
+    }
+
+    /// Writes and flushes `buf` while holding the console lock.
+    pub fn write_atomic(&self, buf: &[u8]) -> io::Result<()> {
+        let _guard = CONSOLE.lock().unwrap_or_else(|e| e.into_inner());
+        match self.target {
+            Target::Stdout => write_and_flush(io::stdout().lock(), buf),
+            Target::Stderr => write_and_flush(io::stderr().lock(), buf),
+        }
+    }
+
+    /// Flushes the stream.
+    pub fn flush(&self) -> io::Result<()> {
+        self.write_atomic(&[])
+    }
+
+    /// Determines if the stream is an interactive terminal.
+    ///
+    /// This is always true on platforms other than Unix.
+    pub fn is_terminal(&self) -> bool {
+        is_terminal(self.target)
+    }
+}
+
+#[cfg(unix)]
+fn is_terminal(target: Target) -> bool {
+    use libc;
+
+    let fd = match target {
+        Target::Stdout => libc::STDOUT_FILENO,
+        Target::Stderr => libc::STDERR_FILENO,
+    };
+    unsafe { libc::isatty(fd) == 1 }
+}
+
+#[cfg(not(unix))]
+fn is_terminal(_: Target) -> bool {
+    true
+}
+
+fn write_and_flush<W: Write>(mut w: W, buf: &[u8]) -> io::Result<()> {
+    try!(w.write_all(buf));
+    w.flush()
+}
+
+/// When a `ConsoleAppender` writes ANSI color codes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ColorMode {
+    /// Always write color codes.
+    Always,
+    /// Never write color codes.
+    Never,
+    /// Write color codes only if the stream is an interactive terminal, and
+    /// not when it is redirected to a file or pipe.
+    Auto,
+}
+
+/// An appender which logs to stdout or stderr.
+///
+/// Each log event is written through a `ConsoleWriter`, so it is not
+/// interleaved with the output of other console appenders.
+///
+/// When colors are disabled by the appender's `ColorMode`, ANSI escape codes
+/// produced by its encoder, such as by the `%H` pattern specifier, are
+/// removed before the log event is written.
 pub struct ConsoleAppender {
-    stdout: Stdout,
-    pattern: PatternLayout,
+    writer: ConsoleWriter,
+    encoder: Box<Encode>,
+    color: bool,
 }
 
 impl Append for ConsoleAppender {
     fn append(&mut self, record: &LogRecord) -> Result<(), Box<Error>> {
-        let mut stdout = self.stdout.lock();
-        try!(self.pattern.append(&mut stdout, record));
-        try!(stdout.flush());
+        let mut buf = vec![];
+        try!(self.encoder.encode(&mut buf, record));
+        self.write(buf)
+    }
+
+    fn append_event(&mut self, event: &LogEvent) -> Result<(), Box<Error>> {
+        let mut buf = vec![];
+        try!(self.encoder.encode_event(&mut buf, event));
+        self.write(buf)
+    }
+
+    fn flush(&mut self) -> Result<(), Box<Error>> {
+        try!(self.writer.flush());
+        Ok(())
+    }
+
+    fn append_text(&mut self, text: &str) -> Result<(), Box<Error>> {
+        try!(self.writer.write_atomic(format!("{}\n", text).as_bytes()));
         Ok(())
     }
 }
@@ -95,28 +690,1197 @@ impl ConsoleAppender {
     /// Creates a new `ConsoleAppender` builder.
     pub fn builder() -> ConsoleAppenderBuilder {
         ConsoleAppenderBuilder {
-            pattern: Default::default(),
+            encoder: Box::new(PatternLayout::default()),
+            target: Target::Stdout,
+            color: ColorMode::Auto,
         }
     }
+
+    fn write(&self, mut buf: Vec<u8>) -> Result<(), Box<Error>> {
+        if !self.color {
+            buf = strip_ansi(&buf);
+        }
+        try!(self.writer.write_atomic(&buf));
+        Ok(())
+    }
+}
+
+// Removes ANSI escape sequences of the form `ESC [ parameters final-byte`.
+fn strip_ansi(buf: &[u8]) -> Vec<u8> {
+    let mut stripped = Vec::with_capacity(buf.len());
+    let mut i = 0;
+    while i < buf.len() {
+        if buf[i] == b'\x1b' && buf.get(i + 1) == Some(&b'[') {
+            i += 2;
+            while i < buf.len() && (buf[i] == b';' || buf[i].is_ascii_digit()) {
+                i += 1;
+            }
+            // skip the final byte
+            i += 1;
+        } else {
+            stripped.push(buf[i]);
+            i += 1;
+        }
+    }
+    stripped
 }
 
 /// A builder for `ConsoleAppender`s.
 pub struct ConsoleAppenderBuilder {
-    pattern: PatternLayout,
+    encoder: Box<Encode>,
+    target: Target,
+    color: ColorMode,
 }
 
 impl ConsoleAppenderBuilder {
     /// Sets the output pattern for the `ConsoleAppender`.
-    pub fn pattern(mut self, pattern: PatternLayout) -> ConsoleAppenderBuilder {
-        self.pattern = pattern;
+    ///
+    /// This is a shorthand for `encoder(Box::new(pattern))`.
+    pub fn pattern(self, pattern: PatternLayout) -> ConsoleAppenderBuilder {
+        self.encoder(Box::new(pattern))
+    }
+
+    /// Sets the encoder used to format output for the `ConsoleAppender`.
+    ///
+    /// Defaults to the default `PatternLayout`.
+    pub fn encoder(mut self, encoder: Box<Encode>) -> ConsoleAppenderBuilder {
+        self.encoder = encoder;
+        self
+    }
+
+    /// Sets the stream the `ConsoleAppender` writes to.
+    ///
+    /// Defaults to `Target::Stdout`.
+    pub fn target(mut self, target: Target) -> ConsoleAppenderBuilder {
+        self.target = target;
+        self
+    }
+
+    /// Sets when the `ConsoleAppender` writes ANSI color codes.
+    ///
+    /// Defaults to `ColorMode::Auto`.
+    pub fn color(mut self, color: ColorMode) -> ConsoleAppenderBuilder {
+        self.color = color;
         self
     }
 
     /// Consumes the `ConsoleAppenderBuilder`, producing a `ConsoleAppender`.
+    ///
+    /// With `ColorMode::Auto`, the stream is checked for a terminal here.
     pub fn build(self) -> ConsoleAppender {
+        let writer = ConsoleWriter::new(self.target);
+        let color = match self.color {
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+            ColorMode::Auto => writer.is_terminal(),
+        };
         ConsoleAppender {
-            stdout: io::stdout(),
-            pattern: self.pattern,
+            writer: writer,
+            encoder: self.encoder,
+            color: color,
+        }
+    }
+}
+
+/// The behavior of an `AsyncAppender` when its queue is full.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Block the logging thread until there is room in the queue.
+    Block,
+    /// Discard the oldest queued log event to make room for the new one.
+    DropOldest,
+    /// Discard the new log event.
+    DropNewest,
+}
+
+// A log event queued by an `AsyncAppender`.
+enum AsyncEvent {
+    // the output of the appender's encoder, for its writer
+    Encoded(Vec<u8>),
+    // a log event for the wrapped appender
+    Record(LogEvent),
+    // text for the wrapped appender's `append_text`
+    Text(String),
+}
+
+// Where an `AsyncAppender` writes its log events.
+enum AsyncOutput {
+    Writer(Box<Write + Send>),
+    Appender(Box<Append>),
+}
+
+impl AsyncOutput {
+    fn write(&mut self, event: AsyncEvent) -> Result<(), Box<Error>> {
+        match (self, event) {
+            (&mut AsyncOutput::Writer(ref mut writer), AsyncEvent::Encoded(buf)) => {
+                try!(writer.write_all(&buf));
+                Ok(())
+            }
+            (&mut AsyncOutput::Appender(ref mut appender), AsyncEvent::Record(event)) => {
+                appender.append_event(&event)
+            }
+            (&mut AsyncOutput::Appender(ref mut appender), AsyncEvent::Text(text)) => {
+                appender.append_text(&text)
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn reopen(&mut self) -> Result<(), Box<Error>> {
+        match *self {
+            AsyncOutput::Writer(_) => Ok(()),
+            AsyncOutput::Appender(ref mut appender) => appender.reopen(),
+        }
+    }
+
+    fn flush(&mut self) -> Result<(), Box<Error>> {
+        match *self {
+            AsyncOutput::Writer(ref mut writer) => {
+                try!(writer.flush());
+                Ok(())
+            }
+            AsyncOutput::Appender(ref mut appender) => appender.flush(),
+        }
+    }
+}
+
+// The queue of an `AsyncAppender` and the state of its output.
+struct AsyncChannel {
+    id: usize,
+    queue: VecDeque<AsyncEvent>,
+    // the number of events ever queued
+    queued: u64,
+    // the number of events written out or dropped from the queue
+    done: u64,
+    // the number of events which must be done before the output is flushed
+    flush_at: Option<u64>,
+    // the number of events known to have been flushed
+    flushed: u64,
+    // the number of events which must be done before the output is reopened
+    reopen_at: Option<u64>,
+    // the number of events discarded because the queue was full
+    dropped: u64,
+    // the value of `dropped` when the last summary was written
+    reported: u64,
+    // set when the appender is dropped, after which the queue is drained
+    closed: bool,
+    // taken by the worker thread while it writes
+    output: Option<AsyncOutput>,
+    fallback: Option<Box<Append>>,
+    report_interval: Duration,
+    next_report: Instant,
+}
+
+enum AsyncJob {
+    Flush(u64),
+    Reopen,
+    Report(u64),
+    Write(AsyncEvent),
+    Close,
+}
+
+impl AsyncChannel {
+    fn next_job(&mut self, now: Instant) -> Option<AsyncJob> {
+        match self.flush_at {
+            Some(at) if self.done >= at => return Some(AsyncJob::Flush(at)),
+            _ => {}
+        }
+
+        match self.reopen_at {
+            Some(at) if self.done >= at => {
+                self.reopen_at = None;
+                return Some(AsyncJob::Reopen);
+            }
+            _ => {}
+        }
+
+        if now >= self.next_report || (self.closed && self.queue.is_empty()) {
+            self.next_report = now + self.report_interval;
+            let dropped = self.dropped - self.reported;
+            self.reported = self.dropped;
+            if dropped > 0 {
+                return Some(AsyncJob::Report(dropped));
+            }
+        }
+
+        match self.queue.pop_front() {
+            Some(buf) => Some(AsyncJob::Write(buf)),
+            None if self.closed => Some(AsyncJob::Close),
+            None => None,
+        }
+    }
+}
+
+struct AsyncWorkerState {
+    channels: Vec<AsyncChannel>,
+    // the index of the channel to check for work first, so that a busy
+    // channel does not starve the others
+    cursor: usize,
+    shutdown: bool,
+}
+
+impl AsyncWorkerState {
+    fn channel(&mut self, id: usize) -> &mut AsyncChannel {
+        self.channels.iter_mut().find(|c| c.id == id).expect("channel removed while in use")
+    }
+}
+
+// A background thread which writes out the queues of one or more appenders.
+struct AsyncWorker {
+    state: Mutex<AsyncWorkerState>,
+    cond: Condvar,
+}
+
+fn run_async(worker: Arc<AsyncWorker>) {
+    let mut state = worker.state.lock().unwrap();
+    loop {
+        let now = Instant::now();
+        let len = state.channels.len();
+        let mut found = None;
+        for i in 0..len {
+            let idx = (state.cursor + i) % len;
+            if let Some(job) = state.channels[idx].next_job(now) {
+                found = Some((idx, job));
+                break;
+            }
+        }
+
+        let (idx, job) = match found {
+            Some(found) => found,
+            None if state.shutdown && state.channels.is_empty() => return,
+            None => {
+                let next_report = state.channels.iter().map(|c| c.next_report).min();
+                state = match next_report {
+                    Some(at) => {
+                        let timeout = at.duration_since(now);
+                        worker.cond.wait_timeout(state, timeout).unwrap().0
+                    }
+                    None => worker.cond.wait(state).unwrap(),
+                };
+                continue;
+            }
+        };
+        state.cursor = idx + 1;
+        // channels are only removed by this thread, so `idx` stays valid
+        // while the lock is released
+        match job {
+            AsyncJob::Flush(at) => {
+                let mut output = state.channels[idx].output.take().unwrap();
+                drop(state);
+                if let Err(err) = output.flush() {
+                    handle_error(&*err);
+                }
+                state = worker.state.lock().unwrap();
+                let channel = &mut state.channels[idx];
+                channel.output = Some(output);
+                channel.flushed = at;
+                if channel.flush_at == Some(at) {
+                    channel.flush_at = None;
+                }
+            }
+            AsyncJob::Reopen => {
+                let mut output = state.channels[idx].output.take().unwrap();
+                drop(state);
+                if let Err(err) = output.reopen() {
+                    handle_error(&*err);
+                }
+                state = worker.state.lock().unwrap();
+                state.channels[idx].output = Some(output);
+            }
+            AsyncJob::Report(dropped) => {
+                let mut fallback = state.channels[idx].fallback.take().unwrap();
+                let interval = state.channels[idx].report_interval;
+                drop(state);
+                report_dropped(&mut *fallback, dropped, interval);
+                state = worker.state.lock().unwrap();
+                state.channels[idx].fallback = Some(fallback);
+            }
+            AsyncJob::Write(event) => {
+                worker.cond.notify_all();
+                let mut output = state.channels[idx].output.take().unwrap();
+                drop(state);
+                if let Err(err) = output.write(event) {
+                    handle_error(&*err);
+                }
+                state = worker.state.lock().unwrap();
+                let channel = &mut state.channels[idx];
+                channel.output = Some(output);
+                channel.done += 1;
+            }
+            AsyncJob::Close => {
+                let mut channel = state.channels.remove(idx);
+                drop(state);
+                if let Err(err) = channel.output.as_mut().unwrap().flush() {
+                    handle_error(&*err);
+                }
+                drop(channel);
+                state = worker.state.lock().unwrap();
+            }
+        }
+        worker.cond.notify_all();
+    }
+}
+
+fn report_dropped(fallback: &mut Append, dropped: u64, interval: Duration) {
+    let secs = interval.as_secs();
+    let interval = if secs == 60 {
+        "minute".to_owned()
+    } else if secs > 0 && secs % 60 == 0 {
+        format!("{} minutes", secs / 60)
+    } else if secs > 0 {
+        format!("{} seconds", secs)
+    } else {
+        format!("{} milliseconds", interval.subsec_nanos() / 1_000_000)
+    };
+    let msg = format!("dropped {} log records in the last {} due to backpressure",
+                      dropped,
+                      interval);
+    if let Err(err) = fallback.append_text(&msg) {
+        handle_error(&*err);
+    }
+}
+
+struct AsyncPoolInner {
+    workers: Vec<Arc<AsyncWorker>>,
+    next: AtomicUsize,
+    next_id: AtomicUsize,
+}
+
+impl Drop for AsyncPoolInner {
+    fn drop(&mut self) {
+        for worker in &self.workers {
+            worker.state.lock().unwrap().shutdown = true;
+            worker.cond.notify_all();
+        }
+    }
+}
+
+/// A fixed number of background threads shared by `AsyncAppender`s.
+///
+/// By default, each `AsyncAppender` starts a thread of its own. Appenders
+/// built with a pool instead have their queues written out by its threads,
+/// which saves a thread per appender when there are many of them. Each
+/// appender is assigned to one of the pool's threads when it is built, in
+/// turn, so the log events of an appender are still written in order, but a
+/// slow writer delays the other appenders sharing its thread.
+///
+/// The pool's threads exit once the pool and all appenders using it have been
+/// dropped. Flushing or dropping an appender drains its queue as it does
+/// without a pool.
+#[derive(Clone)]
+pub struct AsyncPool(Arc<AsyncPoolInner>);
+
+impl AsyncPool {
+    /// Creates a new `AsyncPool`, starting the specified number of threads.
+    ///
+    /// An error is returned if the number of threads is 0 or a thread cannot
+    /// be started.
+    pub fn new(threads: usize) -> io::Result<AsyncPool> {
+        if threads == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "an async pool must have at least 1 thread"));
+        }
+        AsyncPool::spawn(threads, "log4rs async pool")
+    }
+
+    fn spawn(threads: usize, name: &str) -> io::Result<AsyncPool> {
+        let mut workers = vec![];
+        for _ in 0..threads {
+            let worker = Arc::new(AsyncWorker {
+                state: Mutex::new(AsyncWorkerState {
+                    channels: vec![],
+                    cursor: 0,
+                    shutdown: false,
+                }),
+                cond: Condvar::new(),
+            });
+            let thread_worker = worker.clone();
+            try!(thread::Builder::new()
+                .name(name.to_string())
+                .spawn(move || run_async(thread_worker)));
+            workers.push(worker);
+        }
+
+        Ok(AsyncPool(Arc::new(AsyncPoolInner {
+            workers: workers,
+            next: AtomicUsize::new(0),
+            next_id: AtomicUsize::new(0),
+        })))
+    }
+
+    fn assign(&self) -> (Arc<AsyncWorker>, usize) {
+        let next = self.0.next.fetch_add(1, Ordering::Relaxed);
+        let worker = self.0.workers[next % self.0.workers.len()].clone();
+        (worker, self.0.next_id.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// An appender which passes log events to another appender from a background
+/// thread.
+///
+/// Log events are placed in a bounded queue, so `append` returns without
+/// waiting on the wrapped appender. What happens when the queue is full is
+/// determined by the `OverflowPolicy`. `flush` waits until every log event
+/// queued before it was called has been written and the wrapped appender has
+/// been flushed. Dropping the appender drains the queue. `reopen` returns
+/// immediately, and the wrapped appender is reopened on the background thread
+/// once the log events queued before it was called have been written.
+///
+/// Each log event is captured as a `LogEvent` on the logging thread, which
+/// formats its message and copies the time, thread and mapped diagnostic
+/// context, and is passed to the wrapped appender's `append_event` from the
+/// background thread. The wrapped appender, and its encoder, must support
+/// captured log events, as the appenders and encoders in log4rs do.
+///
+/// An `AsyncAppender` may instead write to a `Write`r, in which case log
+/// events are encoded on the logging thread.
+///
+/// Log events discarded under the `DropOldest` and `DropNewest` policies are
+/// counted. At the end of each report interval in which any were discarded,
+/// a summary such as `dropped 5123 log records in the last minute due to
+/// backpressure` is written to the fallback appender, which by default writes
+/// to stderr.
+///
+/// The background thread is started for the appender unless it is built
+/// with an `AsyncPool`.
+pub struct AsyncAppender {
+    worker: Arc<AsyncWorker>,
+    // keeps the pool's threads running while the appender is in use
+    _pool: AsyncPool,
+    id: usize,
+    // `None` when the appender wraps another appender
+    encoder: Option<Box<Encode>>,
+    capacity: usize,
+    policy: OverflowPolicy,
+}
+
+impl Append for AsyncAppender {
+    fn append(&mut self, record: &LogRecord) -> Result<(), Box<Error>> {
+        let event = match self.encoder {
+            Some(ref encoder) => {
+                let mut buf = vec![];
+                try!(encoder.encode(&mut buf, record));
+                AsyncEvent::Encoded(buf)
+            }
+            None => AsyncEvent::Record(LogEvent::new(record)),
+        };
+        self.push(event);
+        Ok(())
+    }
+
+    fn append_event(&mut self, event: &LogEvent) -> Result<(), Box<Error>> {
+        let event = match self.encoder {
+            Some(ref encoder) => {
+                let mut buf = vec![];
+                try!(encoder.encode_event(&mut buf, event));
+                AsyncEvent::Encoded(buf)
+            }
+            None => AsyncEvent::Record(event.clone()),
+        };
+        self.push(event);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<Error>> {
+        let mut state = self.worker.state.lock().unwrap();
+        let target = {
+            let channel = state.channel(self.id);
+            let target = channel.queued;
+            channel.flush_at = Some(channel.flush_at.map_or(target, |at| at.max(target)));
+            target
+        };
+        self.worker.cond.notify_all();
+        while state.channel(self.id).flushed < target {
+            state = self.worker.cond.wait(state).unwrap();
+        }
+        Ok(())
+    }
+
+    fn append_text(&mut self, text: &str) -> Result<(), Box<Error>> {
+        let event = match self.encoder {
+            Some(_) => AsyncEvent::Encoded(format!("{}\n", text).into_bytes()),
+            None => AsyncEvent::Text(text.to_owned()),
+        };
+        self.push(event);
+        Ok(())
+    }
+
+    fn reopen(&mut self) -> Result<(), Box<Error>> {
+        {
+            let mut state = self.worker.state.lock().unwrap();
+            let channel = state.channel(self.id);
+            channel.reopen_at = Some(channel.queued);
+        }
+        self.worker.cond.notify_all();
+        Ok(())
+    }
+}
+
+impl Drop for AsyncAppender {
+    fn drop(&mut self) {
+        let mut state = self.worker.state.lock().unwrap();
+        state.channel(self.id).closed = true;
+        self.worker.cond.notify_all();
+        while state.channels.iter().any(|c| c.id == self.id) {
+            state = self.worker.cond.wait(state).unwrap();
+        }
+    }
+}
+
+impl AsyncAppender {
+    /// Creates a new `AsyncAppender` builder for an appender which will pass
+    /// log events to the provided appender.
+    pub fn builder(appender: Box<Append>) -> AsyncAppenderBuilder {
+        AsyncAppender::builder_for(AsyncOutput::Appender(appender))
+    }
+
+    /// Creates a new `AsyncAppender` builder for an appender which will write
+    /// to the provided writer.
+    pub fn writer_builder(writer: Box<Write + Send>) -> AsyncAppenderBuilder {
+        AsyncAppender::builder_for(AsyncOutput::Writer(writer))
+    }
+
+    fn builder_for(output: AsyncOutput) -> AsyncAppenderBuilder {
+        AsyncAppenderBuilder {
+            output: output,
+            encoder: Box::new(PatternLayout::default()),
+            capacity: 1024,
+            policy: OverflowPolicy::Block,
+            fallback: Box::new(ConsoleAppender::builder().target(Target::Stderr).build()),
+            report_interval: Duration::from_secs(60),
+            pool: None,
+        }
+    }
+
+    /// Returns the number of log events which have been discarded because
+    /// the queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.worker.state.lock().unwrap().channel(self.id).dropped
+    }
+
+    fn push(&self, event: AsyncEvent) {
+        let mut state = self.worker.state.lock().unwrap();
+        while state.channel(self.id).queue.len() >= self.capacity {
+            match self.policy {
+                OverflowPolicy::Block => state = self.worker.cond.wait(state).unwrap(),
+                OverflowPolicy::DropOldest => {
+                    let channel = state.channel(self.id);
+                    channel.queue.pop_front();
+                    channel.done += 1;
+                    channel.dropped += 1;
+                }
+                OverflowPolicy::DropNewest => {
+                    state.channel(self.id).dropped += 1;
+                    return;
+                }
+            }
+        }
+        {
+            let channel = state.channel(self.id);
+            channel.queue.push_back(event);
+            channel.queued += 1;
+        }
+        self.worker.cond.notify_all();
+    }
+}
+
+/// A builder for `AsyncAppender`s.
+pub struct AsyncAppenderBuilder {
+    output: AsyncOutput,
+    encoder: Box<Encode>,
+    capacity: usize,
+    policy: OverflowPolicy,
+    fallback: Box<Append>,
+    report_interval: Duration,
+    pool: Option<AsyncPool>,
+}
+
+impl AsyncAppenderBuilder {
+    /// Sets the output pattern for the `AsyncAppender`.
+    ///
+    /// This is a shorthand for `encoder(Box::new(pattern))`.
+    pub fn pattern(self, pattern: PatternLayout) -> AsyncAppenderBuilder {
+        self.encoder(Box::new(pattern))
+    }
+
+    /// Sets the encoder used to format output for the `AsyncAppender`.
+    ///
+    /// The encoder is only used by an appender which writes to a writer,
+    /// since a wrapped appender formats log events itself. Defaults to the
+    /// default `PatternLayout`.
+    pub fn encoder(mut self, encoder: Box<Encode>) -> AsyncAppenderBuilder {
+        self.encoder = encoder;
+        self
+    }
+
+    /// Sets the maximum number of log events which may be queued.
+    ///
+    /// Defaults to 1024.
+    pub fn capacity(mut self, capacity: usize) -> AsyncAppenderBuilder {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Sets the behavior of the appender when its queue is full.
+    ///
+    /// Defaults to `OverflowPolicy::Block`.
+    pub fn policy(mut self, policy: OverflowPolicy) -> AsyncAppenderBuilder {
+        self.policy = policy;
+        self
+    }
+
+    /// Sets the appender to which summaries of discarded log events are
+    /// written.
+    ///
+    /// The appender is called from the background thread. Defaults to a
+    /// `ConsoleAppender` writing to stderr.
+    pub fn fallback(mut self, fallback: Box<Append>) -> AsyncAppenderBuilder {
+        self.fallback = fallback;
+        self
+    }
+
+    /// Sets the interval over which discarded log events are summarized.
+    ///
+    /// The interval must be nonzero. Defaults to one minute.
+    pub fn report_interval(mut self, report_interval: Duration) -> AsyncAppenderBuilder {
+        self.report_interval = report_interval;
+        self
+    }
+
+    /// Sets the pool whose threads write out the appender's queue.
+    ///
+    /// Defaults to none, in which case the appender starts a thread of its
+    /// own.
+    pub fn pool(mut self, pool: AsyncPool) -> AsyncAppenderBuilder {
+        self.pool = Some(pool);
+        self
+    }
+
+    /// Consumes the `AsyncAppenderBuilder`, producing an `AsyncAppender`.
+    ///
+    /// Unless a pool is set, the background thread is started immediately.
+    /// An error is returned if the capacity or report interval is 0, or if
+    /// the thread cannot be started.
+    pub fn build(self) -> io::Result<AsyncAppender> {
+        if self.capacity == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "capacity must be at least 1"));
+        }
+        if self.report_interval == Duration::from_secs(0) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "report interval must be nonzero"));
+        }
+
+        let pool = match self.pool {
+            Some(pool) => pool,
+            None => try!(AsyncPool::spawn(1, "log4rs async appender")),
+        };
+        let encoder = match self.output {
+            AsyncOutput::Writer(_) => Some(self.encoder),
+            AsyncOutput::Appender(_) => None,
+        };
+        let (worker, id) = pool.assign();
+        worker.state.lock().unwrap().channels.push(AsyncChannel {
+            id: id,
+            queue: VecDeque::new(),
+            queued: 0,
+            done: 0,
+            flush_at: None,
+            flushed: 0,
+            reopen_at: None,
+            dropped: 0,
+            reported: 0,
+            closed: false,
+            output: Some(self.output),
+            fallback: Some(self.fallback),
+            report_interval: self.report_interval,
+            next_report: Instant::now() + self.report_interval,
+        });
+
+        Ok(AsyncAppender {
+            worker: worker,
+            _pool: pool,
+            id: id,
+            encoder: encoder,
+            capacity: self.capacity,
+            policy: self.policy,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io;
+    use std::io::prelude::*;
+    use std::path::Path;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+    use flate2::read::GzDecoder;
+    use log::LogLevelFilter;
+    use time;
+
+    use {Append, config};
+    use clock::ManualClock;
+    use super::*;
+    use super::{AsyncEvent, strip_ansi};
+
+    // Writes to a shared buffer, blocking while the gate is locked.
+    struct GatedWriter {
+        gate: Arc<Mutex<()>>,
+        out: Arc<Mutex<Vec<u8>>>,
+    }
+
+    impl Write for GatedWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let _gate = self.gate.lock().unwrap();
+            self.out.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn async_output(policy: OverflowPolicy) -> String {
+        let gate = Arc::new(Mutex::new(()));
+        let out = Arc::new(Mutex::new(vec![]));
+        let writer = GatedWriter { gate: gate.clone(), out: out.clone() };
+        let mut appender = AsyncAppender::writer_builder(Box::new(writer))
+            .capacity(2)
+            .policy(policy)
+            .build()
+            .unwrap();
+
+        {
+            let _gate = gate.lock().unwrap();
+            appender.push(AsyncEvent::Encoded(b"1".to_vec()));
+            // wait for the writer thread to block on the first event
+            while !appender.worker.state.lock().unwrap().channel(appender.id).queue.is_empty() {
+                thread::sleep(Duration::from_millis(1));
+            }
+            appender.push(AsyncEvent::Encoded(b"2".to_vec()));
+            appender.push(AsyncEvent::Encoded(b"3".to_vec()));
+            if policy != OverflowPolicy::Block {
+                appender.push(AsyncEvent::Encoded(b"4".to_vec()));
+            }
+        }
+
+        appender.flush().unwrap();
+        let out = out.lock().unwrap();
+        String::from_utf8(out.clone()).unwrap()
+    }
+
+    #[test]
+    fn file_append() {
+        let dir = env::temp_dir().join("log4rs-file-append-test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("app.log");
+        File::create(&path).unwrap().write_all(b"a long previous run\n").unwrap();
+
+        FileAppender::builder(&path).build().unwrap().append_text("next").unwrap();
+        assert_eq!(read(&path), "a long previous run\nnext\n");
+
+        FileAppender::builder(&path).append(false).build().unwrap().append_text("new").unwrap();
+        assert_eq!(read(&path), "new\n");
+    }
+
+    fn read(path: &Path) -> String {
+        let mut contents = String::new();
+        File::open(path).unwrap().read_to_string(&mut contents).unwrap();
+        contents
+    }
+
+    #[test]
+    fn file_buffered() {
+        let dir = env::temp_dir().join("log4rs-file-buffered-test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join("records.log");
+        let mut appender = FileAppender::builder(&path)
+            .buffer_size(1024)
+            .flush_records(2)
+            .build()
+            .unwrap();
+        appender.append_text("a").unwrap();
+        assert_eq!(read(&path), "");
+        appender.append_text("b").unwrap();
+        assert_eq!(read(&path), "a\nb\n");
+        appender.append_text("c").unwrap();
+        appender.flush().unwrap();
+        assert_eq!(read(&path), "a\nb\nc\n");
+
+        let path = dir.join("interval.log");
+        let mut appender = FileAppender::builder(&path)
+            .buffer_size(1024)
+            .flush_interval(Duration::from_millis(10))
+            .build()
+            .unwrap();
+        appender.append_text("a").unwrap();
+        for _ in 0..500 {
+            if read(&path) == "a\n" {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(read(&path), "a\n");
+
+        let path = dir.join("drop.log");
+        let mut appender = FileAppender::builder(&path).buffer_size(1024).build().unwrap();
+        appender.append_text("a").unwrap();
+        assert_eq!(read(&path), "");
+        drop(appender);
+        assert_eq!(read(&path), "a\n");
+    }
+
+    #[test]
+    fn file_open_error() {
+        let dir = env::temp_dir().join("log4rs-file-open-error-test");
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("app.log");
+
+        let err = FileAppender::builder(&path).build().err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert!(err.to_string().starts_with(&format!("Unable to open `{}`: ", path.display())));
+    }
+
+    #[test]
+    fn file_create_dirs() {
+        let dir = env::temp_dir().join("log4rs-file-create-dirs-test");
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("sub/app.log");
+
+        assert!(FileAppender::builder(&path).build().is_err());
+        FileAppender::builder(&path).create_dirs(true).build().unwrap();
+        assert!(path.exists());
+
+        File::create(dir.join("file")).unwrap();
+        let err = FileAppender::builder(dir.join("file/app.log"))
+            .create_dirs(true)
+            .build()
+            .err()
+            .unwrap();
+        assert!(err.to_string().starts_with("Unable to create directory"));
+    }
+
+    #[test]
+    fn daily_compression() {
+        let dir = env::temp_dir().join("log4rs-daily-compression-test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let template = format!("{}/app-%Y-%m-%d.log", dir.display());
+
+        let mut appender = DailyRollingFileAppender::builder(&template)
+            .compress(true)
+            .build()
+            .unwrap();
+        appender.file.write_all(b"today\n").unwrap();
+
+        let old = appender.current.clone();
+        let mut tomorrow = time::now();
+        tomorrow.tm_yday += 1;
+        tomorrow.tm_year += 1;
+        appender.roll_if_needed(&tomorrow).unwrap();
+        appender.compression.take().unwrap().join().unwrap();
+
+        assert!(!old.exists());
+        let mut gz_path = old.into_os_string();
+        gz_path.push(".gz");
+        let mut contents = String::new();
+        GzDecoder::new(File::open(&gz_path).unwrap()).read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "today\n");
+    }
+
+    #[test]
+    fn file_reopen() {
+        let dir = env::temp_dir().join("log4rs-file-reopen-test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("app.log");
+
+        let mut appender = FileAppender::builder(&path).buffer_size(1024).build().unwrap();
+        appender.append_text("one").unwrap();
+        fs::rename(&path, dir.join("app.log.1")).unwrap();
+        appender.append_text("two").unwrap();
+        appender.reopen().unwrap();
+        appender.append_text("three").unwrap();
+        appender.flush().unwrap();
+
+        let mut contents = String::new();
+        File::open(dir.join("app.log.1")).unwrap().read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "one\ntwo\n");
+        let mut contents = String::new();
+        File::open(&path).unwrap().read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "three\n");
+    }
+
+    #[test]
+    fn async_reopen() {
+        let dir = env::temp_dir().join("log4rs-async-reopen-test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("app.log");
+
+        let file = FileAppender::builder(&path).build().unwrap();
+        let mut appender = AsyncAppender::builder(Box::new(file)).build().unwrap();
+        appender.append_text("one").unwrap();
+        appender.flush().unwrap();
+        fs::rename(&path, dir.join("app.log.1")).unwrap();
+        appender.append_text("two").unwrap();
+        appender.reopen().unwrap();
+        appender.append_text("three").unwrap();
+        drop(appender);
+
+        let mut contents = String::new();
+        File::open(dir.join("app.log.1")).unwrap().read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "one\ntwo\n");
+        let mut contents = String::new();
+        File::open(&path).unwrap().read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "three\n");
+    }
+
+    #[test]
+    fn async_overflow() {
+        assert_eq!(async_output(OverflowPolicy::Block), "123");
+        assert_eq!(async_output(OverflowPolicy::DropOldest), "134");
+        assert_eq!(async_output(OverflowPolicy::DropNewest), "123");
+    }
+
+    #[test]
+    fn async_reports_dropped() {
+        let gate = Arc::new(Mutex::new(()));
+        let out = Arc::new(Mutex::new(vec![]));
+        let writer = GatedWriter { gate: gate.clone(), out: out };
+        let fallback = MemoryAppender::builder(10).build();
+        let reports = fallback.handle();
+        let appender = AsyncAppender::writer_builder(Box::new(writer))
+            .capacity(1)
+            .policy(OverflowPolicy::DropNewest)
+            .fallback(Box::new(fallback))
+            .report_interval(Duration::from_millis(20))
+            .build()
+            .unwrap();
+
+        {
+            let _gate = gate.lock().unwrap();
+            appender.push(AsyncEvent::Encoded(b"1".to_vec()));
+            while !appender.worker.state.lock().unwrap().channel(appender.id).queue.is_empty() {
+                thread::sleep(Duration::from_millis(1));
+            }
+            for _ in 0..6 {
+                appender.push(AsyncEvent::Encoded(b"2".to_vec()));
+            }
+        }
+        assert_eq!(appender.dropped(), 5);
+
+        while reports.snapshot().is_empty() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(reports.snapshot(),
+                   ["dropped 5 log records in the last 20 milliseconds due to backpressure"]);
+    }
+
+    #[test]
+    fn async_drains_on_drop() {
+        let gate = Arc::new(Mutex::new(()));
+        let out = Arc::new(Mutex::new(vec![]));
+        let writer = GatedWriter { gate: gate, out: out.clone() };
+        let appender = AsyncAppender::writer_builder(Box::new(writer)).build().unwrap();
+        for i in 0..100 {
+            appender.push(AsyncEvent::Encoded(i.to_string().into_bytes()));
+        }
+        drop(appender);
+
+        let expected = (0..100).map(|i| i.to_string()).collect::<String>();
+        assert_eq!(String::from_utf8(out.lock().unwrap().clone()).unwrap(), expected);
+    }
+
+    #[test]
+    fn async_wraps_appender() {
+        let memory = MemoryAppender::builder(10)
+            .pattern(PatternLayout::new("%l %t %T %X{request_id} %m").unwrap())
+            .build();
+        let memory_handle = memory.handle();
+        let appender = AsyncAppender::builder(Box::new(memory)).build().unwrap();
+        let root = config::Root::builder(LogLevelFilter::Off).build();
+        let config = config::Config::builder(root)
+            .appender(config::Appender::builder("async", Box::new(appender)).build())
+            .logger(config::Logger::builder("async_wraps", LogLevelFilter::Info)
+                        .appender("async")
+                        .build())
+            .build()
+            .unwrap();
+
+        let handle = ::test::global_handle();
+        handle.as_ref().unwrap().set_config(config);
+        // the thread and MDC are those of the logging thread rather than the
+        // background thread's
+        let logged = thread::Builder::new()
+            .name("logging".to_owned())
+            .spawn(|| {
+                ::mdc::insert("request_id", "1234");
+                warn!(target: "async_wraps", "one {}", 1);
+                ::mdc::clear();
+                info!(target: "async_wraps", "two");
+            })
+            .unwrap();
+        logged.join().unwrap();
+        // the old appenders are flushed and dropped, draining the queue
+        let root = config::Root::builder(LogLevelFilter::Off).build();
+        handle.as_ref().unwrap().set_config(config::Config::builder(root).build().unwrap());
+
+        assert_eq!(memory_handle.snapshot(),
+                   ["WARN async_wraps logging 1234 one 1", "INFO async_wraps logging  two"]);
+    }
+
+    #[test]
+    fn async_zero_capacity() {
+        assert!(AsyncAppender::writer_builder(Box::new(io::sink())).capacity(0).build().is_err());
+    }
+
+    #[test]
+    fn async_zero_report_interval() {
+        assert!(AsyncAppender::writer_builder(Box::new(io::sink()))
+                    .report_interval(Duration::from_secs(0))
+                    .build()
+                    .is_err());
+    }
+
+    #[test]
+    fn async_pool() {
+        assert!(AsyncPool::new(0).is_err());
+
+        let pool = AsyncPool::new(2).unwrap();
+        let gate = Arc::new(Mutex::new(()));
+        let outs = (0..3).map(|_| Arc::new(Mutex::new(vec![]))).collect::<Vec<_>>();
+        let mut appenders = outs.iter()
+            .map(|out| {
+                let writer = GatedWriter { gate: gate.clone(), out: out.clone() };
+                AsyncAppender::writer_builder(Box::new(writer)).pool(pool.clone()).build().unwrap()
+            })
+            .collect::<Vec<_>>();
+        drop(pool);
+
+        {
+            let _gate = gate.lock().unwrap();
+            for i in 0..50 {
+                for appender in &appenders {
+                    appender.push(AsyncEvent::Encoded(i.to_string().into_bytes()));
+                }
+            }
+        }
+        appenders[0].flush().unwrap();
+        drop(appenders.split_off(1));
+
+        let expected = (0..50).map(|i| i.to_string()).collect::<String>();
+        for out in &outs {
+            assert_eq!(String::from_utf8(out.lock().unwrap().clone()).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn daily_dst() {
+        // New York's clocks went forward on 2016-03-13, making it 23 hours long
+        if !::test::in_time_zone("appender::test::daily_dst", "EST5EDT,M3.2.0,M11.1.0") {
+            return;
         }
+
+        let dir = env::temp_dir().join("log4rs-daily-dst-test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let template = format!("{}/app-%Y-%m-%d.log", dir.display());
+
+        // 2016-03-13 00:30 EST
+        let clock = Arc::new(ManualClock::new(time::Timespec::new(1457847000, 0)));
+        let mut appender = DailyRollingFileAppender::builder(&template)
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        appender.append_text("first").unwrap();
+        // 23:30 EDT on the same day
+        clock.advance(time::Duration::hours(22));
+        appender.append_text("still first").unwrap();
+        // 00:30 EDT on the next day, only 23 hours after the first write
+        clock.advance(time::Duration::hours(1));
+        appender.append_text("second").unwrap();
+
+        let read = |name: &str| {
+            let mut contents = String::new();
+            File::open(dir.join(name)).unwrap().read_to_string(&mut contents).unwrap();
+            contents
+        };
+        assert_eq!(read("app-2016-03-13.log"), "first\nstill first\n");
+        assert_eq!(read("app-2016-03-14.log"), "second\n");
+    }
+
+    #[test]
+    fn daily_rolls_on_day_change() {
+        let dir = env::temp_dir().join("log4rs-daily-rolling-test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let template = format!("{}/app-%Y-%m-%d.log", dir.display());
+
+        let mut first = time::empty_tm();
+        first.tm_year = 115;
+        first.tm_mon = 6;
+        first.tm_mday = 1;
+        first.tm_hour = 12;
+        let clock = Arc::new(ManualClock::new(first.to_timespec()));
+
+        File::create(dir.join("app-2015-07-01.log")).unwrap().write_all(b"existing\n").unwrap();
+
+        let mut appender = DailyRollingFileAppender::builder(&template)
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        appender.append_text("first day").unwrap();
+        clock.advance(time::Duration::days(1));
+        appender.append_text("second day").unwrap();
+
+        let mut contents = String::new();
+        File::open(dir.join("app-2015-07-01.log")).unwrap().read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "existing\nfirst day\n");
+
+        let mut contents = String::new();
+        File::open(dir.join("app-2015-07-02.log")).unwrap().read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "second day\n");
+
+        fs::rename(dir.join("app-2015-07-02.log"), dir.join("moved.log")).unwrap();
+        appender.reopen().unwrap();
+        appender.append_text("reopened").unwrap();
+
+        let mut contents = String::new();
+        File::open(dir.join("app-2015-07-02.log")).unwrap().read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "reopened\n");
+    }
+
+    #[test]
+    fn strip_ansi_codes() {
+        let cases: [(&[u8], &[u8]); 4] = [(b"\x1b[31mERROR\x1b[0m msg", b"ERROR msg"),
+                                          (b"\x1b[1;33mWARN\x1b[0m", b"WARN"),
+                                          (b"plain [text]", b"plain [text]"),
+                                          (b"cut \x1b[", b"cut ")];
+        for &(input, expected) in &cases {
+            assert_eq!(strip_ansi(input), expected);
+        }
+
+        assert!(ConsoleAppender::builder().color(ColorMode::Always).build().color);
+        assert!(!ConsoleAppender::builder().color(ColorMode::Never).build().color);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn file_mode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = env::temp_dir().join("log4rs-file-mode-test.log");
+        let _ = fs::remove_file(&path);
+        FileAppender::builder(&path).mode(0o600).build().unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
     }
 }
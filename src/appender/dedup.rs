@@ -0,0 +1,333 @@
+use std::collections::{HashMap, VecDeque};
+use std::collections::hash_map::DefaultHasher;
+use std::error::Error;
+use std::hash::{Hash, Hasher};
+use std::time::{Duration, Instant};
+use log::LogRecord;
+
+use {Append, LogEvent};
+
+/// An appender which suppresses consecutive repeats of a log event.
+///
+/// Log events are considered to be repeats if they have the same level,
+/// target, and message. Once the same log event has been passed to the
+/// wrapped appender `threshold` times in a row, further repeats are
+/// discarded. When a different log event arrives, a summary is passed to the
+/// wrapped appender before it. The summary is a copy of the first discarded
+/// repeat with the message `... (repeated 412 times)`, passed to the wrapped
+/// appender's `append_event`, so it is formatted by the wrapped appender's
+/// encoder like any other log event.
+///
+/// If a timeout is set, a summary is also emitted for repeats which have been
+/// suppressed for longer than the timeout. Since the appender has no thread
+/// of its own, this happens on the next log event or flush.
+///
+/// If a window is set, repeats are also suppressed when other log events
+/// arrive in between them, which catches errors that flap while other output
+/// is interleaved. Each log event is then passed to the wrapped appender at
+/// most `threshold` times within the window, counted from the first time it
+/// was passed, and further repeats are discarded without a summary. Only a
+/// hash of each log event is kept, along with the time it was first seen,
+/// and entries are discarded once they are older than the window, so memory
+/// use is bounded by the number of distinct log events within a window. Two
+/// distinct log events are very unlikely to share a hash, but if they do,
+/// one may be suppressed as a repeat of the other.
+pub struct DedupAppender {
+    appender: Box<Append>,
+    threshold: u64,
+    timeout: Option<Duration>,
+    window: Option<Duration>,
+    // the number of times each recent log event was passed on, by hash
+    recent: HashMap<u64, u64>,
+    // the hashes of recent log events, in the order they were first seen
+    recent_order: VecDeque<(Instant, u64)>,
+    last: Option<String>,
+    count: u64,
+    suppressed: u64,
+    suppressed_since: Instant,
+    // the first suppressed repeat, from which the summary is built
+    repeat: Option<LogEvent>,
+}
+
+impl Append for DedupAppender {
+    fn append(&mut self, record: &LogRecord) -> Result<(), Box<Error>> {
+        let key = format!("{}\0{}\0{}", record.level(), record.target(), record.args());
+        if try!(self.check(key, Instant::now(), || LogEvent::new(record))) {
+            self.appender.append(record)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn append_event(&mut self, event: &LogEvent) -> Result<(), Box<Error>> {
+        let key = format!("{}\0{}\0{}", event.level(), event.target(), event.message());
+        if try!(self.check(key, Instant::now(), || event.clone())) {
+            self.appender.append_event(event)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn flush(&mut self) -> Result<(), Box<Error>> {
+        try!(self.check_timeout(Instant::now()));
+        self.appender.flush()
+    }
+
+    fn append_text(&mut self, text: &str) -> Result<(), Box<Error>> {
+        self.appender.append_text(text)
+    }
+
+    fn reopen(&mut self) -> Result<(), Box<Error>> {
+        self.appender.reopen()
+    }
+
+    fn accepts(&self, record: &LogRecord) -> bool {
+        self.appender.accepts(record)
+    }
+
+    fn accepts_event(&self, event: &LogEvent) -> bool {
+        self.appender.accepts_event(event)
+    }
+}
+
+impl DedupAppender {
+    /// Creates a new `DedupAppender` builder wrapping the specified appender.
+    pub fn builder(appender: Box<Append>) -> DedupAppenderBuilder {
+        DedupAppenderBuilder {
+            appender: appender,
+            threshold: 1,
+            timeout: None,
+            window: None,
+        }
+    }
+
+    // Returns true if the log event identified by `key` should be passed to
+    // the wrapped appender. `capture` is called for the first repeat which is
+    // suppressed.
+    fn check<F>(&mut self, key: String, now: Instant, capture: F) -> Result<bool, Box<Error>>
+        where F: FnOnce() -> LogEvent
+    {
+        if let Some(window) = self.window {
+            if !self.check_window(&key, now, window) {
+                return Ok(false);
+            }
+        }
+
+        if self.last.as_ref() == Some(&key) {
+            self.count += 1;
+            if self.count <= self.threshold {
+                return Ok(true);
+            }
+
+            if self.suppressed == 0 {
+                self.suppressed_since = now;
+                self.repeat = Some(capture());
+            }
+            self.suppressed += 1;
+            try!(self.check_timeout(now));
+            Ok(false)
+        } else {
+            try!(self.summarize());
+            self.last = Some(key);
+            self.count = 1;
+            Ok(true)
+        }
+    }
+
+    // Returns false if the log event identified by `key` has already been
+    // passed on `threshold` times within the window.
+    fn check_window(&mut self, key: &str, now: Instant, window: Duration) -> bool {
+        while let Some(&(seen, hash)) = self.recent_order.front() {
+            if now.duration_since(seen) < window {
+                break;
+            }
+            self.recent_order.pop_front();
+            self.recent.remove(&hash);
+        }
+
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let hash = hasher.finish();
+        match self.recent.get_mut(&hash) {
+            Some(ref count) if **count >= self.threshold => return false,
+            Some(count) => {
+                *count += 1;
+                return true;
+            }
+            None => {}
+        }
+        self.recent.insert(hash, 1);
+        self.recent_order.push_back((now, hash));
+        true
+    }
+
+    fn check_timeout(&mut self, now: Instant) -> Result<(), Box<Error>> {
+        match self.timeout {
+            Some(timeout) if self.suppressed > 0 && now - self.suppressed_since >= timeout => {
+                self.summarize()
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn summarize(&mut self) -> Result<(), Box<Error>> {
+        if self.suppressed == 0 {
+            return Ok(());
+        }
+        let suppressed = self.suppressed;
+        self.suppressed = 0;
+        let repeat = self.repeat.take().expect("suppressed repeat not captured");
+        let summary = repeat.with_message(format!("... (repeated {} times)", suppressed));
+        self.appender.append_event(&summary)
+    }
+}
+
+/// A builder for `DedupAppender`s.
+pub struct DedupAppenderBuilder {
+    appender: Box<Append>,
+    threshold: u64,
+    timeout: Option<Duration>,
+    window: Option<Duration>,
+}
+
+impl DedupAppenderBuilder {
+    /// Sets the number of times a log event is passed to the wrapped
+    /// appender before repeats are suppressed.
+    ///
+    /// Defaults to 1. A threshold of 0 is treated as 1.
+    pub fn threshold(mut self, threshold: u64) -> DedupAppenderBuilder {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Sets the maximum amount of time that repeats are suppressed before a
+    /// summary is emitted.
+    ///
+    /// Defaults to no timeout.
+    pub fn timeout(mut self, timeout: Duration) -> DedupAppenderBuilder {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the window within which repeats are suppressed even if they are
+    /// not consecutive.
+    ///
+    /// Defaults to no window, in which case only consecutive repeats are
+    /// suppressed.
+    pub fn window(mut self, window: Duration) -> DedupAppenderBuilder {
+        self.window = Some(window);
+        self
+    }
+
+    /// Consumes the `DedupAppenderBuilder`, producing a `DedupAppender`.
+    pub fn build(self) -> DedupAppender {
+        DedupAppender {
+            appender: self.appender,
+            threshold: if self.threshold == 0 { 1 } else { self.threshold },
+            timeout: self.timeout,
+            window: self.window,
+            recent: HashMap::new(),
+            recent_order: VecDeque::new(),
+            last: None,
+            count: 0,
+            suppressed: 0,
+            suppressed_since: Instant::now(),
+            repeat: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::error::Error;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+    use log::{LogLevel, LogRecord};
+
+    use {Append, LogEvent};
+    use super::*;
+
+    struct TextRecorder(Arc<Mutex<Vec<String>>>);
+
+    impl Append for TextRecorder {
+        fn append(&mut self, _: &LogRecord) -> Result<(), Box<Error>> {
+            Ok(())
+        }
+
+        fn append_event(&mut self, event: &LogEvent) -> Result<(), Box<Error>> {
+            let text = format!("{} {} {}", event.level(), event.target(), event.message());
+            self.0.lock().unwrap().push(text);
+            Ok(())
+        }
+    }
+
+    // Checks a log event whose target is `key`.
+    fn check(appender: &mut DedupAppender, key: &str, now: Instant) -> bool {
+        let event = || ::test::log_event(LogLevel::Warn, key, "message");
+        appender.check(key.to_owned(), now, event).unwrap()
+    }
+
+    #[test]
+    fn suppresses_repeats() {
+        let texts = Arc::new(Mutex::new(vec![]));
+        let mut appender = DedupAppender::builder(Box::new(TextRecorder(texts.clone())))
+            .threshold(2)
+            .build();
+        let now = Instant::now();
+
+        let passed = ["a", "a", "a", "a", "a", "b", "a"]
+            .iter()
+            .map(|key| check(&mut appender, key, now))
+            .collect::<Vec<_>>();
+        assert_eq!(passed, [true, true, false, false, false, true, true]);
+        assert_eq!(*texts.lock().unwrap(), ["WARN a ... (repeated 3 times)"]);
+    }
+
+    #[test]
+    fn window() {
+        let texts = Arc::new(Mutex::new(vec![]));
+        let mut appender = DedupAppender::builder(Box::new(TextRecorder(texts.clone())))
+            .threshold(2)
+            .window(Duration::from_secs(60))
+            .build();
+        let now = Instant::now();
+
+        let passed = ["a", "b", "a", "b", "a", "c", "a"]
+            .iter()
+            .enumerate()
+            .map(|(i, key)| check(&mut appender, key, now + Duration::from_secs(i as u64)))
+            .collect::<Vec<_>>();
+        assert_eq!(passed, [true, true, true, true, false, true, false]);
+        assert!(texts.lock().unwrap().is_empty());
+
+        // "a" and "b" were first seen at 0 and 1 seconds
+        assert!(!check(&mut appender, "b", now + Duration::from_secs(59)));
+        assert!(check(&mut appender, "a", now + Duration::from_secs(60)));
+        assert_eq!(appender.recent.len(), 3);
+        assert!(check(&mut appender, "b", now + Duration::from_secs(90)));
+        assert_eq!(appender.recent.len(), 2);
+        check(&mut appender, "d", now + Duration::from_secs(200));
+        assert_eq!(appender.recent.len(), 1);
+    }
+
+    #[test]
+    fn timeout() {
+        let texts = Arc::new(Mutex::new(vec![]));
+        let mut appender = DedupAppender::builder(Box::new(TextRecorder(texts.clone())))
+            .timeout(Duration::from_secs(60))
+            .build();
+        let now = Instant::now();
+
+        assert!(check(&mut appender, "a", now));
+        assert!(!check(&mut appender, "a", now));
+        assert!(!check(&mut appender, "a", now + Duration::from_secs(30)));
+        assert!(texts.lock().unwrap().is_empty());
+        assert!(!check(&mut appender, "a", now + Duration::from_secs(61)));
+        assert_eq!(*texts.lock().unwrap(), ["WARN a ... (repeated 3 times)"]);
+
+        assert!(!check(&mut appender, "a", now + Duration::from_secs(62)));
+        appender.check_timeout(now + Duration::from_secs(200)).unwrap();
+        assert_eq!(*texts.lock().unwrap(),
+                   ["WARN a ... (repeated 3 times)", "WARN a ... (repeated 1 times)"]);
+    }
+}
@@ -0,0 +1,250 @@
+use std::error::Error;
+use std::io;
+use log::{LogLevel, LogRecord};
+
+use {Append, Encode, LogEvent};
+use pattern::PatternLayout;
+
+/// An appender which writes to the Windows Event Log.
+///
+/// Log events are reported to the Application log under the configured event
+/// source. The type of each event is derived from the level of the log event:
+/// `Error` maps to an error event, `Warn` to a warning event, and the
+/// remaining levels to information events. The configured encoder produces
+/// the message of each event, and a single trailing newline is removed from
+/// its output.
+///
+/// Event Viewer looks up the event source in the registry, where it is
+/// normally created by an installer. Events reported under a source which has
+/// not been registered are still recorded, but are displayed with a note that
+/// their description could not be found.
+///
+/// On platforms other than Windows, the appender discards all log events.
+pub struct EventLogAppender {
+    source: sys::EventSource,
+    encoder: Box<Encode>,
+}
+
+impl Append for EventLogAppender {
+    fn append(&mut self, record: &LogRecord) -> Result<(), Box<Error>> {
+        let mut buf = vec![];
+        try!(self.encoder.encode(&mut buf, record));
+        try!(self.report(event_type(record.level()), &buf));
+        Ok(())
+    }
+
+    fn append_event(&mut self, event: &LogEvent) -> Result<(), Box<Error>> {
+        let mut buf = vec![];
+        try!(self.encoder.encode_event(&mut buf, event));
+        try!(self.report(event_type(event.level()), &buf));
+        Ok(())
+    }
+
+    fn append_text(&mut self, text: &str) -> Result<(), Box<Error>> {
+        try!(self.report(EventType::Information, text.as_bytes()));
+        Ok(())
+    }
+}
+
+impl EventLogAppender {
+    /// Creates a new `EventLogAppender` builder for an appender which will
+    /// report events under the specified event source.
+    pub fn builder(source: &str) -> EventLogAppenderBuilder {
+        EventLogAppenderBuilder {
+            source: source.to_owned(),
+            encoder: Box::new(PatternLayout::new("%m").unwrap()),
+        }
+    }
+
+    fn report(&self, event_type: EventType, msg: &[u8]) -> io::Result<()> {
+        let msg = if msg.ends_with(b"\n") {
+            &msg[..msg.len() - 1]
+        } else {
+            msg
+        };
+        self.source.report(event_type, &String::from_utf8_lossy(msg))
+    }
+}
+
+/// A builder for `EventLogAppender`s.
+pub struct EventLogAppenderBuilder {
+    source: String,
+    encoder: Box<Encode>,
+}
+
+impl EventLogAppenderBuilder {
+    /// Sets the output pattern for the `EventLogAppender`.
+    ///
+    /// This is a shorthand for `encoder(Box::new(pattern))`.
+    pub fn pattern(self, pattern: PatternLayout) -> EventLogAppenderBuilder {
+        self.encoder(Box::new(pattern))
+    }
+
+    /// Sets the encoder used to format the message of each event.
+    ///
+    /// Defaults to a `PatternLayout` with the pattern `%m`, since the Event
+    /// Log records the time and type of each event itself.
+    pub fn encoder(mut self, encoder: Box<Encode>) -> EventLogAppenderBuilder {
+        self.encoder = encoder;
+        self
+    }
+
+    /// Consumes the `EventLogAppenderBuilder`, producing an
+    /// `EventLogAppender`.
+    ///
+    /// An error is returned if the event source cannot be opened.
+    pub fn build(self) -> io::Result<EventLogAppender> {
+        let source = try!(sys::EventSource::register(&self.source).map_err(|err| {
+            io::Error::new(err.kind(),
+                           format!("Unable to register event source `{}`: {}", self.source, err))
+        }));
+        Ok(EventLogAppender {
+            source: source,
+            encoder: self.encoder,
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum EventType {
+    Error,
+    Warning,
+    Information,
+}
+
+fn event_type(level: LogLevel) -> EventType {
+    match level {
+        LogLevel::Error => EventType::Error,
+        LogLevel::Warn => EventType::Warning,
+        LogLevel::Info | LogLevel::Debug | LogLevel::Trace => EventType::Information,
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use std::ffi::OsStr;
+    use std::io;
+    use std::os::raw::c_void;
+    use std::os::windows::ffi::OsStrExt;
+    use std::ptr;
+
+    use super::EventType;
+
+    const EVENTLOG_ERROR_TYPE: u16 = 0x0001;
+    const EVENTLOG_WARNING_TYPE: u16 = 0x0002;
+    const EVENTLOG_INFORMATION_TYPE: u16 = 0x0004;
+
+    #[link(name = "advapi32")]
+    extern "system" {
+        fn RegisterEventSourceW(server: *const u16, source: *const u16) -> *mut c_void;
+        fn DeregisterEventSource(handle: *mut c_void) -> i32;
+        fn ReportEventW(handle: *mut c_void,
+                        event_type: u16,
+                        category: u16,
+                        event_id: u32,
+                        user_sid: *mut c_void,
+                        num_strings: u16,
+                        data_size: u32,
+                        strings: *const *const u16,
+                        data: *mut c_void)
+                        -> i32;
+    }
+
+    pub struct EventSource(*mut c_void);
+
+    // event log handles may be used from any thread
+    unsafe impl Send for EventSource {}
+
+    impl EventSource {
+        pub fn register(name: &str) -> io::Result<EventSource> {
+            let name = wide(name);
+            let handle = unsafe { RegisterEventSourceW(ptr::null(), name.as_ptr()) };
+            if handle.is_null() {
+                Err(io::Error::last_os_error())
+            } else {
+                Ok(EventSource(handle))
+            }
+        }
+
+        pub fn report(&self, event_type: EventType, msg: &str) -> io::Result<()> {
+            let event_type = match event_type {
+                EventType::Error => EVENTLOG_ERROR_TYPE,
+                EventType::Warning => EVENTLOG_WARNING_TYPE,
+                EventType::Information => EVENTLOG_INFORMATION_TYPE,
+            };
+            let msg = wide(msg);
+            let strings = [msg.as_ptr()];
+            let ok = unsafe {
+                ReportEventW(self.0,
+                             event_type,
+                             0,
+                             0,
+                             ptr::null_mut(),
+                             1,
+                             0,
+                             strings.as_ptr(),
+                             ptr::null_mut())
+            };
+            if ok == 0 {
+                Err(io::Error::last_os_error())
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    impl Drop for EventSource {
+        fn drop(&mut self) {
+            unsafe {
+                DeregisterEventSource(self.0);
+            }
+        }
+    }
+
+    fn wide(s: &str) -> Vec<u16> {
+        OsStr::new(s).encode_wide().chain(Some(0)).collect()
+    }
+}
+
+#[cfg(not(windows))]
+mod sys {
+    use std::io;
+
+    use super::EventType;
+
+    pub struct EventSource;
+
+    impl EventSource {
+        pub fn register(_: &str) -> io::Result<EventSource> {
+            Ok(EventSource)
+        }
+
+        pub fn report(&self, _: EventType, _: &str) -> io::Result<()> {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use log::LogLevel;
+
+    use Append;
+    use super::*;
+    use super::{EventType, event_type};
+
+    #[test]
+    fn event_types() {
+        assert_eq!(event_type(LogLevel::Error), EventType::Error);
+        assert_eq!(event_type(LogLevel::Warn), EventType::Warning);
+        assert_eq!(event_type(LogLevel::Info), EventType::Information);
+        assert_eq!(event_type(LogLevel::Trace), EventType::Information);
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn discards_elsewhere() {
+        let mut appender = EventLogAppender::builder("log4rs").build().unwrap();
+        appender.append_text("discarded").unwrap();
+    }
+}
@@ -0,0 +1,179 @@
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::{FileTypeExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use libc;
+use log::LogRecord;
+
+use {Append, Encode, LogEvent};
+use pattern::PatternLayout;
+
+/// An appender which writes to a named pipe.
+///
+/// The pipe is opened in non-blocking mode, so logging never waits for the
+/// reading process. Log events are dropped while there is no reader, or if
+/// the pipe is full because the reader has fallen behind, and the pipe is
+/// reopened once a reader connects again. Log events larger than the pipe's
+/// atomic write size (4096 bytes on Linux) may be partially written when the
+/// pipe fills.
+pub struct FifoAppender {
+    path: PathBuf,
+    file: Option<File>,
+    encoder: Box<Encode>,
+}
+
+impl Append for FifoAppender {
+    fn append(&mut self, record: &LogRecord) -> Result<(), Box<Error>> {
+        let mut buf = vec![];
+        try!(self.encoder.encode(&mut buf, record));
+        try!(self.write(&buf));
+        Ok(())
+    }
+
+    fn append_event(&mut self, event: &LogEvent) -> Result<(), Box<Error>> {
+        let mut buf = vec![];
+        try!(self.encoder.encode_event(&mut buf, event));
+        try!(self.write(&buf));
+        Ok(())
+    }
+
+    fn append_text(&mut self, text: &str) -> Result<(), Box<Error>> {
+        try!(self.write(format!("{}\n", text).as_bytes()));
+        Ok(())
+    }
+}
+
+impl FifoAppender {
+    /// Creates a new `FifoAppender` builder for an appender which will log to
+    /// the named pipe at the provided path.
+    pub fn builder<P: AsRef<Path>>(path: P) -> FifoAppenderBuilder {
+        FifoAppenderBuilder {
+            path: path.as_ref().to_path_buf(),
+            encoder: Box::new(PatternLayout::default()),
+        }
+    }
+
+    fn write(&mut self, buf: &[u8]) -> io::Result<()> {
+        if self.file.is_none() {
+            self.file = try!(open(&self.path));
+        }
+
+        let result = match self.file {
+            Some(ref mut file) => file.write_all(buf),
+            None => return Ok(()),
+        };
+        match result {
+            Ok(()) => Ok(()),
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => Ok(()),
+            Err(ref err) if err.kind() == io::ErrorKind::BrokenPipe => {
+                self.file = None;
+                Ok(())
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
+
+// Opens the pipe for writing, returning `None` if it has no reader.
+fn open(path: &Path) -> io::Result<Option<File>> {
+    match OpenOptions::new().write(true).custom_flags(libc::O_NONBLOCK).open(path) {
+        Ok(file) => Ok(Some(file)),
+        Err(ref err) if err.raw_os_error() == Some(libc::ENXIO) => Ok(None),
+        Err(err) => {
+            Err(io::Error::new(err.kind(), format!("Unable to open `{}`: {}", path.display(), err)))
+        }
+    }
+}
+
+/// A builder for `FifoAppender`s.
+pub struct FifoAppenderBuilder {
+    path: PathBuf,
+    encoder: Box<Encode>,
+}
+
+impl FifoAppenderBuilder {
+    /// Sets the output pattern for the `FifoAppender`.
+    ///
+    /// This is a shorthand for `encoder(Box::new(pattern))`.
+    pub fn pattern(self, pattern: PatternLayout) -> FifoAppenderBuilder {
+        self.encoder(Box::new(pattern))
+    }
+
+    /// Sets the encoder used to format log events.
+    pub fn encoder(mut self, encoder: Box<Encode>) -> FifoAppenderBuilder {
+        self.encoder = encoder;
+        self
+    }
+
+    /// Consumes the `FifoAppenderBuilder`, producing a `FifoAppender`.
+    ///
+    /// The pipe must already exist, but need not have a reader.
+    pub fn build(self) -> io::Result<FifoAppender> {
+        let metadata = match fs::metadata(&self.path) {
+            Ok(metadata) => metadata,
+            Err(err) => {
+                let msg = format!("Unable to open `{}`: {}", self.path.display(), err);
+                return Err(io::Error::new(err.kind(), msg));
+            }
+        };
+        if !metadata.file_type().is_fifo() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("`{}` is not a named pipe", self.path.display())));
+        }
+
+        let file = try!(open(&self.path));
+        Ok(FifoAppender {
+            path: self.path,
+            file: file,
+            encoder: self.encoder,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::ffi::CString;
+    use std::fs::{self, File, OpenOptions};
+    use std::io::Read;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::OpenOptionsExt;
+    use libc;
+
+    use Append;
+    use super::*;
+
+    #[test]
+    fn reader_comes_and_goes() {
+        let path = env::temp_dir().join("log4rs-fifo-test");
+        let _ = fs::remove_file(&path);
+        let c_path = CString::new(path.as_os_str().as_bytes()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) }, 0);
+
+        let mut appender = FifoAppender::builder(&path).build().unwrap();
+        appender.append_text("dropped").unwrap();
+
+        let mut reader = OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(&path)
+            .unwrap();
+        appender.append_text("received").unwrap();
+        let mut buf = [0; 64];
+        let len = reader.read(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"received\n");
+
+        drop(reader);
+        appender.append_text("dropped").unwrap();
+        assert!(appender.file.is_none());
+    }
+
+    #[test]
+    fn not_a_fifo() {
+        let path = env::temp_dir().join("log4rs-fifo-regular-file");
+        File::create(&path).unwrap();
+        let err = FifoAppender::builder(&path).build().err().unwrap();
+        assert_eq!(err.to_string(), format!("`{}` is not a named pipe", path.display()));
+    }
+}
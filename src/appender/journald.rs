@@ -0,0 +1,336 @@
+use libc;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, Write};
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::os::unix::net::UnixDatagram;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use log::{LogLevel, LogRecord};
+
+use {Append, Encode, LogEvent, program_name};
+use pattern::PatternLayout;
+use super::{ConsoleWriter, Target};
+
+/// The path of the socket on which journald receives log events.
+pub const JOURNALD_SOCKET: &'static str = "/run/systemd/journal/socket";
+
+/// An appender which sends log events to the systemd journal.
+///
+/// Each log event is sent using journald's native protocol, with the
+/// following fields:
+///
+/// * `MESSAGE` - the output of the configured encoder, with a single
+///     trailing newline removed.
+/// * `PRIORITY` - the syslog severity derived from the level of the log
+///     event: `Error` maps to 3, `Warn` to 4, `Info` to 6, and `Debug` and
+///     `Trace` to 7.
+/// * `CODE_FILE`, `CODE_LINE` and `CODE_MODULE` - the location of the
+///     logging statement.
+/// * `TARGET` - the target of the log event.
+/// * `SYSLOG_IDENTIFIER` - the name of the program.
+///
+/// Log events too large to be sent in a single datagram are written to a
+/// sealed memfd which is passed to journald instead, as `sd_journal_send`
+/// does. If the journal socket does not exist or is not being listened on, as
+/// when the program is not run under systemd, the message of each log event
+/// is written to stderr instead.
+pub struct JournaldAppender {
+    socket: UnixDatagram,
+    path: PathBuf,
+    identifier: String,
+    encoder: Box<Encode>,
+}
+
+impl Append for JournaldAppender {
+    fn append(&mut self, record: &LogRecord) -> Result<(), Box<Error>> {
+        let mut msg = vec![];
+        try!(self.encoder.encode(&mut msg, record));
+
+        let location = record.location();
+        let line = location.line().to_string();
+        try!(self.send(&msg,
+                       &[("PRIORITY", priority(record.level()).as_bytes()),
+                         ("CODE_FILE", location.file().as_bytes()),
+                         ("CODE_LINE", line.as_bytes()),
+                         ("CODE_MODULE", location.module_path().as_bytes()),
+                         ("TARGET", record.target().as_bytes())]));
+        Ok(())
+    }
+
+    fn append_event(&mut self, event: &LogEvent) -> Result<(), Box<Error>> {
+        let mut msg = vec![];
+        try!(self.encoder.encode_event(&mut msg, event));
+
+        let location = event.location();
+        let line = location.line().to_string();
+        try!(self.send(&msg,
+                       &[("PRIORITY", priority(event.level()).as_bytes()),
+                         ("CODE_FILE", location.file().as_bytes()),
+                         ("CODE_LINE", line.as_bytes()),
+                         ("CODE_MODULE", location.module_path().as_bytes()),
+                         ("TARGET", event.target().as_bytes())]));
+        Ok(())
+    }
+
+    fn append_text(&mut self, text: &str) -> Result<(), Box<Error>> {
+        try!(self.send(text.as_bytes(), &[("PRIORITY", priority(LogLevel::Info).as_bytes())]));
+        Ok(())
+    }
+}
+
+impl JournaldAppender {
+    /// Creates a new `JournaldAppender` builder.
+    pub fn builder() -> JournaldAppenderBuilder {
+        JournaldAppenderBuilder {
+            path: PathBuf::from(JOURNALD_SOCKET),
+            identifier: None,
+            encoder: Box::new(PatternLayout::new("%m").unwrap()),
+        }
+    }
+
+    fn send(&self, msg: &[u8], fields: &[(&str, &[u8])]) -> io::Result<()> {
+        let msg = if msg.ends_with(b"\n") {
+            &msg[..msg.len() - 1]
+        } else {
+            msg
+        };
+
+        let mut buf = vec![];
+        write_field(&mut buf, "MESSAGE", msg);
+        for &(name, value) in fields {
+            write_field(&mut buf, name, value);
+        }
+        write_field(&mut buf, "SYSLOG_IDENTIFIER", self.identifier.as_bytes());
+
+        match self.socket.send_to(&buf, &self.path) {
+            Ok(_) => Ok(()),
+            Err(ref err) if err.raw_os_error() == Some(libc::EMSGSIZE) ||
+                            err.raw_os_error() == Some(libc::ENOBUFS) => self.send_memfd(&buf),
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound ||
+                            err.kind() == io::ErrorKind::ConnectionRefused => {
+                let mut line = msg.to_vec();
+                line.push(b'\n');
+                ConsoleWriter::new(Target::Stderr).write_atomic(&line)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    fn send_memfd(&self, buf: &[u8]) -> io::Result<()> {
+        let name = b"log4rs-journald\0";
+        let fd = unsafe {
+            libc::memfd_create(name.as_ptr() as *const libc::c_char,
+                               libc::MFD_CLOEXEC | libc::MFD_ALLOW_SEALING)
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut file = unsafe { File::from_raw_fd(fd) };
+        try!(file.write_all(buf));
+
+        // journald only accepts memfds which can no longer be modified
+        let seals = libc::F_SEAL_SHRINK | libc::F_SEAL_GROW | libc::F_SEAL_WRITE |
+                    libc::F_SEAL_SEAL;
+        if unsafe { libc::fcntl(fd, libc::F_ADD_SEALS, seals) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        send_fd(&self.socket, &self.path, file.as_raw_fd())
+    }
+}
+
+// Sends an empty datagram carrying `fd` to the socket at `path`.
+fn send_fd(socket: &UnixDatagram, path: &Path, fd: RawFd) -> io::Result<()> {
+    unsafe {
+        let mut addr: libc::sockaddr_un = mem::zeroed();
+        addr.sun_family = libc::AF_UNIX as libc::sa_family_t;
+        let path = path.as_os_str().as_bytes();
+        if path.len() >= addr.sun_path.len() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "socket path is too long"));
+        }
+        for (dst, &src) in addr.sun_path.iter_mut().zip(path) {
+            *dst = src as libc::c_char;
+        }
+        let addr_len = mem::size_of::<libc::sa_family_t>() + path.len() + 1;
+
+        let space = libc::CMSG_SPACE(mem::size_of::<RawFd>() as u32) as usize;
+        // u64s keep the control buffer aligned for the cmsghdr
+        let mut control = vec![0u64; (space + 7) / 8];
+        let mut msg: libc::msghdr = mem::zeroed();
+        msg.msg_name = &mut addr as *mut _ as *mut libc::c_void;
+        msg.msg_namelen = addr_len as libc::socklen_t;
+        msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = space as _;
+
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        (*cmsg).cmsg_level = libc::SOL_SOCKET;
+        (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+        (*cmsg).cmsg_len = libc::CMSG_LEN(mem::size_of::<RawFd>() as u32) as _;
+        ptr::write_unaligned(libc::CMSG_DATA(cmsg) as *mut RawFd, fd);
+
+        if libc::sendmsg(socket.as_raw_fd(), &msg, 0) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+fn priority(level: LogLevel) -> &'static str {
+    match level {
+        LogLevel::Error => "3",
+        LogLevel::Warn => "4",
+        LogLevel::Info => "6",
+        LogLevel::Debug | LogLevel::Trace => "7",
+    }
+}
+
+// Values containing a newline are length-prefixed rather than terminated by
+// one.
+fn write_field(buf: &mut Vec<u8>, name: &str, value: &[u8]) {
+    buf.extend_from_slice(name.as_bytes());
+    if value.contains(&b'\n') {
+        buf.push(b'\n');
+        buf.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        buf.push(b'=');
+    }
+    buf.extend_from_slice(value);
+    buf.push(b'\n');
+}
+
+/// A builder for `JournaldAppender`s.
+pub struct JournaldAppenderBuilder {
+    path: PathBuf,
+    identifier: Option<String>,
+    encoder: Box<Encode>,
+}
+
+impl JournaldAppenderBuilder {
+    /// Sets the path of the journal socket.
+    ///
+    /// Defaults to `/run/systemd/journal/socket`.
+    pub fn path<P: AsRef<Path>>(mut self, path: P) -> JournaldAppenderBuilder {
+        self.path = path.as_ref().to_path_buf();
+        self
+    }
+
+    /// Sets the `SYSLOG_IDENTIFIER` field of each log event.
+    ///
+    /// Defaults to the file name of the current executable.
+    pub fn syslog_identifier(mut self, identifier: &str) -> JournaldAppenderBuilder {
+        self.identifier = Some(identifier.to_owned());
+        self
+    }
+
+    /// Sets the output pattern for the `JournaldAppender`.
+    ///
+    /// This is a shorthand for `encoder(Box::new(pattern))`.
+    pub fn pattern(self, pattern: PatternLayout) -> JournaldAppenderBuilder {
+        self.encoder(Box::new(pattern))
+    }
+
+    /// Sets the encoder used to format the `MESSAGE` field of each log event.
+    ///
+    /// Defaults to a `PatternLayout` with the pattern `%m`, since the journal
+    /// records the time and priority of each log event itself.
+    pub fn encoder(mut self, encoder: Box<Encode>) -> JournaldAppenderBuilder {
+        self.encoder = encoder;
+        self
+    }
+
+    /// Consumes the `JournaldAppenderBuilder`, producing a `JournaldAppender`.
+    ///
+    /// The journal socket need not exist.
+    pub fn build(self) -> io::Result<JournaldAppender> {
+        Ok(JournaldAppender {
+            socket: try!(UnixDatagram::unbound()),
+            path: self.path,
+            identifier: self.identifier
+                .or_else(program_name)
+                .unwrap_or_else(|| "log4rs".to_owned()),
+            encoder: self.encoder,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs;
+    use std::io::{Read, Seek, SeekFrom};
+    use std::os::unix::net::UnixDatagram;
+
+    use Append;
+    use super::*;
+
+    #[test]
+    fn native_protocol() {
+        let path = env::temp_dir().join("log4rs-journald-test.socket");
+        let _ = fs::remove_file(&path);
+        let server = UnixDatagram::bind(&path).unwrap();
+
+        let appender = JournaldAppender::builder()
+            .path(&path)
+            .syslog_identifier("app")
+            .build()
+            .unwrap();
+        appender.send(b"one\ntwo\n", &[("PRIORITY", b"3")]).unwrap();
+
+        let mut buf = [0; 256];
+        let len = server.recv(&mut buf).unwrap();
+        let mut expected = b"MESSAGE\n\x07\0\0\0\0\0\0\0one\ntwo\n".to_vec();
+        expected.extend_from_slice(b"PRIORITY=3\nSYSLOG_IDENTIFIER=app\n");
+        assert_eq!(&buf[..len], &expected[..]);
+    }
+
+    // Receives a datagram carrying a file descriptor, and reads the file.
+    fn recv_fd(socket: &UnixDatagram) -> Vec<u8> {
+        unsafe {
+            let mut control = [0u64; 8];
+            let mut msg: libc::msghdr = mem::zeroed();
+            msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+            msg.msg_controllen = mem::size_of_val(&control) as _;
+            assert_eq!(libc::recvmsg(socket.as_raw_fd(), &mut msg, 0), 0);
+
+            let cmsg = libc::CMSG_FIRSTHDR(&msg);
+            assert!(!cmsg.is_null());
+            assert_eq!((*cmsg).cmsg_type, libc::SCM_RIGHTS);
+            let fd = ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const RawFd);
+            let mut file = File::from_raw_fd(fd);
+            let mut buf = vec![];
+            file.seek(SeekFrom::Start(0)).unwrap();
+            file.read_to_end(&mut buf).unwrap();
+            buf
+        }
+    }
+
+    #[test]
+    fn oversized_event() {
+        let path = env::temp_dir().join("log4rs-journald-memfd-test.socket");
+        let _ = fs::remove_file(&path);
+        let server = UnixDatagram::bind(&path).unwrap();
+
+        let appender = JournaldAppender::builder()
+            .path(&path)
+            .syslog_identifier("app")
+            .build()
+            .unwrap();
+        let msg = vec![b'x'; 1024 * 1024];
+        appender.send(&msg, &[("PRIORITY", b"3")]).unwrap();
+
+        let mut expected = b"MESSAGE=".to_vec();
+        expected.extend_from_slice(&msg);
+        expected.extend_from_slice(b"\nPRIORITY=3\nSYSLOG_IDENTIFIER=app\n");
+        assert!(recv_fd(&server) == expected);
+    }
+
+    #[test]
+    fn missing_socket() {
+        let path = env::temp_dir().join("log4rs-journald-missing.socket");
+        let _ = fs::remove_file(&path);
+        let mut appender = JournaldAppender::builder().path(&path).build().unwrap();
+        appender.append_text("written to stderr").unwrap();
+    }
+}
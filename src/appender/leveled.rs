@@ -0,0 +1,187 @@
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use log::{LogLevel, LogRecord};
+
+use {Append, Encode, LogEvent};
+use pattern::PatternLayout;
+
+/// The token replaced by the name of a log level in the path template of a
+/// `LeveledFileAppender`.
+pub const LEVEL_TOKEN: &'static str = "%level";
+
+/// An appender which logs to a separate file for each log level.
+///
+/// The path of each file is generated from a template by replacing each
+/// `%level` token with the lowercase name of the level, so that the template
+/// `log/app-%level.log` sends errors to `log/app-error.log`, warnings to
+/// `log/app-warn.log`, and so on. Files are opened in append mode when the
+/// first log event of their level arrives, so no file is created for a level
+/// which is never logged. Combined with a logger's maximum level, this
+/// replaces a file appender and level range filter per level.
+///
+/// Text which did not come from a log event, such as a summary written by a
+/// wrapping appender, is written to the file of the most recent log event.
+pub struct LeveledFileAppender {
+    template: String,
+    files: [Option<BufWriter<File>>; 5],
+    last: Option<LogLevel>,
+    encoder: Box<Encode>,
+}
+
+impl Append for LeveledFileAppender {
+    fn append(&mut self, record: &LogRecord) -> Result<(), Box<Error>> {
+        let mut buf = vec![];
+        try!(self.encoder.encode(&mut buf, record));
+        try!(self.write(record.level(), &buf));
+        Ok(())
+    }
+
+    fn append_event(&mut self, event: &LogEvent) -> Result<(), Box<Error>> {
+        let mut buf = vec![];
+        try!(self.encoder.encode_event(&mut buf, event));
+        try!(self.write(event.level(), &buf));
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<Error>> {
+        for file in self.files.iter_mut().filter_map(|f| f.as_mut()) {
+            try!(file.flush());
+        }
+        Ok(())
+    }
+
+    fn append_text(&mut self, text: &str) -> Result<(), Box<Error>> {
+        if let Some(level) = self.last {
+            try!(self.write(level, format!("{}\n", text).as_bytes()));
+        }
+        Ok(())
+    }
+
+    // files are opened again by the next log event of their level
+    fn reopen(&mut self) -> Result<(), Box<Error>> {
+        for file in &mut self.files {
+            if let Some(mut file) = file.take() {
+                try!(file.flush());
+            }
+        }
+        Ok(())
+    }
+}
+
+impl LeveledFileAppender {
+    /// Creates a new `LeveledFileAppender` builder for an appender which will
+    /// log to files at paths generated from the provided template.
+    pub fn builder(template: &str) -> LeveledFileAppenderBuilder {
+        LeveledFileAppenderBuilder {
+            template: template.to_owned(),
+            encoder: Box::new(PatternLayout::default()),
+        }
+    }
+
+    fn path(&self, level: LogLevel) -> PathBuf {
+        let name = level.to_string().to_lowercase();
+        PathBuf::from(self.template.replace(LEVEL_TOKEN, &name))
+    }
+
+    fn write(&mut self, level: LogLevel, buf: &[u8]) -> io::Result<()> {
+        let idx = level as usize - 1;
+        if self.files[idx].is_none() {
+            let path = self.path(level);
+            let file = try!(super::open_file(OpenOptions::new()
+                                                 .write(true)
+                                                 .append(true)
+                                                 .create(true),
+                                             &path));
+            self.files[idx] = Some(BufWriter::with_capacity(1024, file));
+        }
+
+        self.last = Some(level);
+        let file = self.files[idx].as_mut().unwrap();
+        try!(file.write_all(buf));
+        file.flush()
+    }
+}
+
+/// A builder for `LeveledFileAppender`s.
+pub struct LeveledFileAppenderBuilder {
+    template: String,
+    encoder: Box<Encode>,
+}
+
+impl LeveledFileAppenderBuilder {
+    /// Sets the output pattern for the `LeveledFileAppender`.
+    ///
+    /// This is a shorthand for `encoder(Box::new(pattern))`.
+    pub fn pattern(self, pattern: PatternLayout) -> LeveledFileAppenderBuilder {
+        self.encoder(Box::new(pattern))
+    }
+
+    /// Sets the encoder used to format output for the `LeveledFileAppender`.
+    ///
+    /// Defaults to the default `PatternLayout`.
+    pub fn encoder(mut self, encoder: Box<Encode>) -> LeveledFileAppenderBuilder {
+        self.encoder = encoder;
+        self
+    }
+
+    /// Consumes the `LeveledFileAppenderBuilder`, producing a
+    /// `LeveledFileAppender`.
+    ///
+    /// An error is returned if the template does not contain a `%level`
+    /// token. No files are opened until they are first written to.
+    pub fn build(self) -> io::Result<LeveledFileAppender> {
+        if !self.template.contains(LEVEL_TOKEN) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("template `{}` must contain `{}`",
+                                              self.template,
+                                              LEVEL_TOKEN)));
+        }
+
+        Ok(LeveledFileAppender {
+            template: self.template,
+            files: [None, None, None, None, None],
+            last: None,
+            encoder: self.encoder,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs;
+    use log::LogLevel;
+
+    use Append;
+    use super::*;
+
+    #[test]
+    fn files_per_level() {
+        let dir = env::temp_dir().join("log4rs-leveled-file-test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let template = format!("{}/app-%level.log", dir.display());
+
+        assert!(LeveledFileAppender::builder("app.log").build().is_err());
+
+        let mut appender = LeveledFileAppender::builder(&template).build().unwrap();
+        appender.append_text("dropped").unwrap();
+        appender.write(LogLevel::Error, b"one\n").unwrap();
+        appender.write(LogLevel::Info, b"two\n").unwrap();
+        appender.append_text("three").unwrap();
+        appender.write(LogLevel::Error, b"four\n").unwrap();
+
+        assert_eq!(fs::read_to_string(dir.join("app-error.log")).unwrap(), "one\nfour\n");
+        assert_eq!(fs::read_to_string(dir.join("app-info.log")).unwrap(), "two\nthree\n");
+        assert!(!dir.join("app-warn.log").exists());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+
+        fs::rename(dir.join("app-error.log"), dir.join("app-error.log.1")).unwrap();
+        appender.reopen().unwrap();
+        appender.write(LogLevel::Error, b"five\n").unwrap();
+        assert_eq!(fs::read_to_string(dir.join("app-error.log.1")).unwrap(), "one\nfour\n");
+        assert_eq!(fs::read_to_string(dir.join("app-error.log")).unwrap(), "five\n");
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::VecDeque;
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+use log::LogRecord;
+
+use {Append, Encode, LogEvent};
+use pattern::PatternLayout;
+
+/// An appender which keeps the most recent log events in memory.
+///
+/// Log events are stored as formatted strings, without their trailing
+/// newline, in a ring buffer of fixed capacity. Once it is full, each new log
+/// event replaces the oldest one. The contents are read through a
+/// `MemoryHandle`, which should be retrieved before the appender is added to
+/// a `Config`.
+pub struct MemoryAppender {
+    buffer: Arc<Mutex<Ring>>,
+    encoder: Box<Encode>,
+}
+
+struct Ring {
+    records: VecDeque<String>,
+    capacity: usize,
+}
+
+impl Ring {
+    fn push(&mut self, record: String) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.records.len() == self.capacity {
+            self.records.pop_front();
+        }
+        self.records.push_back(record);
+    }
+}
+
+impl Append for MemoryAppender {
+    fn append(&mut self, record: &LogRecord) -> Result<(), Box<Error>> {
+        let mut buf = vec![];
+        try!(self.encoder.encode(&mut buf, record));
+        self.push(String::from_utf8_lossy(&buf).into_owned());
+        Ok(())
+    }
+
+    fn append_event(&mut self, event: &LogEvent) -> Result<(), Box<Error>> {
+        let mut buf = vec![];
+        try!(self.encoder.encode_event(&mut buf, event));
+        self.push(String::from_utf8_lossy(&buf).into_owned());
+        Ok(())
+    }
+
+    fn append_text(&mut self, text: &str) -> Result<(), Box<Error>> {
+        self.push(text.to_owned());
+        Ok(())
+    }
+}
+
+impl MemoryAppender {
+    /// Creates a new `MemoryAppender` builder which keeps at most `capacity`
+    /// log events.
+    pub fn builder(capacity: usize) -> MemoryAppenderBuilder {
+        MemoryAppenderBuilder {
+            capacity: capacity,
+            encoder: Box::new(PatternLayout::default()),
+        }
+    }
+
+    /// Returns a handle to the appender's log events.
+    pub fn handle(&self) -> MemoryHandle {
+        MemoryHandle { buffer: self.buffer.clone() }
+    }
+
+    fn push(&mut self, mut record: String) {
+        if record.ends_with('\n') {
+            record.pop();
+            if record.ends_with('\r') {
+                record.pop();
+            }
+        }
+        self.buffer.lock().unwrap().push(record);
+    }
+}
+
+/// A builder for `MemoryAppender`s.
+pub struct MemoryAppenderBuilder {
+    capacity: usize,
+    encoder: Box<Encode>,
+}
+
+impl MemoryAppenderBuilder {
+    /// Sets the output pattern for the `MemoryAppender`.
+    ///
+    /// This is a shorthand for `encoder(Box::new(pattern))`.
+    pub fn pattern(self, pattern: PatternLayout) -> MemoryAppenderBuilder {
+        self.encoder(Box::new(pattern))
+    }
+
+    /// Sets the encoder used to format log events.
+    pub fn encoder(mut self, encoder: Box<Encode>) -> MemoryAppenderBuilder {
+        self.encoder = encoder;
+        self
+    }
+
+    /// Consumes the `MemoryAppenderBuilder`, producing a `MemoryAppender`.
+    pub fn build(self) -> MemoryAppender {
+        MemoryAppender {
+            buffer: Arc::new(Mutex::new(Ring {
+                records: VecDeque::with_capacity(self.capacity),
+                capacity: self.capacity,
+            })),
+            encoder: self.encoder,
+        }
+    }
+}
+
+/// A handle to the log events stored by a `MemoryAppender`.
+///
+/// The handle remains valid after the appender is dropped, for example when
+/// the logger is reconfigured, but will no longer receive log events.
+#[derive(Clone)]
+pub struct MemoryHandle {
+    buffer: Arc<Mutex<Ring>>,
+}
+
+impl MemoryHandle {
+    /// Returns a copy of the stored log events, oldest first.
+    pub fn snapshot(&self) -> Vec<String> {
+        self.buffer.lock().unwrap().records.iter().cloned().collect()
+    }
+
+    /// Removes all stored log events.
+    pub fn clear(&self) {
+        self.buffer.lock().unwrap().records.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use Append;
+    use super::*;
+
+    #[test]
+    fn wraparound() {
+        let mut appender = MemoryAppender::builder(3).build();
+        let handle = appender.handle();
+        assert!(handle.snapshot().is_empty());
+
+        appender.append_text("a").unwrap();
+        appender.append_text("b\n").unwrap();
+        assert_eq!(handle.snapshot(), ["a", "b"]);
+
+        appender.append_text("c").unwrap();
+        appender.append_text("d").unwrap();
+        appender.append_text("e\r\n").unwrap();
+        assert_eq!(handle.snapshot(), ["c", "d", "e"]);
+
+        handle.clone().clear();
+        assert!(handle.snapshot().is_empty());
+        appender.append_text("f").unwrap();
+        assert_eq!(handle.snapshot(), ["f"]);
+    }
+
+    #[test]
+    fn zero_capacity() {
+        let mut appender = MemoryAppender::builder(0).build();
+        appender.append_text("a").unwrap();
+        assert!(appender.handle().snapshot().is_empty());
+    }
+}
@@ -0,0 +1,347 @@
+use std::error::Error;
+use std::io::{self, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+use log::LogRecord;
+
+use {Append, Encode, LogEvent, handle_error};
+use pattern::PatternLayout;
+use super::{AsyncAppender, AsyncPool, OverflowPolicy};
+
+/// The minimum time between restarts of a child process.
+const RESTART_DELAY_MS: u64 = 1000;
+
+/// How long a child process is given to exit after its stdin is closed before
+/// it is killed.
+const EXIT_TIMEOUT_MS: u64 = 1000;
+
+/// Determines what a `ProcessAppender` does when its child process exits.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RestartPolicy {
+    /// Start a new child process for the next log event.
+    ///
+    /// Restarts happen at most once a second, and log events are discarded
+    /// while waiting to restart.
+    Always,
+    /// Discard all further log events.
+    Never,
+}
+
+/// An appender which writes to the stdin of a child process.
+///
+/// The child is started when the appender is built, with its stdout and
+/// stderr inherited from the current process, and runs until the appender is
+/// dropped, at which point its stdin is closed. Log events are written from a
+/// background thread through an `AsyncAppender`, so a child which reads
+/// slowly never blocks the logging thread: once the queue is full, log events
+/// are handled according to the `OverflowPolicy`.
+pub struct ProcessAppender {
+    inner: AsyncAppender,
+}
+
+impl Append for ProcessAppender {
+    fn append(&mut self, record: &LogRecord) -> Result<(), Box<Error>> {
+        self.inner.append(record)
+    }
+
+    fn append_event(&mut self, event: &LogEvent) -> Result<(), Box<Error>> {
+        self.inner.append_event(event)
+    }
+
+    fn flush(&mut self) -> Result<(), Box<Error>> {
+        self.inner.flush()
+    }
+
+    fn append_text(&mut self, text: &str) -> Result<(), Box<Error>> {
+        self.inner.append_text(text)
+    }
+}
+
+impl ProcessAppender {
+    /// Creates a new `ProcessAppender` builder for an appender which will
+    /// write to the stdin of the specified program.
+    pub fn builder(command: &str) -> ProcessAppenderBuilder {
+        ProcessAppenderBuilder {
+            command: command.to_owned(),
+            args: vec![],
+            restart: RestartPolicy::Always,
+            encoder: Box::new(PatternLayout::default()),
+            capacity: 1024,
+            policy: OverflowPolicy::DropNewest,
+            pool: None,
+        }
+    }
+}
+
+/// A builder for `ProcessAppender`s.
+pub struct ProcessAppenderBuilder {
+    command: String,
+    args: Vec<String>,
+    restart: RestartPolicy,
+    encoder: Box<Encode>,
+    capacity: usize,
+    policy: OverflowPolicy,
+    pool: Option<AsyncPool>,
+}
+
+impl ProcessAppenderBuilder {
+    /// Adds an argument to pass to the program.
+    pub fn arg(mut self, arg: &str) -> ProcessAppenderBuilder {
+        self.args.push(arg.to_owned());
+        self
+    }
+
+    /// Adds arguments to pass to the program.
+    pub fn args<I: IntoIterator<Item = String>>(mut self, args: I) -> ProcessAppenderBuilder {
+        self.args.extend(args);
+        self
+    }
+
+    /// Sets what the `ProcessAppender` does when its child process exits.
+    ///
+    /// Defaults to `RestartPolicy::Always`.
+    pub fn restart(mut self, restart: RestartPolicy) -> ProcessAppenderBuilder {
+        self.restart = restart;
+        self
+    }
+
+    /// Sets the output pattern for the `ProcessAppender`.
+    ///
+    /// This is a shorthand for `encoder(Box::new(pattern))`.
+    pub fn pattern(self, pattern: PatternLayout) -> ProcessAppenderBuilder {
+        self.encoder(Box::new(pattern))
+    }
+
+    /// Sets the encoder used to format output for the `ProcessAppender`.
+    ///
+    /// Defaults to the default `PatternLayout`.
+    pub fn encoder(mut self, encoder: Box<Encode>) -> ProcessAppenderBuilder {
+        self.encoder = encoder;
+        self
+    }
+
+    /// Sets the maximum number of log events which may be queued for the
+    /// child process.
+    ///
+    /// Defaults to 1024.
+    pub fn capacity(mut self, capacity: usize) -> ProcessAppenderBuilder {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Sets the behavior of the appender when its queue is full.
+    ///
+    /// Defaults to `OverflowPolicy::DropNewest`. `OverflowPolicy::Block`
+    /// makes the logging thread wait for the child process.
+    pub fn policy(mut self, policy: OverflowPolicy) -> ProcessAppenderBuilder {
+        self.policy = policy;
+        self
+    }
+
+    /// Sets the pool whose threads write to the child process.
+    ///
+    /// Defaults to none, in which case the appender starts a thread of its
+    /// own.
+    pub fn pool(mut self, pool: AsyncPool) -> ProcessAppenderBuilder {
+        self.pool = Some(pool);
+        self
+    }
+
+    /// Consumes the `ProcessAppenderBuilder`, producing a `ProcessAppender`.
+    ///
+    /// An error is returned if the child process cannot be started.
+    pub fn build(self) -> io::Result<ProcessAppender> {
+        let mut writer = ChildWriter {
+            command: self.command,
+            args: self.args,
+            restart: self.restart,
+            child: None,
+            started_at: Instant::now(),
+        };
+        try!(writer.spawn());
+
+        let mut inner = AsyncAppender::writer_builder(Box::new(writer))
+            .encoder(self.encoder)
+            .capacity(self.capacity)
+            .policy(self.policy);
+        if let Some(pool) = self.pool {
+            inner = inner.pool(pool);
+        }
+        let inner = try!(inner.build());
+        Ok(ProcessAppender { inner: inner })
+    }
+}
+
+// Writes to the stdin of a child process, restarting it as allowed by the
+// restart policy.
+struct ChildWriter {
+    command: String,
+    args: Vec<String>,
+    restart: RestartPolicy,
+    child: Option<(Child, ChildStdin)>,
+    started_at: Instant,
+}
+
+impl ChildWriter {
+    fn spawn(&mut self) -> io::Result<()> {
+        self.started_at = Instant::now();
+        let mut child = try!(Command::new(&self.command)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|err| {
+                io::Error::new(err.kind(),
+                               format!("Unable to start `{}`: {}", self.command, err))
+            }));
+        let stdin = child.stdin.take().unwrap();
+        self.child = Some((child, stdin));
+        Ok(())
+    }
+
+    // Returns the stdin of the child, or `None` if the log event should be
+    // discarded.
+    fn stdin(&mut self) -> io::Result<Option<&mut ChildStdin>> {
+        // check for a child which has exited before writing to it, so that the
+        // log event goes to its replacement rather than being lost
+        let exited = match self.child {
+            Some((ref mut child, _)) => child.try_wait().ok().and_then(|status| status),
+            None => None,
+        };
+        if let Some(status) = exited {
+            self.child = None;
+            handle_error(&io::Error::new(io::ErrorKind::Other,
+                                         format!("`{}` exited with {}", self.command, status)));
+        }
+
+        if self.child.is_none() {
+            let delay = Duration::from_millis(RESTART_DELAY_MS);
+            if self.restart == RestartPolicy::Never || self.started_at.elapsed() < delay {
+                return Ok(None);
+            }
+            try!(self.spawn());
+        }
+        Ok(self.child.as_mut().map(|&mut (_, ref mut stdin)| stdin))
+    }
+
+    fn stop(&mut self) {
+        if let Some((mut child, stdin)) = self.child.take() {
+            drop(stdin);
+            let deadline = Instant::now() + Duration::from_millis(EXIT_TIMEOUT_MS);
+            while let Ok(None) = child.try_wait() {
+                if Instant::now() >= deadline {
+                    let _ = child.kill();
+                    break;
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+            let _ = child.wait();
+        }
+    }
+}
+
+impl Write for ChildWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let result = match try!(self.stdin()) {
+            Some(stdin) => stdin.write_all(buf),
+            None => return Ok(buf.len()),
+        };
+        match result {
+            Ok(()) => Ok(buf.len()),
+            Err(err) => {
+                // the child has most likely exited
+                self.stop();
+                Err(io::Error::new(err.kind(),
+                                   format!("Unable to write to `{}`: {}", self.command, err)))
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.child {
+            Some((_, ref mut stdin)) => stdin.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for ChildWriter {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs;
+    use std::io::Write;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use super::*;
+    use super::{ChildWriter, RESTART_DELAY_MS};
+
+    fn child_writer(command: &str, args: &[&str], restart: RestartPolicy) -> ChildWriter {
+        let mut writer = ChildWriter {
+            command: command.to_owned(),
+            args: args.iter().map(|a| a.to_string()).collect(),
+            restart: restart,
+            child: None,
+            started_at: Instant::now(),
+        };
+        writer.spawn().unwrap();
+        writer
+    }
+
+    fn wait_for_exit(writer: &mut ChildWriter) {
+        while let Ok(None) = writer.child.as_mut().unwrap().0.try_wait() {
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn writes_to_stdin() {
+        let path = env::temp_dir().join("log4rs-process-test.log");
+        let _ = fs::remove_file(&path);
+        let script = format!("cat > {}", path.display());
+
+        let mut writer = child_writer("sh", &["-c", &script], RestartPolicy::Always);
+        writer.write_all(b"one\n").unwrap();
+        writer.write_all(b"two\n").unwrap();
+        drop(writer);
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "one\ntwo\n");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn restarts() {
+        let path = env::temp_dir().join("log4rs-process-restart-test.log");
+        let _ = fs::remove_file(&path);
+        // appends the first line it reads and exits
+        let script = format!("head -n 1 >> {}", path.display());
+
+        let mut writer = child_writer("sh", &["-c", &script], RestartPolicy::Always);
+        writer.write_all(b"one\n").unwrap();
+        wait_for_exit(&mut writer);
+        thread::sleep(Duration::from_millis(RESTART_DELAY_MS));
+        // the exit is noticed before writing, so the log event is not lost
+        writer.write_all(b"two\n").unwrap();
+        drop(writer);
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "one\ntwo\n");
+
+        let mut writer = child_writer("true", &[], RestartPolicy::Never);
+        wait_for_exit(&mut writer);
+        thread::sleep(Duration::from_millis(RESTART_DELAY_MS));
+        writer.write_all(b"discarded\n").unwrap();
+        assert!(writer.child.is_none());
+    }
+
+    #[test]
+    fn missing_program() {
+        assert!(ProcessAppender::builder("log4rs-no-such-program").build().is_err());
+    }
+}
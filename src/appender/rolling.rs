@@ -0,0 +1,670 @@
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::io::prelude::*;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+use log::LogRecord;
+use time::{self, Timespec, Tm};
+
+use {Append, Encode, LogEvent, handle_error};
+use clock::{Clock, SystemClock};
+use pattern::PatternLayout;
+use super::{compress, day_of};
+
+/// A trait implemented by rolling policies, which decide when the file of a
+/// `RollingFileAppender` is rolled.
+pub trait Policy: Send + 'static {
+    /// Determines if the log file should be rolled before the next log event
+    /// is written to it.
+    ///
+    /// `current_size` is the size of the file in bytes, and `opened_at` is the
+    /// time at which the appender started writing to it.
+    fn should_roll(&self, current_size: u64, opened_at: &Tm) -> bool;
+}
+
+/// A trait implemented by rollers, which perform the file management when the
+/// file of a `RollingFileAppender` is rolled.
+pub trait Roll: Send + 'static {
+    /// Moves the log file at `path` out of the way, cleaning up older files as
+    /// necessary.
+    ///
+    /// A new file is created at `path` once this returns.
+    fn roll(&self, path: &Path) -> io::Result<()>;
+}
+
+/// A roller which renames the log file by appending `.1` to its name,
+/// replacing any file already there.
+///
+/// If compression is enabled, the renamed file is gzipped to a file with a
+/// further `.gz` extension, such as `app.log.1.gz`.
+#[derive(Debug, Default)]
+pub struct RenameRoller {
+    compress: bool,
+}
+
+impl RenameRoller {
+    /// Creates a new `RenameRoller`.
+    pub fn new() -> RenameRoller {
+        RenameRoller::default()
+    }
+
+    /// Sets whether rolled files are gzipped.
+    ///
+    /// Defaults to `false`.
+    pub fn compress(mut self, compress: bool) -> RenameRoller {
+        self.compress = compress;
+        self
+    }
+}
+
+impl Roll for RenameRoller {
+    fn roll(&self, path: &Path) -> io::Result<()> {
+        let mut backup = path.as_os_str().to_owned();
+        backup.push(".1");
+        try!(fs::rename(path, &backup));
+        if self.compress {
+            compress(Path::new(&backup))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// A roller which keeps a bounded window of numbered files.
+///
+/// The names of rolled files are generated from a pattern containing a single
+/// `{}` token, which is replaced by the index of the file, for example
+/// `log/app.{}.log`. When the log file is rolled it becomes file 1, each
+/// existing file is shifted to the next index, and the file at the highest
+/// index is deleted, so that at most `count` rolled files are kept.
+///
+/// If compression is enabled, rolled files are gzipped and named with a
+/// further `.gz` extension, such as `log/app.1.log.gz`. The file is compressed
+/// while it is rolled, before the appender opens a new log file.
+#[derive(Debug)]
+pub struct FixedWindowRoller {
+    pattern: String,
+    count: u32,
+    compress: bool,
+}
+
+impl FixedWindowRoller {
+    /// Creates a new `FixedWindowRoller` which keeps `count` rolled files
+    /// named according to `pattern`.
+    ///
+    /// An error is returned if `pattern` does not contain exactly one `{}`
+    /// token.
+    pub fn new(pattern: &str, count: u32) -> io::Result<FixedWindowRoller> {
+        if pattern.matches("{}").count() != 1 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("pattern `{}` must contain exactly one `{{}}`",
+                                              pattern)));
+        }
+
+        Ok(FixedWindowRoller {
+            pattern: pattern.to_owned(),
+            count: count,
+            compress: false,
+        })
+    }
+
+    /// Sets whether rolled files are gzipped.
+    ///
+    /// Defaults to `false`.
+    pub fn compress(mut self, compress: bool) -> FixedWindowRoller {
+        self.compress = compress;
+        self
+    }
+
+    fn path(&self, index: u32) -> PathBuf {
+        let mut path = self.pattern.replace("{}", &index.to_string());
+        if self.compress {
+            path.push_str(".gz");
+        }
+        PathBuf::from(path)
+    }
+}
+
+impl Roll for FixedWindowRoller {
+    fn roll(&self, path: &Path) -> io::Result<()> {
+        if self.count == 0 {
+            return fs::remove_file(path);
+        }
+
+        match fs::remove_file(self.path(self.count)) {
+            Ok(()) => {}
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+
+        for index in (1..self.count).rev() {
+            match fs::rename(self.path(index), self.path(index + 1)) {
+                Ok(()) => {}
+                Err(ref err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err),
+            }
+        }
+
+        let first = PathBuf::from(self.pattern.replace("{}", "1"));
+        if let Some(parent) = first.parent() {
+            try!(fs::create_dir_all(parent));
+        }
+        try!(fs::rename(path, &first));
+        if self.compress {
+            compress(&first)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// A policy which rolls the log file once it has grown to at least a size
+/// limit.
+#[derive(Debug)]
+pub struct SizePolicy {
+    limit: u64,
+}
+
+impl SizePolicy {
+    /// Creates a new `SizePolicy` which rolls once the file reaches `limit`
+    /// bytes.
+    pub fn new(limit: u64) -> SizePolicy {
+        SizePolicy { limit: limit }
+    }
+}
+
+impl Policy for SizePolicy {
+    fn should_roll(&self, current_size: u64, _: &Tm) -> bool {
+        current_size >= self.limit
+    }
+}
+
+/// A policy which rolls the log file once the local date differs from the
+/// date on which the file was opened.
+///
+/// Dates are compared in the local time zone rather than by elapsed time, so
+/// the file rolls at local midnight even on days shortened or lengthened by a
+/// daylight saving transition.
+pub struct DailyPolicy {
+    clock: Arc<Clock>,
+}
+
+impl DailyPolicy {
+    /// Creates a new `DailyPolicy`.
+    pub fn new() -> DailyPolicy {
+        DailyPolicy { clock: Arc::new(SystemClock::new()) }
+    }
+
+    /// Sets the clock which determines the current date.
+    ///
+    /// Defaults to `SystemClock`. The same clock should usually be given to
+    /// the `RollingFileAppender`, which records when its file was opened.
+    pub fn clock(mut self, clock: Arc<Clock>) -> DailyPolicy {
+        self.clock = clock;
+        self
+    }
+
+    fn should_roll_at(&self, opened_at: &Tm, now: &Tm) -> bool {
+        day_of(opened_at) != day_of(now)
+    }
+}
+
+impl Policy for DailyPolicy {
+    fn should_roll(&self, _: u64, opened_at: &Tm) -> bool {
+        self.should_roll_at(opened_at, &time::at(self.clock.now()))
+    }
+}
+
+/// An appender which logs to a file, rolling it according to a list of
+/// policies.
+///
+/// Before each log event is written, the policies are checked, and if any
+/// `should_roll` returns `true` the file is rolled by the appender's roller. A
+/// new file is then started. This allows criteria to be combined, for example
+/// rolling at midnight or whenever the file exceeds 100MB, whichever comes
+/// first.
+///
+/// An existing file is appended to, and is considered to have been opened
+/// when it was last modified, unless the appender is configured to roll it
+/// on startup. The same applies to the file opened when the appender is
+/// reopened.
+pub struct RollingFileAppender {
+    path: PathBuf,
+    file: Option<BufWriter<File>>,
+    size: u64,
+    opened_at: Tm,
+    policies: Vec<Box<Policy>>,
+    roller: Box<Roll>,
+    encoder: Box<Encode>,
+    clock: Arc<Clock>,
+    // set until the first write if the file is to be rolled on startup
+    roll_pending: bool,
+}
+
+impl Append for RollingFileAppender {
+    fn append(&mut self, record: &LogRecord) -> Result<(), Box<Error>> {
+        let mut buf = vec![];
+        try!(self.encoder.encode(&mut buf, record));
+        try!(self.write(&buf));
+        Ok(())
+    }
+
+    fn append_event(&mut self, event: &LogEvent) -> Result<(), Box<Error>> {
+        let mut buf = vec![];
+        try!(self.encoder.encode_event(&mut buf, event));
+        try!(self.write(&buf));
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<Error>> {
+        if let Some(ref mut file) = self.file {
+            try!(file.flush());
+        }
+        Ok(())
+    }
+
+    fn append_text(&mut self, text: &str) -> Result<(), Box<Error>> {
+        try!(self.write(format!("{}\n", text).as_bytes()));
+        Ok(())
+    }
+
+    fn reopen(&mut self) -> Result<(), Box<Error>> {
+        if let Some(mut file) = self.file.take() {
+            try!(file.flush());
+        }
+        try!(self.open());
+        Ok(())
+    }
+}
+
+impl RollingFileAppender {
+    /// Creates a new `RollingFileAppender` builder for an appender which will
+    /// log to a file at the provided path.
+    pub fn builder<P: AsRef<Path>>(path: P) -> RollingFileAppenderBuilder {
+        RollingFileAppenderBuilder {
+            path: path.as_ref().to_path_buf(),
+            policies: vec![],
+            roller: Box::new(RenameRoller::new()),
+            encoder: Box::new(PatternLayout::default()),
+            clock: Arc::new(SystemClock::new()),
+            roll_on_startup: false,
+        }
+    }
+
+    fn write(&mut self, buf: &[u8]) -> io::Result<()> {
+        try!(self.roll_if_needed());
+        let file = match self.file {
+            Some(ref mut file) => file,
+            None => try!(self.open()),
+        };
+        try!(file.write_all(buf));
+        try!(file.flush());
+        self.size += buf.len() as u64;
+        Ok(())
+    }
+
+    fn roll_if_needed(&mut self) -> io::Result<()> {
+        let startup = self.roll_pending && self.size > 0;
+        self.roll_pending = false;
+        if !startup && !self.policies.iter().any(|p| p.should_roll(self.size, &self.opened_at)) {
+            return Ok(());
+        }
+
+        if let Some(mut file) = self.file.take() {
+            try!(file.flush());
+        }
+        // If the roll failed, report it and keep writing to the existing
+        // file rather than dropping the log event.
+        if let Err(err) = self.roller.roll(&self.path) {
+            handle_error(&err);
+        }
+        try!(self.open());
+        Ok(())
+    }
+
+    fn open(&mut self) -> io::Result<&mut BufWriter<File>> {
+        let (file, size, opened_at) = try!(open_rolling_file(&self.path, &*self.clock));
+        self.size = size;
+        self.opened_at = opened_at;
+        self.file = Some(file);
+        Ok(self.file.as_mut().unwrap())
+    }
+}
+
+fn open_rolling_file(path: &Path, clock: &Clock) -> io::Result<(BufWriter<File>, u64, Tm)> {
+    let file = try!(super::open_file(OpenOptions::new().write(true).append(true).create(true),
+                                     path));
+
+    let metadata = try!(file.metadata());
+    let opened_at = if metadata.len() == 0 {
+        time::at(clock.now())
+    } else {
+        match metadata.modified().ok().and_then(|m| m.duration_since(UNIX_EPOCH).ok()) {
+            Some(modified) => time::at(Timespec::new(modified.as_secs() as i64, 0)),
+            None => time::at(clock.now()),
+        }
+    };
+
+    Ok((BufWriter::with_capacity(1024, file), metadata.len(), opened_at))
+}
+
+/// A builder for `RollingFileAppender`s.
+pub struct RollingFileAppenderBuilder {
+    path: PathBuf,
+    policies: Vec<Box<Policy>>,
+    roller: Box<Roll>,
+    encoder: Box<Encode>,
+    clock: Arc<Clock>,
+    roll_on_startup: bool,
+}
+
+impl RollingFileAppenderBuilder {
+    /// Sets the output pattern for the `RollingFileAppender`.
+    ///
+    /// This is a shorthand for `encoder(Box::new(pattern))`.
+    pub fn pattern(self, pattern: PatternLayout) -> RollingFileAppenderBuilder {
+        self.encoder(Box::new(pattern))
+    }
+
+    /// Sets the encoder used to format output for the `RollingFileAppender`.
+    ///
+    /// Defaults to the default `PatternLayout`.
+    pub fn encoder(mut self, encoder: Box<Encode>) -> RollingFileAppenderBuilder {
+        self.encoder = encoder;
+        self
+    }
+
+    /// Adds a rolling policy.
+    pub fn policy(mut self, policy: Box<Policy>) -> RollingFileAppenderBuilder {
+        self.policies.push(policy);
+        self
+    }
+
+    /// Sets the roller which moves the log file out of the way when it is
+    /// rolled.
+    ///
+    /// Defaults to `RenameRoller`.
+    pub fn roller(mut self, roller: Box<Roll>) -> RollingFileAppenderBuilder {
+        self.roller = roller;
+        self
+    }
+
+    /// Sets the clock which determines when a new file was opened.
+    ///
+    /// Defaults to `SystemClock`.
+    pub fn clock(mut self, clock: Arc<Clock>) -> RollingFileAppenderBuilder {
+        self.clock = clock;
+        self
+    }
+
+    /// Determines if an existing, non-empty log file is rolled before the
+    /// appender first writes to it, so that each run of the program starts a
+    /// new file.
+    ///
+    /// The file is rolled by the appender's roller, so the previous run's file
+    /// is named and cleaned up like any other rolled file. An appender which
+    /// is dropped without writing anything leaves the file as it was. Defaults
+    /// to `false`.
+    pub fn roll_on_startup(mut self, roll_on_startup: bool) -> RollingFileAppenderBuilder {
+        self.roll_on_startup = roll_on_startup;
+        self
+    }
+
+    /// Consumes the `RollingFileAppenderBuilder`, producing a
+    /// `RollingFileAppender`.
+    pub fn build(self) -> io::Result<RollingFileAppender> {
+        let (file, size, opened_at) = try!(open_rolling_file(&self.path, &*self.clock));
+
+        Ok(RollingFileAppender {
+            path: self.path,
+            file: Some(file),
+            size: size,
+            opened_at: opened_at,
+            policies: self.policies,
+            roller: self.roller,
+            encoder: self.encoder,
+            clock: self.clock,
+            roll_pending: self.roll_on_startup,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::{self, Read, Write};
+    use std::sync::Arc;
+    use flate2::read::GzDecoder;
+    use time;
+
+    use clock::ManualClock;
+    use super::*;
+
+    fn read(path: &Path) -> String {
+        let mut s = String::new();
+        File::open(path).unwrap().read_to_string(&mut s).unwrap();
+        s
+    }
+
+    #[test]
+    fn size_policy() {
+        let dir = env::temp_dir().join("log4rs-rolling-size-test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("app.log");
+
+        let mut appender = RollingFileAppender::builder(&path)
+            .policy(Box::new(SizePolicy::new(10)))
+            .build()
+            .unwrap();
+        appender.write(b"first 1\n").unwrap();
+        appender.write(b"first 2\n").unwrap();
+        appender.write(b"second\n").unwrap();
+
+        assert_eq!(read(&path), "second\n");
+        assert_eq!(read(&dir.join("app.log.1")), "first 1\nfirst 2\n");
+    }
+
+    struct FailingRoller;
+
+    impl Roll for FailingRoller {
+        fn roll(&self, _: &Path) -> io::Result<()> {
+            Err(io::Error::new(io::ErrorKind::PermissionDenied, "failing roller"))
+        }
+    }
+
+    #[test]
+    fn failed_roll() {
+        let dir = env::temp_dir().join("log4rs-rolling-failed-test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("app.log");
+
+        let mut appender = RollingFileAppender::builder(&path)
+            .policy(Box::new(SizePolicy::new(10)))
+            .roller(Box::new(FailingRoller))
+            .build()
+            .unwrap();
+        appender.write(b"first 1\n").unwrap();
+        appender.write(b"first 2\n").unwrap();
+        appender.write(b"second\n").unwrap();
+        appender.write(b"third\n").unwrap();
+
+        assert_eq!(read(&path), "first 1\nfirst 2\nsecond\nthird\n");
+    }
+
+    #[test]
+    fn roll_on_startup() {
+        let dir = env::temp_dir().join("log4rs-rolling-startup-test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("app.log");
+        let pattern = format!("{}/app.{{}}.log", dir.display());
+
+        let build = || {
+            let roller = FixedWindowRoller::new(&pattern, 2).unwrap();
+            RollingFileAppender::builder(&path)
+                .policy(Box::new(SizePolicy::new(1024)))
+                .roller(Box::new(roller))
+                .roll_on_startup(true)
+                .build()
+                .unwrap()
+        };
+
+        // a missing or empty file is not rolled
+        build().write(b"").unwrap();
+        build().write(b"").unwrap();
+        assert!(!dir.join("app.1.log").exists());
+
+        for run in &["run 1\n", "run 2\n", "run 3\n"] {
+            build().write(run.as_bytes()).unwrap();
+        }
+        // an appender which never writes does not roll the file
+        build();
+        assert_eq!(read(&path), "run 3\n");
+        let mut appender = build();
+        appender.write(b"run 4\n").unwrap();
+        appender.write(b"more\n").unwrap();
+        assert_eq!(read(&path), "run 4\nmore\n");
+        assert_eq!(read(&dir.join("app.1.log")), "run 3\n");
+        assert_eq!(read(&dir.join("app.2.log")), "run 2\n");
+        assert!(!dir.join("app.3.log").exists());
+
+        // the startup roll does not depend on any policy
+        let mut appender =
+            RollingFileAppender::builder(&path).roll_on_startup(true).build().unwrap();
+        appender.write(b"run 5\n").unwrap();
+        assert_eq!(read(&path), "run 5\n");
+        assert_eq!(read(&dir.join("app.log.1")), "run 4\nmore\n");
+    }
+
+    #[test]
+    fn fixed_window_roller() {
+        let dir = env::temp_dir().join("log4rs-fixed-window-test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("app.log");
+        let pattern = format!("{}/app.{{}}.log", dir.display());
+
+        assert!(FixedWindowRoller::new("app.log", 3).is_err());
+        assert!(FixedWindowRoller::new("app.{}.{}.log", 3).is_err());
+
+        let roller = FixedWindowRoller::new(&pattern, 3).unwrap();
+        for i in 0..5 {
+            File::create(&path).unwrap().write_all(i.to_string().as_bytes()).unwrap();
+            roller.roll(&path).unwrap();
+        }
+
+        assert!(!path.exists());
+        assert_eq!(read(&dir.join("app.1.log")), "4");
+        assert_eq!(read(&dir.join("app.2.log")), "3");
+        assert_eq!(read(&dir.join("app.3.log")), "2");
+        assert!(!dir.join("app.4.log").exists());
+    }
+
+    #[test]
+    fn compressed_rollers() {
+        let dir = env::temp_dir().join("log4rs-rolling-compress-test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("app.log");
+        let pattern = format!("{}/app.{{}}.log", dir.display());
+
+        let read_gz = |path: &Path| {
+            let mut s = String::new();
+            GzDecoder::new(File::open(path).unwrap()).read_to_string(&mut s).unwrap();
+            s
+        };
+
+        let roller = FixedWindowRoller::new(&pattern, 2).unwrap().compress(true);
+        for i in 0..3 {
+            File::create(&path).unwrap().write_all(i.to_string().as_bytes()).unwrap();
+            roller.roll(&path).unwrap();
+        }
+        assert!(!path.exists());
+        assert!(!dir.join("app.1.log").exists());
+        assert_eq!(read_gz(&dir.join("app.1.log.gz")), "2");
+        assert_eq!(read_gz(&dir.join("app.2.log.gz")), "1");
+        assert!(!dir.join("app.3.log.gz").exists());
+
+        File::create(&path).unwrap().write_all(b"renamed").unwrap();
+        RenameRoller::new().compress(true).roll(&path).unwrap();
+        assert!(!path.exists());
+        assert!(!dir.join("app.log.1").exists());
+        assert_eq!(read_gz(&dir.join("app.log.1.gz")), "renamed");
+    }
+
+    #[test]
+    fn daily_policy() {
+        let policy = DailyPolicy::new();
+        let mut opened_at = time::empty_tm();
+        opened_at.tm_year = 115;
+        opened_at.tm_yday = 40;
+        opened_at.tm_hour = 23;
+        let mut now = opened_at;
+        now.tm_hour = 0;
+        assert!(!policy.should_roll_at(&opened_at, &now));
+        now.tm_yday = 41;
+        assert!(policy.should_roll_at(&opened_at, &now));
+    }
+
+    #[test]
+    fn daily_policy_clock() {
+        let dir = env::temp_dir().join("log4rs-rolling-daily-clock-test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("app.log");
+
+        let clock = Arc::new(ManualClock::new(time::Timespec::new(1435752000, 0)));
+        let policy = DailyPolicy::new().clock(clock.clone());
+        let mut appender = RollingFileAppender::builder(&path)
+            .policy(Box::new(policy))
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        appender.write(b"first\n").unwrap();
+        clock.advance(time::Duration::hours(1));
+        appender.write(b"still first\n").unwrap();
+        clock.advance(time::Duration::days(1));
+        appender.write(b"second\n").unwrap();
+
+        assert_eq!(read(&path), "second\n");
+        assert_eq!(read(&dir.join("app.log.1")), "first\nstill first\n");
+    }
+
+    #[test]
+    fn daily_policy_dst() {
+        // New York's clocks went forward on 2016-03-13, making it 23 hours long
+        if !::test::in_time_zone("appender::rolling::test::daily_policy_dst",
+                                 "EST5EDT,M3.2.0,M11.1.0") {
+            return;
+        }
+
+        let dir = env::temp_dir().join("log4rs-rolling-dst-test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("app.log");
+
+        // 2016-03-13 00:30 EST
+        let clock = Arc::new(ManualClock::new(time::Timespec::new(1457847000, 0)));
+        let mut appender = RollingFileAppender::builder(&path)
+            .policy(Box::new(DailyPolicy::new().clock(clock.clone())))
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        appender.write(b"first\n").unwrap();
+        // 23:30 EDT on the same day
+        clock.advance(time::Duration::hours(22));
+        appender.write(b"still first\n").unwrap();
+        // 00:30 EDT on the next day, only 23 hours after the first write
+        clock.advance(time::Duration::hours(1));
+        appender.write(b"second\n").unwrap();
+
+        assert_eq!(read(&path), "second\n");
+        assert_eq!(read(&dir.join("app.log.1")), "first\nstill first\n");
+    }
+}
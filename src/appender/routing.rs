@@ -0,0 +1,232 @@
+use std::error::Error;
+use log::LogRecord;
+
+use {Append, Filter, FilterResponse, LogEvent};
+
+/// An appender which passes each log event to the first of an ordered list
+/// of appenders whose filter accepts it.
+///
+/// Each route pairs a filter with an appender. A route matches a log event
+/// unless its filter rejects it or its appender does not accept it, and log
+/// events matching no route are discarded. This allows, for example, errors
+/// to be sent to an alerting appender and everything else to a file:
+///
+/// ```no_run
+/// # extern crate log;
+/// # extern crate log4rs;
+/// # use log4rs::appender::{ConsoleAppender, FileAppender, RoutingAppender};
+/// # use log4rs::filter::{LevelRangeFilter, ThresholdFilter};
+/// # use log::{LogLevel, LogLevelFilter};
+/// # fn main() {
+/// let appender = RoutingAppender::builder()
+///     .route(Box::new(ThresholdFilter::new(LogLevelFilter::Error)),
+///            Box::new(ConsoleAppender::builder().build()))
+///     .route(Box::new(LevelRangeFilter::new(LogLevel::Trace, LogLevel::Error)),
+///            Box::new(FileAppender::builder("log/app.log").build().unwrap()))
+///     .build();
+/// # let _ = appender;
+/// # }
+/// ```
+///
+/// A route added with `fallthrough_route` does not stop the search: a log
+/// event written to it continues on to the remaining routes.
+///
+/// Text which did not come from a log event, such as a summary written by a
+/// wrapping appender, is passed to the appenders of all routes.
+pub struct RoutingAppender {
+    routes: Vec<Route>,
+}
+
+struct Route {
+    filter: Box<Filter>,
+    appender: Box<Append>,
+    fallthrough: bool,
+}
+
+impl Append for RoutingAppender {
+    fn append(&mut self, record: &LogRecord) -> Result<(), Box<Error>> {
+        for route in &mut self.routes {
+            if route.filter.filter(record) == FilterResponse::Reject ||
+               !route.appender.accepts(record) {
+                continue;
+            }
+            try!(route.appender.append(record));
+            if !route.fallthrough {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn append_event(&mut self, event: &LogEvent) -> Result<(), Box<Error>> {
+        for route in &mut self.routes {
+            if route.filter.filter_event(event) == FilterResponse::Reject ||
+               !route.appender.accepts_event(event) {
+                continue;
+            }
+            try!(route.appender.append_event(event));
+            if !route.fallthrough {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<Error>> {
+        let mut result = Ok(());
+        for route in &mut self.routes {
+            if let Err(err) = route.appender.flush() {
+                if result.is_ok() {
+                    result = Err(err);
+                }
+            }
+        }
+        result
+    }
+
+    fn append_text(&mut self, text: &str) -> Result<(), Box<Error>> {
+        for route in &mut self.routes {
+            try!(route.appender.append_text(text));
+        }
+        Ok(())
+    }
+
+    fn reopen(&mut self) -> Result<(), Box<Error>> {
+        for route in &mut self.routes {
+            try!(route.appender.reopen());
+        }
+        Ok(())
+    }
+
+    // filters cannot be consulted here, but a log event which none of the
+    // routes' appenders accepts is discarded whichever route it matches
+    fn accepts(&self, record: &LogRecord) -> bool {
+        self.routes.iter().any(|route| route.appender.accepts(record))
+    }
+
+    fn accepts_event(&self, event: &LogEvent) -> bool {
+        self.routes.iter().any(|route| route.appender.accepts_event(event))
+    }
+}
+
+impl RoutingAppender {
+    /// Creates a new `RoutingAppender` builder with no routes.
+    pub fn builder() -> RoutingAppenderBuilder {
+        RoutingAppenderBuilder { routes: vec![] }
+    }
+}
+
+/// A builder for `RoutingAppender`s.
+pub struct RoutingAppenderBuilder {
+    routes: Vec<Route>,
+}
+
+impl RoutingAppenderBuilder {
+    /// Adds a route which passes log events accepted by `filter` to
+    /// `appender`, and no further.
+    pub fn route(self, filter: Box<Filter>, appender: Box<Append>) -> RoutingAppenderBuilder {
+        self.add(filter, appender, false)
+    }
+
+    /// Adds a route which passes log events accepted by `filter` to
+    /// `appender`, and then on to the remaining routes.
+    pub fn fallthrough_route(self, filter: Box<Filter>, appender: Box<Append>)
+                             -> RoutingAppenderBuilder {
+        self.add(filter, appender, true)
+    }
+
+    fn add(mut self, filter: Box<Filter>, appender: Box<Append>, fallthrough: bool)
+           -> RoutingAppenderBuilder {
+        self.routes.push(Route {
+            filter: filter,
+            appender: appender,
+            fallthrough: fallthrough,
+        });
+        self
+    }
+
+    /// Consumes the `RoutingAppenderBuilder`, producing a `RoutingAppender`.
+    pub fn build(self) -> RoutingAppender {
+        RoutingAppender { routes: self.routes }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+    use log::{LogLevel, LogLevelFilter};
+
+    use config;
+    use appender::MemoryAppender;
+    use filter::{LevelRangeFilter, ThresholdFilter};
+    use pattern::PatternLayout;
+    use test::{ErrorsOnly, global_handle};
+    use super::*;
+
+    #[test]
+    fn first_match() {
+        let memory = || {
+            MemoryAppender::builder(10).pattern(PatternLayout::new("%m").unwrap()).build()
+        };
+        let (alerts, audit, file) = (memory(), memory(), memory());
+        let handles = [alerts.handle(), audit.handle(), file.handle()];
+        let appender = RoutingAppender::builder()
+            .route(Box::new(ThresholdFilter::new(LogLevelFilter::Error)), Box::new(alerts))
+            .fallthrough_route(Box::new(ThresholdFilter::new(LogLevelFilter::Warn)),
+                               Box::new(audit))
+            .route(Box::new(LevelRangeFilter::new(LogLevel::Warn, LogLevel::Info)),
+                   Box::new(file))
+            .build();
+        let root = config::Root::builder(LogLevelFilter::Off).build();
+        let config = config::Config::builder(root)
+            .appender(config::Appender::builder("routing", Box::new(appender)).build())
+            .logger(config::Logger::builder("routing", LogLevelFilter::Debug)
+                        .appender("routing")
+                        .build())
+            .build()
+            .unwrap();
+
+        let handle = global_handle();
+        handle.as_ref().unwrap().set_config(config);
+        error!(target: "routing", "error");
+        warn!(target: "routing", "warn");
+        info!(target: "routing", "info");
+        debug!(target: "routing", "debug");
+        let root = config::Root::builder(LogLevelFilter::Off).build();
+        handle.as_ref().unwrap().set_config(config::Config::builder(root).build().unwrap());
+
+        assert_eq!(handles[0].snapshot(), ["error"]);
+        assert_eq!(handles[1].snapshot(), ["warn"]);
+        assert_eq!(handles[2].snapshot(), ["warn", "info"]);
+    }
+
+    #[test]
+    fn skips_unaccepting_appenders() {
+        let errors = Arc::new(Mutex::new(vec![]));
+        let file = MemoryAppender::builder(10).pattern(PatternLayout::new("%m").unwrap()).build();
+        let file_handle = file.handle();
+        let appender = RoutingAppender::builder()
+            .route(Box::new(ThresholdFilter::new(LogLevelFilter::Trace)),
+                   Box::new(ErrorsOnly(errors.clone())))
+            .route(Box::new(ThresholdFilter::new(LogLevelFilter::Trace)), Box::new(file))
+            .build();
+        let root = config::Root::builder(LogLevelFilter::Off).build();
+        let config = config::Config::builder(root)
+            .appender(config::Appender::builder("routing", Box::new(appender)).build())
+            .logger(config::Logger::builder("routing_accepts", LogLevelFilter::Info)
+                        .appender("routing")
+                        .build())
+            .build()
+            .unwrap();
+
+        let handle = global_handle();
+        handle.as_ref().unwrap().set_config(config);
+        error!(target: "routing_accepts", "error");
+        info!(target: "routing_accepts", "info");
+        let root = config::Root::builder(LogLevelFilter::Off).build();
+        handle.as_ref().unwrap().set_config(config::Config::builder(root).build().unwrap());
+
+        assert_eq!(*errors.lock().unwrap(), ["error"]);
+        assert_eq!(file_handle.snapshot(), ["info"]);
+    }
+}
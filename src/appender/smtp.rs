@@ -0,0 +1,573 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::io::prelude::*;
+use std::mem;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use log::LogRecord;
+use time;
+
+use {Append, Encode, LogEvent, handle_error, hostname};
+use pattern::PatternLayout;
+use super::{ConsoleWriter, Target};
+
+const TIMEOUT_SECS: u64 = 30;
+const FLUSH_TIMEOUT_SECS: u64 = 5;
+
+/// The kind of TLS used by an `SmtpAppender`.
+///
+/// TLS requires log4rs to be built with the `native-tls` feature.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Tls {
+    /// Send mail in plain text.
+    None,
+    /// Upgrade the connection with the `STARTTLS` command, as is usual on
+    /// port 587.
+    StartTls,
+    /// Use TLS from the start of the connection, as is usual on port 465.
+    Wrapper,
+}
+
+#[derive(Debug)]
+struct SmtpError(String);
+
+impl fmt::Display for SmtpError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "SMTP error: {}", self.0)
+    }
+}
+
+impl Error for SmtpError {
+    fn description(&self) -> &str {
+        "SMTP error"
+    }
+}
+
+enum Message {
+    Record(String),
+    Flush(Sender<()>),
+}
+
+/// An appender which sends log events by email.
+///
+/// Mail is sent by a background thread, so logging does not block on the
+/// mail server. If a batch window is set, log events arriving within the
+/// window after the first are sent together in a single email, which avoids
+/// flooding recipients when many errors occur at once. Since every log event
+/// is sent, the appender is typically paired with a `ThresholdFilter`.
+///
+/// If an email cannot be sent, the error and the log events it contained are
+/// written to stderr instead. Flushing the appender waits at most five seconds
+/// for pending mail to be sent, so a slow mail server does not hold up a
+/// config reload or shutdown.
+pub struct SmtpAppender {
+    sender: Option<Sender<Message>>,
+    thread: Option<JoinHandle<()>>,
+    encoder: Box<Encode>,
+}
+
+impl Drop for SmtpAppender {
+    fn drop(&mut self) {
+        // the worker sends any remaining events once the channel closes
+        self.sender.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Append for SmtpAppender {
+    fn append(&mut self, record: &LogRecord) -> Result<(), Box<Error>> {
+        let mut buf = vec![];
+        try!(self.encoder.encode(&mut buf, record));
+        self.send(Message::Record(String::from_utf8_lossy(&buf).into_owned()))
+    }
+
+    fn append_event(&mut self, event: &LogEvent) -> Result<(), Box<Error>> {
+        let mut buf = vec![];
+        try!(self.encoder.encode_event(&mut buf, event));
+        self.send(Message::Record(String::from_utf8_lossy(&buf).into_owned()))
+    }
+
+    fn flush(&mut self) -> Result<(), Box<Error>> {
+        let (tx, rx) = mpsc::channel();
+        try!(self.send(Message::Flush(tx)));
+        match rx.recv_timeout(Duration::from_secs(FLUSH_TIMEOUT_SECS)) {
+            Err(RecvTimeoutError::Timeout) => {
+                Err(Box::new(SmtpError("timed out waiting for mail to be sent".to_owned())))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn append_text(&mut self, text: &str) -> Result<(), Box<Error>> {
+        self.send(Message::Record(format!("{}\n", text)))
+    }
+}
+
+impl SmtpAppender {
+    /// Creates a new `SmtpAppender` builder for a mail server at the specified
+    /// `host:port` address, sending mail from the specified address.
+    pub fn builder(server: &str, from: &str) -> SmtpAppenderBuilder {
+        SmtpAppenderBuilder {
+            server: server.to_owned(),
+            from: from.to_owned(),
+            to: vec![],
+            subject: "Log messages".to_owned(),
+            credentials: None,
+            tls: Tls::None,
+            window: None,
+            encoder: Box::new(PatternLayout::default()),
+        }
+    }
+
+    fn send(&mut self, message: Message) -> Result<(), Box<Error>> {
+        match self.sender {
+            Some(ref sender) => {
+                match sender.send(message) {
+                    Ok(()) => Ok(()),
+                    Err(_) => Err(Box::new(SmtpError("mail thread has exited".to_owned()))),
+                }
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+/// A builder for `SmtpAppender`s.
+pub struct SmtpAppenderBuilder {
+    server: String,
+    from: String,
+    to: Vec<String>,
+    subject: String,
+    credentials: Option<(String, String)>,
+    tls: Tls,
+    window: Option<Duration>,
+    encoder: Box<Encode>,
+}
+
+impl SmtpAppenderBuilder {
+    /// Adds a recipient.
+    pub fn to(mut self, to: &str) -> SmtpAppenderBuilder {
+        self.to.push(to.to_owned());
+        self
+    }
+
+    /// Sets the subject of emails.
+    ///
+    /// Defaults to `Log messages`.
+    pub fn subject(mut self, subject: &str) -> SmtpAppenderBuilder {
+        self.subject = subject.to_owned();
+        self
+    }
+
+    /// Sets the username and password used to authenticate with the server.
+    ///
+    /// The `PLAIN` mechanism is used, so this should be combined with TLS.
+    /// Defaults to no authentication.
+    pub fn credentials(mut self, username: &str, password: &str) -> SmtpAppenderBuilder {
+        self.credentials = Some((username.to_owned(), password.to_owned()));
+        self
+    }
+
+    /// Sets the kind of TLS used.
+    ///
+    /// Defaults to `Tls::None`.
+    pub fn tls(mut self, tls: Tls) -> SmtpAppenderBuilder {
+        self.tls = tls;
+        self
+    }
+
+    /// Sets the window over which log events are collected into one email.
+    ///
+    /// Defaults to sending an email for every log event.
+    pub fn batch_window(mut self, window: Duration) -> SmtpAppenderBuilder {
+        self.window = Some(window);
+        self
+    }
+
+    /// Sets the output pattern for the `SmtpAppender`.
+    ///
+    /// This is a shorthand for `encoder(Box::new(pattern))`.
+    pub fn pattern(self, pattern: PatternLayout) -> SmtpAppenderBuilder {
+        self.encoder(Box::new(pattern))
+    }
+
+    /// Sets the encoder used to format log events.
+    pub fn encoder(mut self, encoder: Box<Encode>) -> SmtpAppenderBuilder {
+        self.encoder = encoder;
+        self
+    }
+
+    /// Consumes the `SmtpAppenderBuilder`, producing an `SmtpAppender`.
+    ///
+    /// No connection is made until the first email is sent.
+    pub fn build(self) -> io::Result<SmtpAppender> {
+        if self.to.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "no recipients specified"));
+        }
+        if self.tls != Tls::None && !cfg!(feature = "native-tls") {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "log4rs was built without the `native-tls` feature"));
+        }
+
+        let (tx, rx) = mpsc::channel();
+        let mailer = Mailer {
+            server: self.server,
+            from: self.from,
+            to: self.to,
+            subject: self.subject,
+            credentials: self.credentials,
+            tls: self.tls,
+        };
+        let window = self.window;
+        let thread = try!(thread::Builder::new()
+            .name("log4rs-smtp".to_owned())
+            .spawn(move || mailer.run(rx, window)));
+
+        Ok(SmtpAppender {
+            sender: Some(tx),
+            thread: Some(thread),
+            encoder: self.encoder,
+        })
+    }
+}
+
+struct Mailer {
+    server: String,
+    from: String,
+    to: Vec<String>,
+    subject: String,
+    credentials: Option<(String, String)>,
+    tls: Tls,
+}
+
+impl Mailer {
+    fn run(&self, rx: Receiver<Message>, window: Option<Duration>) {
+        let mut batch = vec![];
+        let mut deadline = None;
+        loop {
+            let message = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        Err(RecvTimeoutError::Timeout)
+                    } else {
+                        rx.recv_timeout(deadline - now)
+                    }
+                }
+                None => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            };
+
+            match message {
+                Ok(Message::Record(record)) => {
+                    batch.push(record);
+                    match window {
+                        Some(window) => {
+                            if deadline.is_none() {
+                                deadline = Some(Instant::now() + window);
+                            }
+                        }
+                        None => self.send_batch(&mut batch),
+                    }
+                }
+                Ok(Message::Flush(done)) => {
+                    self.send_batch(&mut batch);
+                    deadline = None;
+                    let _ = done.send(());
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    self.send_batch(&mut batch);
+                    deadline = None;
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    self.send_batch(&mut batch);
+                    return;
+                }
+            }
+        }
+    }
+
+    fn send_batch(&self, batch: &mut Vec<String>) {
+        if batch.is_empty() {
+            return;
+        }
+        let batch = mem::replace(batch, vec![]);
+        let body = batch.concat();
+        if let Err(err) = self.send_mail(&body) {
+            handle_error(&*err);
+            let _ = ConsoleWriter::new(Target::Stderr).write_atomic(body.as_bytes());
+        }
+    }
+
+    fn send_mail(&self, body: &str) -> Result<(), Box<Error>> {
+        let stream = try!(connect(&self.server));
+        let host = self.server.rsplitn(2, ':').last().unwrap_or("").to_owned();
+        let mut conn = Connection { stream: try!(wrap(stream, &host, self.tls == Tls::Wrapper)) };
+        let local = hostname().unwrap_or_else(|| "localhost".to_owned());
+
+        try!(conn.expect(220));
+        try!(conn.command(&format!("EHLO {}", local), 250));
+        if self.tls == Tls::StartTls {
+            try!(conn.command("STARTTLS", 220));
+            conn = Connection { stream: try!(conn.stream.start_tls(&host)) };
+            try!(conn.command(&format!("EHLO {}", local), 250));
+        }
+        if let Some((ref username, ref password)) = self.credentials {
+            let auth = base64(format!("\0{}\0{}", username, password).as_bytes());
+            try!(conn.command(&format!("AUTH PLAIN {}", auth), 235));
+        }
+        try!(conn.command(&format!("MAIL FROM:<{}>", self.from), 250));
+        for to in &self.to {
+            try!(conn.command(&format!("RCPT TO:<{}>", to), 250));
+        }
+        try!(conn.command("DATA", 354));
+        try!(conn.write(&format_message(&self.from,
+                                         &self.to,
+                                         &self.subject,
+                                         &time::now().rfc822z().to_string(),
+                                         body)));
+        try!(conn.command(".", 250));
+        try!(conn.command("QUIT", 221));
+        Ok(())
+    }
+}
+
+// Formats an email for the `DATA` command, normalizing line endings to CRLF
+// and escaping lines which begin with a `.`.
+fn format_message(from: &str, to: &[String], subject: &str, date: &str, body: &str) -> String {
+    let mut message = format!("From: <{}>\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\n\
+                               Content-Type: text/plain; charset=utf-8\r\n\r\n",
+                              from,
+                              to.iter().map(|t| format!("<{}>", t)).collect::<Vec<_>>().join(", "),
+                              subject,
+                              date);
+    let body = body.replace("\r\n", "\n").replace('\r', "\n");
+    for line in body.lines() {
+        if line.starts_with('.') {
+            message.push('.');
+        }
+        message.push_str(line);
+        message.push_str("\r\n");
+    }
+    message
+}
+
+fn base64(input: &[u8]) -> String {
+    const CHARS: &'static [u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ\
+                                   abcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::new();
+    for chunk in input.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (b[0] as usize) << 16 | (b[1] as usize) << 8 | b[2] as usize;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(CHARS[n >> (18 - 6 * i) & 0x3f] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+fn connect(server: &str) -> io::Result<TcpStream> {
+    let timeout = Duration::from_secs(TIMEOUT_SECS);
+    let mut last_err = io::Error::new(io::ErrorKind::InvalidInput,
+                                      "address did not resolve to any addresses");
+    for addr in try!(server.to_socket_addrs()) {
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(stream) => {
+                try!(stream.set_read_timeout(Some(timeout)));
+                try!(stream.set_write_timeout(Some(timeout)));
+                return Ok(stream);
+            }
+            Err(err) => last_err = err,
+        }
+    }
+    Err(last_err)
+}
+
+trait Stream: Read + Write {
+    fn start_tls(self: Box<Self>, host: &str) -> Result<Box<Stream>, Box<Error>>;
+}
+
+impl Stream for TcpStream {
+    fn start_tls(self: Box<Self>, host: &str) -> Result<Box<Stream>, Box<Error>> {
+        wrap(*self, host, true)
+    }
+}
+
+#[cfg(feature = "native-tls")]
+impl Stream for ::native_tls::TlsStream<TcpStream> {
+    fn start_tls(self: Box<Self>, _: &str) -> Result<Box<Stream>, Box<Error>> {
+        Err(Box::new(SmtpError("connection is already encrypted".to_owned())))
+    }
+}
+
+#[cfg(feature = "native-tls")]
+fn wrap(stream: TcpStream, host: &str, tls: bool) -> Result<Box<Stream>, Box<Error>> {
+    if tls {
+        let connector = try!(::native_tls::TlsConnector::new());
+        match connector.connect(host, stream) {
+            Ok(stream) => Ok(Box::new(stream)),
+            Err(err) => Err(Box::new(SmtpError(err.to_string()))),
+        }
+    } else {
+        Ok(Box::new(stream))
+    }
+}
+
+#[cfg(not(feature = "native-tls"))]
+fn wrap(stream: TcpStream, _: &str, tls: bool) -> Result<Box<Stream>, Box<Error>> {
+    if tls {
+        Err(Box::new(SmtpError("log4rs was built without the `native-tls` feature".to_owned())))
+    } else {
+        Ok(Box::new(stream))
+    }
+}
+
+struct Connection {
+    stream: Box<Stream>,
+}
+
+impl Connection {
+    fn write(&mut self, data: &str) -> io::Result<()> {
+        try!(self.stream.write_all(data.as_bytes()));
+        self.stream.flush()
+    }
+
+    fn command(&mut self, command: &str, code: u32) -> Result<(), Box<Error>> {
+        try!(self.write(&format!("{}\r\n", command)));
+        self.expect(code)
+    }
+
+    // Reads a possibly multi-line reply, failing if its code is not `code`.
+    fn expect(&mut self, code: u32) -> Result<(), Box<Error>> {
+        loop {
+            let line = try!(self.read_line());
+            let actual = line.get(..3).and_then(|c| c.parse::<u32>().ok());
+            if actual != Some(code) {
+                return Err(Box::new(SmtpError(format!("unexpected reply `{}`", line))));
+            }
+            if line.as_bytes().get(3) != Some(&b'-') {
+                return Ok(());
+            }
+        }
+    }
+
+    // Reads a byte at a time, so that nothing is buffered when the connection
+    // is upgraded to TLS.
+    fn read_line(&mut self) -> io::Result<String> {
+        let mut line = vec![];
+        let mut byte = [0];
+        loop {
+            if try!(self.stream.read(&mut byte)) == 0 {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed"));
+            }
+            if byte[0] == b'\n' {
+                break;
+            }
+            line.push(byte[0]);
+        }
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        Ok(String::from_utf8_lossy(&line).into_owned())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+    use std::time::Duration;
+
+    use Append;
+    use super::*;
+
+    #[test]
+    fn base64_encoding() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"\0user\0pass"), "AHVzZXIAcGFzcw==");
+    }
+
+    #[test]
+    fn message_format() {
+        let message = format_message("log@example.com",
+                                     &["a@example.com".to_owned(), "b@example.com".to_owned()],
+                                     "Errors",
+                                     "Thu, 01 Jan 1970 00:00:00 +0000",
+                                     "one\n.two\r\nthree\rfour\r");
+        assert_eq!(message,
+                   "From: <log@example.com>\r\nTo: <a@example.com>, <b@example.com>\r\n\
+                    Subject: Errors\r\nDate: Thu, 01 Jan 1970 00:00:00 +0000\r\n\
+                    Content-Type: text/plain; charset=utf-8\r\n\r\none\r\n..two\r\nthree\r\n\
+                    four\r\n");
+    }
+
+    #[test]
+    fn batches() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut writer = stream;
+            writer.write_all(b"220 hello\r\n").unwrap();
+            let mut data = String::new();
+            let mut in_data = false;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if in_data {
+                    if line == ".\r\n" {
+                        in_data = false;
+                        writer.write_all(b"250 ok\r\n").unwrap();
+                    } else {
+                        data.push_str(&line);
+                    }
+                } else if line.starts_with("EHLO") {
+                    writer.write_all(b"250-hi\r\n250 AUTH PLAIN\r\n").unwrap();
+                } else if line.starts_with("AUTH PLAIN") {
+                    assert_eq!(line, "AUTH PLAIN AHVzZXIAcGFzcw==\r\n");
+                    writer.write_all(b"235 ok\r\n").unwrap();
+                } else if line == "DATA\r\n" {
+                    in_data = true;
+                    writer.write_all(b"354 go\r\n").unwrap();
+                } else if line == "QUIT\r\n" {
+                    writer.write_all(b"221 bye\r\n").unwrap();
+                    return data;
+                } else {
+                    writer.write_all(b"250 ok\r\n").unwrap();
+                }
+            }
+        });
+
+        let mut appender = SmtpAppender::builder(&addr, "log@example.com")
+            .to("oncall@example.com")
+            .credentials("user", "pass")
+            .batch_window(Duration::from_secs(60))
+            .build()
+            .unwrap();
+        appender.append_text("first").unwrap();
+        appender.append_text("second").unwrap();
+        appender.flush().unwrap();
+
+        let data = server.join().unwrap();
+        assert!(data.contains("To: <oncall@example.com>\r\n"));
+        assert!(data.ends_with("\r\n\r\nfirst\r\nsecond\r\n"));
+    }
+
+    #[test]
+    fn no_recipients() {
+        assert!(SmtpAppender::builder("127.0.0.1:25", "log@example.com").build().is_err());
+    }
+}
@@ -0,0 +1,348 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::io::prelude::*;
+use std::net::UdpSocket;
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+use std::path::PathBuf;
+use std::process;
+use std::str::FromStr;
+use log::{LogLevel, LogRecord};
+use time::{self, Tm};
+
+use {Append, Encode, LogEvent, hostname, program_name};
+use pattern::PatternLayout;
+
+/// A syslog facility.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[allow(missing_docs)]
+pub enum Facility {
+    Kern = 0,
+    User = 1,
+    Mail = 2,
+    Daemon = 3,
+    Auth = 4,
+    Syslog = 5,
+    Lpr = 6,
+    News = 7,
+    Uucp = 8,
+    Cron = 9,
+    Authpriv = 10,
+    Ftp = 11,
+    Local0 = 16,
+    Local1 = 17,
+    Local2 = 18,
+    Local3 = 19,
+    Local4 = 20,
+    Local5 = 21,
+    Local6 = 22,
+    Local7 = 23,
+}
+
+/// An error parsing a `Facility`.
+#[derive(Debug)]
+pub struct ParseFacilityError(String);
+
+impl fmt::Display for ParseFacilityError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "Invalid syslog facility `{}`", self.0)
+    }
+}
+
+impl Error for ParseFacilityError {
+    fn description(&self) -> &str {
+        "Invalid syslog facility"
+    }
+}
+
+impl FromStr for Facility {
+    type Err = ParseFacilityError;
+
+    /// Parses a facility from its lowercase name, for example `"local0"`.
+    fn from_str(s: &str) -> Result<Facility, ParseFacilityError> {
+        let facility = match s {
+            "kern" => Facility::Kern,
+            "user" => Facility::User,
+            "mail" => Facility::Mail,
+            "daemon" => Facility::Daemon,
+            "auth" => Facility::Auth,
+            "syslog" => Facility::Syslog,
+            "lpr" => Facility::Lpr,
+            "news" => Facility::News,
+            "uucp" => Facility::Uucp,
+            "cron" => Facility::Cron,
+            "authpriv" => Facility::Authpriv,
+            "ftp" => Facility::Ftp,
+            "local0" => Facility::Local0,
+            "local1" => Facility::Local1,
+            "local2" => Facility::Local2,
+            "local3" => Facility::Local3,
+            "local4" => Facility::Local4,
+            "local5" => Facility::Local5,
+            "local6" => Facility::Local6,
+            "local7" => Facility::Local7,
+            _ => return Err(ParseFacilityError(s.to_owned())),
+        };
+        Ok(facility)
+    }
+}
+
+/// The transport used by a `SyslogAppender`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyslogTransport {
+    /// A Unix datagram socket at the specified path, typically `/dev/log`.
+    ///
+    /// Only supported on Unix platforms.
+    Unix(PathBuf),
+    /// UDP to the specified address, typically port 514.
+    Udp(String),
+}
+
+enum Socket {
+    #[cfg(unix)]
+    Unix(UnixDatagram),
+    Udp(UdpSocket),
+}
+
+/// An appender which sends log events to a syslog daemon, formatted as
+/// described in RFC 5424.
+///
+/// The configured encoder produces the `MSG` part of each syslog message, and
+/// a single trailing newline is removed from its output. The severity of each
+/// message is derived from the level of the log event: `Error` maps to
+/// `err`, `Warn` to `warning`, `Info` to `info`, and `Debug` and `Trace` to
+/// `debug`.
+pub struct SyslogAppender {
+    socket: Socket,
+    facility: Facility,
+    hostname: String,
+    app_name: String,
+    pid: u32,
+    encoder: Box<Encode>,
+}
+
+impl Append for SyslogAppender {
+    fn append(&mut self, record: &LogRecord) -> Result<(), Box<Error>> {
+        let mut msg = vec![];
+        try!(self.encoder.encode(&mut msg, record));
+        self.send(record.level(), &time::now_utc(), &msg)
+    }
+
+    fn append_event(&mut self, event: &LogEvent) -> Result<(), Box<Error>> {
+        let mut msg = vec![];
+        try!(self.encoder.encode_event(&mut msg, event));
+        self.send(event.level(), &time::at_utc(event.time()), &msg)
+    }
+}
+
+impl SyslogAppender {
+    /// Creates a new `SyslogAppender` builder.
+    pub fn builder() -> SyslogAppenderBuilder {
+        SyslogAppenderBuilder {
+            transport: default_transport(),
+            facility: Facility::User,
+            app_name: None,
+            encoder: Box::new(PatternLayout::new("%m").unwrap()),
+        }
+    }
+
+    fn priority(&self, level: LogLevel) -> u8 {
+        let severity = match level {
+            LogLevel::Error => 3,
+            LogLevel::Warn => 4,
+            LogLevel::Info => 6,
+            LogLevel::Debug | LogLevel::Trace => 7,
+        };
+        self.facility as u8 * 8 + severity
+    }
+
+    fn send(&self, level: LogLevel, now: &Tm, msg: &[u8]) -> Result<(), Box<Error>> {
+        let mut buf = vec![];
+        try!(self.format_message(&mut buf, level, now, msg));
+        match self.socket {
+            #[cfg(unix)]
+            Socket::Unix(ref socket) => try!(socket.send(&buf)),
+            Socket::Udp(ref socket) => try!(socket.send(&buf)),
+        };
+        Ok(())
+    }
+
+    fn format_message(&self, w: &mut Vec<u8>, level: LogLevel, now: &Tm, msg: &[u8])
+                      -> io::Result<()> {
+        let msg = if msg.ends_with(b"\n") {
+            &msg[..msg.len() - 1]
+        } else {
+            msg
+        };
+
+        try!(write!(w,
+                    "<{}>1 {}.{:06}Z {} {} {} - - ",
+                    self.priority(level),
+                    now.strftime("%Y-%m-%dT%H:%M:%S").unwrap(),
+                    now.tm_nsec / 1000,
+                    self.hostname,
+                    self.app_name,
+                    self.pid));
+        w.write_all(msg)
+    }
+}
+
+#[cfg(unix)]
+fn default_transport() -> SyslogTransport {
+    SyslogTransport::Unix(PathBuf::from("/dev/log"))
+}
+
+#[cfg(not(unix))]
+fn default_transport() -> SyslogTransport {
+    SyslogTransport::Udp("127.0.0.1:514".to_owned())
+}
+
+/// A builder for `SyslogAppender`s.
+pub struct SyslogAppenderBuilder {
+    transport: SyslogTransport,
+    facility: Facility,
+    app_name: Option<String>,
+    encoder: Box<Encode>,
+}
+
+impl SyslogAppenderBuilder {
+    /// Sets the transport used to reach the syslog daemon.
+    ///
+    /// Defaults to the Unix socket at `/dev/log` on Unix platforms, and UDP
+    /// to `127.0.0.1:514` elsewhere.
+    pub fn transport(mut self, transport: SyslogTransport) -> SyslogAppenderBuilder {
+        self.transport = transport;
+        self
+    }
+
+    /// Sets the facility of messages.
+    ///
+    /// Defaults to `Facility::User`.
+    pub fn facility(mut self, facility: Facility) -> SyslogAppenderBuilder {
+        self.facility = facility;
+        self
+    }
+
+    /// Sets the `APP-NAME` field of messages.
+    ///
+    /// Spaces and non-printable characters are replaced with `_`, and the
+    /// name is truncated to 48 characters as required by RFC 5424.
+    ///
+    /// Defaults to the file name of the current executable.
+    pub fn app_name(mut self, app_name: String) -> SyslogAppenderBuilder {
+        self.app_name = Some(app_name);
+        self
+    }
+
+    /// Sets the output pattern for the `SyslogAppender`.
+    ///
+    /// This is a shorthand for `encoder(Box::new(pattern))`.
+    pub fn pattern(self, pattern: PatternLayout) -> SyslogAppenderBuilder {
+        self.encoder(Box::new(pattern))
+    }
+
+    /// Sets the encoder used to format the message of the `SyslogAppender`.
+    ///
+    /// Defaults to a `PatternLayout` with the pattern `%m`.
+    pub fn encoder(mut self, encoder: Box<Encode>) -> SyslogAppenderBuilder {
+        self.encoder = encoder;
+        self
+    }
+
+    /// Consumes the `SyslogAppenderBuilder`, producing a `SyslogAppender`.
+    pub fn build(self) -> io::Result<SyslogAppender> {
+        let socket = match self.transport {
+            #[cfg(unix)]
+            SyslogTransport::Unix(ref path) => {
+                let socket = try!(UnixDatagram::unbound());
+                try!(socket.connect(path));
+                Socket::Unix(socket)
+            }
+            #[cfg(not(unix))]
+            SyslogTransport::Unix(_) => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                          "Unix sockets are not supported on this platform"));
+            }
+            SyslogTransport::Udp(ref addr) => {
+                let socket = try!(UdpSocket::bind("0.0.0.0:0"));
+                try!(socket.connect(&**addr));
+                Socket::Udp(socket)
+            }
+        };
+
+        let app_name = match self.app_name {
+            Some(app_name) => app_name,
+            None => program_name().unwrap_or_default(),
+        };
+        let hostname = hostname().unwrap_or_default();
+
+        Ok(SyslogAppender {
+            socket: socket,
+            facility: self.facility,
+            hostname: header_field(&hostname, 255),
+            app_name: header_field(&app_name, 48),
+            pid: process::id(),
+            encoder: self.encoder,
+        })
+    }
+}
+
+// Makes a value safe for a field of the header, which may only contain
+// printable ASCII characters other than space, with `-` denoting no value.
+fn header_field(value: &str, max_len: usize) -> String {
+    if value.is_empty() {
+        return "-".to_owned();
+    }
+
+    value.chars()
+         .take(max_len)
+         .map(|c| if c > ' ' && c <= '~' { c } else { '_' })
+         .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::UdpSocket;
+    use log::LogLevel;
+    use time;
+
+    use super::*;
+
+    #[test]
+    fn facility_from_str() {
+        assert_eq!("local3".parse::<Facility>().unwrap(), Facility::Local3);
+        assert!("local8".parse::<Facility>().is_err());
+    }
+
+    #[test]
+    fn header_field_sanitizing() {
+        assert_eq!(super::header_field("app", 48), "app");
+        assert_eq!(super::header_field("my app\n\u{e9}", 48), "my_app__");
+        assert_eq!(super::header_field(&"a".repeat(60), 48), "a".repeat(48));
+        assert_eq!(super::header_field("", 48), "-");
+    }
+
+    #[test]
+    fn udp() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap().to_string();
+
+        let appender = SyslogAppender::builder()
+            .transport(SyslogTransport::Udp(addr))
+            .facility(Facility::Local0)
+            .app_name("app".to_owned())
+            .build()
+            .unwrap();
+        assert_eq!(appender.priority(LogLevel::Error), 131);
+        assert_eq!(appender.priority(LogLevel::Trace), 135);
+
+        let now = time::at_utc(time::Timespec::new(1435708800, 3000000));
+        let mut buf = vec![];
+        appender.format_message(&mut buf, LogLevel::Warn, &now, b"the message\n").unwrap();
+        let expected = format!("<132>1 2015-07-01T00:00:00.003000Z {} app {} - - the message",
+                               appender.hostname,
+                               appender.pid);
+        assert_eq!(String::from_utf8(buf).unwrap(), expected);
+    }
+}
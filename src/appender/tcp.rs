@@ -0,0 +1,424 @@
+use std::cmp;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::io::prelude::*;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+use log::LogRecord;
+
+use {Append, Encode, LogEvent};
+use pattern::PatternLayout;
+
+const CONNECT_TIMEOUT_MS: u64 = 1000;
+const WRITE_TIMEOUT_MS: u64 = 1000;
+const MIN_BACKOFF_MS: u64 = 100;
+const MAX_BACKOFF_MS: u64 = 30000;
+
+/// The behavior of a `TcpAppender` while it is disconnected from the
+/// collector.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OutagePolicy {
+    /// Discard log events until the connection is reestablished.
+    Drop,
+    /// Hold up to the specified number of log events in memory, discarding the
+    /// oldest when full, and send them once the connection is reestablished.
+    Buffer(usize),
+}
+
+#[derive(Debug)]
+struct OutageError(io::Error);
+
+impl fmt::Display for OutageError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "Lost connection to log collector: {}", self.0)
+    }
+}
+
+impl Error for OutageError {
+    fn description(&self) -> &str {
+        "Lost connection to log collector"
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        Some(&self.0)
+    }
+}
+
+/// An appender which writes log events to a TCP socket.
+///
+/// Each log event is followed by a newline. If the connection breaks, the
+/// appender reconnects on a background thread with exponential backoff, and
+/// log events are buffered or dropped in the meantime according to its
+/// `OutagePolicy`. A connection error is reported only by the first log event
+/// of each outage.
+///
+/// Writes to the collector time out after a second, so a stalled collector
+/// does not block logging threads indefinitely. Log events which could not be
+/// written in time are handled as they are during an outage, and a partially
+/// written log event is completed before any other is sent.
+pub struct TcpAppender {
+    addr: String,
+    stream: Option<TcpStream>,
+    connecting: Option<Receiver<io::Result<TcpStream>>>,
+    buffer: VecDeque<Vec<u8>>,
+    written: usize,
+    policy: OutagePolicy,
+    backoff: Duration,
+    next_attempt: Instant,
+    reported: bool,
+    encoder: Box<Encode>,
+}
+
+impl Append for TcpAppender {
+    fn append(&mut self, record: &LogRecord) -> Result<(), Box<Error>> {
+        let mut msg = vec![];
+        try!(self.encoder.encode(&mut msg, record));
+        self.send(msg, Instant::now())
+    }
+
+    fn append_event(&mut self, event: &LogEvent) -> Result<(), Box<Error>> {
+        let mut msg = vec![];
+        try!(self.encoder.encode_event(&mut msg, event));
+        self.send(msg, Instant::now())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<Error>> {
+        if let Some(ref mut stream) = self.stream {
+            try!(stream.flush());
+        }
+        Ok(())
+    }
+
+    fn append_text(&mut self, text: &str) -> Result<(), Box<Error>> {
+        self.send(text.as_bytes().to_vec(), Instant::now())
+    }
+}
+
+impl TcpAppender {
+    /// Creates a new `TcpAppender` builder for a collector at the specified
+    /// `host:port` address.
+    pub fn builder(addr: &str) -> TcpAppenderBuilder {
+        TcpAppenderBuilder {
+            addr: addr.to_owned(),
+            policy: OutagePolicy::Buffer(1024),
+            encoder: Box::new(PatternLayout::default()),
+        }
+    }
+
+    fn send(&mut self, mut msg: Vec<u8>, now: Instant) -> Result<(), Box<Error>> {
+        if !msg.ends_with(b"\n") {
+            msg.push(b'\n');
+        }
+        self.buffer.push_back(msg);
+
+        let mut err = None;
+        if self.stream.is_none() {
+            err = self.poll_connect(now);
+        }
+
+        if let Some(ref mut stream) = self.stream {
+            match write_buffered(stream, &mut self.buffer, &mut self.written) {
+                Ok(()) => {
+                    self.reported = false;
+                    return Ok(());
+                }
+                Err(e) => err = Some(e),
+            }
+        }
+
+        // a write which timed out leaves the connection usable, but any other
+        // error loses it along with the partially written log event
+        let lost = match err {
+            Some(ref err) => !is_timeout(err),
+            None => false,
+        };
+        if lost && self.stream.take().is_some() {
+            self.written = 0;
+        }
+        self.trim();
+
+        match err {
+            Some(err) => {
+                if lost {
+                    self.next_attempt = now + self.backoff;
+                    self.backoff = cmp::min(self.backoff * 2,
+                                            Duration::from_millis(MAX_BACKOFF_MS));
+                }
+                if self.reported {
+                    Ok(())
+                } else {
+                    self.reported = true;
+                    Err(Box::new(OutageError(err)))
+                }
+            }
+            None => Ok(()),
+        }
+    }
+
+    // Picks up the result of a background connection attempt, starting one if
+    // none is running and the backoff has elapsed.
+    fn poll_connect(&mut self, now: Instant) -> Option<io::Error> {
+        let result = match self.connecting {
+            Some(ref connecting) => {
+                match connecting.try_recv() {
+                    Ok(result) => result,
+                    Err(TryRecvError::Empty) => return None,
+                    Err(TryRecvError::Disconnected) => {
+                        Err(io::Error::new(io::ErrorKind::Other, "connection thread panicked"))
+                    }
+                }
+            }
+            None => {
+                if now >= self.next_attempt {
+                    self.connecting = Some(connect_in_background(self.addr.clone()));
+                }
+                return None;
+            }
+        };
+        self.connecting = None;
+
+        match result {
+            Ok(stream) => {
+                self.stream = Some(stream);
+                self.backoff = Duration::from_millis(MIN_BACKOFF_MS);
+                None
+            }
+            Err(err) => Some(err),
+        }
+    }
+
+    // Discards the oldest log events beyond the capacity of the outage policy.
+    // A partially written log event is never discarded, as the connection
+    // could not be used for anything else until it is completed.
+    fn trim(&mut self) {
+        let capacity = match self.policy {
+            OutagePolicy::Drop => 0,
+            OutagePolicy::Buffer(capacity) => capacity,
+        };
+        let keep = if self.written > 0 { 1 } else { 0 };
+        while self.buffer.len() > cmp::max(capacity, keep) {
+            self.buffer.remove(keep);
+        }
+    }
+}
+
+fn connect(addr: &str) -> io::Result<TcpStream> {
+    let mut last_err = io::Error::new(io::ErrorKind::InvalidInput,
+                                      "address did not resolve to any addresses");
+    for addr in try!(addr.to_socket_addrs()) {
+        match TcpStream::connect_timeout(&addr, Duration::from_millis(CONNECT_TIMEOUT_MS)) {
+            Ok(stream) => {
+                try!(stream.set_write_timeout(Some(Duration::from_millis(WRITE_TIMEOUT_MS))));
+                return Ok(stream);
+            }
+            Err(err) => last_err = err,
+        }
+    }
+    Err(last_err)
+}
+
+fn connect_in_background(addr: String) -> Receiver<io::Result<TcpStream>> {
+    let (tx, rx) = mpsc::channel();
+    let thread = thread::Builder::new()
+        .name("log4rs tcp connect".to_string())
+        .spawn(move || {
+            let _ = tx.send(connect(&addr));
+        });
+    if let Err(err) = thread {
+        let (tx, rx) = mpsc::channel();
+        let _ = tx.send(Err(err));
+        return rx;
+    }
+    rx
+}
+
+fn is_timeout(err: &io::Error) -> bool {
+    match err.kind() {
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => true,
+        _ => false,
+    }
+}
+
+// Writes out buffered messages, the first of which has already been written up
+// to `written`. Messages are only removed from the buffer once they have been
+// written in full.
+fn write_buffered(stream: &mut TcpStream, buffer: &mut VecDeque<Vec<u8>>, written: &mut usize)
+                  -> io::Result<()> {
+    loop {
+        let len = match buffer.front() {
+            Some(msg) => {
+                match stream.write(&msg[*written..]) {
+                    Ok(0) => {
+                        return Err(io::Error::new(io::ErrorKind::WriteZero,
+                                                  "failed to write log event"));
+                    }
+                    Ok(n) => *written += n,
+                    Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+                    Err(err) => return Err(err),
+                }
+                msg.len()
+            }
+            None => return Ok(()),
+        };
+        if *written == len {
+            buffer.pop_front();
+            *written = 0;
+        }
+    }
+}
+
+/// A builder for `TcpAppender`s.
+pub struct TcpAppenderBuilder {
+    addr: String,
+    policy: OutagePolicy,
+    encoder: Box<Encode>,
+}
+
+impl TcpAppenderBuilder {
+    /// Sets the behavior of the appender while it is disconnected.
+    ///
+    /// Defaults to `OutagePolicy::Buffer(1024)`.
+    pub fn policy(mut self, policy: OutagePolicy) -> TcpAppenderBuilder {
+        self.policy = policy;
+        self
+    }
+
+    /// Sets the output pattern for the `TcpAppender`.
+    ///
+    /// This is a shorthand for `encoder(Box::new(pattern))`.
+    pub fn pattern(self, pattern: PatternLayout) -> TcpAppenderBuilder {
+        self.encoder(Box::new(pattern))
+    }
+
+    /// Sets the encoder used to format log events.
+    pub fn encoder(mut self, encoder: Box<Encode>) -> TcpAppenderBuilder {
+        self.encoder = encoder;
+        self
+    }
+
+    /// Consumes the `TcpAppenderBuilder`, producing a `TcpAppender`.
+    ///
+    /// A connection is attempted immediately, but failure to connect is not
+    /// an error. It is instead reported by the first log event, and the
+    /// connection is retried as it would be after an outage.
+    pub fn build(self) -> TcpAppender {
+        let now = Instant::now();
+        let mut appender = TcpAppender {
+            addr: self.addr,
+            stream: None,
+            connecting: None,
+            buffer: VecDeque::new(),
+            written: 0,
+            policy: self.policy,
+            backoff: Duration::from_millis(MIN_BACKOFF_MS),
+            next_attempt: now,
+            reported: false,
+            encoder: self.encoder,
+        };
+        match connect(&appender.addr) {
+            Ok(stream) => appender.stream = Some(stream),
+            Err(err) => {
+                // reported by the first log event
+                let (tx, rx) = mpsc::channel();
+                let _ = tx.send(Err(err));
+                appender.connecting = Some(rx);
+            }
+        }
+        appender
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    fn unused_addr() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().to_string()
+    }
+
+    // Waits for the background connection attempt to complete.
+    fn finish_connect(appender: &mut TcpAppender) {
+        let result = appender.connecting.take().unwrap().recv().unwrap();
+        let (tx, rx) = mpsc::channel();
+        tx.send(result).unwrap();
+        appender.connecting = Some(rx);
+    }
+
+    #[test]
+    fn reconnect() {
+        let addr = unused_addr();
+        let mut appender = TcpAppender::builder(&addr).policy(OutagePolicy::Buffer(2)).build();
+        let now = Instant::now();
+
+        // the outage is reported once
+        assert!(appender.send(b"one".to_vec(), now).is_err());
+        assert!(appender.send(b"two".to_vec(), now + Duration::from_millis(200)).is_ok());
+        finish_connect(&mut appender);
+        assert!(appender.send(b"three".to_vec(), now + Duration::from_millis(200)).is_ok());
+        assert!(appender.connecting.is_none());
+
+        let listener = TcpListener::bind(&*addr).unwrap();
+        // still backing off
+        appender.send(b"four".to_vec(), now + Duration::from_millis(300)).unwrap();
+        assert!(appender.connecting.is_none());
+        // connects in the background
+        appender.send(b"five\n".to_vec(), now + Duration::from_secs(1)).unwrap();
+        assert!(appender.stream.is_none());
+        finish_connect(&mut appender);
+        appender.send(b"six".to_vec(), now + Duration::from_secs(1)).unwrap();
+        assert!(appender.stream.is_some());
+        drop(appender);
+
+        let (stream, _) = listener.accept().unwrap();
+        let lines = BufReader::new(stream).lines().map(|l| l.unwrap()).collect::<Vec<_>>();
+        assert_eq!(lines, ["four", "five", "six"]);
+    }
+
+    #[test]
+    fn stalled_collector() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let mut appender = TcpAppender::builder(&addr).policy(OutagePolicy::Buffer(100)).build();
+        let (stream, _) = listener.accept().unwrap();
+
+        // nothing is read, so a write eventually times out
+        let line = vec![b'x'; 1024 * 1024];
+        let mut count = 1;
+        while appender.send(line.clone(), Instant::now()).is_ok() {
+            count += 1;
+        }
+        assert!(appender.stream.is_some());
+
+        let reader = thread::spawn(move || {
+            BufReader::new(stream).lines().map(|l| l.unwrap()).collect::<Vec<_>>()
+        });
+        appender.send(b"end".to_vec(), Instant::now()).unwrap();
+        assert!(appender.buffer.is_empty());
+        drop(appender);
+
+        // every line arrives once and in full
+        let lines = reader.join().unwrap();
+        assert_eq!(lines.len(), count + 1);
+        assert!(lines[..count].iter().all(|l| l.as_bytes() == &line[..]));
+        assert_eq!(lines[count], "end");
+    }
+
+    #[test]
+    fn drop_policy() {
+        let addr = unused_addr();
+        let mut appender = TcpAppender::builder(&addr).policy(OutagePolicy::Drop).build();
+        assert!(appender.send(b"one".to_vec(), Instant::now()).is_err());
+        assert!(appender.buffer.is_empty());
+    }
+}
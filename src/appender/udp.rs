@@ -0,0 +1,276 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+use log::LogRecord;
+
+use {Append, Encode, LogEvent};
+use pattern::PatternLayout;
+
+const DEFAULT_MTU: usize = 1472;
+const ERROR_INTERVAL_SECS: u64 = 60;
+
+/// The behavior of a `UdpAppender` when a log event does not fit in a single
+/// datagram.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Oversize {
+    /// Send only as much of the log event as fits in one datagram.
+    Truncate,
+    /// Send the log event as a sequence of datagrams.
+    Split,
+}
+
+#[derive(Debug)]
+struct SendError {
+    err: io::Error,
+    suppressed: u64,
+}
+
+impl fmt::Display for SendError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(fmt, "Error sending log event: {}", self.err));
+        if self.suppressed > 0 {
+            try!(write!(fmt, " ({} more errors suppressed)", self.suppressed));
+        }
+        Ok(())
+    }
+}
+
+impl Error for SendError {
+    fn description(&self) -> &str {
+        "Error sending log event"
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        Some(&self.err)
+    }
+}
+
+/// An appender which sends each log event as a UDP datagram.
+///
+/// The socket is non-blocking, so log events are dropped rather than delayed
+/// if they cannot be sent immediately. A single trailing newline is removed
+/// from the encoder's output. Since a dead collector would otherwise produce
+/// an error for every log event, at most one error is reported per minute.
+pub struct UdpAppender {
+    socket: UdpSocket,
+    addr: SocketAddr,
+    mtu: usize,
+    oversize: Oversize,
+    last_error: Option<Instant>,
+    suppressed: u64,
+    encoder: Box<Encode>,
+}
+
+impl Append for UdpAppender {
+    fn append(&mut self, record: &LogRecord) -> Result<(), Box<Error>> {
+        let mut msg = vec![];
+        try!(self.encoder.encode(&mut msg, record));
+        self.send(&msg, Instant::now())
+    }
+
+    fn append_event(&mut self, event: &LogEvent) -> Result<(), Box<Error>> {
+        let mut msg = vec![];
+        try!(self.encoder.encode_event(&mut msg, event));
+        self.send(&msg, Instant::now())
+    }
+
+    fn append_text(&mut self, text: &str) -> Result<(), Box<Error>> {
+        self.send(text.as_bytes(), Instant::now())
+    }
+}
+
+impl UdpAppender {
+    /// Creates a new `UdpAppender` builder for a collector at the specified
+    /// `host:port` address.
+    pub fn builder(addr: &str) -> UdpAppenderBuilder {
+        UdpAppenderBuilder {
+            addr: addr.to_owned(),
+            mtu: DEFAULT_MTU,
+            oversize: Oversize::Truncate,
+            encoder: Box::new(PatternLayout::default()),
+        }
+    }
+
+    fn send(&mut self, msg: &[u8], now: Instant) -> Result<(), Box<Error>> {
+        let mut msg = msg;
+        if msg.ends_with(b"\n") {
+            msg = &msg[..msg.len() - 1];
+            if msg.ends_with(b"\r") {
+                msg = &msg[..msg.len() - 1];
+            }
+        }
+
+        for datagram in split(msg, self.mtu, self.oversize) {
+            if let Err(err) = self.socket.send_to(datagram, &self.addr) {
+                return self.error(err, now);
+            }
+        }
+        Ok(())
+    }
+
+    fn error(&mut self, err: io::Error, now: Instant) -> Result<(), Box<Error>> {
+        let interval = Duration::from_secs(ERROR_INTERVAL_SECS);
+        match self.last_error {
+            Some(last) if now - last < interval => {
+                self.suppressed += 1;
+                Ok(())
+            }
+            _ => {
+                self.last_error = Some(now);
+                let suppressed = self.suppressed;
+                self.suppressed = 0;
+                Err(Box::new(SendError {
+                    err: err,
+                    suppressed: suppressed,
+                }))
+            }
+        }
+    }
+}
+
+// Divides `msg` into chunks of at most `mtu` bytes. Chunks end on UTF-8
+// character boundaries where possible, so that text is not mangled.
+fn split(msg: &[u8], mtu: usize, oversize: Oversize) -> Vec<&[u8]> {
+    let mut datagrams = vec![];
+    let mut rest = msg;
+    loop {
+        if rest.len() <= mtu {
+            datagrams.push(rest);
+            return datagrams;
+        }
+
+        let mut end = mtu;
+        while end > 0 && rest[end] & 0xc0 == 0x80 {
+            end -= 1;
+        }
+        if end == 0 {
+            end = mtu;
+        }
+        datagrams.push(&rest[..end]);
+        if oversize == Oversize::Truncate {
+            return datagrams;
+        }
+        rest = &rest[end..];
+    }
+}
+
+/// A builder for `UdpAppender`s.
+pub struct UdpAppenderBuilder {
+    addr: String,
+    mtu: usize,
+    oversize: Oversize,
+    encoder: Box<Encode>,
+}
+
+impl UdpAppenderBuilder {
+    /// Sets the maximum size of a datagram in bytes.
+    ///
+    /// Defaults to 1472, the largest payload which fits in a single Ethernet
+    /// frame. An MTU of 0 is treated as 1.
+    pub fn mtu(mut self, mtu: usize) -> UdpAppenderBuilder {
+        self.mtu = mtu;
+        self
+    }
+
+    /// Sets the behavior of the appender for log events larger than the MTU.
+    ///
+    /// Defaults to `Oversize::Truncate`.
+    pub fn oversize(mut self, oversize: Oversize) -> UdpAppenderBuilder {
+        self.oversize = oversize;
+        self
+    }
+
+    /// Sets the output pattern for the `UdpAppender`.
+    ///
+    /// This is a shorthand for `encoder(Box::new(pattern))`.
+    pub fn pattern(self, pattern: PatternLayout) -> UdpAppenderBuilder {
+        self.encoder(Box::new(pattern))
+    }
+
+    /// Sets the encoder used to format log events.
+    pub fn encoder(mut self, encoder: Box<Encode>) -> UdpAppenderBuilder {
+        self.encoder = encoder;
+        self
+    }
+
+    /// Consumes the `UdpAppenderBuilder`, producing a `UdpAppender`.
+    ///
+    /// The address is resolved once, here.
+    pub fn build(self) -> io::Result<UdpAppender> {
+        let addr = match try!(self.addr.to_socket_addrs()).next() {
+            Some(addr) => addr,
+            None => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                          "address did not resolve to any addresses"));
+            }
+        };
+        let socket = match addr {
+            SocketAddr::V4(_) => try!(UdpSocket::bind("0.0.0.0:0")),
+            SocketAddr::V6(_) => try!(UdpSocket::bind("[::]:0")),
+        };
+        try!(socket.set_nonblocking(true));
+
+        Ok(UdpAppender {
+            socket: socket,
+            addr: addr,
+            mtu: if self.mtu == 0 { 1 } else { self.mtu },
+            oversize: self.oversize,
+            last_error: None,
+            suppressed: 0,
+            encoder: self.encoder,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io;
+    use std::net::UdpSocket;
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    #[test]
+    fn send() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap().to_string();
+        let mut appender = UdpAppender::builder(&addr)
+            .mtu(4)
+            .oversize(Oversize::Split)
+            .build()
+            .unwrap();
+
+        appender.send(b"hello\n", Instant::now()).unwrap();
+        let mut buf = [0; 16];
+        let len = server.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"hell");
+        let len = server.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"o");
+    }
+
+    #[test]
+    fn split_datagrams() {
+        assert_eq!(split(b"abcdefg", 3, Oversize::Split), [&b"abc"[..], b"def", b"g"]);
+        assert_eq!(split(b"abcdefg", 3, Oversize::Truncate), [&b"abc"[..]]);
+        assert_eq!(split(b"", 3, Oversize::Split), [&b""[..]]);
+        // "é" is two bytes and is not split across datagrams
+        assert_eq!(split("aéb".as_bytes(), 2, Oversize::Split),
+                   [&b"a"[..], "é".as_bytes(), b"b"]);
+    }
+
+    #[test]
+    fn rate_limited_errors() {
+        let mut appender = UdpAppender::builder("127.0.0.1:9").build().unwrap();
+        let now = Instant::now();
+        let err = || io::Error::new(io::ErrorKind::Other, "unreachable");
+
+        assert!(appender.error(err(), now).is_err());
+        assert!(appender.error(err(), now + Duration::from_secs(30)).is_ok());
+        assert!(appender.error(err(), now + Duration::from_secs(59)).is_ok());
+        let err = appender.error(err(), now + Duration::from_secs(61)).unwrap_err();
+        assert_eq!(err.to_string(),
+                   "Error sending log event: unreachable (2 more errors suppressed)");
+    }
+}
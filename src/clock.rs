@@ -0,0 +1,172 @@
+//! Sources of the current time.
+//!
+//! The `%d` pattern specifier and the appenders which roll their files by
+//! date read the time from a `Clock`, which defaults to `SystemClock`. Tests
+//! can supply a `ManualClock` instead to control the time those components
+//! observe.
+//!
+//! The resolution of `SystemClock` depends on the platform: it is a
+//! nanosecond on Linux and most other Unix platforms, a microsecond on macOS,
+//! and 100 nanoseconds on Windows. The system time may be adjusted while the
+//! program runs, so consecutive readings can go backwards. Where the
+//! timestamps of log events are used to order them, a `MonotonicClock` can be
+//! used instead.
+//!
+//! ```
+//! extern crate log4rs;
+//! extern crate time;
+//!
+//! use std::sync::Arc;
+//! use log4rs::clock::ManualClock;
+//! use log4rs::pattern::PatternLayout;
+//!
+//! # fn main() {
+//! let clock = Arc::new(ManualClock::new(time::Timespec::new(1435708800, 0)));
+//! let layout = PatternLayout::new("%d{%Y-%m-%d}{utc} %m").unwrap().clock(clock.clone());
+//! clock.advance(time::Duration::days(1));
+//! # let _ = layout;
+//! # }
+//! ```
+
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use time::{Duration, Timespec};
+
+/// A trait implemented by sources of the current time.
+pub trait Clock: Send + Sync + 'static {
+    /// Returns the current time.
+    fn now(&self) -> Timespec;
+}
+
+impl fmt::Debug for Clock {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "Clock {{ now: {:?} }}", self.now())
+    }
+}
+
+/// A clock which reads the system's real time.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct SystemClock(());
+
+impl SystemClock {
+    /// Creates a new `SystemClock`.
+    pub fn new() -> SystemClock {
+        SystemClock(())
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Timespec {
+        let epoch = Timespec::new(0, 0);
+        match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(since) => epoch + Duration::from_std(since).unwrap(),
+            Err(err) => epoch - Duration::from_std(err.duration()).unwrap(),
+        }
+    }
+}
+
+/// A clock which reads the system's real time when it is created, and
+/// advances with the system's monotonic clock from then on.
+///
+/// Its readings never go backwards, and have a resolution of a nanosecond on
+/// Unix platforms and 100 nanoseconds or better on Windows, so the timestamps
+/// of log events written through it order them exactly. Adjustments to the
+/// system time made after the clock is created are not reflected in its
+/// readings, so it gradually drifts from the system time.
+#[derive(Debug, Copy, Clone)]
+pub struct MonotonicClock {
+    base: Timespec,
+    start: Instant,
+}
+
+impl MonotonicClock {
+    /// Creates a new `MonotonicClock` starting at the current system time.
+    pub fn new() -> MonotonicClock {
+        MonotonicClock {
+            base: SystemClock::new().now(),
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Default for MonotonicClock {
+    fn default() -> MonotonicClock {
+        MonotonicClock::new()
+    }
+}
+
+impl Clock for MonotonicClock {
+    fn now(&self) -> Timespec {
+        self.base + Duration::from_std(self.start.elapsed()).unwrap()
+    }
+}
+
+/// A clock which only moves when it is told to.
+///
+/// It is shared with the components using it through an `Arc`, so that it can
+/// be set or advanced after they have been built.
+#[derive(Debug)]
+pub struct ManualClock {
+    now: Mutex<Timespec>,
+}
+
+impl ManualClock {
+    /// Creates a new `ManualClock` stopped at the specified time.
+    pub fn new(now: Timespec) -> ManualClock {
+        ManualClock { now: Mutex::new(now) }
+    }
+
+    /// Sets the time reported by the clock.
+    pub fn set(&self, now: Timespec) {
+        *self.now.lock().unwrap() = now;
+    }
+
+    /// Moves the time reported by the clock forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        let mut now = self.now.lock().unwrap();
+        *now = *now + duration;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Timespec {
+        *self.now.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use time::{self, Duration, Timespec};
+
+    use super::*;
+
+    #[test]
+    fn system() {
+        let before = time::get_time();
+        let now = SystemClock::new().now();
+        assert!(now >= before - Duration::seconds(1) && now <= before + Duration::seconds(1));
+    }
+
+    #[test]
+    fn monotonic() {
+        let clock = MonotonicClock::new();
+        let mut last = clock.now();
+        assert!((last - SystemClock::new().now()).num_seconds().abs() <= 1);
+        for _ in 0..1000 {
+            let now = clock.now();
+            assert!(now >= last);
+            last = now;
+        }
+    }
+
+    #[test]
+    fn manual() {
+        let clock = ManualClock::new(Timespec::new(100, 0));
+        assert_eq!(clock.now(), Timespec::new(100, 0));
+        clock.advance(Duration::seconds(5));
+        assert_eq!(clock.now(), Timespec::new(105, 0));
+        clock.set(Timespec::new(7, 0));
+        assert_eq!(clock.now(), Timespec::new(7, 0));
+    }
+}
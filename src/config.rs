@@ -1,10 +1,31 @@
 //! log4rs configuration
+//!
+//! # Examples
+//!
+//! ```
+//! # extern crate log;
+//! # extern crate log4rs;
+//! use log::LogLevelFilter;
+//! use log4rs::appender::ConsoleAppender;
+//! use log4rs::config::{Appender, Config, Logger, Root};
+//!
+//! # fn main() {
+//! let stdout = ConsoleAppender::builder().build();
+//!
+//! let config = Config::builder(Root::builder(LogLevelFilter::Warn).appender("stdout").build())
+//!     .appender(Appender::builder("stdout", Box::new(stdout)).build())
+//!     .logger(Logger::builder("app::backend::db", LogLevelFilter::Info).build())
+//!     .build()
+//!     .unwrap();
+//! # let _ = config;
+//! # }
+//! ```
 
 use std::collections::HashSet;
 use std::fmt;
 use std::iter::IntoIterator;
 use std::error;
-use log::LogLevelFilter;
+use log::{LogLevel, LogLevelFilter};
 
 use {Append, Filter, ConfigPrivateExt, PrivateConfigErrorsExt, PrivateConfigAppenderExt};
 
@@ -41,8 +62,8 @@ pub struct RootBuilder(Root);
 
 impl RootBuilder {
     /// Adds an appender.
-    pub fn appender(mut self, appender: String) -> RootBuilder {
-        self.0.appenders.push(appender);
+    pub fn appender<N: Into<String>>(mut self, appender: N) -> RootBuilder {
+        self.0.appenders.push(appender.into());
         self
     }
 
@@ -58,11 +79,32 @@ impl RootBuilder {
     }
 }
 
+/// What happens when an appender fails to write a log event.
+///
+/// Errors are always passed to the error handler set by
+/// `log4rs::set_error_handler`, but a broken appender would otherwise report
+/// an error for every log event.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Report every error and continue to use the appender.
+    Ignore,
+    /// Report an error, then discard log events sent to the appender for a
+    /// period which doubles with each consecutive failure, from one second up
+    /// to one minute. A successful write resets the period.
+    Backoff,
+    /// Report the first error and stop using the appender.
+    Disable,
+}
+
 /// Configuration for an appender.
 pub struct Appender {
     name: String,
-    appender: Box<Append>,
+    // `None` for an appender to be taken over from the current configuration
+    appender: Option<Box<Append>>,
     filters: Vec<Box<Filter>>,
+    on_error: ErrorPolicy,
+    required: bool,
+    identity: Option<String>,
 }
 
 impl fmt::Debug for Appender {
@@ -73,11 +115,14 @@ impl fmt::Debug for Appender {
 
 impl Appender {
     /// Creates a new `AppenderBuilder` with the specified name and `Append` trait object.
-    pub fn builder(name: String, appender: Box<Append>) -> AppenderBuilder {
+    pub fn builder<N: Into<String>>(name: N, appender: Box<Append>) -> AppenderBuilder {
         AppenderBuilder(Appender {
-            name: name,
-            appender: appender,
+            name: name.into(),
+            appender: Some(appender),
             filters: vec![],
+            on_error: ErrorPolicy::Ignore,
+            required: false,
+            identity: None,
         })
     }
 
@@ -87,20 +132,57 @@ impl Appender {
     }
 
     /// Returns the appender.
-    pub fn appender(&self) -> &Append {
-        &*self.appender
+    ///
+    /// `None` is returned for an appender left unchanged by a reloaded config
+    /// file, which is taken over from the logger's current configuration
+    /// rather than created again.
+    pub fn appender(&self) -> Option<&Append> {
+        self.appender.as_ref().map(|a| &**a)
     }
 
     /// Returns the filters attached to the appender.
     pub fn filters(&self) -> &[Box<Filter>] {
         &self.filters
     }
+
+    /// Returns the policy applied when the appender fails.
+    pub fn on_error(&self) -> ErrorPolicy {
+        self.on_error
+    }
+
+    /// Determines if a failure of the appender stops a log event from being
+    /// passed to the remaining appenders.
+    pub fn required(&self) -> bool {
+        self.required
+    }
+
+    /// Returns the identity of the appender's settings, if it has one.
+    pub fn identity(&self) -> Option<&str> {
+        self.identity.as_ref().map(|s| &**s)
+    }
 }
 
 impl PrivateConfigAppenderExt for Appender {
-    fn unpack(self) -> (String, Box<Append>, Vec<Box<Filter>>) {
-        let Appender { name, appender, filters } = self;
-        (name, appender, filters)
+    fn reused(name: String) -> AppenderBuilder {
+        AppenderBuilder(Appender {
+            name: name,
+            appender: None,
+            filters: vec![],
+            on_error: ErrorPolicy::Ignore,
+            required: false,
+            identity: None,
+        })
+    }
+
+    fn unpack(self)
+              -> (String,
+                  Option<Box<Append>>,
+                  Vec<Box<Filter>>,
+                  ErrorPolicy,
+                  bool,
+                  Option<String>) {
+        let Appender { name, appender, filters, on_error, required, identity } = self;
+        (name, appender, filters, on_error, required, identity)
     }
 }
 
@@ -121,6 +203,41 @@ impl AppenderBuilder {
         self
     }
 
+    /// Sets the policy applied when the appender fails.
+    ///
+    /// Defaults to `ErrorPolicy::Ignore`.
+    pub fn on_error(mut self, on_error: ErrorPolicy) -> AppenderBuilder {
+        self.0.on_error = on_error;
+        self
+    }
+
+    /// Marks the appender as required.
+    ///
+    /// A log event is passed to the appenders of its logger in order: first
+    /// those listed for the logger itself, then those inherited from its
+    /// parent. Each appender finishes writing the log event before the next
+    /// is called. If a required appender fails to write a log event, the
+    /// error is reported and the log event is not passed to the appenders
+    /// after it. Log events which a required appender skips because of its
+    /// error policy or filters are still passed on. Defaults to `false`.
+    pub fn required(mut self, required: bool) -> AppenderBuilder {
+        self.0.required = required;
+        self
+    }
+
+    /// Sets an identity describing the appender's settings.
+    ///
+    /// When a `Handle` replaces the logger's configuration, an appender with
+    /// the same name and identity as one already in use is discarded, and the
+    /// existing appender is kept along with its open files and buffered
+    /// output. Appenders created from config files are given an identity
+    /// derived from their settings. Defaults to none, in which case the
+    /// appender is always replaced.
+    pub fn identity<I: Into<String>>(mut self, identity: I) -> AppenderBuilder {
+        self.0.identity = Some(identity.into());
+        self
+    }
+
     /// Consumes the `AppenderBuilder`, returning the `Appender`.
     pub fn build(self) -> Appender {
         self.0
@@ -128,6 +245,29 @@ impl AppenderBuilder {
 }
 
 /// Configuration for a logger.
+///
+/// Log events are matched to loggers by their target. The target defaults to
+/// the module path of the logging statement, but may be set explicitly, for
+/// example with `info!(target: "http::access", ...)`, so that events can be
+/// configured separately from the module which produced them.
+///
+/// An event is handled by the logger with the longest name matching its
+/// target. A logger named `hyper` matches the target `hyper` and all targets
+/// below it, such as `hyper::client::pool`. A logger whose name ends in `::*`
+/// matches only the targets below its prefix, and takes precedence over the
+/// prefix's own logger for them. The most specific logger always wins, so
+/// given loggers named `hyper`, `hyper::*`, and `hyper::client`:
+///
+/// * `hyper` handles events with the target `hyper`.
+/// * `hyper::client` handles events with the targets `hyper::client` and
+///     `hyper::client::pool`.
+/// * `hyper::*` handles all other events below `hyper`, such as
+///     `hyper::server`.
+///
+/// A wildcard logger is treated as the parent of its siblings, so with
+/// additivity `hyper::client` inherits the appenders of `hyper::*`, which in
+/// turn inherits those of `hyper`. A logger named `*` matches every target
+/// which has no more specific logger, but not the root logger.
 #[derive(Debug)]
 pub struct Logger {
     /// The name of the logger.
@@ -144,9 +284,9 @@ impl Logger {
     /// Creates a new `LoggerBuilder` with the specified name and level.
     ///
     /// There are initially no appenders attached and `additive` is `true`.
-    pub fn builder(name: String, level: LogLevelFilter) -> LoggerBuilder {
+    pub fn builder<N: Into<String>>(name: N, level: LogLevelFilter) -> LoggerBuilder {
         LoggerBuilder(Logger {
-            name: name,
+            name: name.into(),
             level: level,
             appenders: vec![],
             additive: true,
@@ -180,8 +320,8 @@ pub struct LoggerBuilder(Logger);
 
 impl LoggerBuilder {
     /// Adds an appender.
-    pub fn appender(mut self, appender: String) -> LoggerBuilder {
-        self.0.appenders.push(appender);
+    pub fn appender<N: Into<String>>(mut self, appender: N) -> LoggerBuilder {
+        self.0.appenders.push(appender.into());
         self
     }
 
@@ -192,6 +332,26 @@ impl LoggerBuilder {
     }
 
     /// Sets the additivity of the logger.
+    ///
+    /// An additive logger uses the appenders of its parent in addition to its
+    /// own. Since the parent's appenders include those it inherited in turn,
+    /// an additive logger uses the appenders of the whole chain of loggers
+    /// above it, up to and including the root logger or the nearest
+    /// non-additive ancestor. The parent is the closest configured ancestor.
+    /// For example, with additive loggers `a` and `a::b::c` and a
+    /// non-additive logger `a::b`, events for `a::b::c` are written to the
+    /// appenders of `a::b::c` and `a::b`, but not of `a` or the root.
+    ///
+    /// A non-additive logger's own appenders replace the inherited set
+    /// entirely. If it has no appenders of its own, log events sent to it are
+    /// discarded, as are those sent to its descendants unless they have
+    /// appenders of their own. This can be used to silence a noisy module
+    /// while leaving its level in place for its descendants to inherit.
+    /// Additivity only affects appenders: a logger always uses its own level,
+    /// and descendants which are not configured inherit both its level and
+    /// its final set of appenders.
+    ///
+    /// Defaults to `true`.
     pub fn additive(mut self, additive: bool) -> LoggerBuilder {
         self.0.additive = additive;
         self
@@ -214,11 +374,14 @@ pub struct Config {
 impl Config {
     /// Creates a new `ConfigBuilder` with the specified `Root`.
     pub fn builder(root: Root) -> ConfigBuilder {
-        ConfigBuilder(Config {
-            appenders: vec![],
-            root: root,
-            loggers: vec![],
-        })
+        ConfigBuilder {
+            config: Config {
+                appenders: vec![],
+                root: root,
+                loggers: vec![],
+            },
+            attachments: vec![],
+        }
     }
 
     /// Returns the `Appender`s associated with the `Config`.
@@ -238,30 +401,50 @@ impl Config {
 }
 
 /// A builder for `Config`s.
-pub struct ConfigBuilder(Config);
+pub struct ConfigBuilder {
+    config: Config,
+    // (appender, logger) pairs added by `attach`
+    attachments: Vec<(String, String)>,
+}
 
 impl ConfigBuilder {
     /// Adds an appender.
     pub fn appender(mut self, appender: Appender) -> ConfigBuilder {
-        self.0.appenders.push(appender);
+        self.config.appenders.push(appender);
         self
     }
 
     /// Adds appenders.
     pub fn appenders<I: IntoIterator<Item=Appender>>(mut self, appenders: I) -> ConfigBuilder {
-        self.0.appenders.extend(appenders);
+        self.config.appenders.extend(appenders);
         self
     }
 
+    /// Adds an appender and attaches it to each of the named loggers.
+    ///
+    /// This is equivalent to adding the appender's name to each logger, and
+    /// the loggers may be added before or after the appender. A single
+    /// instance of the appender is shared by all of them. It is an error for
+    /// a named logger not to exist.
+    pub fn attach<I, N>(mut self, appender: Appender, loggers: I) -> ConfigBuilder
+        where I: IntoIterator<Item=N>,
+              N: Into<String>
+    {
+        for logger in loggers {
+            self.attachments.push((appender.name.clone(), logger.into()));
+        }
+        self.appender(appender)
+    }
+
     /// Adds a logger.
     pub fn logger(mut self, logger: Logger) -> ConfigBuilder {
-        self.0.loggers.push(logger);
+        self.config.loggers.push(logger);
         self
     }
 
     /// Adds loggers.
     pub fn loggers<I: IntoIterator<Item=Logger>>(mut self, loggers: I) -> ConfigBuilder {
-        self.0.loggers.extend(loggers);
+        self.config.loggers.extend(loggers);
         self
     }
 
@@ -272,7 +455,19 @@ impl ConfigBuilder {
     pub fn build_lossy(self) -> (Config, Result<(), Errors>) {
         let mut errors = vec![];
 
-        let Config { appenders, mut root, loggers } = self.0;
+        let Config { appenders, mut root, mut loggers } = self.config;
+
+        for (appender, name) in self.attachments {
+            match loggers.iter_mut().find(|logger| logger.name == name) {
+                Some(logger) => logger.appenders.push(appender),
+                None => {
+                    errors.push(Error::NonexistentLogger {
+                        appender: appender,
+                        logger: name,
+                    })
+                }
+            }
+        }
 
         let mut ok_appenders = vec![];
         let mut appender_names = HashSet::new();
@@ -286,10 +481,16 @@ impl ConfigBuilder {
 
         let mut ok_root_appenders = vec![];
         for appender in root.appenders {
+            if ok_root_appenders.contains(&appender) {
+                continue;
+            }
             if appender_names.contains(&appender) {
                 ok_root_appenders.push(appender);
             } else {
-                errors.push(Error::NonexistentAppender(appender));
+                errors.push(Error::NonexistentAppender {
+                    logger: None,
+                    appender: appender,
+                });
             }
         }
         root.appenders = ok_root_appenders;
@@ -309,10 +510,16 @@ impl ConfigBuilder {
 
             let mut ok_logger_appenders = vec![];
             for appender in logger.appenders {
+                if ok_logger_appenders.contains(&appender) {
+                    continue;
+                }
                 if appender_names.contains(&appender) {
                     ok_logger_appenders.push(appender);
                 } else {
-                    errors.push(Error::NonexistentAppender(appender));
+                    errors.push(Error::NonexistentAppender {
+                        logger: Some(logger.name.clone()),
+                        appender: appender,
+                    });
                 }
             }
             logger.appenders = ok_logger_appenders;
@@ -347,6 +554,13 @@ fn check_logger_name(name: &str) -> Result<(), Error> {
         return Err(Error::InvalidLoggerName(name.to_string()));
     }
 
+    // a wildcard may only appear as the entire final segment
+    if let Some(idx) = name.find('*') {
+        if idx != name.len() - 1 || !(idx == 0 || name[..idx].ends_with("::")) {
+            return Err(Error::InvalidLoggerName(name.to_string()));
+        }
+    }
+
     let mut streak = 0;
     for ch in name.chars() {
         if ch != ':' {
@@ -415,8 +629,20 @@ impl PrivateConfigErrorsExt for Errors {
 pub enum Error {
     /// Multiple appenders were registered with the same name.
     DuplicateAppenderName(String),
-    /// A reference to a nonexistant appender.
-    NonexistentAppender(String),
+    /// A logger referenced a nonexistent appender.
+    NonexistentAppender {
+        /// The name of the logger, or `None` for the root logger.
+        logger: Option<String>,
+        /// The name of the appender.
+        appender: String,
+    },
+    /// An appender was attached to a nonexistent logger.
+    NonexistentLogger {
+        /// The name of the appender.
+        appender: String,
+        /// The name of the logger.
+        logger: String,
+    },
     /// Multiple loggers were registered with the same name.
     DuplicateLoggerName(String),
     /// A logger name was invalid.
@@ -427,10 +653,22 @@ impl fmt::Display for Error {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             Error::DuplicateAppenderName(ref n) => write!(fmt, "Duplicate appender name `{}`", n),
-            Error::NonexistentAppender(ref n) => {
-                write!(fmt, "Reference to nonexistent appender: `{}`", n)
+            Error::NonexistentAppender { logger: None, ref appender } => {
+                write!(fmt, "Root logger references nonexistent appender `{}`", appender)
+            }
+            Error::NonexistentAppender { logger: Some(ref logger), ref appender } => {
+                write!(fmt, "Logger `{}` references nonexistent appender `{}`", logger, appender)
+            }
+            Error::NonexistentLogger { ref appender, ref logger } => {
+                write!(fmt,
+                       "Appender `{}` is attached to nonexistent logger `{}`",
+                       appender,
+                       logger)
             }
             Error::DuplicateLoggerName(ref n) => write!(fmt, "Duplicate logger name `{}`", n),
+            Error::InvalidLoggerName(ref n) if n.is_empty() => {
+                fmt.write_str("Logger names must not be empty")
+            }
             Error::InvalidLoggerName(ref n) => write!(fmt, "Invalid logger name `{}`", n),
         }
     }
@@ -442,8 +680,156 @@ impl error::Error for Error {
     }
 }
 
+/// An error parsing a log level.
+#[derive(Debug)]
+pub struct ParseLevelError {
+    level: String,
+    filter: bool,
+}
+
+impl fmt::Display for ParseLevelError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(fmt, "unknown level \"{}\", expected one of ", self.level));
+        if self.filter {
+            try!(write!(fmt, "\"off\", "));
+        }
+        write!(fmt, "\"error\", \"warn\", \"info\", \"debug\" or \"trace\"")
+    }
+}
+
+impl error::Error for ParseLevelError {
+    fn description(&self) -> &str {
+        "unknown level"
+    }
+}
+
+/// Parses a level filter, as accepted by config files.
+///
+/// Parsing ignores case, and `"warning"` and `"err"` are accepted as
+/// alternate spellings of `"warn"` and `"error"`.
+pub fn parse_level_filter(level: &str) -> Result<LogLevelFilter, ParseLevelError> {
+    if level.eq_ignore_ascii_case("off") {
+        return Ok(LogLevelFilter::Off);
+    }
+
+    match parse_level(level) {
+        Ok(level) => Ok(level.to_log_level_filter()),
+        Err(err) => {
+            Err(ParseLevelError {
+                level: err.level,
+                filter: true,
+            })
+        }
+    }
+}
+
+/// Parses a level, as accepted by config files.
+///
+/// This behaves like `parse_level_filter`, except that `"off"` is rejected.
+pub fn parse_level(level: &str) -> Result<LogLevel, ParseLevelError> {
+    match &*level.to_ascii_lowercase() {
+        "error" | "err" => Ok(LogLevel::Error),
+        "warn" | "warning" => Ok(LogLevel::Warn),
+        "info" => Ok(LogLevel::Info),
+        "debug" => Ok(LogLevel::Debug),
+        "trace" => Ok(LogLevel::Trace),
+        _ => {
+            Err(ParseLevelError {
+                level: level.to_owned(),
+                filter: false,
+            })
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
+    use std::error;
+    use log::{LogLevel, LogLevelFilter, LogRecord};
+
+    use Append;
+    use super::*;
+
+    #[test]
+    fn level_parsing() {
+        assert_eq!(parse_level_filter("WARNING").unwrap(), LogLevelFilter::Warn);
+        assert_eq!(parse_level_filter("Warn").unwrap(), LogLevelFilter::Warn);
+        assert_eq!(parse_level_filter("OFF").unwrap(), LogLevelFilter::Off);
+        assert_eq!(parse_level("err").unwrap(), LogLevel::Error);
+        assert_eq!(parse_level("TRACE").unwrap(), LogLevel::Trace);
+        assert!(parse_level("off").is_err());
+
+        let err = parse_level_filter("wran").unwrap_err();
+        assert_eq!(err.to_string(),
+                   "unknown level \"wran\", expected one of \"off\", \"error\", \"warn\", \
+                    \"info\", \"debug\" or \"trace\"");
+    }
+
+    struct NopAppender;
+
+    impl Append for NopAppender {
+        fn append(&mut self, _: &LogRecord) -> Result<(), Box<error::Error>> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn validation() {
+        let root = Root::builder(LogLevelFilter::Warn).appender("a").appender("missing").build();
+        let (config, errors) = Config::builder(root)
+            .appender(Appender::builder("a", Box::new(NopAppender)).build())
+            .appender(Appender::builder("a", Box::new(NopAppender)).build())
+            .logger(Logger::builder("foo", LogLevelFilter::Info).appender("a").build())
+            .logger(Logger::builder("foo", LogLevelFilter::Info).build())
+            .logger(Logger::builder("bar", LogLevelFilter::Info).appender("gone").build())
+            .build_lossy();
+
+        let errors = errors.unwrap_err();
+        match errors.errors() {
+            [Error::DuplicateAppenderName(ref a),
+             Error::NonexistentAppender { logger: None, appender: ref missing },
+             Error::DuplicateLoggerName(ref foo),
+             Error::NonexistentAppender { logger: Some(ref bar), appender: ref gone }] => {
+                assert_eq!(a, "a");
+                assert_eq!(missing, "missing");
+                assert_eq!(foo, "foo");
+                assert_eq!(bar, "bar");
+                assert_eq!(gone, "gone");
+            }
+            errors => panic!("unexpected errors {:?}", errors),
+        }
+
+        assert_eq!(config.appenders().len(), 1);
+        assert_eq!(config.root().appenders(), &["a".to_string()]);
+        assert_eq!(config.loggers().len(), 2);
+        assert!(config.loggers()[1].appenders().is_empty());
+
+        assert_eq!(errors.errors()[1].to_string(),
+                   "Root logger references nonexistent appender `missing`");
+        assert_eq!(errors.errors()[3].to_string(),
+                   "Logger `bar` references nonexistent appender `gone`");
+    }
+
+    #[test]
+    fn attach() {
+        let root = Root::builder(LogLevelFilter::Warn).appender("a").appender("a").build();
+        let (config, errors) = Config::builder(root)
+            .logger(Logger::builder("foo", LogLevelFilter::Info).appender("a").build())
+            .attach(Appender::builder("a", Box::new(NopAppender)).build(),
+                    vec!["foo", "bar", "missing"])
+            .logger(Logger::builder("bar", LogLevelFilter::Info).build())
+            .build_lossy();
+
+        let errors = errors.unwrap_err();
+        assert_eq!(errors.errors().len(), 1);
+        assert_eq!(errors.errors()[0].to_string(),
+                   "Appender `a` is attached to nonexistent logger `missing`");
+        assert_eq!(config.appenders().len(), 1);
+        assert_eq!(config.root().appenders(), &["a".to_string()]);
+        assert_eq!(config.loggers()[0].appenders(), &["a".to_string()]);
+        assert_eq!(config.loggers()[1].appenders(), &["a".to_string()]);
+    }
+
     #[test]
     fn check_logger_name() {
         let tests = [
@@ -455,6 +841,12 @@ mod test {
             ("asdf:jkl", false),
             ("asdf:::jkl", false),
             ("asdf::jkl::", false),
+            ("*", true),
+            ("asdf::*", true),
+            ("asdf::*::jkl", false),
+            ("asdf*", false),
+            ("asdf::j*", false),
+            ("asdf::**", false),
         ];
 
         for &(ref name, expected) in &tests {
@@ -0,0 +1,376 @@
+//! Encoders which format `LogRecord`s for output.
+//!
+//! `PatternLayout` in the `pattern` module is also an encoder.
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::io::prelude::*;
+use std::str::FromStr;
+use log::{LogLevel, LogRecord};
+use time::{self, Tm};
+
+use {Encode, LogEvent, Location, write_json_escaped};
+use mdc;
+
+/// The names of the fields written by `JsonEncoder` for every log event.
+const JSON_FIELDS: [&'static str; 7] =
+    ["timestamp", "level", "target", "module", "file", "line", "message"];
+
+/// A terminator for encoded log events.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LineEnding {
+    /// `\n`
+    Lf,
+    /// `\r\n`
+    CrLf,
+}
+
+impl LineEnding {
+    /// Returns the terminator as a string.
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+}
+
+/// An error returned when parsing a `LineEnding` fails.
+#[derive(Debug)]
+pub struct ParseLineEndingError(String);
+
+impl fmt::Display for ParseLineEndingError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "Invalid line ending `{}`", self.0)
+    }
+}
+
+impl error::Error for ParseLineEndingError {
+    fn description(&self) -> &str {
+        "Invalid line ending"
+    }
+}
+
+impl FromStr for LineEnding {
+    type Err = ParseLineEndingError;
+
+    /// Parses `lf` or `crlf`, ignoring case.
+    fn from_str(s: &str) -> Result<LineEnding, ParseLineEndingError> {
+        match &*s.to_lowercase() {
+            "lf" => Ok(LineEnding::Lf),
+            "crlf" => Ok(LineEnding::CrLf),
+            _ => Err(ParseLineEndingError(s.to_owned())),
+        }
+    }
+}
+
+/// An encoder which ensures that each log event produced by another encoder
+/// ends with exactly one terminator.
+///
+/// Any line terminators at the end of the wrapped encoder's output are
+/// replaced by the configured `LineEnding`, and one is added if there were
+/// none.
+pub struct LineEndingEncoder {
+    encoder: Box<Encode>,
+    line_ending: LineEnding,
+}
+
+impl LineEndingEncoder {
+    /// Creates a new `LineEndingEncoder` wrapping the specified encoder.
+    pub fn new(encoder: Box<Encode>, line_ending: LineEnding) -> LineEndingEncoder {
+        LineEndingEncoder {
+            encoder: encoder,
+            line_ending: line_ending,
+        }
+    }
+}
+
+impl Encode for LineEndingEncoder {
+    fn encode(&self, w: &mut Write, record: &LogRecord) -> io::Result<()> {
+        let mut buf = vec![];
+        try!(self.encoder.encode(&mut buf, record));
+        write_terminated(w, &buf, self.line_ending)
+    }
+
+    fn encode_event(&self, w: &mut Write, event: &LogEvent) -> io::Result<()> {
+        let mut buf = vec![];
+        try!(self.encoder.encode_event(&mut buf, event));
+        write_terminated(w, &buf, self.line_ending)
+    }
+}
+
+fn write_terminated(w: &mut Write, buf: &[u8], line_ending: LineEnding) -> io::Result<()> {
+    let end = buf.iter().rposition(|&b| b != b'\n' && b != b'\r').map_or(0, |i| i + 1);
+    try!(w.write_all(&buf[..end]));
+    w.write_all(line_ending.as_str().as_bytes())
+}
+
+/// The marker written in place of the end of a truncated log event.
+pub const TRUNCATION_MARKER: &'static str = "...";
+
+/// An encoder which limits the length of each log event produced by another
+/// encoder.
+///
+/// Output longer than `max_len` bytes, not counting any line terminators at
+/// its end, is cut to at most `max_len` bytes and followed by
+/// `TRUNCATION_MARKER` and the original terminators. The cut never splits a
+/// UTF-8 encoded character.
+pub struct TruncatingEncoder {
+    encoder: Box<Encode>,
+    max_len: usize,
+}
+
+impl TruncatingEncoder {
+    /// Creates a new `TruncatingEncoder` wrapping the specified encoder.
+    pub fn new(encoder: Box<Encode>, max_len: usize) -> TruncatingEncoder {
+        TruncatingEncoder {
+            encoder: encoder,
+            max_len: max_len,
+        }
+    }
+}
+
+impl Encode for TruncatingEncoder {
+    fn encode(&self, w: &mut Write, record: &LogRecord) -> io::Result<()> {
+        let mut buf = vec![];
+        try!(self.encoder.encode(&mut buf, record));
+        write_truncated(w, &buf, self.max_len)
+    }
+
+    fn encode_event(&self, w: &mut Write, event: &LogEvent) -> io::Result<()> {
+        let mut buf = vec![];
+        try!(self.encoder.encode_event(&mut buf, event));
+        write_truncated(w, &buf, self.max_len)
+    }
+}
+
+fn write_truncated(w: &mut Write, buf: &[u8], max_len: usize) -> io::Result<()> {
+    let end = buf.iter().rposition(|&b| b != b'\n' && b != b'\r').map_or(0, |i| i + 1);
+    if end <= max_len {
+        return w.write_all(buf);
+    }
+
+    // back up to the start of a character
+    let mut cut = max_len;
+    while cut > 0 && buf[cut] & 0xc0 == 0x80 {
+        cut -= 1;
+    }
+    try!(w.write_all(&buf[..cut]));
+    try!(w.write_all(TRUNCATION_MARKER.as_bytes()));
+    w.write_all(&buf[end..])
+}
+
+/// An encoder which writes each log event as a single-line JSON object.
+///
+/// The object has the following fields:
+///
+/// * `timestamp` - The current time in the ISO 8601 format.
+/// * `level` - The log level.
+/// * `target` - The target of the log message.
+/// * `module` - The module that the log message came from.
+/// * `file` - The source file that the log message came from.
+/// * `line` - The line that the log message came from, as a number.
+/// * `message` - The log message.
+///
+/// Each value in the mapped diagnostic context of the thread that the log
+/// message came from is written as an additional string field, in order of
+/// key. A key which is the name of one of the fields above is prefixed with
+/// `mdc.` so that it does not replace the field: a `level` key is written as
+/// `mdc.level`.
+#[derive(Debug, Default)]
+pub struct JsonEncoder(());
+
+impl JsonEncoder {
+    /// Creates a new `JsonEncoder`.
+    pub fn new() -> JsonEncoder {
+        JsonEncoder(())
+    }
+
+    fn encode_inner(&self,
+                    w: &mut Write,
+                    time: &Tm,
+                    level: LogLevel,
+                    target: &str,
+                    location: &Location,
+                    args: &fmt::Arguments,
+                    fields: &[(String, String)])
+                    -> io::Result<()> {
+        try!(write!(w, "{{\"timestamp\":\"{}\",\"level\":", time.rfc3339()));
+        try!(write_json_str(w, &level.to_string()));
+        try!(write!(w, ",\"target\":"));
+        try!(write_json_str(w, target));
+        try!(write!(w, ",\"module\":"));
+        try!(write_json_str(w, location.module_path));
+        try!(write!(w, ",\"file\":"));
+        try!(write_json_str(w, location.file));
+        try!(write!(w, ",\"line\":{},\"message\":", location.line));
+        try!(write_json_str(w, &args.to_string()));
+        for &(ref key, ref value) in fields {
+            try!(w.write_all(b","));
+            if JSON_FIELDS.contains(&&**key) {
+                try!(write_json_str(w, &format!("mdc.{}", key)));
+            } else {
+                try!(write_json_str(w, key));
+            }
+            try!(w.write_all(b":"));
+            try!(write_json_str(w, value));
+        }
+        writeln!(w, "}}")
+    }
+}
+
+impl Encode for JsonEncoder {
+    fn encode(&self, w: &mut Write, record: &LogRecord) -> io::Result<()> {
+        let location = Location::from_record(record);
+        self.encode_inner(w, &time::now(), record.level(), record.target(), &location,
+                          record.args(), &mdc::entries())
+    }
+
+    fn encode_event(&self, w: &mut Write, event: &LogEvent) -> io::Result<()> {
+        let location = Location::from_event(event);
+        self.encode_inner(w, &time::at(event.time()), event.level(), event.target(), &location,
+                          &format_args!("{}", event.message()), event.mdc_entries())
+    }
+}
+
+/// An encoder which writes only the message of each log event, followed by a
+/// newline.
+///
+/// No other information about the log event is included, and the message is
+/// not escaped. This is intended for output consumed by other programs, which
+/// carry any metadata they need in the messages themselves.
+#[derive(Debug, Default)]
+pub struct RawEncoder(());
+
+impl RawEncoder {
+    /// Creates a new `RawEncoder`.
+    pub fn new() -> RawEncoder {
+        RawEncoder(())
+    }
+
+    fn encode_inner(&self, w: &mut Write, args: &fmt::Arguments) -> io::Result<()> {
+        try!(w.write_fmt(*args));
+        w.write_all(b"\n")
+    }
+}
+
+impl Encode for RawEncoder {
+    fn encode(&self, w: &mut Write, record: &LogRecord) -> io::Result<()> {
+        self.encode_inner(w, record.args())
+    }
+
+    fn encode_event(&self, w: &mut Write, event: &LogEvent) -> io::Result<()> {
+        self.encode_inner(w, &format_args!("{}", event.message()))
+    }
+}
+
+fn write_json_str(w: &mut Write, s: &str) -> io::Result<()> {
+    try!(w.write_all(b"\""));
+    try!(write_json_escaped(w, s));
+    w.write_all(b"\"")
+}
+
+#[cfg(test)]
+mod test {
+    use log::LogLevel;
+    use time;
+
+    use Location;
+    use super::*;
+    use super::{write_terminated, write_truncated};
+
+    #[test]
+    fn line_ending() {
+        let cases = [("msg", LineEnding::Lf, "msg\n"),
+                     ("msg\n", LineEnding::Lf, "msg\n"),
+                     ("msg\r\n", LineEnding::Lf, "msg\n"),
+                     ("msg\n\n", LineEnding::CrLf, "msg\r\n"),
+                     ("msg", LineEnding::CrLf, "msg\r\n"),
+                     ("a\nb\n", LineEnding::CrLf, "a\nb\r\n"),
+                     ("", LineEnding::Lf, "\n")];
+        for &(input, line_ending, expected) in &cases {
+            let mut buf = vec![];
+            write_terminated(&mut buf, input.as_bytes(), line_ending).unwrap();
+            assert_eq!(String::from_utf8(buf).unwrap(), expected);
+        }
+
+        assert_eq!("CRLF".parse::<LineEnding>().unwrap(), LineEnding::CrLf);
+        assert_eq!("lf".parse::<LineEnding>().unwrap(), LineEnding::Lf);
+        assert!("cr".parse::<LineEnding>().is_err());
+    }
+
+    #[test]
+    fn truncation() {
+        let cases = [("short\n", 5, "short\n"),
+                     ("longer\n", 5, "longe...\n"),
+                     ("longer", 5, "longe..."),
+                     ("caf\u{e9} au lait\r\n", 4, "caf...\r\n"),
+                     ("caf\u{e9} au lait", 5, "caf\u{e9}..."),
+                     ("\u{1f600}\u{1f600}", 3, "..."),
+                     ("abc", 0, "...")];
+        for &(input, max_len, expected) in &cases {
+            let mut buf = vec![];
+            write_truncated(&mut buf, input.as_bytes(), max_len).unwrap();
+            assert_eq!(String::from_utf8(buf).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn json() {
+        static LOCATION: Location<'static> = Location {
+            module_path: "mod path",
+            file: "the file",
+            line: 132,
+        };
+        let time = time::at_utc(time::Timespec::new(1435708800, 0));
+
+        let mut buf = vec![];
+        JsonEncoder::new().encode_inner(&mut buf,
+                                        &time,
+                                        LogLevel::Info,
+                                        "target",
+                                        &LOCATION,
+                                        &format_args!("a \"quoted\"\nmessage\\\u{1}"),
+                                        &[]).unwrap();
+
+        let expected = "{\"timestamp\":\"2015-07-01T00:00:00Z\",\"level\":\"INFO\",\
+                        \"target\":\"target\",\"module\":\"mod path\",\"file\":\"the file\",\
+                        \"line\":132,\"message\":\"a \\\"quoted\\\"\\nmessage\\\\\\u0001\"}\n";
+        assert_eq!(String::from_utf8(buf).unwrap(), expected);
+    }
+
+    #[test]
+    fn json_mdc() {
+        static LOCATION: Location<'static> = Location {
+            module_path: "m",
+            file: "f",
+            line: 1,
+        };
+        let time = time::at_utc(time::Timespec::new(1435708800, 0));
+        let fields = [("level".to_owned(), "custom".to_owned()),
+                      ("request_id".to_owned(), "12\"34".to_owned())];
+
+        let mut buf = vec![];
+        JsonEncoder::new().encode_inner(&mut buf,
+                                        &time,
+                                        LogLevel::Warn,
+                                        "t",
+                                        &LOCATION,
+                                        &format_args!("msg"),
+                                        &fields).unwrap();
+
+        let expected = "{\"timestamp\":\"2015-07-01T00:00:00Z\",\"level\":\"WARN\",\
+                        \"target\":\"t\",\"module\":\"m\",\"file\":\"f\",\"line\":1,\
+                        \"message\":\"msg\",\"mdc.level\":\"custom\",\
+                        \"request_id\":\"12\\\"34\"}\n";
+        assert_eq!(String::from_utf8(buf).unwrap(), expected);
+    }
+
+    #[test]
+    fn raw() {
+        let mut buf = vec![];
+        RawEncoder::new().encode_inner(&mut buf, &format_args!("{} \"raw\"\tmessage", 1)).unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(), "1 \"raw\"\tmessage\n");
+    }
+}
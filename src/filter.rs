@@ -1,9 +1,12 @@
 //! A set of common filters.
-use log::{LogRecord, LogLevelFilter};
+use std::cmp;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use log::{LogLevel, LogRecord, LogLevelFilter};
 
-use {Filter, FilterResponse};
+use {Filter, FilterResponse, LogEvent};
 
 /// A filter that rejects all events at a level below a provided threshold.
+#[derive(Debug)]
 pub struct ThresholdFilter {
     level: LogLevelFilter,
 }
@@ -17,12 +20,243 @@ impl ThresholdFilter {
     }
 }
 
+impl ThresholdFilter {
+    fn filter_level(&self, level: LogLevel) -> FilterResponse {
+        if level > self.level {
+            FilterResponse::Reject
+        } else {
+            FilterResponse::Neutral
+        }
+    }
+}
+
 impl Filter for ThresholdFilter {
     fn filter(&mut self, record: &LogRecord) -> FilterResponse {
-        if record.level() > self.level {
+        self.filter_level(record.level())
+    }
+
+    fn filter_event(&mut self, event: &LogEvent) -> FilterResponse {
+        self.filter_level(event.level())
+    }
+
+    fn max_level(&self) -> Option<LogLevelFilter> {
+        Some(self.level)
+    }
+}
+
+/// A filter that rejects all events at a level outside of a provided range.
+///
+/// The bounds are inclusive, and may be given in either order. For example, a
+/// range from `Warn` to `Error` accepts only warnings and errors, while a range
+/// from `Debug` to `Debug` accepts only debug messages.
+#[derive(Debug)]
+pub struct LevelRangeFilter {
+    min: LogLevel,
+    max: LogLevel,
+}
+
+impl LevelRangeFilter {
+    /// Creates a new `LevelRangeFilter` accepting levels from `min` to `max`.
+    pub fn new(min: LogLevel, max: LogLevel) -> LevelRangeFilter {
+        LevelRangeFilter {
+            min: cmp::min(min, max),
+            max: cmp::max(min, max),
+        }
+    }
+}
+
+impl LevelRangeFilter {
+    fn filter_level(&self, level: LogLevel) -> FilterResponse {
+        if level < self.min || level > self.max {
             FilterResponse::Reject
         } else {
             FilterResponse::Neutral
         }
     }
 }
+
+impl Filter for LevelRangeFilter {
+    fn filter(&mut self, record: &LogRecord) -> FilterResponse {
+        self.filter_level(record.level())
+    }
+
+    fn filter_event(&mut self, event: &LogEvent) -> FilterResponse {
+        self.filter_level(event.level())
+    }
+
+    fn max_level(&self) -> Option<LogLevelFilter> {
+        Some(self.max.to_log_level_filter())
+    }
+}
+
+/// A filter that passes one in every `rate` log events and rejects the rest.
+///
+/// The first log event is passed, followed by every `rate`th one after it.
+/// Passed log events continue on to any remaining filters.
+///
+/// Sampling is per filter, and so per appender: two appenders with their own
+/// `SamplingFilter`s each pass one in `rate` of the log events they see,
+/// which will not in general be the same log events.
+#[derive(Debug)]
+pub struct SamplingFilter {
+    rate: usize,
+    count: AtomicUsize,
+}
+
+impl SamplingFilter {
+    /// Creates a new `SamplingFilter` which passes one in every `rate` log
+    /// events.
+    ///
+    /// A rate of 0 is treated as 1, passing every log event.
+    pub fn new(rate: usize) -> SamplingFilter {
+        SamplingFilter {
+            rate: cmp::max(rate, 1),
+            count: AtomicUsize::new(0),
+        }
+    }
+
+    fn sample(&self) -> FilterResponse {
+        if self.count.fetch_add(1, Ordering::Relaxed) % self.rate == 0 {
+            FilterResponse::Neutral
+        } else {
+            FilterResponse::Reject
+        }
+    }
+}
+
+impl Filter for SamplingFilter {
+    fn filter(&mut self, _: &LogRecord) -> FilterResponse {
+        self.sample()
+    }
+
+    fn filter_event(&mut self, _: &LogEvent) -> FilterResponse {
+        self.sample()
+    }
+}
+
+/// A filter that matches the message of each log event against a substring.
+///
+/// A log event whose message contains the substring receives the `on_match`
+/// response, and any other log event the `on_mismatch` response, which
+/// defaults to `Neutral`. For example, a known-benign warning can be dropped
+/// with a `Reject` response on match, while a `Reject` response on mismatch
+/// keeps only log events mentioning the substring.
+#[derive(Debug)]
+pub struct MessageFilter {
+    substring: String,
+    on_match: FilterResponse,
+    on_mismatch: FilterResponse,
+}
+
+impl MessageFilter {
+    /// Creates a new `MessageFilter` which responds with `on_match` to log
+    /// events whose message contains `substring`.
+    pub fn new(substring: &str, on_match: FilterResponse) -> MessageFilter {
+        MessageFilter {
+            substring: substring.to_owned(),
+            on_match: on_match,
+            on_mismatch: FilterResponse::Neutral,
+        }
+    }
+
+    /// Sets the response to log events whose message does not contain the
+    /// substring.
+    ///
+    /// Defaults to `Neutral`.
+    pub fn on_mismatch(mut self, on_mismatch: FilterResponse) -> MessageFilter {
+        self.on_mismatch = on_mismatch;
+        self
+    }
+
+    fn filter_message(&self, message: &str) -> FilterResponse {
+        if message.contains(&*self.substring) {
+            self.on_match
+        } else {
+            self.on_mismatch
+        }
+    }
+}
+
+impl Filter for MessageFilter {
+    fn filter(&mut self, record: &LogRecord) -> FilterResponse {
+        self.filter_message(&record.args().to_string())
+    }
+
+    fn filter_event(&mut self, event: &LogEvent) -> FilterResponse {
+        self.filter_message(event.message())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::thread;
+    use log::{LogLevel, LogLevelFilter};
+
+    use FilterResponse;
+    use super::*;
+
+    #[test]
+    fn threshold() {
+        let filter = ThresholdFilter::new(LogLevelFilter::Warn);
+        assert_eq!(filter.filter_level(LogLevel::Error), FilterResponse::Neutral);
+        assert_eq!(filter.filter_level(LogLevel::Warn), FilterResponse::Neutral);
+        assert_eq!(filter.filter_level(LogLevel::Info), FilterResponse::Reject);
+        assert_eq!(filter.filter_level(LogLevel::Trace), FilterResponse::Reject);
+
+        let filter = ThresholdFilter::new(LogLevelFilter::Off);
+        assert_eq!(filter.filter_level(LogLevel::Error), FilterResponse::Reject);
+    }
+
+    #[test]
+    fn level_range() {
+        let filter = LevelRangeFilter::new(LogLevel::Warn, LogLevel::Error);
+        assert_eq!(filter.filter_level(LogLevel::Error), FilterResponse::Neutral);
+        assert_eq!(filter.filter_level(LogLevel::Warn), FilterResponse::Neutral);
+        assert_eq!(filter.filter_level(LogLevel::Info), FilterResponse::Reject);
+        assert_eq!(filter.filter_level(LogLevel::Trace), FilterResponse::Reject);
+
+        let filter = LevelRangeFilter::new(LogLevel::Info, LogLevel::Debug);
+        assert_eq!(filter.filter_level(LogLevel::Warn), FilterResponse::Reject);
+        assert_eq!(filter.filter_level(LogLevel::Info), FilterResponse::Neutral);
+        assert_eq!(filter.filter_level(LogLevel::Debug), FilterResponse::Neutral);
+        assert_eq!(filter.filter_level(LogLevel::Trace), FilterResponse::Reject);
+
+        let filter = LevelRangeFilter::new(LogLevel::Debug, LogLevel::Debug);
+        assert_eq!(filter.filter_level(LogLevel::Info), FilterResponse::Reject);
+        assert_eq!(filter.filter_level(LogLevel::Debug), FilterResponse::Neutral);
+        assert_eq!(filter.filter_level(LogLevel::Trace), FilterResponse::Reject);
+    }
+
+    #[test]
+    fn sampling() {
+        let filter = SamplingFilter::new(3);
+        let passed = (0..7).map(|_| filter.sample() == FilterResponse::Neutral).collect::<Vec<_>>();
+        assert_eq!(passed, [true, false, false, true, false, false, true]);
+
+        let filter = SamplingFilter::new(0);
+        assert!((0..5).all(|_| filter.sample() == FilterResponse::Neutral));
+
+        let filter = Arc::new(SamplingFilter::new(10));
+        let threads = (0..4).map(|_| {
+            let filter = filter.clone();
+            thread::spawn(move || {
+                (0..1000).filter(|_| filter.sample() == FilterResponse::Neutral).count()
+            })
+        }).collect::<Vec<_>>();
+        let passed = threads.into_iter().map(|t| t.join().unwrap()).sum::<usize>();
+        assert_eq!(passed, 400);
+    }
+
+    #[test]
+    fn message() {
+        let filter = MessageFilter::new("benign", FilterResponse::Reject);
+        assert_eq!(filter.filter_message("a benign warning"), FilterResponse::Reject);
+        assert_eq!(filter.filter_message("a real warning"), FilterResponse::Neutral);
+
+        let filter = MessageFilter::new("request", FilterResponse::Accept)
+            .on_mismatch(FilterResponse::Reject);
+        assert_eq!(filter.filter_message("request failed"), FilterResponse::Accept);
+        assert_eq!(filter.filter_message("Request failed"), FilterResponse::Reject);
+    }
+}
@@ -52,7 +52,10 @@
 //! well as a `Creator` object which is responsible for instantiating the
 //! various objects specified by the config file. The `toml` module
 //! documentation covers the exact configuration syntax, but an example is
-//! provided below.
+//! provided below. Config files with a `.yml` or `.yaml` extension are
+//! instead parsed as YAML, as described in the `yaml` module. The
+//! `init_from_env` function instead reads the path from an environment
+//! variable, such as `LOG4RS_CONFIG`.
 //!
 //! # Examples
 //!
@@ -91,39 +94,132 @@
 #![doc(html_root_url="https://sfackler.github.io/log4rs/doc/v0.3.3")]
 #![warn(missing_docs)]
 
+extern crate flate2;
+extern crate libc;
+#[macro_use]
 extern crate log;
+#[cfg(feature = "native-tls")]
+extern crate native_tls;
 extern crate time;
 extern crate toml as toml_parser;
+extern crate yaml_rust;
 
+use std::any::Any;
 use std::borrow::ToOwned;
+use std::cell::Cell;
 use std::convert::AsRef;
 use std::cmp;
 use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
 use std::error;
-use std::fs::File;
+use std::fmt;
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
 use std::io;
 use std::io::prelude::*;
+use std::mem;
+use std::panic;
 use std::path::{Path, PathBuf};
-use std::sync::{Mutex, Arc};
+use std::sync::{Mutex, Arc, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::thread;
-use time::Duration;
-use log::{LogLevel, LogMetadata, LogRecord, LogLevelFilter, SetLoggerError, MaxLogLevelFilter};
+use std::time::{Duration as StdDuration, Instant, SystemTime};
+use time::{Duration, Timespec};
+use log::{LogLevel, LogLocation, LogMetadata, LogRecord, LogLevelFilter, SetLoggerError,
+          MaxLogLevelFilter};
 
 use toml::Creator;
 
 pub mod appender;
+pub mod clock;
 pub mod config;
+pub mod encoder;
 pub mod filter;
+pub mod mdc;
 pub mod pattern;
 pub mod toml;
+pub mod yaml;
+
+mod watch;
 
 /// A trait implemented by log4rs appenders.
 pub trait Append: Send + 'static {
     /// Processes the provided `LogRecord`.
     fn append(&mut self, record: &LogRecord) -> Result<(), Box<error::Error>>;
+
+    /// Processes the provided `LogEvent`, which was captured from a
+    /// `LogRecord` on another thread, as by an `AsyncAppender`.
+    ///
+    /// The default implementation returns an error, since the appender has
+    /// no way to write the log event.
+    fn append_event(&mut self, _: &LogEvent) -> Result<(), Box<error::Error>> {
+        Err(Box::new(io::Error::new(io::ErrorKind::Other,
+                                    "appender does not support captured log events")))
+    }
+
+    /// Flushes any buffered output.
+    ///
+    /// The default implementation does nothing.
+    fn flush(&mut self) -> Result<(), Box<error::Error>> {
+        Ok(())
+    }
+
+    /// Writes a line of text which did not come from a log event, such as a
+    /// summary produced by a wrapping appender.
+    ///
+    /// The text is written as-is rather than through the appender's encoder.
+    /// The default implementation discards it.
+    fn append_text(&mut self, _: &str) -> Result<(), Box<error::Error>> {
+        Ok(())
+    }
+
+    /// Closes and reopens any files the appender writes to by path.
+    ///
+    /// This is called after a tool such as `logrotate` has moved a log file
+    /// aside, so that output goes to a new file at the configured path rather
+    /// than to the moved one. The default implementation does nothing.
+    fn reopen(&mut self) -> Result<(), Box<error::Error>> {
+        Ok(())
+    }
+
+    /// Determines if the appender would write the provided `LogRecord`.
+    ///
+    /// log4rs calls this once a log event has passed the appender's filters,
+    /// and does not pass the log event to `append` if it returns false, so an
+    /// appender which discards some log events can avoid formatting them.
+    /// It should be cheap, and must not format the message of the log event.
+    /// The default implementation returns true.
+    fn accepts(&self, _: &LogRecord) -> bool {
+        true
+    }
+
+    /// Determines if the appender would write the provided `LogEvent`.
+    ///
+    /// This is the counterpart of `accepts` for `append_event`. The default
+    /// implementation returns true.
+    fn accepts_event(&self, _: &LogEvent) -> bool {
+        true
+    }
+}
+
+/// A trait implemented by log4rs encoders, which format `LogRecord`s for
+/// output by an appender.
+pub trait Encode: Send + 'static {
+    /// Writes the provided `LogRecord` to the provided writer.
+    fn encode(&self, w: &mut Write, record: &LogRecord) -> io::Result<()>;
+
+    /// Writes the provided `LogEvent` to the provided writer.
+    ///
+    /// The output should be the same as that of `encode` for the record the
+    /// log event was captured from, on the thread which logged it. The
+    /// default implementation returns an error.
+    fn encode_event(&self, _: &mut Write, _: &LogEvent) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Other, "encoder does not support captured log events"))
+    }
 }
 
 /// The response returned by a filter.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum FilterResponse {
     /// Accept the log event.
     ///
@@ -145,10 +241,178 @@ pub enum FilterResponse {
 pub trait Filter: Send + 'static {
     /// Filters a log event.
     fn filter(&mut self, record: &LogRecord) -> FilterResponse;
+
+    /// Filters a log event captured from a `LogRecord` on another thread.
+    ///
+    /// This is used where filters are applied by an appender wrapped by an
+    /// `AsyncAppender`, such as the routes of a `RoutingAppender`. The
+    /// default implementation returns `FilterResponse::Neutral`.
+    fn filter_event(&mut self, _: &LogEvent) -> FilterResponse {
+        FilterResponse::Neutral
+    }
+
+    /// Returns the most verbose level of log event which the filter may pass,
+    /// if it decides based on level alone.
+    ///
+    /// A filter returning `Some` must never accept a log event, and must
+    /// reject every log event more verbose than the returned level. log4rs
+    /// uses this to avoid handling log events which no appender will write.
+    /// The default implementation returns `None`.
+    fn max_level(&self) -> Option<LogLevelFilter> {
+        None
+    }
+}
+
+/// A log event copied out of a `LogRecord`, so that it can be written once
+/// the record is gone, for example from a background thread.
+///
+/// Along with the contents of the record, the current time, the name and
+/// numbers of the logging thread, and its mapped diagnostic context are
+/// captured when the `LogEvent` is created. Encoders use them in place of
+/// the state of the thread which writes the log event, so its output is the
+/// same as if the record had been written directly.
+#[derive(Debug, Clone)]
+pub struct LogEvent {
+    level: LogLevel,
+    target: String,
+    location: LogLocation,
+    message: String,
+    time: Timespec,
+    instant: Instant,
+    thread_name: Option<String>,
+    thread_id: String,
+    thread_number: usize,
+    mdc: Vec<(String, String)>,
+}
+
+impl LogEvent {
+    /// Captures a `LogEvent` from the provided `LogRecord` on the current
+    /// thread.
+    pub fn new(record: &LogRecord) -> LogEvent {
+        LogEvent {
+            level: record.level(),
+            target: record.target().to_owned(),
+            location: *record.location(),
+            message: record.args().to_string(),
+            time: time::get_time(),
+            instant: Instant::now(),
+            thread_name: thread::current().name().map(|n| n.to_owned()),
+            thread_id: thread_id(),
+            thread_number: thread_number(),
+            mdc: mdc::entries(),
+        }
+    }
+
+    /// Returns the level of the log event.
+    pub fn level(&self) -> LogLevel {
+        self.level
+    }
+
+    /// Returns the target of the log event.
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    /// Returns the location of the logging call.
+    pub fn location(&self) -> &LogLocation {
+        &self.location
+    }
+
+    /// Returns the formatted message of the log event.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Returns the time at which the log event was captured.
+    pub fn time(&self) -> Timespec {
+        self.time
+    }
+
+    /// Returns the name of the thread which logged the log event, if it has
+    /// one.
+    pub fn thread_name(&self) -> Option<&str> {
+        self.thread_name.as_ref().map(|n| &**n)
+    }
+
+    /// Returns a value in the mapped diagnostic context of the thread which
+    /// logged the log event.
+    pub fn mdc(&self, key: &str) -> Option<&str> {
+        self.mdc.iter().find(|&&(ref k, _)| k == key).map(|&(_, ref v)| &**v)
+    }
+
+    /// Returns all values in the mapped diagnostic context of the thread
+    /// which logged the log event, sorted by key.
+    pub fn mdc_entries(&self) -> &[(String, String)] {
+        &self.mdc
+    }
+
+    // Returns a copy of the log event with a different message, dated now.
+    fn with_message(&self, message: String) -> LogEvent {
+        LogEvent {
+            message: message,
+            time: time::get_time(),
+            instant: Instant::now(),
+            ..self.clone()
+        }
+    }
+}
+
+struct Location<'a> {
+    module_path: &'a str,
+    file: &'a str,
+    line: u32,
+}
+
+impl<'a> Location<'a> {
+    fn from_record(record: &'a LogRecord) -> Location<'a> {
+        Location {
+            module_path: record.location().module_path(),
+            file: record.location().file(),
+            line: record.location().line(),
+        }
+    }
+
+    fn from_event(event: &'a LogEvent) -> Location<'a> {
+        Location {
+            module_path: event.location.module_path(),
+            file: event.location.file(),
+            line: event.location.line(),
+        }
+    }
+}
+
+// The numeric ID of the current thread, as written by the `%I` pattern
+// specifier.
+fn thread_id() -> String {
+    // `ThreadId` only exposes its numeric value through its `Debug` output,
+    // which has the form `ThreadId(N)`.
+    let id = format!("{:?}", thread::current().id());
+    id.chars().filter(|c| c.is_digit(10)).collect()
+}
+
+// The number to be assigned to the next thread to ask for one.
+static NEXT_THREAD_NUMBER: AtomicUsize = AtomicUsize::new(1);
+
+thread_local!(static THREAD_NUMBER: Cell<usize> = Cell::new(0));
+
+// A small number identifying the current thread, as written by the
+// `%thread_id` pattern specifier.
+fn thread_number() -> usize {
+    THREAD_NUMBER.with(|number| {
+        if number.get() == 0 {
+            number.set(NEXT_THREAD_NUMBER.fetch_add(1, Ordering::Relaxed));
+        }
+        number.get()
+    })
 }
 
+const WILDCARD: &'static str = "*";
+
 struct ConfiguredLogger {
     level: LogLevelFilter,
+    // the most verbose level which both this logger and one of its appenders'
+    // filters will pass
+    max_level: LogLevelFilter,
     appenders: Vec<usize>,
     children: Vec<(String, Box<ConfiguredLogger>)>,
 }
@@ -167,20 +431,33 @@ impl ConfiguredLogger {
             }
         }
 
+        // New loggers below this one inherit from its wildcard logger, if any.
+        let (parent_level, parent_appenders) = match self.wildcard() {
+            Some(wildcard) if part != WILDCARD => (wildcard.level, wildcard.appenders.clone()),
+            _ => (self.level, self.appenders.clone()),
+        };
+
         let child = if rest.is_empty() {
+            // an appender shared with an ancestor is only written to once
             if additive {
-                appenders.extend(self.appenders.iter().cloned());
+                for appender in parent_appenders {
+                    if !appenders.contains(&appender) {
+                        appenders.push(appender);
+                    }
+                }
             }
 
             ConfiguredLogger {
                 level: level,
+                max_level: level,
                 appenders: appenders,
                 children: vec![],
             }
         } else {
             let mut child = ConfiguredLogger {
-                level: self.level,
-                appenders: self.appenders.clone(),
+                level: parent_level,
+                max_level: parent_level,
+                appenders: parent_appenders,
                 children: vec![],
             };
             child.add(rest, appenders, additive, level);
@@ -190,14 +467,30 @@ impl ConfiguredLogger {
         self.children.push((part.to_owned(), Box::new(child)));
     }
 
+    // Lowers the maximum level of each logger to the most verbose level any of
+    // its appenders will accept.
+    fn limit(&mut self, appenders: &[Arc<Mutex<Appender>>]) {
+        let accepted = self.appenders
+            .iter()
+            .map(|&idx| appenders[idx].lock().unwrap().max_level())
+            .max()
+            .unwrap_or(LogLevelFilter::Off);
+        self.max_level = cmp::min(self.level, accepted);
+        for &mut (_, ref mut child) in &mut self.children {
+            child.limit(appenders);
+        }
+    }
+
     fn max_log_level(&self) -> LogLevelFilter {
-        let mut max = self.level;
+        let mut max = self.max_level;
         for &(_, ref child) in &self.children {
             max = cmp::max(max, child.max_log_level());
         }
         max
     }
 
+    // `path` is the target of a log event, which is not necessarily its module
+    // path.
     fn find(&self, path: &str) -> &ConfiguredLogger {
         let mut node = self;
 
@@ -209,56 +502,241 @@ impl ConfiguredLogger {
                 }
             }
 
+            // wildcards are always terminal, so there is nothing more to match
+            if let Some(wildcard) = node.wildcard() {
+                node = wildcard;
+            }
             break;
         }
 
         node
     }
 
+    fn wildcard(&self) -> Option<&ConfiguredLogger> {
+        self.children
+            .iter()
+            .find(|&&(ref part, _)| part == WILDCARD)
+            .map(|&(_, ref child)| &**child)
+    }
+
     fn enabled(&self, level: LogLevel) -> bool {
         self.level >= level
     }
+}
 
-    fn log(&self, record: &log::LogRecord, appenders: &mut [Appender]) {
-        if self.enabled(record.level()) {
-            for &idx in &self.appenders {
-                if let Err(err) = appenders[idx].append(record) {
-                    handle_error(&*err);
-                }
-            }
-        }
+#[derive(Debug)]
+struct AppenderError<'a> {
+    name: &'a str,
+    action: String,
+    err: Box<error::Error>,
+}
+
+impl<'a> fmt::Display for AppenderError<'a> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "Error in appender `{}`, {}: {}", self.name, self.action, self.err)
+    }
+}
+
+impl<'a> error::Error for AppenderError<'a> {
+    fn description(&self) -> &str {
+        "Error in appender"
+    }
+}
+
+/// Counts of the log events handled by an appender.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct AppenderStats {
+    records: u64,
+    errors: u64,
+}
+
+impl AppenderStats {
+    /// Returns the number of log events passed to the appender by its
+    /// filters, including those which it failed to write.
+    ///
+    /// Log events skipped because of the appender's error policy, or because
+    /// its `Append::accepts` returned false, are not counted.
+    pub fn records(&self) -> u64 {
+        self.records
+    }
+
+    /// Returns the number of log events which the appender failed to write.
+    pub fn errors(&self) -> u64 {
+        self.errors
     }
 }
 
+const MIN_ERROR_BACKOFF_MS: u64 = 1000;
+const MAX_ERROR_BACKOFF_MS: u64 = 60000;
+
 struct Appender {
-    appender: Box<Append>,
+    name: String,
+    // `None` until `SharedLogger::reuse` fills the slot with an existing
+    // appender
+    appender: Option<Box<Append>>,
     filters: Vec<Box<Filter>>,
+    on_error: config::ErrorPolicy,
+    required: bool,
+    identity: Option<String>,
+    stats: AppenderStats,
+    disabled: bool,
+    backoff: StdDuration,
+    retry_at: Option<Instant>,
 }
 
 impl Appender {
-    fn append(&mut self, record: &LogRecord) -> Result<(), Box<error::Error>> {
+    fn new(name: String,
+           appender: Option<Box<Append>>,
+           filters: Vec<Box<Filter>>,
+           on_error: config::ErrorPolicy,
+           required: bool,
+           identity: Option<String>)
+           -> Appender {
+        Appender {
+            name: name,
+            appender: appender,
+            filters: filters,
+            on_error: on_error,
+            required: required,
+            identity: identity,
+            stats: AppenderStats::default(),
+            disabled: false,
+            backoff: StdDuration::from_millis(MIN_ERROR_BACKOFF_MS),
+            retry_at: None,
+        }
+    }
+
+    // Returns the most verbose level the appender's filters may pass. Filters
+    // which do not report a level may accept log events, bypassing those
+    // after them.
+    fn max_level(&self) -> LogLevelFilter {
+        let mut max = LogLevelFilter::Trace;
+        for filter in &self.filters {
+            match filter.max_level() {
+                Some(level) => max = cmp::min(max, level),
+                None => break,
+            }
+        }
+        max
+    }
+
+    // Returns false if the log event should not be passed to the remaining
+    // appenders.
+    fn append(&mut self, record: &LogRecord) -> bool {
         for filter in &mut self.filters {
             match filter.filter(record) {
                 FilterResponse::Accept => break,
                 FilterResponse::Neutral => {}
-                FilterResponse::Reject => return Ok(()),
+                FilterResponse::Reject => return true,
+            }
+        }
+        if !self.appender.as_ref().map_or(true, |a| a.accepts(record)) {
+            return true;
+        }
+
+        let now = Instant::now();
+        if !self.ready(now) {
+            return true;
+        }
+        self.stats.records += 1;
+        let result = match self.appender {
+            Some(ref mut appender) => appender.append(record),
+            None => Err(Box::new(ReuseError) as Box<error::Error>),
+        };
+        match result {
+            Ok(()) => {
+                self.retry_at = None;
+                self.backoff = StdDuration::from_millis(MIN_ERROR_BACKOFF_MS);
+                true
+            }
+            Err(err) => {
+                self.fail(err, now);
+                !self.required
+            }
+        }
+    }
+
+    // Determines if the appender should be used, according to its policy.
+    fn ready(&self, now: Instant) -> bool {
+        if self.disabled {
+            return false;
+        }
+        match self.retry_at {
+            Some(retry_at) => now >= retry_at,
+            None => true,
+        }
+    }
+
+    fn fail(&mut self, err: Box<error::Error>, now: Instant) {
+        self.stats.errors += 1;
+        let skipping = if self.required {
+            ", skipping the remaining appenders"
+        } else {
+            ""
+        };
+        match self.on_error {
+            config::ErrorPolicy::Ignore if !self.required => handle_error(&*err),
+            config::ErrorPolicy::Ignore => {
+                handle_error(&AppenderError {
+                    name: &self.name,
+                    action: "skipping the remaining appenders".to_owned(),
+                    err: err,
+                });
             }
+            config::ErrorPolicy::Backoff => {
+                handle_error(&AppenderError {
+                    name: &self.name,
+                    action: format!("retrying in {}s{}", self.backoff.as_secs(), skipping),
+                    err: err,
+                });
+                self.retry_at = Some(now + self.backoff);
+                self.backoff = cmp::min(self.backoff * 2,
+                                        StdDuration::from_millis(MAX_ERROR_BACKOFF_MS));
+            }
+            config::ErrorPolicy::Disable => {
+                handle_error(&AppenderError {
+                    name: &self.name,
+                    action: format!("disabling it{}", skipping),
+                    err: err,
+                });
+                self.disabled = true;
+            }
+        }
+    }
+
+    fn flush(&mut self) -> Result<(), Box<error::Error>> {
+        match self.appender {
+            Some(ref mut appender) => appender.flush(),
+            None => Ok(()),
         }
+    }
 
-        self.appender.append(record)
+    fn reopen(&mut self) -> Result<(), Box<error::Error>> {
+        match self.appender {
+            Some(ref mut appender) => appender.reopen(),
+            None => Ok(()),
+        }
     }
 }
 
+// Each appender has a lock of its own, so that the logger's lock is only held
+// while the appenders for a log event are looked up, and log events sent to
+// different appenders are written concurrently.
 struct SharedLogger {
     root: ConfiguredLogger,
-    appenders: Vec<Appender>,
+    appenders: Vec<Arc<Mutex<Appender>>>,
 }
 
+static SHARED: Mutex<Option<Arc<Mutex<SharedLogger>>>> = Mutex::new(None);
+
 impl SharedLogger {
     fn new(config: config::Config) -> SharedLogger {
         let (appenders, root, loggers) = config.unpack();
 
-        let root = {
+        // `Config`s can only be constructed through `ConfigBuilder`, which
+        // strips references to nonexistent appenders, so the lookups below
+        // cannot fail.
+        let mut root = {
             let appender_map = appenders
                 .iter()
                 .enumerate()
@@ -267,6 +745,7 @@ impl SharedLogger {
 
             let mut root = ConfiguredLogger {
                 level: root.level(),
+                max_level: root.level(),
                 appenders: root.appenders()
                     .iter()
                     .map(|appender| appender_map[&**appender])
@@ -274,6 +753,13 @@ impl SharedLogger {
                 children: vec![],
             };
 
+            // Parents must be added before their children so that the children
+            // can inherit from them, and wildcards before their siblings.
+            let mut loggers = loggers;
+            loggers.sort_by_key(|logger| {
+                (logger.name().split("::").count(), !logger.name().ends_with(WILDCARD))
+            });
+
             for logger in loggers {
                 let appenders = logger.appenders()
                     .iter()
@@ -286,31 +772,140 @@ impl SharedLogger {
         };
 
         let appenders = appenders.into_iter().map(|appender| {
-            let (_, appender, filters) = appender.unpack();
-            Appender {
-                appender: appender,
-                filters: filters,
-            }
-        }).collect();
+            let (name, appender, filters, on_error, required, identity) = appender.unpack();
+            Arc::new(Mutex::new(Appender::new(name, appender, filters, on_error, required,
+                                              identity)))
+        }).collect::<Vec<_>>();
+
+        root.limit(&appenders);
 
         SharedLogger {
             root: root,
             appenders: appenders,
         }
     }
+
+    // Looks up the appenders a log event is written to.
+    fn route(&self, record: &LogRecord) -> Vec<Arc<Mutex<Appender>>> {
+        let logger = self.root.find(record.target());
+        // skip log events which no appender's filters would pass
+        if logger.max_level < record.level() {
+            return vec![];
+        }
+        logger.appenders.iter().map(|&idx| self.appenders[idx].clone()).collect()
+    }
+
+    fn flush(&self) {
+        for appender in &self.appenders {
+            if let Err(err) = appender.lock().unwrap().flush() {
+                handle_error(&*err);
+            }
+        }
+    }
+
+    fn reopen(&self) {
+        for appender in &self.appenders {
+            if let Err(err) = appender.lock().unwrap().reopen() {
+                handle_error(&*err);
+            }
+        }
+    }
+
+    // Takes over the appenders of `old` which have the same name and identity
+    // as appenders of this logger, updating their filters and error policies
+    // to match this logger's. A log event still being written through `old`
+    // goes to the same appender. This fills the slots of appenders which were
+    // not created again, unless `old` no longer has them because the config
+    // was replaced in the meantime.
+    fn reuse(&mut self, old: &SharedLogger) {
+        for appender in &mut self.appenders {
+            let reusable = {
+                let new = appender.lock().unwrap();
+                if new.identity.is_none() {
+                    continue;
+                }
+                old.appenders
+                    .iter()
+                    .find(|old| {
+                        let old = old.lock().unwrap();
+                        old.name == new.name && old.identity == new.identity
+                    })
+                    .cloned()
+            };
+            if let Some(reusable) = reusable {
+                {
+                    let mut new = appender.lock().unwrap();
+                    let mut old = reusable.lock().unwrap();
+                    mem::swap(&mut *new, &mut *old);
+                    mem::swap(&mut new.appender, &mut old.appender);
+                    old.stats = new.stats;
+                }
+                *appender = reusable;
+            }
+        }
+    }
+
+    fn stats(&self) -> HashMap<String, AppenderStats> {
+        self.appenders
+            .iter()
+            .map(|a| {
+                let a = a.lock().unwrap();
+                (a.name.clone(), a.stats)
+            })
+            .collect()
+    }
+
+    fn identities(&self) -> HashMap<String, String> {
+        self.appenders
+            .iter()
+            .filter_map(|a| {
+                let a = a.lock().unwrap();
+                a.identity.as_ref().map(|id| (a.name.clone(), id.clone()))
+            })
+            .collect()
+    }
+}
+
+// Reported by an appender slot which `SharedLogger::reuse` could not fill.
+#[derive(Debug)]
+struct ReuseError;
+
+impl fmt::Display for ReuseError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str("appender was removed while the config was reloading")
+    }
+}
+
+impl error::Error for ReuseError {
+    fn description(&self) -> &str {
+        "appender was removed while the config was reloading"
+    }
 }
 
 struct Logger {
     inner: Arc<Mutex<SharedLogger>>,
 }
 
+// The time at which the first logger was created, used by the `%r` pattern
+// specifier.
+static START: Mutex<Option<Instant>> = Mutex::new(None);
+
+fn start_time() -> Instant {
+    *START.lock().unwrap().get_or_insert_with(Instant::now)
+}
+
 impl Logger {
     fn new(config: config::Config) -> Logger {
+        start_time();
         Logger {
             inner: Arc::new(Mutex::new(SharedLogger::new(config)))
         }
     }
 
+    fn register(&self) {
+        *SHARED.lock().unwrap() = Some(self.inner.clone());
+    }
+
     fn max_log_level(&self) -> LogLevelFilter {
         self.inner.lock().unwrap().root.max_log_level()
     }
@@ -318,155 +913,697 @@ impl Logger {
 
 impl log::Log for Logger {
     fn enabled(&self, metadata: &LogMetadata) -> bool {
+        if LOGGING.with(|logging| logging.get()) {
+            return false;
+        }
         self.enabled_inner(metadata.level(), metadata.target())
     }
 
     fn log(&self, record: &log::LogRecord) {
-        let shared = &mut *self.inner.lock().unwrap();
-        shared.root.find(record.target()).log(record, &mut shared.appenders);
+        // the logger is already locked by this thread, for example by an
+        // appender's error handler
+        if LOGGING.with(|logging| logging.get()) {
+            return;
+        }
+        let _guard = LoggingGuard::new();
+        if REOPEN_REQUESTED.load(Ordering::Relaxed) &&
+           REOPEN_REQUESTED.swap(false, Ordering::SeqCst) {
+            self.inner.lock().unwrap().reopen();
+        }
+        let appenders = self.inner.lock().unwrap().route(record);
+        append_all(&appenders, record);
     }
 }
 
-impl Logger {
-    fn enabled_inner(&self, level: LogLevel, target: &str) -> bool {
-        self.inner.lock().unwrap().root.find(target).enabled(level)
+fn append_all(appenders: &[Arc<Mutex<Appender>>], record: &LogRecord) {
+    for appender in appenders {
+        if !appender.lock().unwrap().append(record) {
+            break;
+        }
     }
 }
 
-fn handle_error<E: error::Error+?Sized>(e: &E) {
-    let stderr = io::stderr();
-    let mut stderr = stderr.lock();
-    let _ = writeln!(&mut stderr, "{}", e);
+thread_local!(static LOGGING: Cell<bool> = Cell::new(false));
+
+// Marks the current thread as logging, and possibly holding an appender's
+// lock, so that the panic hook does not deadlock if an appender panics.
+struct LoggingGuard;
+
+impl LoggingGuard {
+    fn new() -> LoggingGuard {
+        LOGGING.with(|logging| logging.set(true));
+        LoggingGuard
+    }
 }
 
-/// Initializes the global logger with a log4rs logger configured by `config`.
-pub fn init_config(config: config::Config) -> Result<(), SetLoggerError> {
-    log::set_logger(|max_log_level| {
-        let logger = Logger::new(config);
-        max_log_level.set(logger.max_log_level());
-        Box::new(logger)
-    })
+impl Drop for LoggingGuard {
+    fn drop(&mut self) {
+        LOGGING.with(|logging| logging.set(false));
+    }
 }
 
-/// Initializes the global logger with a log4rs logger.
+impl Logger {
+    fn enabled_inner(&self, level: LogLevel, target: &str) -> bool {
+        self.inner.lock().unwrap().root.find(target).enabled(level)
+    }
+}
+
+static ERROR_HANDLER: RwLock<Option<Arc<Fn(&error::Error) + Send + Sync>>> = RwLock::new(None);
+
+/// Sets the function which is called with errors encountered by log4rs.
 ///
-/// Configuration is read from a TOML file located at the provided path on the
-/// filesystem and appenders are created from the provided `Creator`.
+/// This includes errors returned by appenders, as well as errors reading or
+/// parsing config files, both initially and when reloading them. By default,
+/// errors are written to stderr.
 ///
-/// Any errors encountered when processing the configuration are reported to
-/// stderr.
-pub fn init_file<P: AsRef<Path>>(path: P, creator: Creator) -> Result<(), SetLoggerError> {
-    log::set_logger(|max_log_level| {
-        let path = path.as_ref().to_path_buf();
-        let (source, refresh_rate, config) = match read_config(&path) {
-            Ok(source) => {
-                match parse_config(&source, &creator) {
-                    Ok(config) => {
-                        let (refresh_rate, config) = config.unpack();
-                        (source, refresh_rate, config)
-                    }
-                    Err(err) => {
-                        handle_error(&*err);
-                        ("".to_string(), None, config::Config::builder(
-                                config::Root::builder(LogLevelFilter::Off).build()).build().unwrap())
-                    }
-                }
-            },
-            Err(err) => {
-                handle_error(&err);
-                ("".to_string(), None, config::Config::builder(
-                        config::Root::builder(LogLevelFilter::Off).build()).build().unwrap())
-            }
-        };
-        let logger = Logger::new(config);
-        max_log_level.set(logger.max_log_level());
-        if let Some(refresh_rate) = refresh_rate {
-            ConfigReloader::start(path, refresh_rate, source, creator, &logger, max_log_level);
-        }
-        Box::new(logger)
-    })
+/// The handler may be called while the logger is in use, for example when an
+/// appender fails. Log events emitted by the handler in that case are
+/// discarded rather than deadlocking.
+pub fn set_error_handler(handler: Box<Fn(&error::Error) + Send + Sync>) {
+    *ERROR_HANDLER.write().unwrap_or_else(|e| e.into_inner()) = Some(Arc::from(handler));
 }
 
-fn read_config(path: &Path) -> Result<String, io::Error> {
-    let mut file = try!(File::open(path));
-    let mut s = String::new();
-    try!(file.read_to_string(&mut s));
-    Ok(s)
+fn handle_error(e: &error::Error) {
+    // the handler is called without holding the lock, so that it may itself
+    // replace the handler
+    let handler = ERROR_HANDLER.read().unwrap_or_else(|e| e.into_inner()).clone();
+    match handler {
+        Some(handler) => handler(e),
+        None => {
+            let stderr = appender::ConsoleWriter::new(appender::Target::Stderr);
+            let _ = stderr.write_atomic(format!("{}\n", e).as_bytes());
+        }
+    }
 }
 
-fn parse_config(source: &str, creator: &Creator) -> Result<toml::Config, Box<error::Error>> {
-    let (config, errors) = try!(toml::Config::parse(&source, creator));
-    if let Err(errors) = errors {
-        for error in errors.errors() {
-            handle_error(error);
-        }
+#[cfg(unix)]
+fn hostname() -> Option<String> {
+    let mut buf = [0u8; 256];
+    let ret = unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) };
+    if ret != 0 {
+        return None;
     }
-    Ok(config)
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    String::from_utf8(buf[..len].to_vec()).ok()
 }
 
-struct ConfigReloader {
-    path: PathBuf,
-    rate: Duration,
-    source: String,
-    creator: Creator,
-    shared: Arc<Mutex<SharedLogger>>,
-    max_log_level: MaxLogLevelFilter,
+#[cfg(not(unix))]
+fn hostname() -> Option<String> {
+    std::env::var("COMPUTERNAME").ok()
 }
 
-impl ConfigReloader {
-    fn start(path: PathBuf, rate: Duration, source: String, creator: Creator, logger: &Logger,
-             max_log_level: MaxLogLevelFilter) {
-        let mut reloader = ConfigReloader {
-            path: path,
-            rate: rate,
-            source: source,
-            creator: creator,
-            shared: logger.inner.clone(),
-            max_log_level: max_log_level,
-        };
+// The file name of the current executable.
+fn program_name() -> Option<String> {
+    std::env::current_exe()
+        .ok()
+        .and_then(|p| p.file_name().and_then(|n| n.to_str()).map(|n| n.to_owned()))
+}
 
-        thread::Builder::new()
-            .name("log4rs config refresh thread".to_string())
-            .spawn(move || reloader.run())
-            .unwrap();
+// Writes `s` escaped for use inside a JSON string, without the surrounding
+// quotes.
+fn write_json_escaped<W: Write + ?Sized>(w: &mut W, s: &str) -> io::Result<()> {
+    let mut start = 0;
+    for (i, ch) in s.char_indices() {
+        let escape = match ch {
+            '"' => "\\\"".to_owned(),
+            '\\' => "\\\\".to_owned(),
+            '\n' => "\\n".to_owned(),
+            '\r' => "\\r".to_owned(),
+            '\t' => "\\t".to_owned(),
+            ch if ch < ' ' => format!("\\u{:04x}", ch as u32),
+            _ => continue,
+        };
+        try!(w.write_all(s[start..i].as_bytes()));
+        try!(w.write_all(escape.as_bytes()));
+        start = i + ch.len_utf8();
     }
+    w.write_all(s[start..].as_bytes())
+}
 
-    fn run(&mut self) {
-        loop {
-            thread::sleep_ms(self.rate.num_milliseconds() as u32);
+/// A log4rs logger which is used directly rather than installed as the
+/// global logger.
+///
+/// Log events passed to a `DirectLogger` are routed to its appenders in the
+/// same way as those passed to the global logger, synchronously and on the
+/// calling thread. Any number of them may be created, which makes them
+/// useful for testing `Append` implementations, and for forwarding the log
+/// events seen by another `log::Log` implementation.
+pub struct DirectLogger {
+    shared: SharedLogger,
+}
 
-            let source = match read_config(&self.path) {
-                Ok(source) => source,
-                Err(err) => {
-                    handle_error(&err);
-                    continue;
-                }
-            };
+impl DirectLogger {
+    /// Creates a new `DirectLogger` configured by `config`.
+    pub fn new(config: config::Config) -> DirectLogger {
+        DirectLogger { shared: SharedLogger::new(config) }
+    }
 
-            if source == self.source {
-                continue;
+    /// Determines if a log event with the specified level and target would
+    /// be logged.
+    pub fn enabled(&self, level: LogLevel, target: &str) -> bool {
+        self.shared.root.find(target).enabled(level)
+    }
+
+    /// Logs a log event.
+    pub fn log(&mut self, record: &LogRecord) {
+        append_all(&self.shared.route(record), record);
+    }
+
+    /// Flushes all appenders, reporting any errors to the error handler.
+    pub fn flush(&mut self) {
+        self.shared.flush();
+    }
+
+    /// Returns the counts of log events handled by each appender, keyed by
+    /// name.
+    pub fn stats(&self) -> HashMap<String, AppenderStats> {
+        self.shared.stats()
+    }
+}
+
+/// A handle to the global log4rs logger which can be used to reconfigure it.
+#[derive(Clone)]
+pub struct Handle {
+    shared: Arc<Mutex<SharedLogger>>,
+    max_log_level: Arc<MaxLogLevelFilter>,
+}
+
+impl Handle {
+    fn new(logger: &Logger, max_log_level: MaxLogLevelFilter) -> Handle {
+        Handle {
+            shared: logger.inner.clone(),
+            max_log_level: Arc::new(max_log_level),
+        }
+    }
+
+    /// Replaces the configuration of the logger.
+    ///
+    /// Appenders of the previous configuration are flushed before they are
+    /// dropped, except for those with the same name and identity as an
+    /// appender of the new configuration, which are kept in its place. See
+    /// `config::AppenderBuilder::identity`.
+    pub fn set_config(&self, config: config::Config) {
+        let mut shared = SharedLogger::new(config);
+        self.max_log_level.set(shared.root.max_log_level());
+        let old = {
+            let mut current = self.shared.lock().unwrap();
+            shared.reuse(&current);
+            mem::replace(&mut *current, shared)
+        };
+        old.flush();
+    }
+
+    /// Returns the level which currently applies to log events with the
+    /// specified target.
+    ///
+    /// The target is matched to a logger in the same way as a log event,
+    /// falling back to the root logger's level.
+    pub fn effective_level(&self, target: &str) -> LogLevelFilter {
+        self.shared.lock().unwrap().root.find(target).level
+    }
+
+    /// Returns the counts of log events handled by each appender, keyed by
+    /// name.
+    ///
+    /// The counts of an appender start from zero when it is added by a new
+    /// configuration, and carry over when it is kept by one.
+    pub fn stats(&self) -> HashMap<String, AppenderStats> {
+        self.shared.lock().unwrap().stats()
+    }
+
+    /// Reopens the files written to by the logger's appenders.
+    ///
+    /// See `Append::reopen`. Errors are reported to the error handler.
+    pub fn reopen(&self) {
+        let shared = self.shared.lock().unwrap();
+        shared.reopen();
+    }
+}
+
+// Set by the `SIGHUP` handler and checked before each log event is written,
+// since files cannot be reopened from within a signal handler.
+static REOPEN_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Installs a handler for `SIGHUP` which makes the global logger reopen the
+/// files written to by its appenders.
+///
+/// This is the signal `logrotate` and similar tools send after moving a log
+/// file aside. The files are reopened just before the next log event is
+/// written, as by `Handle::reopen`. The handler replaces any other handler
+/// for `SIGHUP`, so it should not be installed by programs which use the
+/// signal for something else.
+#[cfg(unix)]
+pub fn reopen_on_sighup() -> io::Result<()> {
+    extern "C" fn on_sighup(_: libc::c_int) {
+        REOPEN_REQUESTED.store(true, Ordering::SeqCst);
+    }
+
+    let handler = on_sighup as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    if unsafe { libc::signal(libc::SIGHUP, handler) } == libc::SIG_ERR {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// Initializes the global logger with a log4rs logger configured by `config`.
+///
+/// The returned `Handle` can be used to reconfigure the logger later.
+pub fn init_config(config: config::Config) -> Result<Handle, SetLoggerError> {
+    let mut handle = None;
+    try!(log::set_logger(|max_log_level| {
+        let logger = Logger::new(config);
+        max_log_level.set(logger.max_log_level());
+        logger.register();
+        handle = Some(Handle::new(&logger, max_log_level));
+        Box::new(logger)
+    }));
+    Ok(handle.unwrap())
+}
+
+/// Initializes the global logger with a log4rs logger.
+///
+/// Configuration is read from a file located at the provided path on the
+/// filesystem and appenders are created from the provided `Creator`. The file
+/// is parsed as YAML if its extension is `yml` or `yaml`, and as TOML
+/// otherwise.
+///
+/// If the configuration specifies a refresh rate, a background thread
+/// rereads the file at that rate and reconfigures the logger when it changes.
+/// If it enables `watch`, the thread instead waits for change notifications
+/// from the operating system where they are supported. A change to either
+/// setting takes effect immediately. If a reloaded configuration sets neither,
+/// it is applied and the thread then exits; later changes to the file are not
+/// picked up.
+///
+/// Any errors encountered when processing the configuration are passed to the
+/// error handler, which writes them to stderr unless `set_error_handler` has
+/// been called.
+pub fn init_file<P: AsRef<Path>>(path: P, creator: Creator) -> Result<(), SetLoggerError> {
+    init_file_with_reloader(path, creator).map(|_| ())
+}
+
+/// Initializes the global logger with a log4rs logger, returning a handle to
+/// the reload thread.
+///
+/// This behaves like `init_file`, except that the returned `Reloader` can be
+/// used to stop the reload thread and wait for it to exit, for example during
+/// a graceful shutdown.
+pub fn init_file_with_reloader<P: AsRef<Path>>(path: P, creator: Creator)
+                                               -> Result<Reloader, SetLoggerError> {
+    let mut reloader = None;
+    try!(log::set_logger(|max_log_level| {
+        let path = path.as_ref().to_path_buf();
+        let (source, refresh_rate, watch, reload_on, config) = match read_config(&path) {
+            Ok(source) => {
+                match parse_config(&path, &source, &creator) {
+                    Ok(config) => {
+                        let reload_on = config.reload_on();
+                        let (refresh_rate, watch, config) = config.unpack();
+                        (source, refresh_rate, watch, reload_on, config)
+                    }
+                    Err(err) => {
+                        handle_error(&*err);
+                        ("".to_string(), None, false, toml::ReloadOn::Content,
+                         config::Config::builder(
+                                config::Root::builder(LogLevelFilter::Off).build()).build().unwrap())
+                    }
+                }
+            },
+            Err(err) => {
+                handle_error(&err);
+                ("".to_string(), None, false, toml::ReloadOn::Content, config::Config::builder(
+                        config::Root::builder(LogLevelFilter::Off).build()).build().unwrap())
             }
+        };
+        let logger = Logger::new(config);
+        max_log_level.set(logger.max_log_level());
+        logger.register();
+        reloader = Some(if refresh_rate.is_some() || watch {
+            let handle = Handle::new(&logger, max_log_level);
+            ConfigReloader::start(path, refresh_rate, watch, reload_on, &source, creator, handle)
+        } else {
+            Reloader::stopped()
+        });
+        Box::new(logger)
+    }));
+    Ok(reloader.unwrap())
+}
 
-            self.source = source;
+/// Initializes the global logger with a log4rs logger, failing on any error
+/// in the configuration file.
+///
+/// This behaves like `init_file`, except that an error is returned instead of
+/// being reported if the file cannot be read or parsed, or if any part of the
+/// configuration is invalid. The logger is not installed in that case. Errors
+/// encountered by the reload thread are still reported to the error handler,
+/// and leave the current configuration in place.
+pub fn init_file_strict<P: AsRef<Path>>(path: P, creator: Creator)
+                                        -> Result<Handle, Box<error::Error>> {
+    let path = path.as_ref().to_path_buf();
+    let source = try!(read_config(&path));
+    let (config, errors) = try!(parse_config_strict(&path, &source, &creator));
+    try!(errors);
+    let reload_on = config.reload_on();
+    let (refresh_rate, watch, config) = config.unpack();
 
-            let config = match parse_config(&self.source, &self.creator) {
-                Ok(config) => config,
-                Err(err) => {
-                    handle_error(&*err);
-                    continue;
+    let mut handle = None;
+    try!(log::set_logger(|max_log_level| {
+        let logger = Logger::new(config);
+        max_log_level.set(logger.max_log_level());
+        logger.register();
+        let new_handle = Handle::new(&logger, max_log_level);
+        if refresh_rate.is_some() || watch {
+            let handle = new_handle.clone();
+            ConfigReloader::start(path, refresh_rate, watch, reload_on, &source, creator, handle);
+        }
+        handle = Some(new_handle);
+        Box::new(logger)
+    }));
+    Ok(handle.unwrap())
+}
+
+/// Initializes the global logger with a log4rs logger configured by the file
+/// named by an environment variable.
+///
+/// If the variable `var` is set and not empty, its value is the path of the
+/// config file. Otherwise, the file at `default` is used if it exists. The
+/// logger is then initialized as it is by `init_file`.
+///
+/// An error is returned if the variable is unset and there is no file at
+/// `default`, rather than installing a logger which writes nothing, or if the
+/// logger cannot be installed.
+pub fn init_from_env<P: AsRef<Path>>(var: &str, default: P, creator: Creator)
+                                     -> Result<(), Box<error::Error>> {
+    let path = try!(config_path_from_env(var, default.as_ref()));
+    try!(init_file(path, creator));
+    Ok(())
+}
+
+fn config_path_from_env(var: &str, default: &Path) -> io::Result<PathBuf> {
+    match std::env::var_os(var) {
+        Some(ref path) if !path.is_empty() => Ok(PathBuf::from(path)),
+        _ if default.is_file() => Ok(default.to_path_buf()),
+        _ => {
+            Err(io::Error::new(io::ErrorKind::NotFound,
+                               format!("`{}` is not set and the default config file `{}` does \
+                                        not exist",
+                                       var,
+                                       default.display())))
+        }
+    }
+}
+
+/// Initializes the global logger with a log4rs logger configured by a TOML
+/// config string.
+///
+/// Appenders are created from the provided `Creator`. Unlike `init_file`, no
+/// files are read and the `refresh_rate` and `watch` settings are ignored.
+///
+/// An error is returned if the string cannot be parsed or the logger cannot
+/// be installed. Errors in individual appender, filter or logger
+/// configurations are reported to the error handler, and the remainder of the
+/// configuration is used.
+pub fn init_config_str(config: &str, creator: Creator) -> Result<Handle, Box<error::Error>> {
+    let (config, errors) = try!(toml::Config::parse(config, &creator));
+    if let Err(errors) = errors {
+        for error in errors.errors() {
+            handle_error(error);
+        }
+    }
+    let (_, _, config) = config.unpack();
+    Ok(try!(init_config(config)))
+}
+
+/// Flushes all appenders of the global log4rs logger.
+///
+/// This should be called before the process exits to ensure that any output
+/// buffered by appenders is written out. Errors are reported to the error
+/// handler. It does nothing if a log4rs logger has not been initialized.
+pub fn shutdown() {
+    let shared = SHARED.lock().unwrap().clone();
+    if let Some(shared) = shared {
+        shared.lock().unwrap().flush();
+    }
+}
+
+fn read_config(path: &Path) -> Result<String, io::Error> {
+    let mut file = try!(File::open(path));
+    let mut s = String::new();
+    try!(file.read_to_string(&mut s));
+    Ok(s)
+}
+
+fn parse_config(path: &Path, source: &str, creator: &Creator)
+                -> Result<toml::Config, Box<error::Error>> {
+    let (config, errors) = try!(parse_config_strict(path, source, creator));
+    if let Err(errors) = errors {
+        for error in errors.errors() {
+            handle_error(error);
+        }
+    }
+    Ok(config)
+}
+
+fn parse_config_strict(path: &Path, source: &str, creator: &Creator)
+                       -> Result<(toml::Config, Result<(), toml::Errors>), Box<error::Error>> {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("yml") | Some("yaml") => Ok(try!(yaml::parse(source, creator))),
+        _ => Ok(try!(toml::Config::parse_file(source, path, creator))),
+    }
+}
+
+/// A handle to the background thread which reloads a config file.
+///
+/// Dropping a `Reloader` leaves the thread running.
+pub struct Reloader {
+    thread: Option<thread::JoinHandle<()>>,
+    stop: Arc<watch::Stop>,
+}
+
+impl Reloader {
+    fn stopped() -> Reloader {
+        Reloader {
+            thread: None,
+            stop: Arc::new(watch::Stop::new()),
+        }
+    }
+
+    /// Returns the reload thread, if one was started.
+    ///
+    /// A thread is only started if the config file specifies a refresh rate
+    /// or enables `watch`.
+    pub fn thread(&self) -> Option<&thread::Thread> {
+        self.thread.as_ref().map(|t| t.thread())
+    }
+
+    /// Signals the reload thread to exit.
+    ///
+    /// The thread exits promptly, without waiting for the next refresh. The
+    /// current configuration of the logger remains in place.
+    pub fn stop(&self) {
+        self.stop.stop();
+    }
+
+    /// Waits for the reload thread to exit.
+    ///
+    /// Unless `stop` has been called, this only returns once a config which
+    /// sets neither a refresh rate nor `watch` has been loaded. An error is
+    /// returned if the thread panicked.
+    pub fn join(mut self) -> thread::Result<()> {
+        match self.thread.take() {
+            Some(thread) => thread.join(),
+            None => Ok(()),
+        }
+    }
+}
+
+// Modification times this close to the present are not trusted to detect a
+// change, since a later write within the timestamp granularity of the
+// filesystem would leave them unchanged.
+const RACY_MTIME_SECS: u64 = 2;
+
+struct ConfigReloader {
+    path: PathBuf,
+    rate: Option<Duration>,
+    watch: bool,
+    reload_on: toml::ReloadOn,
+    // the hash of the contents of the file last loaded
+    hash: u64,
+    // the modification time of the file last loaded, if it can be relied on
+    mtime: Option<SystemTime>,
+    creator: Creator,
+}
+
+impl ConfigReloader {
+    fn start(path: PathBuf,
+             rate: Option<Duration>,
+             watch: bool,
+             reload_on: toml::ReloadOn,
+             source: &str,
+             mut creator: Creator,
+             handle: Handle)
+             -> Reloader {
+        // configs loaded from here on replace the running one
+        creator.set_reloading(true);
+        let mut reloader = ConfigReloader {
+            path: path,
+            rate: rate,
+            watch: watch,
+            reload_on: reload_on,
+            hash: hash_source(source),
+            mtime: None,
+            creator: creator,
+        };
+        let mtime = reloader.read_mtime();
+        reloader.record_mtime(mtime);
+        let stop = Arc::new(watch::Stop::new());
+        let thread_stop = stop.clone();
+
+        let thread = thread::Builder::new()
+            .name("log4rs config refresh thread".to_string())
+            .spawn(move || reloader.run(handle, &thread_stop))
+            .unwrap();
+        Reloader {
+            thread: Some(thread),
+            stop: stop,
+        }
+    }
+
+    fn run(&mut self, handle: Handle, stop: &watch::Stop) {
+        let mut watcher = None;
+
+        // The thread exits once a config without a refresh rate or `watch`
+        // has been applied, and there is no way to restart it.
+        loop {
+            let stopped = match (self.watch, self.rate) {
+                (true, rate) => {
+                    let path = &self.path;
+                    watcher.get_or_insert_with(|| watch::Watcher::new(path)).wait(rate, stop)
                 }
+                (false, Some(rate)) => stop.sleep(rate),
+                (false, None) => return,
             };
-            let (refresh_rate, config) = config.unpack();
+            if stopped {
+                return;
+            }
+
+            if let Some(config) = self.reload(&handle.shared) {
+                handle.set_config(config);
+            }
+        }
+    }
 
-            let shared = SharedLogger::new(config);
-            self.max_log_level.set(shared.root.max_log_level());
-            *self.shared.lock().unwrap() = shared;
+    // Returns the new config if the file has changed, updating the refresh
+    // rate so that a new one takes effect for the very next sleep. The source
+    // is only recorded once it has loaded successfully, so a file which failed
+    // to load is tried again even if it is unchanged, for example after a file
+    // it includes has been fixed.
+    fn reload(&mut self, shared: &Mutex<SharedLogger>) -> Option<config::Config> {
+        // the modification time is a cheap check before reading the file
+        let mtime = self.read_mtime();
+        if mtime.is_some() && mtime == self.mtime {
+            return None;
+        }
 
-            match refresh_rate {
-                Some(rate) => self.rate = rate,
-                None => return,
+        let source = match read_config(&self.path) {
+            Ok(source) => source,
+            Err(err) => {
+                handle_error(&err);
+                return None;
             }
+        };
+
+        let hash = hash_source(&source);
+        if self.reload_on == toml::ReloadOn::Content && hash == self.hash {
+            self.record_mtime(mtime);
+            return None;
         }
+
+        // unchanged appenders are taken from the current config rather than
+        // created again
+        self.creator.set_reusable(shared.lock().unwrap().identities());
+        let config = match parse_config(&self.path, &source, &self.creator) {
+            Ok(config) => config,
+            Err(err) => {
+                handle_error(&*err);
+                return None;
+            }
+        };
+        self.hash = hash;
+        self.record_mtime(mtime);
+        self.reload_on = config.reload_on();
+        let (refresh_rate, watch, config) = config.unpack();
+        self.rate = refresh_rate;
+        self.watch = watch;
+        Some(config)
+    }
+
+    fn read_mtime(&self) -> Option<SystemTime> {
+        fs::metadata(&self.path).and_then(|metadata| metadata.modified()).ok()
+    }
+
+    fn record_mtime(&mut self, mtime: Option<SystemTime>) {
+        self.mtime = match self.reload_on {
+            toml::ReloadOn::Mtime => mtime,
+            toml::ReloadOn::Content => {
+                mtime.filter(|mtime| {
+                    match SystemTime::now().duration_since(*mtime) {
+                        Ok(age) => age >= StdDuration::from_secs(RACY_MTIME_SECS),
+                        Err(_) => false,
+                    }
+                })
+            }
+        };
+    }
+}
+
+fn hash_source(source: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    source.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Installs a panic hook which logs panics through log4rs.
+///
+/// Each panic is logged at the `Error` level with the target `panic`, and
+/// its message includes the name of the panicking thread and the location of
+/// the panic. The previously installed hook is called afterwards, so by
+/// default panics are still printed to stderr as well. Panics raised by an
+/// appender while it is logging are not logged.
+pub fn install_panic_hook() {
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        if !LOGGING.with(|logging| logging.get()) {
+            let thread = thread::current();
+            let message = panic_message(thread.name().unwrap_or("<unnamed>"),
+                                        info.payload(),
+                                        info.location());
+            error!(target: "panic", "{}", message);
+        }
+        previous(info);
+    }));
+}
+
+fn panic_message(thread: &str, payload: &Any, location: Option<&panic::Location>) -> String {
+    let message = match payload.downcast_ref::<&'static str>() {
+        Some(message) => *message,
+        None => {
+            match payload.downcast_ref::<String>() {
+                Some(message) => &**message,
+                None => "Box<Any>",
+            }
+        }
+    };
+    match location {
+        Some(location) => {
+            format!("thread '{}' panicked at '{}', {}:{}",
+                    thread,
+                    message,
+                    location.file(),
+                    location.line())
+        }
+        None => format!("thread '{}' panicked at '{}'", thread, message),
     }
 }
 
@@ -476,8 +1613,16 @@ trait ConfigPrivateExt {
 }
 
 #[doc(hidden)]
-trait PrivateTomlConfigExt {
-    fn unpack(self) -> (Option<Duration>, config::Config);
+trait PrivateTomlConfigExt: Sized {
+    fn unpack(self) -> (Option<Duration>, bool, config::Config);
+
+    fn parse_table(table: toml_parser::Table, creator: &Creator)
+                   -> Result<(Self, Result<(), toml::Errors>), toml::ParseErrors>;
+}
+
+#[doc(hidden)]
+trait PrivateTomlParseErrorsExt {
+    fn new(errors: Vec<String>) -> Self;
 }
 
 #[doc(hidden)]
@@ -487,15 +1632,70 @@ trait PrivateConfigErrorsExt {
 
 #[doc(hidden)]
 trait PrivateConfigAppenderExt {
-    fn unpack(self) -> (String, Box<Append>, Vec<Box<Filter>>);
+    // Creates a slot for an appender to be taken over from the current
+    // configuration by `SharedLogger::reuse`.
+    fn reused(name: String) -> config::AppenderBuilder;
+
+    fn unpack(self)
+              -> (String,
+                  Option<Box<Append>>,
+                  Vec<Box<Filter>>,
+                  config::ErrorPolicy,
+                  bool,
+                  Option<String>);
+}
+
+#[doc(hidden)]
+trait PrivateCreatorExt {
+    fn set_reusable(&mut self, reusable: HashMap<String, String>);
+
+    fn set_reloading(&mut self, reloading: bool);
 }
 
 #[cfg(test)]
 mod test {
-    use log::{LogLevel, LogLevelFilter};
+    use log::{LogLevel, LogLevelFilter, LogRecord};
+    use std::env;
+    use std::error;
+    use std::fs;
+    use std::io::{self, Write};
+    use std::process::Command;
+    use std::sync::{Arc, Mutex, MutexGuard, mpsc};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::{Duration as StdDuration, Instant, UNIX_EPOCH};
 
     use super::*;
 
+    struct FlushCounter(Arc<AtomicUsize>);
+
+    impl Append for FlushCounter {
+        fn append(&mut self, _: &LogRecord) -> Result<(), Box<error::Error>> {
+            Ok(())
+        }
+
+        fn flush(&mut self) -> Result<(), Box<error::Error>> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn flush() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let root = config::Root::builder(LogLevelFilter::Debug).build();
+        let config = config::Config::builder(root)
+            .appender(config::Appender::builder("a".to_string(),
+                                                Box::new(FlushCounter(count.clone()))).build())
+            .appender(config::Appender::builder("b".to_string(),
+                                                Box::new(FlushCounter(count.clone()))).build())
+            .build()
+            .unwrap();
+
+        super::SharedLogger::new(config).flush();
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+
     #[test]
     fn enabled() {
         let root = config::Root::builder(LogLevelFilter::Debug).build();
@@ -521,4 +1721,989 @@ mod test {
         assert!(!logger.enabled_inner(LogLevel::Warn, "foo::baz::buz::bar"));
         assert!(logger.enabled_inner(LogLevel::Error, "foo::baz::buz::bar"));
     }
+
+    #[test]
+    fn target_routing() {
+        let root = config::Root::builder(LogLevelFilter::Warn).build();
+        let appender = Box::new(FlushCounter(Arc::new(AtomicUsize::new(0))));
+        let config = config::Config::builder(root)
+            .appender(config::Appender::builder("access", appender).build())
+            .logger(config::Logger::builder("http::access", LogLevelFilter::Info)
+                        .appender("access")
+                        .build())
+            .logger(config::Logger::builder("my_crate::http", LogLevelFilter::Error).build())
+            .build()
+            .unwrap();
+
+        let logger = super::SharedLogger::new(config);
+        // a library in `my_crate::http` logging with `target: "http::access"`
+        assert!(logger.root.find("http::access").enabled(LogLevel::Info));
+        assert_eq!(logger.root.find("http::access").appenders, [0]);
+        assert!(!logger.root.find("my_crate::http").enabled(LogLevel::Info));
+        assert!(logger.root.find("my_crate::http").appenders.is_empty());
+    }
+
+    #[test]
+    fn filtered_max_level() {
+        use filter::{MessageFilter, ThresholdFilter};
+
+        let counter = || Box::new(FlushCounter(Arc::new(AtomicUsize::new(0))));
+        let threshold = |level| Box::new(ThresholdFilter::new(level));
+        let root = config::Root::builder(LogLevelFilter::Trace).appender("warn").build();
+        let config = config::Config::builder(root)
+            .appender(config::Appender::builder("warn", counter())
+                          .filter(threshold(LogLevelFilter::Warn))
+                          .build())
+            .appender(config::Appender::builder("info", counter())
+                          .filter(threshold(LogLevelFilter::Debug))
+                          .filter(threshold(LogLevelFilter::Info))
+                          .build())
+            .appender(config::Appender::builder("message", counter())
+                          .filter(Box::new(MessageFilter::new("x", FilterResponse::Accept)))
+                          .filter(threshold(LogLevelFilter::Error))
+                          .build())
+            .logger(config::Logger::builder("info", LogLevelFilter::Trace)
+                        .appender("info")
+                        .build())
+            .logger(config::Logger::builder("quiet", LogLevelFilter::Error).build())
+            .logger(config::Logger::builder("message", LogLevelFilter::Debug)
+                        .appender("message")
+                        .additive(false)
+                        .build())
+            .logger(config::Logger::builder("none", LogLevelFilter::Trace)
+                        .additive(false)
+                        .build())
+            .build()
+            .unwrap();
+
+        let logger = super::SharedLogger::new(config);
+        assert_eq!(logger.root.max_level, LogLevelFilter::Warn);
+        assert_eq!(logger.root.find("info").max_level, LogLevelFilter::Info);
+        assert_eq!(logger.root.find("quiet").max_level, LogLevelFilter::Error);
+        assert_eq!(logger.root.find("message").max_level, LogLevelFilter::Debug);
+        assert_eq!(logger.root.find("none").max_level, LogLevelFilter::Off);
+        assert_eq!(logger.root.max_log_level(), LogLevelFilter::Debug);
+    }
+
+    #[test]
+    fn root_off() {
+        let path = env::temp_dir().join("log4rs-root-off-test.log");
+        let source = format!("[appender.file]\n\
+                              kind = \"file\"\n\
+                              path = {:?}\n\
+                              append = false\n\
+                              pattern = \"%t %m\"\n\
+                              [root]\n\
+                              level = \"off\"\n\
+                              appenders = [\"file\"]\n\
+                              [[logger]]\n\
+                              name = \"root_off::enabled\"\n\
+                              level = \"info\"",
+                             path.to_str().unwrap());
+        let (config, errors) = toml::Config::parse(&source, &Creator::default()).unwrap();
+        errors.unwrap();
+        let config = config.unpack().2;
+
+        let logger = super::Logger::new(config);
+        assert!(!logger.enabled_inner(LogLevel::Error, "root_off"));
+        assert!(!logger.enabled_inner(LogLevel::Error, "root_off::other"));
+        assert!(logger.enabled_inner(LogLevel::Info, "root_off::enabled"));
+        assert!(!logger.enabled_inner(LogLevel::Debug, "root_off::enabled"));
+        assert!(logger.enabled_inner(LogLevel::Info, "root_off::enabled::child"));
+        assert_eq!(logger.max_log_level(), LogLevelFilter::Info);
+
+        let (config, _) = toml::Config::parse(&source, &Creator::default()).unwrap();
+        let handle = global_handle();
+        handle.as_ref().unwrap().set_config(config.unpack().2);
+        error!(target: "root_off", "one");
+        info!(target: "root_off::enabled", "two");
+        debug!(target: "root_off::enabled", "three");
+        info!(target: "root_off::enabled::child", "four");
+        let root = config::Root::builder(LogLevelFilter::Off).build();
+        handle.as_ref().unwrap().set_config(config::Config::builder(root).build().unwrap());
+
+        assert_eq!(fs::read_to_string(&path).unwrap(),
+                   "root_off::enabled two\nroot_off::enabled::child four\n");
+    }
+
+    #[test]
+    fn error_policies() {
+        let err = || Box::new(io::Error::new(io::ErrorKind::Other, "broken")) as Box<error::Error>;
+        let appender = |on_error| {
+            let counter = Box::new(FlushCounter(Arc::new(AtomicUsize::new(0))));
+            super::Appender::new("a".to_owned(), Some(counter), vec![], on_error, false, None)
+        };
+        let now = Instant::now();
+
+        let mut ignore = appender(config::ErrorPolicy::Ignore);
+        ignore.fail(err(), now);
+        assert!(ignore.ready(now));
+
+        let mut disable = appender(config::ErrorPolicy::Disable);
+        disable.fail(err(), now);
+        assert!(!disable.ready(now + StdDuration::from_secs(3600)));
+
+        let mut backoff = appender(config::ErrorPolicy::Backoff);
+        backoff.fail(err(), now);
+        assert!(!backoff.ready(now));
+        assert!(backoff.ready(now + StdDuration::from_secs(1)));
+        backoff.fail(err(), now);
+        assert!(!backoff.ready(now + StdDuration::from_secs(1)));
+        assert!(backoff.ready(now + StdDuration::from_secs(2)));
+        for _ in 0..10 {
+            backoff.fail(err(), now);
+        }
+        assert!(backoff.ready(now + StdDuration::from_secs(60)));
+        assert_eq!(backoff.stats.errors(), 12);
+    }
+
+    #[test]
+    fn wildcard_loggers() {
+        let root = config::Root::builder(LogLevelFilter::Warn).build();
+        let counter = || Box::new(FlushCounter(Arc::new(AtomicUsize::new(0))));
+        let config = config::Config::builder(root)
+            .appender(config::Appender::builder("a", counter()).build())
+            .appender(config::Appender::builder("b", counter()).build())
+            .logger(config::Logger::builder("hyper::client", LogLevelFilter::Trace).build())
+            .logger(config::Logger::builder("hyper::*", LogLevelFilter::Debug)
+                        .appender("b")
+                        .build())
+            .logger(config::Logger::builder("hyper", LogLevelFilter::Error)
+                        .appender("a")
+                        .build())
+            .build()
+            .unwrap();
+
+        let logger = super::SharedLogger::new(config);
+        let hyper = logger.root.find("hyper");
+        assert_eq!(hyper.level, LogLevelFilter::Error);
+        assert_eq!(hyper.appenders, [0]);
+        for target in &["hyper::server", "hyper::server::conn"] {
+            let server = logger.root.find(target);
+            assert_eq!(server.level, LogLevelFilter::Debug);
+            assert_eq!(server.appenders, [1, 0]);
+        }
+        for target in &["hyper::client", "hyper::client::pool"] {
+            let client = logger.root.find(target);
+            assert_eq!(client.level, LogLevelFilter::Trace);
+            assert_eq!(client.appenders, [1, 0]);
+        }
+        assert_eq!(logger.root.find("hyperx").level, LogLevelFilter::Warn);
+    }
+
+    // The global logger can only be set once per process, so tests which log
+    // through it share a handle and take turns.
+    pub fn global_handle() -> MutexGuard<'static, Option<Handle>> {
+        static HANDLE: Mutex<Option<Handle>> = Mutex::new(None);
+        let mut handle = HANDLE.lock().unwrap_or_else(|e| e.into_inner());
+        if handle.is_none() {
+            let root = config::Root::builder(LogLevelFilter::Off).build();
+            *handle = Some(init_config(config::Config::builder(root).build().unwrap()).unwrap());
+        }
+        handle
+    }
+
+    // Records the messages of error log events, and accepts no others.
+    pub struct ErrorsOnly(pub Arc<Mutex<Vec<String>>>);
+
+    impl Append for ErrorsOnly {
+        fn append(&mut self, record: &LogRecord) -> Result<(), Box<error::Error>> {
+            assert_eq!(record.level(), LogLevel::Error);
+            self.0.lock().unwrap().push(record.args().to_string());
+            Ok(())
+        }
+
+        fn accepts(&self, record: &LogRecord) -> bool {
+            record.level() == LogLevel::Error
+        }
+    }
+
+    // Runs the named test again in a child process with `TZ` set, since the
+    // time zone is process-wide. Returns true in the child, which should go
+    // on to run the test, and false in the parent once the child has passed.
+    pub fn in_time_zone(test: &str, tz: &str) -> bool {
+        if env::var("LOG4RS_TEST_TZ").ok().map_or(false, |t| t == tz) {
+            return true;
+        }
+        let output = Command::new(env::current_exe().unwrap())
+            .args(&[test, "--exact", "--test-threads", "1"])
+            .env("TZ", tz)
+            .env("LOG4RS_TEST_TZ", tz)
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(output.status.success() && stdout.contains("1 passed"),
+                "{} failed in time zone {}:\n{}{}",
+                test,
+                tz,
+                stdout,
+                String::from_utf8_lossy(&output.stderr));
+        false
+    }
+
+    // Creates a `LogEvent` as if it had been logged by the current thread,
+    // since tests cannot create the `LogRecord` it would be captured from.
+    pub fn log_event(level: LogLevel, target: &str, message: &str) -> LogEvent {
+        LogEvent {
+            level: level,
+            target: target.to_owned(),
+            location: LogLocation {
+                __module_path: module_path!(),
+                __file: file!(),
+                __line: line!(),
+            },
+            message: message.to_owned(),
+            time: time::get_time(),
+            instant: Instant::now(),
+            thread_name: thread::current().name().map(|n| n.to_owned()),
+            thread_id: thread_id(),
+            thread_number: thread_number(),
+            mdc: mdc::entries(),
+        }
+    }
+
+    #[test]
+    fn shared_appender() {
+        let path = env::temp_dir().join("log4rs-shared-appender-test.log");
+        let appender = appender::FileAppender::builder(&path)
+            .pattern(pattern::PatternLayout::new("%t %m").unwrap())
+            .append(false)
+            .build()
+            .unwrap();
+        let root = config::Root::builder(LogLevelFilter::Off).build();
+        let config = config::Config::builder(root)
+            .logger(config::Logger::builder("foo", LogLevelFilter::Info).build())
+            .attach(config::Appender::builder("file", Box::new(appender)).build(),
+                    vec!["foo", "foo::bar"])
+            .logger(config::Logger::builder("foo::bar", LogLevelFilter::Info).build())
+            .build()
+            .unwrap();
+
+        let handle = global_handle();
+        handle.as_ref().unwrap().set_config(config);
+        info!(target: "foo", "one");
+        info!(target: "foo::bar", "two");
+        info!(target: "foo", "three");
+        let root = config::Root::builder(LogLevelFilter::Off).build();
+        handle.as_ref().unwrap().set_config(config::Config::builder(root).build().unwrap());
+
+        // `foo::bar` inherits the appender from `foo` as well, but writes once
+        assert_eq!(fs::read_to_string(&path).unwrap(), "foo one\nfoo::bar two\nfoo three\n");
+    }
+
+    struct Failing;
+
+    impl Append for Failing {
+        fn append(&mut self, _: &LogRecord) -> Result<(), Box<error::Error>> {
+            Err(Box::new(io::Error::other("failing appender")))
+        }
+    }
+
+    #[test]
+    fn error_handler() {
+        // the handle also serializes the tests which set the error handler
+        let handle = global_handle();
+        let errors = Arc::new(Mutex::new(vec![]));
+        let errors2 = errors.clone();
+        set_error_handler(Box::new(move |err| {
+            errors2.lock().unwrap().push(err.to_string());
+            // would deadlock if it were not discarded
+            error!(target: "error_handler", "{}", err);
+        }));
+        let root = config::Root::builder(LogLevelFilter::Off).build();
+        let config = config::Config::builder(root)
+            .appender(config::Appender::builder("failing", Box::new(Failing)).build())
+            .logger(config::Logger::builder("error_handler", LogLevelFilter::Info)
+                        .appender("failing")
+                        .build())
+            .build()
+            .unwrap();
+
+        handle.as_ref().unwrap().set_config(config);
+        info!(target: "error_handler", "one");
+        let root = config::Root::builder(LogLevelFilter::Off).build();
+        handle.as_ref().unwrap().set_config(config::Config::builder(root).build().unwrap());
+        *super::ERROR_HANDLER.write().unwrap() = None;
+
+        assert!(errors.lock().unwrap().iter().any(|e| e == "failing appender"));
+    }
+
+    #[test]
+    fn error_handler_replaced_by_itself() {
+        let _handle = global_handle();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let calls2 = calls.clone();
+        set_error_handler(Box::new(move |_| {
+            calls2.fetch_add(1, Ordering::SeqCst);
+            // would deadlock if the handler were called under the lock
+            set_error_handler(Box::new(|_| {}));
+        }));
+        handle_error(&io::Error::other("one"));
+        handle_error(&io::Error::other("two"));
+        *super::ERROR_HANDLER.write().unwrap() = None;
+
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn required_appender() {
+        let memory = appender::MemoryAppender::builder(10)
+            .pattern(pattern::PatternLayout::new("%t").unwrap())
+            .build();
+        let memory_handle = memory.handle();
+        let root = config::Root::builder(LogLevelFilter::Off).build();
+        let config = config::Config::builder(root)
+            .appender(config::Appender::builder("memory", Box::new(memory)).build())
+            .appender(config::Appender::builder("failing", Box::new(Failing)).build())
+            .appender(config::Appender::builder("required", Box::new(Failing))
+                          .required(true)
+                          .build())
+            .logger(config::Logger::builder("required::before", LogLevelFilter::Info)
+                        .appenders(vec!["required".to_owned(), "memory".to_owned()])
+                        .build())
+            .logger(config::Logger::builder("required::after", LogLevelFilter::Info)
+                        .appenders(vec!["memory".to_owned(), "required".to_owned()])
+                        .build())
+            .logger(config::Logger::builder("required::optional", LogLevelFilter::Info)
+                        .appenders(vec!["failing".to_owned(), "memory".to_owned()])
+                        .build())
+            .logger(config::Logger::builder("required::child", LogLevelFilter::Info)
+                        .appender("required")
+                        .build())
+            .logger(config::Logger::builder("required::child::inherited", LogLevelFilter::Info)
+                        .appender("memory")
+                        .build())
+            .build()
+            .unwrap();
+
+        let handle = global_handle();
+        handle.as_ref().unwrap().set_config(config);
+        info!(target: "required::before", "one");
+        info!(target: "required::after", "two");
+        info!(target: "required::optional", "three");
+        // a logger's own appenders come before those it inherits
+        info!(target: "required::child::inherited", "four");
+        let root = config::Root::builder(LogLevelFilter::Off).build();
+        handle.as_ref().unwrap().set_config(config::Config::builder(root).build().unwrap());
+
+        assert_eq!(memory_handle.snapshot(),
+                   ["required::after", "required::optional", "required::child::inherited"]);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn reopen_on_sighup() {
+        let dir = env::temp_dir().join("log4rs-reopen-on-sighup-test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("app.log");
+        let file = appender::FileAppender::builder(&path)
+            .pattern(pattern::PatternLayout::new("%m").unwrap())
+            .build()
+            .unwrap();
+        let root = config::Root::builder(LogLevelFilter::Off).build();
+        let config = config::Config::builder(root)
+            .appender(config::Appender::builder("file", Box::new(file)).build())
+            .logger(config::Logger::builder("reopen_on_sighup", LogLevelFilter::Info)
+                        .appender("file")
+                        .build())
+            .build()
+            .unwrap();
+
+        super::reopen_on_sighup().unwrap();
+        let handle = global_handle();
+        handle.as_ref().unwrap().set_config(config);
+        info!(target: "reopen_on_sighup", "one");
+        fs::rename(&path, dir.join("app.log.1")).unwrap();
+        info!(target: "reopen_on_sighup", "two");
+        unsafe {
+            libc::raise(libc::SIGHUP);
+        }
+        info!(target: "reopen_on_sighup", "three");
+        let root = config::Root::builder(LogLevelFilter::Off).build();
+        handle.as_ref().unwrap().set_config(config::Config::builder(root).build().unwrap());
+
+        assert_eq!(fs::read_to_string(dir.join("app.log.1")).unwrap(), "one\ntwo\n");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "three\n");
+    }
+
+    #[test]
+    fn flush_level() {
+        let path = env::temp_dir().join("log4rs-flush-level-test.log");
+        let file = appender::FileAppender::builder(&path)
+            .pattern(pattern::PatternLayout::new("%m").unwrap())
+            .append(false)
+            .buffer_size(1024)
+            .flush_level(LogLevel::Warn)
+            .build()
+            .unwrap();
+        let root = config::Root::builder(LogLevelFilter::Off).build();
+        let config = config::Config::builder(root)
+            .appender(config::Appender::builder("file", Box::new(file)).build())
+            .logger(config::Logger::builder("flush_level", LogLevelFilter::Info)
+                        .appender("file")
+                        .build())
+            .build()
+            .unwrap();
+
+        let handle = global_handle();
+        handle.as_ref().unwrap().set_config(config);
+        info!(target: "flush_level", "one");
+        let buffered = fs::read_to_string(&path).unwrap();
+        warn!(target: "flush_level", "two");
+        let flushed = fs::read_to_string(&path).unwrap();
+        info!(target: "flush_level", "three");
+        let root = config::Root::builder(LogLevelFilter::Off).build();
+        handle.as_ref().unwrap().set_config(config::Config::builder(root).build().unwrap());
+
+        assert_eq!(buffered, "");
+        assert_eq!(flushed, "one\ntwo\n");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "one\ntwo\nthree\n");
+    }
+
+    // Hands the log events it receives on to a `DirectLogger`, since they
+    // cannot be constructed directly.
+    struct Forward(Arc<Mutex<DirectLogger>>);
+
+    impl Append for Forward {
+        fn append(&mut self, record: &LogRecord) -> Result<(), Box<error::Error>> {
+            self.0.lock().unwrap().log(record);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn direct_logger() {
+        let memory = appender::MemoryAppender::builder(10)
+            .pattern(pattern::PatternLayout::new("%l %m").unwrap())
+            .build();
+        let memory_handle = memory.handle();
+        let root = config::Root::builder(LogLevelFilter::Warn).appender("memory").build();
+        let config = config::Config::builder(root)
+            .appender(config::Appender::builder("memory", Box::new(memory)).build())
+            .build()
+            .unwrap();
+        let direct = Arc::new(Mutex::new(DirectLogger::new(config)));
+        assert!(direct.lock().unwrap().enabled(LogLevel::Warn, "direct"));
+        assert!(!direct.lock().unwrap().enabled(LogLevel::Info, "direct"));
+
+        let root = config::Root::builder(LogLevelFilter::Off).build();
+        let config = config::Config::builder(root)
+            .appender(config::Appender::builder("forward", Box::new(Forward(direct.clone())))
+                          .build())
+            .logger(config::Logger::builder("direct", LogLevelFilter::Info)
+                        .appender("forward")
+                        .build())
+            .build()
+            .unwrap();
+
+        let handle = global_handle();
+        handle.as_ref().unwrap().set_config(config);
+        error!(target: "direct", "one");
+        info!(target: "direct", "two");
+        let root = config::Root::builder(LogLevelFilter::Off).build();
+        handle.as_ref().unwrap().set_config(config::Config::builder(root).build().unwrap());
+
+        assert_eq!(memory_handle.snapshot(), ["ERROR one"]);
+        assert_eq!(direct.lock().unwrap().stats()["memory"].records(), 1);
+    }
+
+    #[test]
+    fn accepts() {
+        let messages = Arc::new(Mutex::new(vec![]));
+        let root = config::Root::builder(LogLevelFilter::Off).build();
+        let config = config::Config::builder(root)
+            .appender(config::Appender::builder("errors", Box::new(ErrorsOnly(messages.clone())))
+                          .build())
+            .logger(config::Logger::builder("accepts", LogLevelFilter::Info)
+                        .appender("errors")
+                        .build())
+            .build()
+            .unwrap();
+
+        let handle = global_handle();
+        handle.as_ref().unwrap().set_config(config);
+        error!(target: "accepts", "one");
+        info!(target: "accepts", "two");
+        let stats = handle.as_ref().unwrap().stats();
+        let root = config::Root::builder(LogLevelFilter::Off).build();
+        handle.as_ref().unwrap().set_config(config::Config::builder(root).build().unwrap());
+
+        assert_eq!(*messages.lock().unwrap(), ["one"]);
+        assert_eq!(stats["errors"].records(), 1);
+    }
+
+    #[test]
+    fn effective_level() {
+        let root = config::Root::builder(LogLevelFilter::Warn).build();
+        let config = config::Config::builder(root)
+            .logger(config::Logger::builder("effective", LogLevelFilter::Debug).build())
+            .logger(config::Logger::builder("effective::quiet", LogLevelFilter::Error).build())
+            .build()
+            .unwrap();
+
+        let handle = global_handle();
+        let handle = handle.as_ref().unwrap();
+        handle.set_config(config);
+        let levels = ["other", "effective", "effective::child", "effective::quiet::child"]
+            .iter()
+            .map(|t| handle.effective_level(t))
+            .collect::<Vec<_>>();
+        let root = config::Root::builder(LogLevelFilter::Off).build();
+        handle.set_config(config::Config::builder(root).build().unwrap());
+
+        assert_eq!(levels,
+                   [LogLevelFilter::Warn,
+                    LogLevelFilter::Debug,
+                    LogLevelFilter::Debug,
+                    LogLevelFilter::Error]);
+        assert_eq!(handle.effective_level("effective"), LogLevelFilter::Off);
+    }
+
+    // Signals when it starts appending, then waits for the gate to open.
+    struct Gated {
+        started: mpsc::Sender<()>,
+        gate: Arc<Mutex<()>>,
+    }
+
+    impl Append for Gated {
+        fn append(&mut self, _: &LogRecord) -> Result<(), Box<error::Error>> {
+            self.started.send(()).unwrap();
+            let _gate = self.gate.lock().unwrap();
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn concurrent_appenders() {
+        let (started, started_rx) = mpsc::channel();
+        let gate = Arc::new(Mutex::new(()));
+        let fast = appender::MemoryAppender::builder(10)
+            .pattern(pattern::PatternLayout::new("%m").unwrap())
+            .build();
+        let fast_handle = fast.handle();
+        let gated = Gated {
+            started: started,
+            gate: gate.clone(),
+        };
+        let root = config::Root::builder(LogLevelFilter::Off).build();
+        let config = config::Config::builder(root)
+            .appender(config::Appender::builder("slow", Box::new(gated)).build())
+            .appender(config::Appender::builder("fast", Box::new(fast)).build())
+            .logger(config::Logger::builder("concurrent::slow", LogLevelFilter::Info)
+                        .appender("slow")
+                        .build())
+            .logger(config::Logger::builder("concurrent::fast", LogLevelFilter::Info)
+                        .appender("fast")
+                        .build())
+            .build()
+            .unwrap();
+
+        let handle = global_handle();
+        handle.as_ref().unwrap().set_config(config);
+        let closed = gate.lock().unwrap();
+        let slow = thread::spawn(|| info!(target: "concurrent::slow", "slow"));
+        started_rx.recv().unwrap();
+        // would deadlock if the slow appender held up the whole logger
+        info!(target: "concurrent::fast", "fast");
+        assert_eq!(fast_handle.snapshot(), ["fast"]);
+        drop(closed);
+        slow.join().unwrap();
+        let root = config::Root::builder(LogLevelFilter::Off).build();
+        handle.as_ref().unwrap().set_config(config::Config::builder(root).build().unwrap());
+    }
+
+    #[test]
+    fn root_appenders_end_to_end() {
+        let dir = env::temp_dir().join("log4rs-root-appenders-test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let console = dir.join("console.log");
+        let json = dir.join("json.log");
+        // stdout cannot be captured, so the console appender's pattern and
+        // filter are given to a file appender instead
+        let source = format!("[appender.console]\n\
+                              kind = \"file\"\n\
+                              path = {:?}\n\
+                              pattern = \"%H{{%l}} %t - %m\"\n\
+                              [[appender.console.filter]]\n\
+                              kind = \"threshold\"\n\
+                              level = \"warn\"\n\
+                              [appender.json]\n\
+                              kind = \"file\"\n\
+                              path = {:?}\n\
+                              encoder = \"json\"\n\
+                              [root]\n\
+                              level = \"trace\"\n\
+                              appenders = [\"console\", \"json\"]",
+                             console.to_str().unwrap(),
+                             json.to_str().unwrap());
+        let (config, errors) = toml::Config::parse(&source, &Creator::default()).unwrap();
+        errors.unwrap();
+
+        let handle = global_handle();
+        handle.as_ref().unwrap().set_config(config.unpack().2);
+        error!(target: "end_to_end", "one");
+        warn!(target: "end_to_end", "two");
+        info!(target: "end_to_end", "three");
+        trace!(target: "end_to_end", "four");
+        let root = config::Root::builder(LogLevelFilter::Off).build();
+        handle.as_ref().unwrap().set_config(config::Config::builder(root).build().unwrap());
+
+        assert_eq!(fs::read_to_string(&console).unwrap(),
+                   "\x1b[31mERROR\x1b[0m end_to_end - one\n\
+                    \x1b[33mWARN\x1b[0m end_to_end - two\n");
+        let json = fs::read_to_string(&json).unwrap();
+        let lines = json.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 4);
+        for (line, &(level, message)) in lines.iter().zip(&[("ERROR", "one"),
+                                                            ("WARN", "two"),
+                                                            ("INFO", "three"),
+                                                            ("TRACE", "four")]) {
+            assert!(line.starts_with("{\"timestamp\":\""), "{}", line);
+            assert!(line.contains(&format!("\"level\":\"{}\",\"target\":\"end_to_end\"", level)),
+                    "{}",
+                    line);
+            assert!(line.ends_with(&format!("\"message\":\"{}\"}}", message)), "{}", line);
+        }
+    }
+
+    #[test]
+    fn additivity_chain() {
+        let root = config::Root::builder(LogLevelFilter::Warn).appender("root").build();
+        let counter = || Box::new(FlushCounter(Arc::new(AtomicUsize::new(0))));
+        let mut config = config::Config::builder(root);
+        for name in &["root", "a", "b", "c", "d"] {
+            config = config.appender(config::Appender::builder(*name, counter()).build());
+        }
+        // added in an order which does not match the hierarchy
+        let config = config
+            .logger(config::Logger::builder("a::b::c::d", LogLevelFilter::Info)
+                        .appender("d")
+                        .build())
+            .logger(config::Logger::builder("a::b::c", LogLevelFilter::Info)
+                        .appender("c")
+                        .build())
+            .logger(config::Logger::builder("a", LogLevelFilter::Info).appender("a").build())
+            .logger(config::Logger::builder("a::b", LogLevelFilter::Info)
+                        .appender("b")
+                        .additive(false)
+                        .build())
+            .build()
+            .unwrap();
+
+        let logger = super::SharedLogger::new(config);
+        let cases: [(&str, &[usize]); 7] = [("other", &[0]),
+                                            ("a", &[1, 0]),
+                                            ("a::x", &[1, 0]),
+                                            ("a::b", &[2]),
+                                            ("a::b::x", &[2]),
+                                            ("a::b::c", &[3, 2]),
+                                            ("a::b::c::d::e", &[4, 3, 2])];
+        for &(target, appenders) in &cases {
+            assert_eq!(logger.root.find(target).appenders, appenders, "{}", target);
+        }
+    }
+
+    #[test]
+    fn non_additive() {
+        let root = config::Root::builder(LogLevelFilter::Warn).appender("root").build();
+        let counter = || Box::new(FlushCounter(Arc::new(AtomicUsize::new(0))));
+        let config = config::Config::builder(root)
+            .appender(config::Appender::builder("root", counter()).build())
+            .appender(config::Appender::builder("own", counter()).build())
+            .appender(config::Appender::builder("child", counter()).build())
+            .logger(config::Logger::builder("own", LogLevelFilter::Debug)
+                        .appender("own")
+                        .additive(false)
+                        .build())
+            .logger(config::Logger::builder("silent", LogLevelFilter::Trace)
+                        .additive(false)
+                        .build())
+            .logger(config::Logger::builder("silent::child", LogLevelFilter::Info)
+                        .appender("child")
+                        .build())
+            .build()
+            .unwrap();
+
+        let logger = super::SharedLogger::new(config);
+
+        // own appenders replace the inherited ones, for descendants too
+        let own = logger.root.find("own::x");
+        assert_eq!(own.appenders, [1]);
+        assert_eq!(own.level, LogLevelFilter::Debug);
+
+        // no appenders at all discards log events, but the level is inherited
+        let silent = logger.root.find("silent::x");
+        assert!(silent.appenders.is_empty());
+        assert_eq!(silent.level, LogLevelFilter::Trace);
+        assert_eq!(silent.max_level, LogLevelFilter::Off);
+
+        // an additive descendant only has its own appenders to add to
+        let child = logger.root.find("silent::child");
+        assert_eq!(child.appenders, [2]);
+        assert_eq!(child.level, LogLevelFilter::Info);
+    }
+
+    #[test]
+    fn reload_refresh_rate() {
+        let path = env::temp_dir().join("log4rs-reload-refresh-rate-test.toml");
+        fs::File::create(&path).unwrap().write_all(b"refresh_rate = 30").unwrap();
+
+        let mut reloader = super::ConfigReloader {
+            path: path.clone(),
+            rate: Some(Duration::seconds(30)),
+            watch: false,
+            reload_on: toml::ReloadOn::Content,
+            hash: super::hash_source("refresh_rate = 30"),
+            mtime: None,
+            creator: Creator::default(),
+        };
+        let shared = Mutex::new(SharedLogger::new(config::Config::builder(
+            config::Root::builder(LogLevelFilter::Warn).build()).build().unwrap()));
+        assert!(reloader.reload(&shared).is_none());
+
+        fs::File::create(&path).unwrap().write_all(b"refresh_rate = 1").unwrap();
+        assert!(reloader.reload(&shared).is_some());
+        assert_eq!(reloader.rate, Some(Duration::seconds(1)));
+
+        fs::File::create(&path).unwrap().write_all(b"watch = true").unwrap();
+        assert!(reloader.reload(&shared).is_some());
+        assert_eq!(reloader.rate, None);
+        assert!(reloader.watch);
+
+        fs::File::create(&path).unwrap().write_all(b"[root]\nlevel = \"warn\"").unwrap();
+        let config = reloader.reload(&shared).unwrap();
+        assert_eq!(config.root().level(), LogLevelFilter::Warn);
+        assert_eq!(reloader.rate, None);
+        assert!(!reloader.watch);
+    }
+
+    #[test]
+    fn reload_retries_failed_config() {
+        let dir = env::temp_dir().join("log4rs-reload-retry-test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("log4rs.toml");
+        fs::File::create(&path).unwrap().write_all(b"refresh_rate = 30").unwrap();
+
+        let mut reloader = super::ConfigReloader {
+            path: path.clone(),
+            rate: Some(Duration::seconds(30)),
+            watch: false,
+            reload_on: toml::ReloadOn::Content,
+            hash: super::hash_source("refresh_rate = 30"),
+            mtime: None,
+            creator: Creator::default(),
+        };
+        let shared = Mutex::new(SharedLogger::new(config::Config::builder(
+            config::Root::builder(LogLevelFilter::Warn).build()).build().unwrap()));
+
+        fs::File::create(&path)
+            .unwrap()
+            .write_all(b"include = [\"levels.toml\"]\nrefresh_rate = 30")
+            .unwrap();
+        assert!(reloader.reload(&shared).is_none());
+        assert!(reloader.reload(&shared).is_none());
+        assert_eq!(reloader.hash, super::hash_source("refresh_rate = 30"));
+
+        // the config file itself is unchanged
+        fs::File::create(dir.join("levels.toml"))
+            .unwrap()
+            .write_all(b"[root]\nlevel = \"info\"")
+            .unwrap();
+        let config = reloader.reload(&shared).unwrap();
+        assert_eq!(config.root().level(), LogLevelFilter::Info);
+        assert!(reloader.reload(&shared).is_none());
+    }
+
+    #[test]
+    fn reload_on() {
+        let path = env::temp_dir().join("log4rs-reload-on-test.toml");
+        let source = "reload_on = \"mtime\"";
+        fs::File::create(&path).unwrap().write_all(source.as_bytes()).unwrap();
+
+        let mut reloader = super::ConfigReloader {
+            path: path.clone(),
+            rate: None,
+            watch: false,
+            reload_on: toml::ReloadOn::Mtime,
+            hash: super::hash_source(source),
+            mtime: Some(UNIX_EPOCH),
+            creator: Creator::default(),
+        };
+        let shared = Mutex::new(SharedLogger::new(config::Config::builder(
+            config::Root::builder(LogLevelFilter::Warn).build()).build().unwrap()));
+
+        // a new modification time is enough to reload
+        assert!(reloader.reload(&shared).is_some());
+        assert_eq!(reloader.reload_on, toml::ReloadOn::Mtime);
+        assert!(reloader.mtime.is_some());
+        assert!(reloader.reload(&shared).is_none());
+
+        let source = "reload_on = \"content\"";
+        fs::File::create(&path).unwrap().write_all(source.as_bytes()).unwrap();
+        assert!(reloader.reload(&shared).is_some());
+        assert_eq!(reloader.reload_on, toml::ReloadOn::Content);
+
+        // but not once the contents are compared
+        reloader.mtime = Some(UNIX_EPOCH);
+        assert!(reloader.reload(&shared).is_none());
+    }
+
+    #[test]
+    fn reloader_stop() {
+        let path = env::temp_dir().join("log4rs-reloader-stop-test.toml");
+        fs::File::create(&path).unwrap().write_all(b"refresh_rate = 30").unwrap();
+
+        let handle = global_handle();
+        let reloader = super::ConfigReloader::start(path,
+                                                    Some(Duration::seconds(30)),
+                                                    false,
+                                                    toml::ReloadOn::Content,
+                                                    "refresh_rate = 30",
+                                                    Creator::default(),
+                                                    handle.clone().unwrap());
+        assert!(reloader.thread().is_some());
+        let start = Instant::now();
+        reloader.stop();
+        reloader.join().unwrap();
+        assert!(start.elapsed() < StdDuration::from_secs(5));
+
+        assert!(super::Reloader::stopped().join().is_ok());
+    }
+
+    #[test]
+    fn panic_messages() {
+        let payload: Box<Any> = Box::new("boom");
+        assert_eq!(super::panic_message("main", &*payload, None),
+                   "thread 'main' panicked at 'boom'");
+
+        let payload: Box<Any> = Box::new(format!("code {}", 7));
+        let location = panic::Location::caller();
+        assert_eq!(super::panic_message("worker", &*payload, Some(location)),
+                   format!("thread 'worker' panicked at 'code 7', {}:{}",
+                           location.file(),
+                           location.line()));
+    }
+
+    #[test]
+    fn reuse_unchanged_appenders() {
+        let counts = (0..4).map(|_| Arc::new(AtomicUsize::new(0))).collect::<Vec<_>>();
+        let logger = |ids: [Option<&str>; 2], counts: &[Arc<AtomicUsize>]| {
+            let root = config::Root::builder(LogLevelFilter::Warn).build();
+            let mut config = config::Config::builder(root);
+            for (i, (name, id)) in ["a", "b"].iter().zip(&ids).enumerate() {
+                let counter = Box::new(FlushCounter(counts[i].clone()));
+                let mut appender = config::Appender::builder(*name, counter);
+                if let Some(id) = *id {
+                    appender = appender.identity(id);
+                }
+                config = config.appender(appender.build());
+            }
+            SharedLogger::new(config.build().unwrap())
+        };
+        let flushes = |counts: &[Arc<AtomicUsize>]| {
+            counts.iter().map(|c| c.load(Ordering::SeqCst)).collect::<Vec<_>>()
+        };
+
+        let old = logger([Some("x"), Some("y")], &counts[..2]);
+        old.appenders[0].lock().unwrap().stats.records = 3;
+        old.appenders[1].lock().unwrap().stats.records = 4;
+        let mut new = logger([Some("x"), Some("z")], &counts[2..]);
+        new.reuse(&old);
+        new.flush();
+        assert_eq!(flushes(&counts), [1, 0, 0, 1]);
+        let stats = new.stats();
+        assert_eq!(stats["a"].records(), 3);
+        assert_eq!(stats["b"].records(), 0);
+
+        let old = logger([None, None], &counts[..2]);
+        let mut new = logger([None, None], &counts[2..]);
+        new.reuse(&old);
+        new.flush();
+        assert_eq!(flushes(&counts), [1, 0, 1, 2]);
+    }
+
+    #[test]
+    fn reload_preserves_unchanged_appenders() {
+        let dir = env::temp_dir().join("log4rs-reload-preserves-test");
+        let _ = fs::create_dir(&dir);
+        let log = dir.join("foo.log");
+        let path = dir.join("log4rs.toml");
+        let config = |level: &str| {
+            format!("[appender.foo]\nkind = \"file\"\npath = {:?}\nappend = false\n\
+                     [root]\nlevel = \"{}\"\nappenders = [\"foo\"]",
+                    log.to_str().unwrap(),
+                    level)
+        };
+
+        let source = config("warn");
+        fs::File::create(&path).unwrap().write_all(source.as_bytes()).unwrap();
+        let initial = parse_config(&path, &source, &Creator::default()).unwrap().unpack().2;
+        let shared = Mutex::new(SharedLogger::new(initial));
+        fs::File::create(&log).unwrap().write_all(b"kept\n").unwrap();
+
+        let mut reloader = super::ConfigReloader {
+            path: path.clone(),
+            rate: None,
+            watch: false,
+            reload_on: toml::ReloadOn::Content,
+            hash: super::hash_source(&source),
+            mtime: None,
+            creator: Creator::default(),
+        };
+        fs::File::create(&path).unwrap().write_all(config("error").as_bytes()).unwrap();
+        let mut new = SharedLogger::new(reloader.reload(&shared).unwrap());
+        new.reuse(&shared.lock().unwrap());
+
+        // reopening the file with `append = false` would have truncated it
+        assert_eq!(fs::read_to_string(&log).unwrap(), "kept\n");
+    }
+
+    #[test]
+    fn init_file_strict_invalid() {
+        let path = env::temp_dir().join("log4rs-init-file-strict-missing.toml");
+        let _ = fs::remove_file(&path);
+        assert!(super::init_file_strict(&path, Creator::default()).is_err());
+
+        let path = env::temp_dir().join("log4rs-init-file-strict-test.toml");
+        fs::File::create(&path)
+            .unwrap()
+            .write_all(b"[appender.foo]\nkind = \"nonexistent\"")
+            .unwrap();
+        assert!(super::init_file_strict(&path, Creator::default()).is_err());
+    }
+
+    #[test]
+    fn config_path_from_env() {
+        let var = "LOG4RS_TEST_CONFIG";
+        let default = env::temp_dir().join("log4rs-config-path-default.toml");
+        let _ = fs::remove_file(&default);
+
+        env::remove_var(var);
+        let err = super::config_path_from_env(var, &default).unwrap_err();
+        assert_eq!(err.to_string(),
+                   format!("`LOG4RS_TEST_CONFIG` is not set and the default config file `{}` \
+                            does not exist",
+                           default.display()));
+        assert!(super::init_from_env(var, &default, Creator::default()).is_err());
+
+        fs::File::create(&default).unwrap();
+        assert_eq!(super::config_path_from_env(var, &default).unwrap(), default);
+
+        env::set_var(var, "");
+        assert_eq!(super::config_path_from_env(var, &default).unwrap(), default);
+
+        env::set_var(var, "/etc/app/log4rs.toml");
+        assert_eq!(super::config_path_from_env(var, &default).unwrap(),
+                   Path::new("/etc/app/log4rs.toml"));
+        env::remove_var(var);
+    }
+
+    #[test]
+    fn init_config_str_invalid() {
+        assert!(super::init_config_str("[root", Creator::default()).is_err());
+        assert!(super::init_config_str("[root]\nlevel = 1", Creator::default()).is_err());
+    }
 }
@@ -0,0 +1,94 @@
+//! A thread-local mapped diagnostic context.
+//!
+//! The MDC associates key-value pairs with the current thread, which can then
+//! be included in log output with the `%X{key}` pattern specifier without
+//! passing them to every logging call. Values inserted on one thread are not
+//! visible on other threads.
+//!
+//! The whole context can be written with the `%kv` pattern specifier, and
+//! `JsonEncoder` writes each value as a field of its own.
+//!
+//! ```
+//! log4rs::mdc::insert("request_id", "1234");
+//! assert_eq!(log4rs::mdc::get("request_id"), Some("1234".to_owned()));
+//! log4rs::mdc::remove("request_id");
+//! ```
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+thread_local! {
+    static MDC: RefCell<HashMap<String, String>> = RefCell::new(HashMap::new());
+}
+
+/// Inserts a value into the current thread's diagnostic context, returning
+/// the previous value associated with the key.
+pub fn insert<K, V>(key: K, value: V) -> Option<String>
+    where K: Into<String>,
+          V: Into<String>
+{
+    MDC.with(|m| m.borrow_mut().insert(key.into(), value.into()))
+}
+
+/// Removes a value from the current thread's diagnostic context, returning
+/// it.
+pub fn remove(key: &str) -> Option<String> {
+    MDC.with(|m| m.borrow_mut().remove(key))
+}
+
+/// Returns a copy of a value in the current thread's diagnostic context.
+pub fn get(key: &str) -> Option<String> {
+    with(key, |v| v.map(|v| v.to_owned()))
+}
+
+/// Removes all values from the current thread's diagnostic context.
+pub fn clear() {
+    MDC.with(|m| m.borrow_mut().clear())
+}
+
+/// Returns a copy of all values in the current thread's diagnostic context,
+/// sorted by key.
+pub fn entries() -> Vec<(String, String)> {
+    let mut entries = MDC.with(|m| {
+        m.borrow().iter().map(|(k, v)| (k.clone(), v.clone())).collect::<Vec<_>>()
+    });
+    entries.sort();
+    entries
+}
+
+/// Invokes `f` with a value in the current thread's diagnostic context
+/// without copying it.
+pub fn with<F, T>(key: &str, f: F) -> T
+    where F: FnOnce(Option<&str>) -> T
+{
+    MDC.with(|m| f(m.borrow().get(key).map(|v| &**v)))
+}
+
+#[cfg(test)]
+mod test {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn thread_local() {
+        insert("request_id", "a");
+        assert_eq!(insert("request_id", "b"), Some("a".to_owned()));
+
+        thread::spawn(|| {
+                assert_eq!(get("request_id"), None);
+                insert("request_id", "c");
+            })
+            .join()
+            .unwrap();
+
+        assert_eq!(get("request_id"), Some("b".to_owned()));
+        insert("user", "x");
+        assert_eq!(entries(),
+                   [("request_id".to_owned(), "b".to_owned()),
+                    ("user".to_owned(), "x".to_owned())]);
+        remove("user");
+        assert_eq!(remove("request_id"), Some("b".to_owned()));
+        assert_eq!(get("request_id"), None);
+    }
+}
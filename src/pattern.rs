@@ -3,15 +3,103 @@
 //! # Basic Specifiers
 //!
 //! * `%d` - The current time. By default, the ISO 8601 format is used. A
-//!     custom format may be provided in the syntax accepted by `Tm::sprintf`,
-//!     enclosed in `{}`s immediately after the specifier: `%d{%Y/%m/%d}`.
-//! * `%f` - The source file that the log message came from.
-//! * `%l` - The log level.
-//! * `%L` - The line that the log message came from.
-//! * `%m` - The log message.
+//!     custom format may be provided in the syntax accepted by `Tm::strftime`,
+//!     enclosed in `{}`s immediately after the specifier: `%d{%Y/%m/%d}`. In
+//!     addition, `%.3f`, `%.6f` and `%.9f` (or any digit from 1 to 9) write a
+//!     `.` followed by the fractional seconds to millisecond, microsecond, and
+//!     nanosecond precision: `%d{%Y-%m-%dT%H:%M:%S%.3f}`. The time is in the local time
+//!     zone unless a second `{}` group containing `utc` follows the format:
+//!     `%d{%H:%M:%S}{utc}`. An empty format selects the default: `%d{}{utc}`.
+//!     The digits beyond the resolution of the layout's clock are zero; see
+//!     the `clock` module for the resolution available on each platform.
+//! * `%f`, `%F` - The source file that the log message came from.
+//! * `%l` - The log level. By default, the full name of the level is written
+//!     in upper case, as in `WARN`, but the names can be overridden with
+//!     `PatternLayout::level_name`. One of the builtin styles may instead be
+//!     given in `{}`s: `%l{full}` writes `WARN` regardless of any overrides,
+//!     `%l{lower}` writes `warn`, `%l{short}` writes a three letter name such
+//!     as `WRN`, and `%l{char}` writes a single letter such as `W`.
+//! * `%L` - The line that the log message came from, or nothing if it is
+//!     unknown.
+//! * `%m` - The log message. Control characters in the message can be
+//!     escaped with `PatternLayout::escape`, so that a message built from
+//!     untrusted input cannot forge log lines or write ANSI escape codes.
+//! * `%n` - A platform-specific newline: `\r\n` on Windows and `\n`
+//!     elsewhere.
 //! * `%M` - The module that the log message came from.
-//! * `%T` - The name of the thread that the log message came from.
+//! * `%T` - The name of the thread that the log message came from, or
+//!     `<unnamed>` if the thread has no name.
+//! * `%I` - The numeric ID of the thread that the log message came from.
+//! * `%thread_id` - A small number identifying the thread that the log
+//!     message came from. Threads are numbered from 1 in the order in which
+//!     they first log a message with this specifier, which makes them easier
+//!     to tell apart than by `%I`. Numbers are not reused within a process.
+//! * `%P` - The ID of the current process.
+//! * `%r` - The number of milliseconds elapsed since the logger was
+//!     initialized.
+//! * `%h` - The hostname of the machine, or `unknown` if it cannot be
+//!     determined. It is looked up when the pattern is created.
 //! * `%t` - The target of the log message.
+//! * `%c` - The name of the logger, which is the target of the log message.
+//!     A precision may be given in `{}`s to abbreviate it. `%c{N}` writes
+//!     only the last `N` segments, so `%c{1}` writes `c` for `a::b::c`.
+//!     `%c{N.}` shortens every segment but the last to its first `N`
+//!     characters, so `%c{1.}` writes `a::b::c` for `alpha::beta::c`.
+//! * `%X{key}` - The value associated with `key` in the mapped diagnostic
+//!     context of the thread that the log message came from, or nothing if
+//!     there is none. See the `mdc` module.
+//! * `%kv` - All values in the mapped diagnostic context of the thread that
+//!     the log message came from, as a JSON object with keys in sorted order:
+//!     `{"request_id":"1234","user":"bob"}`.
+//!
+//! # Width Modifiers
+//!
+//! The output of any specifier can be padded or truncated to a width by
+//! placing modifiers between the `%` and the specifier, in the form
+//! `%[-][min][.[-]max]`:
+//!
+//! * A minimum width pads the output with spaces. The output is
+//!     right-justified unless the width is preceded by `-`: `%-5l`.
+//! * A maximum width, preceded by `.`, truncates the output. By default
+//!     characters are removed from the start, which keeps the most specific
+//!     part of a module path: `%.20M`. If the width is preceded by `-`,
+//!     characters are instead removed from the end: `%.-10m`.
+//!
+//! Both may be combined, as in `%-10.10M`. Minimum widths may be at most 1024.
+//!
+//! # Highlighting
+//!
+//! * `%H{...}` - Wraps the enclosed pattern in ANSI escape codes, colored
+//!     according to the level of the log message: `%H{%l} %m`. The color used
+//!     for each level can be overridden with `PatternLayout::color`, and
+//!     escape codes can be disabled entirely with `PatternLayout::ansi` when
+//!     the output is not a terminal.
+//!
+//! # Escaping
+//!
+//! * `%%` - A literal `%`. A `%` followed by anything other than a specifier
+//!     or width modifier, or at the end of the pattern, is an error.
+//! * `%J{...}` - Escapes the output of the enclosed pattern for use inside a
+//!     JSON string. Quotes, backslashes, and control characters are escaped,
+//!     but the surrounding quotes are not written, so that structured output
+//!     can be produced without a full JSON encoder: `{"msg": "%J{%m}"}`.
+//!
+//! # Wrapping
+//!
+//! * `%wrap{N}{...}` - Wraps the output of the enclosed pattern so that no
+//!     line extends past column `N`: `%d %l - %wrap{80}{%m}`. Lines are
+//!     broken at spaces where possible, and words too long for a line are
+//!     split. Continuation lines are indented to the column at which the
+//!     enclosed output started, so that they align under its first line.
+//!     Newlines in the output are kept, and the lines following them are
+//!     indented and wrapped in the same way. ANSI escape codes written
+//!     before the specifier, as by `%H`, are not counted towards the column.
+//!
+//! # Line Terminators
+//!
+//! A newline is written after each log event unless the pattern already ends
+//! with one, for example with `%n`. To use a different terminator, wrap the
+//! layout in an `encoder::LineEndingEncoder`.
 //!
 
 use std::borrow::ToOwned;
@@ -21,9 +109,35 @@ use std::fmt;
 use std::thread;
 use std::io;
 use std::io::Write;
+use std::iter::Peekable;
+use std::process;
+use std::str::{Chars, FromStr};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 
 use log::{LogRecord, LogLevel};
-use time;
+use time::{self, Tm};
+
+use {Encode, LogEvent, Location, hostname, start_time, thread_id, thread_number,
+     write_json_escaped};
+use clock::{Clock, SystemClock};
+use mdc;
+
+#[cfg(windows)]
+const NEWLINE: &'static str = "\r\n";
+#[cfg(not(windows))]
+const NEWLINE: &'static str = "\n";
+
+// The remainder of the `%thread_id` specifier after its `t`.
+const THREAD_NUMBER_SUFFIX: &'static str = "hread_id";
+
+// The remainder of the `%wrap` specifier after its `w`.
+const WRAP_SUFFIX: &'static str = "rap";
+
+// The largest minimum width, which keeps a mistyped width from padding every
+// log event with a huge number of spaces.
+const MAX_MIN_WIDTH: usize = 1024;
 
 #[derive(Debug)]
 #[cfg_attr(test, derive(PartialEq))]
@@ -32,18 +146,170 @@ enum TimeFmt {
     Str(String),
 }
 
+#[derive(Debug, Copy, Clone)]
+#[cfg_attr(test, derive(PartialEq))]
+enum TimeZone {
+    Local,
+    Utc,
+}
+
 #[derive(Debug)]
 #[cfg_attr(test, derive(PartialEq))]
 enum Chunk {
     Text(String),
-    Time(TimeFmt),
-    Level,
+    Highlight(Vec<Chunk>),
+    Time(TimeFmt, TimeZone),
+    Level(Option<LevelStyle>),
     Message,
     Module,
     File,
     Line,
     Thread,
+    ThreadId,
+    ThreadNumber,
+    ProcessId,
+    Elapsed,
+    Hostname(String),
     Target,
+    Logger(Abbreviation),
+    Mdc(String),
+    MdcAll,
+    Json(Vec<Chunk>),
+    Wrap(usize, Vec<Chunk>),
+    Padded(Box<Chunk>, Width),
+}
+
+#[derive(Debug, Copy, Clone)]
+#[cfg_attr(test, derive(PartialEq))]
+enum LevelStyle {
+    Full,
+    Lower,
+    Short,
+    Char,
+}
+
+impl LevelStyle {
+    fn parse(style: &str) -> Result<LevelStyle, Error> {
+        match &*style.to_lowercase() {
+            "full" => Ok(LevelStyle::Full),
+            "lower" => Ok(LevelStyle::Lower),
+            "short" => Ok(LevelStyle::Short),
+            "char" => Ok(LevelStyle::Char),
+            _ => Err(Error(format!("Invalid level style `{}`", style))),
+        }
+    }
+
+    fn write<W>(&self, w: &mut W, level: LogLevel) -> io::Result<()> where W: Write + ?Sized {
+        let short = match level {
+            LogLevel::Error => "ERR",
+            LogLevel::Warn => "WRN",
+            LogLevel::Info => "INF",
+            LogLevel::Debug => "DBG",
+            LogLevel::Trace => "TRC",
+        };
+        match *self {
+            LevelStyle::Full => write!(w, "{}", level),
+            LevelStyle::Lower => write!(w, "{}", level.to_string().to_lowercase()),
+            LevelStyle::Short => write!(w, "{}", short),
+            LevelStyle::Char => write!(w, "{}", &short[..1]),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+#[cfg_attr(test, derive(PartialEq))]
+enum Abbreviation {
+    Full,
+    Segments(usize),
+    Compressed(usize),
+}
+
+impl Abbreviation {
+    fn parse(precision: &str) -> Result<Abbreviation, Error> {
+        let (digits, compressed) = if precision.ends_with('.') {
+            (&precision[..precision.len() - 1], true)
+        } else {
+            (precision, false)
+        };
+        let n = match digits.parse::<usize>() {
+            Ok(n) if n > 0 => n,
+            _ => return Err(Error(format!("Invalid logger precision `{}`", precision))),
+        };
+        if compressed {
+            Ok(Abbreviation::Compressed(n))
+        } else {
+            Ok(Abbreviation::Segments(n))
+        }
+    }
+
+    fn write<W>(&self, w: &mut W, name: &str) -> io::Result<()> where W: Write + ?Sized {
+        match *self {
+            Abbreviation::Full => write!(w, "{}", name),
+            Abbreviation::Segments(n) => {
+                let start = name.rmatch_indices("::")
+                    .nth(n - 1)
+                    .map(|(idx, _)| idx + 2)
+                    .unwrap_or(0);
+                write!(w, "{}", &name[start..])
+            }
+            Abbreviation::Compressed(n) => {
+                let mut segments = name.split("::").peekable();
+                while let Some(segment) = segments.next() {
+                    if segments.peek().is_some() {
+                        let end = segment.char_indices().nth(n).map(|(idx, _)| idx);
+                        try!(write!(w, "{}::", &segment[..end.unwrap_or(segment.len())]));
+                    } else {
+                        try!(write!(w, "{}", segment));
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(test, derive(PartialEq))]
+struct Width {
+    min: usize,
+    left_justify: bool,
+    max: Option<usize>,
+    truncate_end: bool,
+}
+
+impl Width {
+    fn write<W>(&self, w: &mut W, s: &str) -> io::Result<()> where W: Write + ?Sized {
+        let len = s.chars().count();
+        let (s, len) = match self.max {
+            Some(max) if len > max => {
+                let s = if self.truncate_end {
+                    match s.char_indices().nth(max) {
+                        Some((idx, _)) => &s[..idx],
+                        None => s,
+                    }
+                } else {
+                    match s.char_indices().nth(len - max) {
+                        Some((idx, _)) => &s[idx..],
+                        None => s,
+                    }
+                };
+                (s, max)
+            }
+            _ => (s, len),
+        };
+
+        let padding = self.min.saturating_sub(len);
+        if self.left_justify {
+            try!(write!(w, "{}", s));
+        }
+        for _ in 0..padding {
+            try!(write!(w, " "));
+        }
+        if !self.left_justify {
+            try!(write!(w, "{}", s));
+        }
+        Ok(())
+    }
 }
 
 /// An error parsing a `PatternLayout` pattern.
@@ -62,10 +328,117 @@ impl error::Error for Error {
     }
 }
 
+/// A color used by the `%H` highlight specifier.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Color {
+    /// Black.
+    Black,
+    /// Red.
+    Red,
+    /// Green.
+    Green,
+    /// Yellow.
+    Yellow,
+    /// Blue.
+    Blue,
+    /// Magenta.
+    Magenta,
+    /// Cyan.
+    Cyan,
+    /// White.
+    White,
+}
+
+impl Color {
+    fn ansi_code(&self) -> u8 {
+        match *self {
+            Color::Black => 30,
+            Color::Red => 31,
+            Color::Green => 32,
+            Color::Yellow => 33,
+            Color::Blue => 34,
+            Color::Magenta => 35,
+            Color::Cyan => 36,
+            Color::White => 37,
+        }
+    }
+}
+
+/// Determines which characters of the log message `%m` escapes.
+///
+/// Newlines, carriage returns and tabs are written as `\n`, `\r` and `\t`,
+/// and other control characters as `\u{..}` with their hexadecimal code, so
+/// `ESC` becomes `\u{1b}`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Escape {
+    /// The message is written unchanged.
+    Off,
+    /// Control characters other than newlines are escaped.
+    Control,
+    /// All control characters, including newlines, are escaped.
+    All,
+}
+
+/// An error returned when parsing an `Escape` fails.
+#[derive(Debug)]
+pub struct ParseEscapeError(String);
+
+impl fmt::Display for ParseEscapeError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "Invalid escape `{}`", self.0)
+    }
+}
+
+impl error::Error for ParseEscapeError {
+    fn description(&self) -> &str {
+        "Invalid escape"
+    }
+}
+
+impl FromStr for Escape {
+    type Err = ParseEscapeError;
+
+    /// Parses `off`, `control` or `all`, ignoring case.
+    fn from_str(s: &str) -> Result<Escape, ParseEscapeError> {
+        match &*s.to_lowercase() {
+            "off" => Ok(Escape::Off),
+            "control" => Ok(Escape::Control),
+            "all" => Ok(Escape::All),
+            _ => Err(ParseEscapeError(s.to_owned())),
+        }
+    }
+}
+
+fn write_escaped<W>(w: &mut W, s: &str, escape: Escape) -> io::Result<()>
+    where W: Write + ?Sized
+{
+    let mut start = 0;
+    for (idx, ch) in s.char_indices() {
+        if !ch.is_control() || ch == '\n' && escape == Escape::Control {
+            continue;
+        }
+        try!(w.write_all(s[start..idx].as_bytes()));
+        match ch {
+            '\n' => try!(write!(w, "\\n")),
+            '\r' => try!(write!(w, "\\r")),
+            '\t' => try!(write!(w, "\\t")),
+            _ => try!(write!(w, "\\u{{{:x}}}", ch as u32)),
+        }
+        start = idx + ch.len_utf8();
+    }
+    w.write_all(s[start..].as_bytes())
+}
+
 /// A formatter object for `LogRecord`s.
 #[derive(Debug)]
 pub struct PatternLayout {
     pattern: Vec<Chunk>,
+    terminated: bool,
+    colors: [Color; 5],
+    level_names: [String; 5],
+    ansi: bool,
+    escape: Escape,
+    clock: Arc<Clock>,
 }
 
 impl Default for PatternLayout {
@@ -80,197 +453,976 @@ impl PatternLayout {
     ///
     /// The pattern string syntax is documented in the `pattern` module.
     pub fn new(pattern: &str) -> Result<PatternLayout, Error> {
-        let mut parsed = vec![];
-        let mut next_text = String::new();
         let mut it = pattern.chars().peekable();
-
-        while let Some(ch) = it.next() {
-            if ch == '%' {
-                let chunk = match it.next() {
-                    Some('%') => {
-                        next_text.push('%');
-                        None
-                    }
-                    Some('d') => {
-                        let fmt = match it.peek() {
-                            Some(&'{') => {
-                                it.next();
-                                let mut fmt = String::new();
-                                loop {
-                                    match it.next() {
-                                        Some('}') => break,
-                                        Some(c) => fmt.push(c),
-                                        None => {
-                                            return Err(Error("Unterminated time format".to_owned()));
-                                        }
-                                    }
-                                }
-                                if let Err(err) = time::now().strftime(&*fmt) {
-                                    return Err(Error(err.to_string()));
-                                }
-                                TimeFmt::Str(fmt)
-                            }
-                            _ => TimeFmt::Rfc3339,
-                        };
-                        Some(Chunk::Time(fmt))
-                    }
-                    Some('l') => Some(Chunk::Level),
-                    Some('m') => Some(Chunk::Message),
-                    Some('M') => Some(Chunk::Module),
-                    Some('f') => Some(Chunk::File),
-                    Some('L') => Some(Chunk::Line),
-                    Some('T') => Some(Chunk::Thread),
-                    Some('t') => Some(Chunk::Target),
-                    Some(ch) => return Err(Error(format!("Invalid formatter `%{}`", ch))),
-                    None => return Err(Error("Unexpected end of pattern".to_owned())),
-                };
-
-                if let Some(chunk) = chunk {
-                    if !next_text.is_empty() {
-                        parsed.push(Chunk::Text(next_text));
-                        next_text = String::new();
-                    }
-                    parsed.push(chunk);
-                }
-            } else {
-                next_text.push(ch);
-            }
-        }
-
-        if !next_text.is_empty() {
-            parsed.push(Chunk::Text(next_text));
-        }
+        let parsed = try!(parse(&mut it, false));
+        let terminated = match parsed.last() {
+            Some(&Chunk::Text(ref text)) => text.ends_with('\n'),
+            _ => false,
+        };
 
         Ok(PatternLayout {
             pattern: parsed,
+            terminated: terminated,
+            colors: [Color::Red, Color::Yellow, Color::Green, Color::Blue, Color::Cyan],
+            level_names: [LogLevel::Error.to_string(),
+                          LogLevel::Warn.to_string(),
+                          LogLevel::Info.to_string(),
+                          LogLevel::Debug.to_string(),
+                          LogLevel::Trace.to_string()],
+            ansi: true,
+            escape: Escape::Off,
+            clock: Arc::new(SystemClock::new()),
         })
     }
 
+    /// Sets the color used by `%H` for log messages of the specified level.
+    ///
+    /// Defaults to red for `Error`, yellow for `Warn`, green for `Info`, blue
+    /// for `Debug`, and cyan for `Trace`.
+    pub fn color(mut self, level: LogLevel, color: Color) -> PatternLayout {
+        self.colors[level as usize - 1] = color;
+        self
+    }
+
+    /// Sets the name written by `%l` for log messages of the specified level.
+    ///
+    /// Defaults to the full name of the level in upper case, as in `WARN`.
+    /// Names are not used by the builtin styles selected with `%l{...}`.
+    pub fn level_name(mut self, level: LogLevel, name: &str) -> PatternLayout {
+        self.level_names[level as usize - 1] = name.to_owned();
+        self
+    }
+
+    /// Determines if `%H` will emit ANSI escape codes.
+    ///
+    /// If `false`, the enclosed pattern is written without any coloring. This
+    /// should be disabled when the output is not a terminal, although a
+    /// `ConsoleAppender` removes the codes itself according to its
+    /// `ColorMode`. Defaults to `true`.
+    pub fn ansi(mut self, ansi: bool) -> PatternLayout {
+        self.ansi = ansi;
+        self
+    }
+
+    /// Determines which characters of the log message `%m` escapes.
+    ///
+    /// Defaults to `Escape::Off`, since escaping requires the message to be
+    /// formatted into a temporary buffer.
+    pub fn escape(mut self, escape: Escape) -> PatternLayout {
+        self.escape = escape;
+        self
+    }
+
+    /// Sets the clock read by `%d`.
+    ///
+    /// Defaults to `SystemClock`.
+    pub fn clock(mut self, clock: Arc<Clock>) -> PatternLayout {
+        self.clock = clock;
+        self
+    }
+
     /// Writes the specified `LogRecord` to the specified `Write`r according
     /// to its pattern.
-    pub fn append<W>(&self, w: &mut W, record: &LogRecord) -> io::Result<()> where W: Write {
-        let location = Location {
-            module_path: record.location().module_path(),
-            file: record.location().file(),
-            line: record.location().line(),
-        };
+    pub fn append<W>(&self, w: &mut W, record: &LogRecord) -> io::Result<()> where W: Write + ?Sized {
+        let location = Location::from_record(record);
         self.append_inner(w, record.level(), record.target(), &location, record.args())
     }
 
+    /// Writes the specified `LogEvent` to the specified `Write`r according
+    /// to its pattern.
+    ///
+    /// The time, thread and mapped diagnostic context captured by the log
+    /// event are written rather than those of the current thread. The
+    /// layout's clock is not consulted.
+    pub fn append_event<W>(&self, w: &mut W, event: &LogEvent) -> io::Result<()>
+        where W: Write + ?Sized
+    {
+        let location = Location::from_event(event);
+        self.append_captured(w,
+                             event.level(),
+                             event.target(),
+                             &location,
+                             &format_args!("{}", event.message()),
+                             Some(event))
+    }
+
     fn append_inner<W>(&self,
                        w: &mut W,
                        level: LogLevel,
                        target: &str,
                        location: &Location,
                        args: &fmt::Arguments)
-                       -> io::Result<()> where W: Write {
-        for chunk in self.pattern.iter() {
-            try!(match *chunk {
-                Chunk::Text(ref text) => write!(w, "{}", text),
-                Chunk::Time(TimeFmt::Str(ref fmt)) => {
-                    time::now().strftime(&**fmt).map(|time| write!(w, "{}", time))
-                        .unwrap_or(Ok(()))
+                       -> io::Result<()> where W: Write + ?Sized {
+        self.append_captured(w, level, target, location, args, None)
+    }
+
+    // `event` is the log event being written if it was captured on another
+    // thread.
+    fn append_captured<W>(&self,
+                          w: &mut W,
+                          level: LogLevel,
+                          target: &str,
+                          location: &Location,
+                          args: &fmt::Arguments,
+                          event: Option<&LogEvent>)
+                          -> io::Result<()> where W: Write + ?Sized {
+        try!(self.append_chunks(&self.pattern, w, level, target, location, args, event));
+        if self.terminated {
+            Ok(())
+        } else {
+            writeln!(w, "")
+        }
+    }
+
+    fn append_chunks<W>(&self,
+                        chunks: &[Chunk],
+                        w: &mut W,
+                        level: LogLevel,
+                        target: &str,
+                        location: &Location,
+                        args: &fmt::Arguments,
+                        event: Option<&LogEvent>)
+                        -> io::Result<()> where W: Write + ?Sized {
+        let wraps = chunks.iter().any(|chunk| match *chunk {
+            Chunk::Wrap(..) => true,
+            _ => false,
+        });
+        if !wraps {
+            for chunk in chunks {
+                try!(self.append_chunk(chunk, w, level, target, location, args, event));
+            }
+            return Ok(());
+        }
+
+        // the preceding output is needed to find the column a wrap starts at
+        let mut buf = vec![];
+        for chunk in chunks {
+            match *chunk {
+                Chunk::Wrap(width, ref chunks) => {
+                    let column = line_width(&buf);
+                    try!(self.append_wrapped(width,
+                                             column,
+                                             chunks,
+                                             &mut buf,
+                                             level,
+                                             target,
+                                             location,
+                                             args,
+                                             event));
+                }
+                _ => {
+                    try!(self.append_chunk(chunk, &mut buf, level, target, location, args, event))
+                }
+            }
+        }
+        w.write_all(&buf)
+    }
+
+    fn append_wrapped<W>(&self,
+                         width: usize,
+                         column: usize,
+                         chunks: &[Chunk],
+                         w: &mut W,
+                         level: LogLevel,
+                         target: &str,
+                         location: &Location,
+                         args: &fmt::Arguments,
+                         event: Option<&LogEvent>)
+                         -> io::Result<()> where W: Write + ?Sized {
+        let mut buf = vec![];
+        try!(self.append_chunks(chunks, &mut buf, level, target, location, args, event));
+        write_wrapped(w, &String::from_utf8_lossy(&buf), width, column)
+    }
+
+    fn append_chunk<W>(&self,
+                       chunk: &Chunk,
+                       w: &mut W,
+                       level: LogLevel,
+                       target: &str,
+                       location: &Location,
+                       args: &fmt::Arguments,
+                       event: Option<&LogEvent>)
+                       -> io::Result<()> where W: Write + ?Sized {
+        match *chunk {
+            Chunk::Text(ref text) => write!(w, "{}", text),
+            Chunk::Highlight(ref chunks) => {
+                if self.ansi {
+                    let color = self.colors[level as usize - 1];
+                    try!(write!(w, "\x1b[{}m", color.ansi_code()));
+                    try!(self.append_chunks(chunks, w, level, target, location, args, event));
+                    write!(w, "\x1b[0m")
+                } else {
+                    self.append_chunks(chunks, w, level, target, location, args, event)
+                }
+            }
+            Chunk::Time(ref fmt, tz) => {
+                let now = event.map_or_else(|| self.clock.now(), |e| e.time());
+                let now = match tz {
+                    TimeZone::Local => time::at(now),
+                    TimeZone::Utc => time::at_utc(now),
+                };
+                match *fmt {
+                    TimeFmt::Str(ref fmt) => {
+                        // the format was validated when the pattern was parsed
+                        match write_time(w, &now, fmt) {
+                            Err(TimeError::Io(err)) => Err(err),
+                            _ => Ok(()),
+                        }
+                    }
+                    TimeFmt::Rfc3339 => write!(w, "{}", now.rfc3339()),
+                }
+            }
+            Chunk::Level(Some(style)) => style.write(w, level),
+            Chunk::Level(None) => write!(w, "{}", self.level_names[level as usize - 1]),
+            Chunk::Message if self.escape == Escape::Off => write!(w, "{}", args),
+            Chunk::Message => write_escaped(w, &fmt::format(*args), self.escape),
+            Chunk::Module => write!(w, "{}", location.module_path),
+            Chunk::File => write!(w, "{}", location.file),
+            Chunk::Line if location.line == 0 => Ok(()),
+            Chunk::Line => write!(w, "{}", location.line),
+            Chunk::Thread => {
+                match event {
+                    Some(event) => write!(w, "{}", event.thread_name().unwrap_or("<unnamed>")),
+                    None => write!(w, "{}", thread::current().name().unwrap_or("<unnamed>")),
+                }
+            }
+            Chunk::ThreadId => {
+                match event {
+                    Some(event) => write!(w, "{}", event.thread_id),
+                    None => write!(w, "{}", thread_id()),
+                }
+            }
+            Chunk::ThreadNumber => {
+                write!(w, "{}", event.map_or_else(thread_number, |e| e.thread_number))
+            }
+            Chunk::ProcessId => write!(w, "{}", process_id()),
+            Chunk::Elapsed => {
+                let now = event.map_or_else(Instant::now, |e| e.instant);
+                write!(w, "{}", elapsed_millis(now.duration_since(start_time())))
+            }
+            Chunk::Hostname(ref hostname) => write!(w, "{}", hostname),
+            Chunk::Target => write!(w, "{}", target),
+            Chunk::Logger(abbreviation) => abbreviation.write(w, target),
+            Chunk::Mdc(ref key) => {
+                match event {
+                    Some(event) => write!(w, "{}", event.mdc(key).unwrap_or("")),
+                    None => mdc::with(key, |value| write!(w, "{}", value.unwrap_or(""))),
                 }
-                Chunk::Time(TimeFmt::Rfc3339) => write!(w, "{}", time::now().rfc3339()),
-                Chunk::Level => write!(w, "{}", level),
-                Chunk::Message => write!(w, "{}", args),
-                Chunk::Module => write!(w, "{}", location.module_path),
-                Chunk::File => write!(w, "{}", location.file),
-                Chunk::Line => write!(w, "{}", location.line),
-                Chunk::Thread => {
-                    write!(w, "{}", thread::current().name().unwrap_or("<unnamed>"))
+            }
+            Chunk::MdcAll => {
+                let entries = match event {
+                    Some(event) => event.mdc_entries().to_vec(),
+                    None => mdc::entries(),
+                };
+                try!(write!(w, "{{"));
+                for (i, (key, value)) in entries.into_iter().enumerate() {
+                    if i > 0 {
+                        try!(write!(w, ","));
+                    }
+                    try!(write!(w, "\""));
+                    try!(write_json_escaped(w, &key));
+                    try!(write!(w, "\":\""));
+                    try!(write_json_escaped(w, &value));
+                    try!(write!(w, "\""));
                 }
-                Chunk::Target => write!(w, "{}", target),
-            });
+                write!(w, "}}")
+            }
+            Chunk::Json(ref chunks) => {
+                let mut buf = vec![];
+                try!(self.append_chunks(chunks, &mut buf, level, target, location, args, event));
+                write_json_escaped(w, &String::from_utf8_lossy(&buf))
+            }
+            Chunk::Wrap(width, ref chunks) => {
+                self.append_wrapped(width, 0, chunks, w, level, target, location, args, event)
+            }
+            Chunk::Padded(ref chunk, ref width) => {
+                let mut buf = vec![];
+                try!(self.append_chunk(chunk, &mut buf, level, target, location, args, event));
+                width.write(w, &String::from_utf8_lossy(&buf))
+            }
         }
-        writeln!(w, "")
     }
 }
 
-struct Location<'a> {
-    module_path: &'a str,
-    file: &'a str,
-    line: u32,
+// Returns the number of characters after the last newline in `buf`, not
+// counting ANSI escape codes.
+fn line_width(buf: &[u8]) -> usize {
+    let start = buf.iter().rposition(|&b| b == b'\n').map_or(0, |idx| idx + 1);
+    let line = String::from_utf8_lossy(&buf[start..]);
+    let mut width = 0;
+    let mut chars = line.chars();
+    while let Some(ch) = chars.next() {
+        if ch == '\x1b' {
+            for ch in &mut chars {
+                if ch.is_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            width += 1;
+        }
+    }
+    width
 }
 
-#[cfg(test)]
-mod tests {
-    use std::default::Default;
-    use std::thread;
+// Writes `text` broken into lines which end before `width`, given that it
+// starts at `column`. Lines after the first are indented to `column`.
+fn write_wrapped<W>(w: &mut W, text: &str, width: usize, column: usize) -> io::Result<()>
+    where W: Write + ?Sized
+{
+    let available = width.saturating_sub(column);
+    let indent = " ".repeat(column);
 
-    use log::LogLevel;
+    for (i, line) in text.split('\n').enumerate() {
+        if i > 0 {
+            try!(write!(w, "\n{}", indent));
+        }
+        if available == 0 {
+            try!(write!(w, "{}", line));
+            continue;
+        }
 
-    use super::{Chunk, TimeFmt, PatternLayout, Location};
+        let mut len = 0;
+        for (j, word) in line.split(' ').enumerate() {
+            let mut word = word;
+            let mut word_len = word.chars().count();
+            if j > 0 {
+                if len > 0 && len + 1 + word_len > available {
+                    try!(write!(w, "\n{}", indent));
+                    len = 0;
+                } else {
+                    try!(write!(w, " "));
+                    len += 1;
+                }
+            }
+            while len + word_len > available {
+                let split = available - len;
+                let idx = word.char_indices().nth(split).map_or(word.len(), |(idx, _)| idx);
+                try!(write!(w, "{}\n{}", &word[..idx], indent));
+                word = &word[idx..];
+                word_len -= split;
+                len = 0;
+            }
+            try!(write!(w, "{}", word));
+            len += word_len;
+        }
+    }
+    Ok(())
+}
 
-    #[test]
-    fn test_parse() {
-        let expected = [Chunk::Text("hi".to_string()),
-                        Chunk::Time(TimeFmt::Str("%Y-%m-%d".to_string())),
-                        Chunk::Time(TimeFmt::Rfc3339),
-                        Chunk::Level,
-                        Chunk::Message,
-                        Chunk::Module,
-                        Chunk::File,
-                        Chunk::Line,
-                        Chunk::Thread,
-                        Chunk::Target,
-                        Chunk::Text("%".to_string())];
-        let actual = PatternLayout::new("hi%d{%Y-%m-%d}%d%l%m%M%f%L%T%t%%").unwrap().pattern;
-        assert_eq!(actual, expected)
+fn read_width(it: &mut Peekable<Chars>) -> Result<Option<usize>, Error> {
+    let mut width = None;
+    while let Some(digit) = it.peek().and_then(|c| c.to_digit(10)) {
+        it.next();
+        let next = width.unwrap_or(0usize)
+            .checked_mul(10)
+            .and_then(|w| w.checked_add(digit as usize));
+        match next {
+            Some(next) => width = Some(next),
+            None => return Err(Error("Invalid width".to_owned())),
+        }
     }
+    Ok(width)
+}
 
-    #[test]
-    fn test_invalid_date_format() {
-        assert!(PatternLayout::new("%d{%q}").is_err());
+fn parse_width(it: &mut Peekable<Chars>) -> Result<Option<Width>, Error> {
+    let left_justify = it.peek() == Some(&'-');
+    if left_justify {
+        it.next();
+    }
+    let min = try!(read_width(it));
+    if left_justify && min.is_none() {
+        return Err(Error("Expected a minimum width after `-`".to_owned()));
+    }
+    if min.map_or(false, |min| min > MAX_MIN_WIDTH) {
+        return Err(Error(format!("Minimum width may be at most {}", MAX_MIN_WIDTH)));
     }
 
-    #[test]
-    fn test_log() {
-        let pw = PatternLayout::new("%l %m at %M in %f:%L").unwrap();
+    let (max, truncate_end) = if it.peek() == Some(&'.') {
+        it.next();
+        let truncate_end = it.peek() == Some(&'-');
+        if truncate_end {
+            it.next();
+        }
+        match try!(read_width(it)) {
+            Some(max) => (Some(max), truncate_end),
+            None => return Err(Error("Expected a maximum width after `.`".to_owned())),
+        }
+    } else {
+        (None, false)
+    };
 
-        static LOCATION: Location<'static> = Location {
-            module_path: "mod path",
-            file: "the file",
-            line: 132,
-        };
-        let mut buf = vec![];
-        pw.append_inner(&mut buf,
-                        LogLevel::Debug,
-                        "target",
-                        &LOCATION,
-                        &format_args!("the message")).unwrap();
+    if min.is_none() && max.is_none() {
+        return Ok(None);
+    }
 
-        assert_eq!(buf, &b"DEBUG the message at mod path in the file:132\n"[..]);
+    Ok(Some(Width {
+        min: min.unwrap_or(0),
+        left_justify: left_justify,
+        max: max,
+        truncate_end: truncate_end,
+    }))
+}
+
+fn read_braced(it: &mut Peekable<Chars>, name: &str) -> Result<String, Error> {
+    let mut s = String::new();
+    loop {
+        match it.next() {
+            Some('}') => return Ok(s),
+            Some(c) => s.push(c),
+            None => return Err(Error(format!("Unterminated {}", name))),
+        }
     }
+}
 
-    #[test]
-    fn test_unnamed_thread() {
-        thread::spawn(|| {
-            let pw = PatternLayout::new("%T").unwrap();
-            static LOCATION: Location<'static> = Location {
-                module_path: "path",
-                file: "file",
-                line: 132,
-            };
-            let mut buf = vec![];
-            pw.append_inner(&mut buf,
-                            LogLevel::Debug,
-                            "target",
-                            &LOCATION,
-                            &format_args!("message")).unwrap();
-            assert_eq!(buf, b"<unnamed>\n");
-        }).join().unwrap();
+enum TimeError {
+    Io(io::Error),
+    Parse(time::ParseError),
+}
+
+impl fmt::Display for TimeError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TimeError::Io(ref err) => fmt::Display::fmt(err, fmt),
+            TimeError::Parse(ref err) => fmt::Display::fmt(err, fmt),
+        }
     }
+}
 
-    #[test]
-    fn test_named_thread() {
-        thread::Builder::new().name("foobar".to_string()).spawn(|| {
-            let pw = PatternLayout::new("%T").unwrap();
-            static LOCATION: Location<'static> = Location {
-                module_path: "path",
-                file: "file",
-                line: 132,
-            };
+/// Writes `tm` formatted by `fmt`, which is in the syntax accepted by
+/// `Tm::strftime` extended with `%.Nf`, which writes a `.` followed by the
+/// first `N` (1 to 9) digits of the fractional seconds.
+fn write_time<W>(w: &mut W, tm: &Tm, fmt: &str) -> Result<(), TimeError>
+        where W: Write + ?Sized {
+    let mut segment = String::new();
+    let mut it = fmt.chars().peekable();
+
+    while let Some(ch) = it.next() {
+        if ch != '%' {
+            segment.push(ch);
+            continue;
+        }
+
+        match it.next() {
+            Some('.') => {
+                let invalid = TimeError::Parse(time::ParseError::InvalidFormatSpecifier('.'));
+                let digits = match it.next().and_then(|c| c.to_digit(10)) {
+                    Some(digits) if digits >= 1 && digits <= 9 => digits as usize,
+                    _ => return Err(invalid),
+                };
+                if it.next() != Some('f') {
+                    return Err(invalid);
+                }
+                try!(write_strftime(w, tm, &segment));
+                segment.clear();
+                let nanos = format!("{:09}", tm.tm_nsec);
+                try!(write!(w, ".{}", &nanos[..digits]).map_err(TimeError::Io));
+            }
+            Some(ch) => {
+                segment.push('%');
+                segment.push(ch);
+            }
+            None => segment.push('%'),
+        }
+    }
+
+    write_strftime(w, tm, &segment)
+}
+
+fn write_strftime<W>(w: &mut W, tm: &Tm, fmt: &str) -> Result<(), TimeError>
+        where W: Write + ?Sized {
+    if fmt.is_empty() {
+        return Ok(());
+    }
+    let formatted = try!(tm.strftime(fmt).map_err(TimeError::Parse));
+    write!(w, "{}", formatted).map_err(TimeError::Io)
+}
+
+fn elapsed_millis(elapsed: Duration) -> u64 {
+    elapsed.as_secs() * 1000 + elapsed.subsec_nanos() as u64 / 1000000
+}
+
+// The process ID, or 0 if it has not been looked up yet.
+static PROCESS_ID: AtomicUsize = AtomicUsize::new(0);
+
+fn process_id() -> usize {
+    let id = PROCESS_ID.load(Ordering::Relaxed);
+    if id != 0 {
+        return id;
+    }
+
+    reset_process_id_on_fork();
+    let id = process::id() as usize;
+    PROCESS_ID.store(id, Ordering::Relaxed);
+    id
+}
+
+// A forked child must not report its parent's cached ID.
+#[cfg(unix)]
+fn reset_process_id_on_fork() {
+    use libc;
+    use std::sync::Once;
+
+    extern "C" fn reset() {
+        PROCESS_ID.store(0, Ordering::Relaxed);
+    }
+
+    static REGISTER: Once = Once::new();
+    REGISTER.call_once(|| unsafe {
+        libc::pthread_atfork(None, None, Some(reset));
+    });
+}
+
+#[cfg(not(unix))]
+fn reset_process_id_on_fork() {}
+
+impl Encode for PatternLayout {
+    fn encode(&self, w: &mut Write, record: &LogRecord) -> io::Result<()> {
+        self.append(w, record)
+    }
+
+    fn encode_event(&self, w: &mut Write, event: &LogEvent) -> io::Result<()> {
+        self.append_event(w, event)
+    }
+}
+
+fn parse(it: &mut Peekable<Chars>, nested: bool) -> Result<Vec<Chunk>, Error> {
+    let mut parsed = vec![];
+    let mut next_text = String::new();
+
+    loop {
+        let ch = match it.next() {
+            Some('}') if nested => break,
+            Some(ch) => ch,
+            None if nested => return Err(Error("Unterminated highlight pattern".to_owned())),
+            None => break,
+        };
+
+        if ch == '%' {
+            let width = try!(parse_width(it));
+            let chunk = match it.next() {
+                Some('%') => {
+                    next_text.push('%');
+                    None
+                }
+                Some('d') => {
+                    let fmt = match it.peek() {
+                        Some(&'{') => {
+                            it.next();
+                            let fmt = try!(read_braced(it, "time format"));
+                            if fmt.is_empty() {
+                                TimeFmt::Rfc3339
+                            } else {
+                                let now = time::now();
+                                if let Err(err) = write_time(&mut io::sink(), &now, &fmt) {
+                                    return Err(Error(err.to_string()));
+                                }
+                                TimeFmt::Str(fmt)
+                            }
+                        }
+                        _ => TimeFmt::Rfc3339,
+                    };
+                    let tz = match it.peek() {
+                        Some(&'{') => {
+                            it.next();
+                            let tz = try!(read_braced(it, "time zone"));
+                            match &*tz.to_lowercase() {
+                                "utc" => TimeZone::Utc,
+                                "local" => TimeZone::Local,
+                                _ => return Err(Error(format!("Invalid time zone `{}`", tz))),
+                            }
+                        }
+                        _ => TimeZone::Local,
+                    };
+                    Some(Chunk::Time(fmt, tz))
+                }
+                Some('H') => {
+                    match it.next() {
+                        Some('{') => {}
+                        _ => return Err(Error("`%H` must be followed by `{`".to_owned())),
+                    }
+                    Some(Chunk::Highlight(try!(parse(it, true))))
+                }
+                Some('J') => {
+                    match it.next() {
+                        Some('{') => {}
+                        _ => return Err(Error("`%J` must be followed by `{`".to_owned())),
+                    }
+                    Some(Chunk::Json(try!(parse(it, true))))
+                }
+                Some('l') => {
+                    let style = match it.peek() {
+                        Some(&'{') => {
+                            it.next();
+                            Some(try!(LevelStyle::parse(&try!(read_braced(it, "level style")))))
+                        }
+                        _ => None,
+                    };
+                    Some(Chunk::Level(style))
+                }
+                Some('m') => Some(Chunk::Message),
+                Some('n') => Some(Chunk::Text(NEWLINE.to_owned())),
+                Some('M') => Some(Chunk::Module),
+                Some('f') | Some('F') => Some(Chunk::File),
+                Some('L') => Some(Chunk::Line),
+                Some('T') => Some(Chunk::Thread),
+                Some('I') => Some(Chunk::ThreadId),
+                Some('P') => Some(Chunk::ProcessId),
+                Some('r') => Some(Chunk::Elapsed),
+                Some('h') => {
+                    Some(Chunk::Hostname(hostname().unwrap_or_else(|| "unknown".to_owned())))
+                }
+                Some('w') => {
+                    let mut ahead = it.clone();
+                    if !WRAP_SUFFIX.chars().all(|ch| ahead.next() == Some(ch)) {
+                        return Err(Error("Invalid formatter `%w`; use `%%` for a literal `%`"
+                                             .to_owned()));
+                    }
+                    *it = ahead;
+                    match it.next() {
+                        Some('{') => {}
+                        _ => return Err(Error("`%wrap` must be followed by `{`".to_owned())),
+                    }
+                    let width = try!(read_braced(it, "wrap width"));
+                    let width = match width.parse::<usize>() {
+                        Ok(width) if width > 0 => width,
+                        _ => return Err(Error(format!("Invalid wrap width `{}`", width))),
+                    };
+                    match it.next() {
+                        Some('{') => {}
+                        _ => {
+                            return Err(Error("`%wrap{N}` must be followed by `{`".to_owned()));
+                        }
+                    }
+                    Some(Chunk::Wrap(width, try!(parse(it, true))))
+                }
+                Some('t') => {
+                    let mut ahead = it.clone();
+                    if THREAD_NUMBER_SUFFIX.chars().all(|ch| ahead.next() == Some(ch)) {
+                        *it = ahead;
+                        Some(Chunk::ThreadNumber)
+                    } else {
+                        Some(Chunk::Target)
+                    }
+                }
+                Some('c') => {
+                    let abbreviation = match it.peek() {
+                        Some(&'{') => {
+                            it.next();
+                            try!(Abbreviation::parse(&try!(read_braced(it, "logger precision"))))
+                        }
+                        _ => Abbreviation::Full,
+                    };
+                    Some(Chunk::Logger(abbreviation))
+                }
+                Some('X') => {
+                    match it.next() {
+                        Some('{') => {}
+                        _ => return Err(Error("`%X` must be followed by `{`".to_owned())),
+                    }
+                    Some(Chunk::Mdc(try!(read_braced(it, "MDC key"))))
+                }
+                Some('k') => {
+                    match it.next() {
+                        Some('v') => {}
+                        _ => return Err(Error("`%k` must be followed by `v`".to_owned())),
+                    }
+                    Some(Chunk::MdcAll)
+                }
+                Some(ch) => {
+                    return Err(Error(format!("Invalid formatter `%{}`; use `%%` for a literal `%`",
+                                             ch)));
+                }
+                None => {
+                    return Err(Error("Unexpected end of pattern after `%`; use `%%` for a \
+                                      literal `%`"
+                                         .to_owned()));
+                }
+            };
+
+            let chunk = match (chunk, width) {
+                (Some(chunk), Some(width)) => Some(Chunk::Padded(Box::new(chunk), width)),
+                (None, Some(_)) => {
+                    return Err(Error("Width modifiers cannot be applied to `%%`".to_owned()));
+                }
+                (chunk, None) => chunk,
+            };
+
+            if let Some(chunk) = chunk {
+                if !next_text.is_empty() {
+                    parsed.push(Chunk::Text(next_text));
+                    next_text = String::new();
+                }
+                parsed.push(chunk);
+            }
+        } else {
+            next_text.push(ch);
+        }
+    }
+
+    if !next_text.is_empty() {
+        parsed.push(Chunk::Text(next_text));
+    }
+
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::default::Default;
+    use std::process;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    use log::LogLevel;
+    use time;
+
+    use {Location, hostname};
+    use clock::ManualClock;
+    use mdc;
+    use super::{Abbreviation, Chunk, Color, Escape, LevelStyle, TimeFmt, TimeZone, PatternLayout,
+                Width, NEWLINE, elapsed_millis, write_time};
+
+    #[test]
+    fn test_parse() {
+        let expected = [Chunk::Text("hi".to_string()),
+                        Chunk::Time(TimeFmt::Str("%Y-%m-%d".to_string()), TimeZone::Local),
+                        Chunk::Time(TimeFmt::Rfc3339, TimeZone::Local),
+                        Chunk::Level(None),
+                        Chunk::Message,
+                        Chunk::Module,
+                        Chunk::File,
+                        Chunk::Line,
+                        Chunk::Thread,
+                        Chunk::ThreadId,
+                        Chunk::ProcessId,
+                        Chunk::Elapsed,
+                        Chunk::Hostname(hostname().unwrap_or_else(|| "unknown".to_owned())),
+                        Chunk::Target,
+                        Chunk::Text("%".to_string()),
+                        Chunk::Text(NEWLINE.to_string())];
+        let actual = PatternLayout::new("hi%d{%Y-%m-%d}%d%l%m%M%f%L%T%I%P%r%h%t%%%n")
+            .unwrap()
+            .pattern;
+        assert_eq!(actual, expected)
+    }
+
+    #[test]
+    fn test_logger_abbreviation() {
+        assert_eq!(PatternLayout::new("%c%c{2}%c{3.}").unwrap().pattern,
+                   [Chunk::Logger(Abbreviation::Full),
+                    Chunk::Logger(Abbreviation::Segments(2)),
+                    Chunk::Logger(Abbreviation::Compressed(3))]);
+        assert!(PatternLayout::new("%c{0}").is_err());
+        assert!(PatternLayout::new("%c{x}").is_err());
+        assert!(PatternLayout::new("%c{1").is_err());
+
+        let cases = [(Abbreviation::Full, "alpha::beta::c", "alpha::beta::c"),
+                     (Abbreviation::Segments(1), "alpha::beta::c", "c"),
+                     (Abbreviation::Segments(2), "alpha::beta::c", "beta::c"),
+                     (Abbreviation::Segments(5), "alpha::beta::c", "alpha::beta::c"),
+                     (Abbreviation::Segments(1), "alpha", "alpha"),
+                     (Abbreviation::Compressed(1), "alpha::beta::c", "a::b::c"),
+                     (Abbreviation::Compressed(3), "alpha::be::c", "alp::be::c"),
+                     (Abbreviation::Compressed(1), "alpha", "alpha")];
+        for &(abbreviation, name, expected) in &cases {
+            let mut buf = vec![];
+            abbreviation.write(&mut buf, name).unwrap();
+            assert_eq!(String::from_utf8(buf).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_level_names() {
+        static LOCATION: Location<'static> = Location {
+            module_path: "path",
+            file: "file",
+            line: 132,
+        };
+
+        assert_eq!(PatternLayout::new("%l%l{short}%l{CHAR}").unwrap().pattern,
+                   [Chunk::Level(None),
+                    Chunk::Level(Some(LevelStyle::Short)),
+                    Chunk::Level(Some(LevelStyle::Char))]);
+        assert!(PatternLayout::new("%l{tiny}").is_err());
+
+        let pw = PatternLayout::new("%l %l{full} %l{lower} %l{short} %l{char}")
+            .unwrap()
+            .level_name(LogLevel::Warn, "WARNING");
+        let mut buf = vec![];
+        pw.append_inner(&mut buf,
+                        LogLevel::Warn,
+                        "target",
+                        &LOCATION,
+                        &format_args!("message")).unwrap();
+        assert_eq!(buf, &b"WARNING WARN warn WRN W\n"[..]);
+
+        let mut buf = vec![];
+        pw.append_inner(&mut buf,
+                        LogLevel::Trace,
+                        "target",
+                        &LOCATION,
+                        &format_args!("message")).unwrap();
+        assert_eq!(buf, &b"TRACE TRACE trace TRC T\n"[..]);
+    }
+
+    #[test]
+    fn test_elapsed() {
+        assert_eq!(elapsed_millis(Duration::new(0, 999999)), 0);
+        assert_eq!(elapsed_millis(Duration::new(12, 345678901)), 12345);
+    }
+
+    #[test]
+    fn test_escapes() {
+        assert_eq!(PatternLayout::new("100%% %m%%").unwrap().pattern,
+                   [Chunk::Text("100% ".to_owned()),
+                    Chunk::Message,
+                    Chunk::Text("%".to_owned())]);
+        assert_eq!(PatternLayout::new("%m %").unwrap_err().to_string(),
+                   "Unexpected end of pattern after `%`; use `%%` for a literal `%`");
+        assert_eq!(PatternLayout::new("%q").unwrap_err().to_string(),
+                   "Invalid formatter `%q`; use `%%` for a literal `%`");
+    }
+
+    #[test]
+    fn test_invalid_date_format() {
+        assert!(PatternLayout::new("%d{%q}").is_err());
+        assert!(PatternLayout::new("%d{%.0f}").is_err());
+        assert!(PatternLayout::new("%d{%.3}").is_err());
+        assert!(PatternLayout::new("%d{%H}{mars}").is_err());
+        assert!(PatternLayout::new("%d{%H}{utc").is_err());
+    }
+
+    #[test]
+    fn test_parse_time_zone() {
+        let expected = [Chunk::Time(TimeFmt::Str("%H".to_string()), TimeZone::Utc),
+                        Chunk::Time(TimeFmt::Rfc3339, TimeZone::Utc),
+                        Chunk::Time(TimeFmt::Str("%H".to_string()), TimeZone::Local)];
+        let actual = PatternLayout::new("%d{%H}{UTC}%d{}{utc}%d{%H}{local}").unwrap().pattern;
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_fractional_seconds() {
+        let tm = time::at_utc(time::Timespec::new(1435708800, 123456789));
+        let mut buf = vec![];
+        write_time(&mut buf, &tm, "%H:%M:%S%.3f|%.6f|%.9f|%%.3f").ok().unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(), "00:00:00.123|.123456|.123456789|%.3f");
+    }
+
+    #[test]
+    fn test_clock() {
+        let clock = Arc::new(ManualClock::new(time::Timespec::new(1435708800, 0)));
+        let pw = PatternLayout::new("%d{%Y-%m-%d %H:%M}{utc} %d{}{utc}")
+            .unwrap()
+            .clock(clock.clone());
+
+        static LOCATION: Location<'static> = Location {
+            module_path: "mod path",
+            file: "the file",
+            line: 132,
+        };
+        let mut buf = vec![];
+        clock.advance(time::Duration::minutes(90));
+        pw.append_inner(&mut buf,
+                        LogLevel::Debug,
+                        "target",
+                        &LOCATION,
+                        &format_args!("the message")).unwrap();
+
+        assert_eq!(String::from_utf8(buf).unwrap(), "2015-07-01 01:30 2015-07-01T01:30:00Z\n");
+    }
+
+    #[test]
+    fn test_log() {
+        let pw = PatternLayout::new("%l %m at %M in %f:%L").unwrap();
+
+        static LOCATION: Location<'static> = Location {
+            module_path: "mod path",
+            file: "the file",
+            line: 132,
+        };
+        let mut buf = vec![];
+        pw.append_inner(&mut buf,
+                        LogLevel::Debug,
+                        "target",
+                        &LOCATION,
+                        &format_args!("the message")).unwrap();
+
+        assert_eq!(buf, &b"DEBUG the message at mod path in the file:132\n"[..]);
+    }
+
+    #[test]
+    fn test_unknown_line() {
+        let pw = PatternLayout::new("%F:%L").unwrap();
+
+        static LOCATION: Location<'static> = Location {
+            module_path: "mod path",
+            file: "the file",
+            line: 0,
+        };
+        let mut buf = vec![];
+        pw.append_inner(&mut buf,
+                        LogLevel::Debug,
+                        "target",
+                        &LOCATION,
+                        &format_args!("the message")).unwrap();
+
+        assert_eq!(buf, &b"the file:\n"[..]);
+    }
+
+    #[test]
+    fn test_trailing_newline() {
+        static LOCATION: Location<'static> = Location {
+            module_path: "mod path",
+            file: "the file",
+            line: 132,
+        };
+
+        let cases = [("%m", "the message\n".to_owned()),
+                     ("%m\n", "the message\n".to_owned()),
+                     ("%m%n", format!("the message{}", NEWLINE))];
+        for &(pattern, ref expected) in &cases {
+            let pw = PatternLayout::new(pattern).unwrap();
+            let mut buf = vec![];
+            pw.append_inner(&mut buf,
+                            LogLevel::Debug,
+                            "target",
+                            &LOCATION,
+                            &format_args!("the message")).unwrap();
+            assert_eq!(String::from_utf8(buf).unwrap(), *expected);
+        }
+    }
+
+    #[test]
+    fn test_unnamed_thread() {
+        thread::spawn(|| {
+            let pw = PatternLayout::new("%T").unwrap();
+            static LOCATION: Location<'static> = Location {
+                module_path: "path",
+                file: "file",
+                line: 132,
+            };
+            let mut buf = vec![];
+            pw.append_inner(&mut buf,
+                            LogLevel::Debug,
+                            "target",
+                            &LOCATION,
+                            &format_args!("message")).unwrap();
+            assert_eq!(buf, b"<unnamed>\n");
+        }).join().unwrap();
+    }
+
+    #[test]
+    fn test_named_thread() {
+        thread::Builder::new().name("foobar".to_string()).spawn(|| {
+            let pw = PatternLayout::new("%T").unwrap();
+            static LOCATION: Location<'static> = Location {
+                module_path: "path",
+                file: "file",
+                line: 132,
+            };
             let mut buf = vec![];
             pw.append_inner(&mut buf,
                             LogLevel::Debug,
@@ -281,9 +1433,321 @@ mod tests {
         }).unwrap().join().unwrap();
     }
 
+    #[test]
+    fn test_parse_highlight() {
+        let expected = [Chunk::Highlight(vec![Chunk::Level(None), Chunk::Text(" ".to_string()),
+                                              Chunk::Time(TimeFmt::Str("%H".to_string()),
+                                                          TimeZone::Local)]),
+                        Chunk::Text("}".to_string())];
+        let actual = PatternLayout::new("%H{%l %d{%H}}}").unwrap().pattern;
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_unterminated_highlight() {
+        assert!(PatternLayout::new("%H{%l").is_err());
+        assert!(PatternLayout::new("%H%l").is_err());
+    }
+
+    #[test]
+    fn test_json_escape() {
+        static LOCATION: Location<'static> = Location {
+            module_path: "path",
+            file: "file",
+            line: 132,
+        };
+
+        let pw = PatternLayout::new("%l \"%J{%m (%t)}\"").unwrap();
+        let mut buf = vec![];
+        pw.append_inner(&mut buf,
+                        LogLevel::Error,
+                        "target",
+                        &LOCATION,
+                        &format_args!("a \"quoted\"\nmessage\\\u{1}")).unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(),
+                   "ERROR \"a \\\"quoted\\\"\\nmessage\\\\\\u0001 (target)\"\n");
+
+        assert!(PatternLayout::new("%J{%m").is_err());
+        assert!(PatternLayout::new("%J%m").is_err());
+    }
+
+    #[test]
+    fn test_wrap() {
+        static LOCATION: Location<'static> = Location {
+            module_path: "path",
+            file: "file",
+            line: 132,
+        };
+
+        let pw = PatternLayout::new("%H{%l} - %wrap{20}{%m}").unwrap();
+        let mut buf = vec![];
+        pw.append_inner(&mut buf,
+                        LogLevel::Info,
+                        "target",
+                        &LOCATION,
+                        &format_args!("the quick brown fox jumps\nover  the lazy dog")).unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(),
+                   "\x1b[32mINFO\x1b[0m - the quick\n       brown fox\n       jumps\n       \
+                    over  the\n       lazy dog\n");
+
+        let pw = PatternLayout::new("%wrap{4}{%m}").unwrap();
+        let mut buf = vec![];
+        pw.append_inner(&mut buf,
+                        LogLevel::Info,
+                        "target",
+                        &LOCATION,
+                        &format_args!("abcdefghij k")).unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(), "abcd\nefgh\nij k\n");
+
+        assert!(PatternLayout::new("%wrap{0}{%m}").is_err());
+        assert!(PatternLayout::new("%wrap{10}%m").is_err());
+        assert!(PatternLayout::new("%wrap{10}{%m").is_err());
+        assert!(PatternLayout::new("%w").is_err());
+    }
+
+    #[test]
+    fn test_escape() {
+        static LOCATION: Location<'static> = Location {
+            module_path: "path",
+            file: "file",
+            line: 132,
+        };
+
+        let message = "a\nforged line\r\t\x1b[31mred\u{7f}";
+        let cases = [(Escape::Off, "a\nforged line\r\t\x1b[31mred\u{7f}"),
+                     (Escape::Control, "a\nforged line\\r\\t\\u{1b}[31mred\\u{7f}"),
+                     (Escape::All, "a\\nforged line\\r\\t\\u{1b}[31mred\\u{7f}")];
+        for &(escape, expected) in &cases {
+            let pw = PatternLayout::new("%m%n").unwrap().escape(escape);
+            let mut buf = vec![];
+            pw.append_inner(&mut buf,
+                            LogLevel::Info,
+                            "target",
+                            &LOCATION,
+                            &format_args!("{}", message)).unwrap();
+            assert_eq!(String::from_utf8(buf).unwrap(), format!("{}{}", expected, NEWLINE));
+        }
+
+        assert_eq!("ALL".parse::<Escape>().unwrap(), Escape::All);
+        assert!("newlines".parse::<Escape>().is_err());
+    }
+
+    #[test]
+    fn test_highlight() {
+        static LOCATION: Location<'static> = Location {
+            module_path: "path",
+            file: "file",
+            line: 132,
+        };
+
+        let pw = PatternLayout::new("%H{%l} %m").unwrap();
+        let mut buf = vec![];
+        pw.append_inner(&mut buf,
+                        LogLevel::Error,
+                        "target",
+                        &LOCATION,
+                        &format_args!("message")).unwrap();
+        assert_eq!(buf, &b"\x1b[31mERROR\x1b[0m message\n"[..]);
+
+        let pw = PatternLayout::new("%H{%l} %m").unwrap().color(LogLevel::Error, Color::Magenta);
+        let mut buf = vec![];
+        pw.append_inner(&mut buf,
+                        LogLevel::Error,
+                        "target",
+                        &LOCATION,
+                        &format_args!("message")).unwrap();
+        assert_eq!(buf, &b"\x1b[35mERROR\x1b[0m message\n"[..]);
+
+        let pw = PatternLayout::new("%H{%l} %m").unwrap().ansi(false);
+        let mut buf = vec![];
+        pw.append_inner(&mut buf,
+                        LogLevel::Error,
+                        "target",
+                        &LOCATION,
+                        &format_args!("message")).unwrap();
+        assert_eq!(buf, &b"ERROR message\n"[..]);
+    }
+
+    #[test]
+    fn test_thread_id() {
+        fn render() -> String {
+            let pw = PatternLayout::new("%I").unwrap();
+            static LOCATION: Location<'static> = Location {
+                module_path: "path",
+                file: "file",
+                line: 132,
+            };
+            let mut buf = vec![];
+            pw.append_inner(&mut buf,
+                            LogLevel::Debug,
+                            "target",
+                            &LOCATION,
+                            &format_args!("message")).unwrap();
+            String::from_utf8(buf).unwrap()
+        }
+
+        let main = render();
+        let other = thread::spawn(render).join().unwrap();
+        assert!(main.trim().parse::<u64>().is_ok(), "{}", main);
+        assert!(other.trim().parse::<u64>().is_ok(), "{}", other);
+        assert!(main != other);
+    }
+
+    #[test]
+    fn test_thread_number() {
+        fn render() -> String {
+            let pw = PatternLayout::new("%thread_id %t").unwrap();
+            static LOCATION: Location<'static> = Location {
+                module_path: "path",
+                file: "file",
+                line: 132,
+            };
+            let mut buf = vec![];
+            pw.append_inner(&mut buf,
+                            LogLevel::Debug,
+                            "target",
+                            &LOCATION,
+                            &format_args!("message")).unwrap();
+            String::from_utf8(buf).unwrap()
+        }
+
+        let first = thread::spawn(|| (render(), render())).join().unwrap();
+        let second = thread::spawn(render).join().unwrap();
+        assert_eq!(first.0, first.1);
+        let number = |s: &str| s.split(' ').next().unwrap().parse::<usize>().unwrap();
+        assert!(number(&second) > number(&first.0));
+        assert!(first.0.ends_with(" target\n"), "{}", first.0);
+
+        assert_eq!(PatternLayout::new("%thread").unwrap().pattern,
+                   [Chunk::Target, Chunk::Text("hread".to_owned())]);
+    }
+
     #[test]
     fn test_default_okay() {
         let _: PatternLayout = Default::default();
     }
-}
 
+    #[test]
+    fn test_mdc() {
+        static LOCATION: Location<'static> = Location {
+            module_path: "path",
+            file: "file",
+            line: 132,
+        };
+
+        let pw = PatternLayout::new("[%X{request_id}] %X{missing}%m").unwrap();
+        mdc::insert("request_id", "1234");
+        let mut buf = vec![];
+        pw.append_inner(&mut buf,
+                        LogLevel::Info,
+                        "target",
+                        &LOCATION,
+                        &format_args!("message")).unwrap();
+        mdc::remove("request_id");
+        assert_eq!(buf, &b"[1234] message\n"[..]);
+
+        assert!(PatternLayout::new("%X").is_err());
+        assert!(PatternLayout::new("%X{key").is_err());
+    }
+
+    #[test]
+    fn test_mdc_all() {
+        static LOCATION: Location<'static> = Location {
+            module_path: "path",
+            file: "file",
+            line: 132,
+        };
+
+        let pw = PatternLayout::new("%kv %m").unwrap();
+        let mut buf = vec![];
+        pw.append_inner(&mut buf, LogLevel::Info, "target", &LOCATION, &format_args!("a"))
+            .unwrap();
+        mdc::insert("user", "bob");
+        mdc::insert("request_id", "\"1234\"");
+        pw.append_inner(&mut buf, LogLevel::Info, "target", &LOCATION, &format_args!("b"))
+            .unwrap();
+        mdc::clear();
+        assert_eq!(String::from_utf8(buf).unwrap(),
+                   "{} a\n{\"request_id\":\"\\\"1234\\\"\",\"user\":\"bob\"} b\n");
+
+        assert!(PatternLayout::new("%k").is_err());
+        assert!(PatternLayout::new("%kx").is_err());
+    }
+
+    #[test]
+    fn test_parse_width() {
+        let expected = [Chunk::Padded(Box::new(Chunk::Level(None)),
+                                      Width {
+                                          min: 5,
+                                          left_justify: true,
+                                          max: None,
+                                          truncate_end: false,
+                                      }),
+                        Chunk::Padded(Box::new(Chunk::Module),
+                                      Width {
+                                          min: 0,
+                                          left_justify: false,
+                                          max: Some(20),
+                                          truncate_end: false,
+                                      }),
+                        Chunk::Padded(Box::new(Chunk::Message),
+                                      Width {
+                                          min: 3,
+                                          left_justify: false,
+                                          max: Some(12),
+                                          truncate_end: true,
+                                      })];
+        let actual = PatternLayout::new("%-5l%.20M%3.-12m").unwrap().pattern;
+        assert_eq!(actual, expected);
+
+        assert!(PatternLayout::new("%-l").is_err());
+        assert!(PatternLayout::new("%5.l").is_err());
+        assert!(PatternLayout::new("%5%").is_err());
+    }
+
+    #[test]
+    fn test_width() {
+        static LOCATION: Location<'static> = Location {
+            module_path: "foo::bar::baz",
+            file: "file",
+            line: 132,
+        };
+
+        let pw = PatternLayout::new("[%-7l][%7l][%.8M][%.-6M][%4.6m]").unwrap();
+        let mut buf = vec![];
+        pw.append_inner(&mut buf,
+                        LogLevel::Info,
+                        "target",
+                        &LOCATION,
+                        &format_args!("hi")).unwrap();
+        assert_eq!(buf, &b"[INFO   ][   INFO][bar::baz][foo::b][  hi]\n"[..]);
+
+        assert!(PatternLayout::new("%1024m").is_ok());
+        assert!(PatternLayout::new("%1025m").is_err());
+        assert!(PatternLayout::new("%-1025.1025m").is_err());
+        assert!(PatternLayout::new("%.2000m").is_ok());
+        assert!(PatternLayout::new("%10.2000m").is_ok());
+        assert!(PatternLayout::new("%99999999999999999999999m").is_err());
+        assert!(PatternLayout::new("%.99999999999999999999999m").is_err());
+    }
+
+    #[test]
+    fn test_process_id() {
+        static LOCATION: Location<'static> = Location {
+            module_path: "path",
+            file: "file",
+            line: 132,
+        };
+
+        let pw = PatternLayout::new("%P").unwrap();
+        for _ in 0..2 {
+            let mut buf = vec![];
+            pw.append_inner(&mut buf,
+                            LogLevel::Info,
+                            "target",
+                            &LOCATION,
+                            &format_args!("message")).unwrap();
+            assert_eq!(String::from_utf8(buf).unwrap(), format!("{}\n", process::id()));
+        }
+    }
+}
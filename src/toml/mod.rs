@@ -3,10 +3,52 @@
 //! # Syntax
 //!
 //! ```toml
+//! # The version of the config format the file is written for. A file with a
+//! # newer version than this build supports is rejected with an error naming
+//! # both versions, rather than with errors about the keys it doesn't
+//! # recognize. If not specified, defaults to 1, which is currently the only
+//! # version.
+//! version = 1
+//!
 //! # If set, log4rs will scan the file at the specified rate in seconds for
-//! # changes and automatically reconfigure the logger.
+//! # changes and automatically reconfigure the logger. Appenders whose
+//! # settings have not changed are kept, along with their open files and
+//! # buffered output, rather than being created again.
 //! refresh_rate = 30
 //!
+//! # If true, log4rs will instead watch the file for changes using the
+//! # platform's file notification API where one is supported. On other
+//! # platforms the file is scanned at the refresh rate, or every 5 seconds if
+//! # none is set.
+//! # watch = true
+//!
+//! # Determines what counts as a change to the file. With "content" (the
+//! # default), the file is reread when its modification time changes, but
+//! # only reloaded if its contents differ from those last loaded. With
+//! # "mtime", a new modification time alone is enough, so touching the file
+//! # forces a reload.
+//! # reload_on = "content"
+//!
+//! # The default `escape` setting of every appender, described below.
+//! # escape = "all"
+//!
+//! # Other config files may be merged into this one, with paths resolved
+//! # relative to the directory containing this file. Included files are
+//! # merged in order, followed by this file. Their appenders and loggers are
+//! # combined, though an appender name may only be defined once, and any other
+//! # key in a later file overrides the same key in an earlier one. Changes to
+//! # included files are not detected by `refresh_rate` or `watch`.
+//! # include = ["appenders.toml"]
+//!
+//! # Each async appender normally starts a thread of its own. Pools of
+//! # threads which they share instead are configured as tables inside the
+//! # "async_pool" table, and are referenced by name from the `pool` key of an
+//! # appender. A pool's threads are started when an appender first uses it.
+//! # The `Creator` keeps its pools when the file is reloaded, so the same
+//! # pool continues to be used unless its number of threads changes.
+//! # [async_pool.shared]
+//! # threads = 2
+//!
 //! # Appenders are configured as tables inside the "appender" table. This
 //! # appender is named "foo".
 //! [appender.foo]
@@ -18,8 +60,55 @@
 //! # Arbitrary fields may be added to appender configurations. Remaining
 //! # entries will be passed to the `CreateAppender` object associated with
 //! # the specified kind.
+//! #
+//! # Each appender has its own pattern, so for example a console appender
+//! # can use a terse colored layout while a file appender records the source
+//! # location of each message.
 //! pattern = "%d [%t] %m"
 //!
+//! # String values in appender and filter configurations may reference
+//! # environment variables as `$NAME` or `${NAME}`. It is an error for the
+//! # variable to be unset unless a default is provided: `${NAME:-default}`.
+//! # A literal `$` is written as `$$`.
+//! # path = "${LOG_DIR:-log}/foo.log"
+//!
+//! # Instead of a pattern, the builtin appenders accept an `encoder` key to
+//! # select a different output format: "json", "raw" for the message alone,
+//! # or the kind of an encoder registered with the `Creator`. An encoder
+//! # which takes settings of its own is configured as a table instead, which
+//! # must specify a "kind":
+//! # encoder = { kind = "json" }
+//! # encoder = "json"
+//!
+//! # The names written by `%l` in the pattern may be overridden for some or
+//! # all levels. The other levels keep their full names.
+//! # level_names = { warn = "WARNING", error = "FATAL" }
+//!
+//! # Control characters in log messages may be escaped, so that a message
+//! # built from untrusted input cannot forge log lines or write ANSI escape
+//! # codes: "control" escapes all but newlines, and "all" escapes newlines as
+//! # well. Defaults to "off", or to the top-level `escape` setting. Without a
+//! # pattern, the default pattern "%d %l %t - %m" is used.
+//! # escape = "control"
+//!
+//! # Each log event ends with exactly one line terminator, which is "lf" by
+//! # default and may be set to "crlf".
+//! # line_ending = "crlf"
+//!
+//! # The `on_error` key may be set on any appender to control what happens
+//! # when it fails: "ignore" reports every error and carries on (the
+//! # default), "backoff" reports an error and skips the appender for a
+//! # growing period, and "disable" reports the first error and stops using
+//! # the appender.
+//! # on_error = "backoff"
+//!
+//! # A log event is passed to the appenders of its logger in the order they
+//! # are listed, followed by those inherited from its parent. If the
+//! # `required` key is set to true and this appender fails to write a log
+//! # event, the log event is not passed to the appenders after it. Defaults
+//! # to false.
+//! # required = true
+//!
 //! # Filters attached to an appender are configured inside the "filter" array.
 //! [[appender.foo.filter]]
 //! # Like appenders, filters must specify a "kind".
@@ -34,7 +123,9 @@
 //! # "debug" and no appenders.
 //! [root]
 //! # The maximum log level for the root logger. Must be specified if the
-//! # "root" table is defined.
+//! # "root" table is defined. With "off", only log events matched by a
+//! # logger with a level of its own are written, so logging can be disabled
+//! # everywhere but in selected modules.
 //! level = "warn"
 //!
 //! # The list of names of appenders attached to the root logger. If not
@@ -55,33 +146,71 @@
 //! appenders = ["foo"]
 //!
 //! # The additivity of the logger. If true, the appenders attached to this
-//! # logger's parent will also be attached to this logger. If not specified,
-//! # defaults to true.
+//! # logger's parent, including those the parent inherited, will also be
+//! # attached to this logger. If false, only this logger's own appenders are
+//! # used, so a non-additive logger with no appenders discards log events.
+//! # If not specified, defaults to true.
 //! additive = false
+//!
+//! # The reserved `meta` and `_` tables are ignored, so that the file can
+//! # carry annotations for other tools. Any other unrecognized top-level key
+//! # or table is an error.
+//! [meta]
+//! owner = "ops"
 //! ```
-use log::LogLevelFilter;
+use log::{LogLevel, LogLevelFilter};
 use std::collections::HashMap;
 use std::default::Default;
+use std::env;
 use std::error;
 use std::fmt;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration as StdDuration;
 use time::Duration;
 use toml_parser::{self, Value};
 
-use appender::{FileAppender, ConsoleAppender};
-use filter::{ThresholdFilter};
+use appender::{FileAppender, DailyRollingFileAppender, ConsoleAppender, Target, ColorMode,
+               AsyncAppender, AsyncPool, OverflowPolicy, SyslogAppender, SyslogTransport,
+               TcpAppender, OutagePolicy, UdpAppender, Oversize, SmtpAppender, Tls,
+               LeveledFileAppender, DedupAppender, RollingFileAppender, Policy, Roll, SizePolicy,
+               DailyPolicy, RenameRoller, FixedWindowRoller, ProcessAppender, RestartPolicy,
+               EventLogAppender};
+#[cfg(unix)]
+use appender::FifoAppender;
+#[cfg(target_os = "linux")]
+use appender::JournaldAppender;
+use filter::{ThresholdFilter, LevelRangeFilter, SamplingFilter, MessageFilter};
 use config;
-use pattern::PatternLayout;
-use {Append, Filter, PrivateTomlConfigExt, PrivateConfigErrorsExt};
+use encoder::{JsonEncoder, LineEnding, LineEndingEncoder, RawEncoder, TruncatingEncoder};
+use pattern::{Escape, PatternLayout};
+use {Append, Encode, Filter, FilterResponse, PrivateTomlConfigExt, PrivateTomlParseErrorsExt,
+     PrivateConfigErrorsExt, PrivateCreatorExt, PrivateConfigAppenderExt, handle_error};
 
 mod raw;
 
 /// A trait implemented by types that can create appenders.
 pub trait CreateAppender: Send + 'static {
     /// Creates an appender with the specified config.
-    fn create_appender(&self, config: toml_parser::Table)
+    ///
+    /// Resources such as files should be acquired here rather than when the
+    /// first log event arrives, so that a misconfigured appender is reported
+    /// along with its name when the config is loaded. The context gives
+    /// access to the encoders registered with the `Creator`.
+    fn create_appender(&self, config: toml_parser::Table, context: &Context)
                        -> Result<Box<Append>, Box<error::Error>>;
 }
 
+impl<F> CreateAppender for F
+    where F: Fn(toml_parser::Table, &Context) -> Result<Box<Append>, Box<error::Error>>
+                 + Send + 'static
+{
+    fn create_appender(&self, config: toml_parser::Table, context: &Context)
+                       -> Result<Box<Append>, Box<error::Error>> {
+        self(config, context)
+    }
+}
+
 /// A trait implemented by types that can create filters.
 pub trait CreateFilter: Send + 'static {
     /// Creates a filter with the specified config.
@@ -89,6 +218,35 @@ pub trait CreateFilter: Send + 'static {
                      -> Result<Box<Filter>, Box<error::Error>>;
 }
 
+impl<F> CreateFilter for F
+    where F: Fn(toml_parser::Table) -> Result<Box<Filter>, Box<error::Error>> + Send + 'static
+{
+    fn create_filter(&self, config: toml_parser::Table)
+                     -> Result<Box<Filter>, Box<error::Error>> {
+        self(config)
+    }
+}
+
+/// A trait implemented by types that can create encoders.
+pub trait CreateEncoder: Send + Sync + 'static {
+    /// Creates an encoder with the specified config.
+    ///
+    /// The config is empty when the encoder is selected by kind alone, as in
+    /// `encoder = "kind"`.
+    fn create_encoder(&self, config: toml_parser::Table)
+                      -> Result<Box<Encode>, Box<error::Error>>;
+}
+
+impl<F> CreateEncoder for F
+    where F: Fn(toml_parser::Table) -> Result<Box<Encode>, Box<error::Error>>
+                 + Send + Sync + 'static
+{
+    fn create_encoder(&self, config: toml_parser::Table)
+                      -> Result<Box<Encode>, Box<error::Error>> {
+        self(config)
+    }
+}
+
 /// A type that can create appenders.
 ///
 /// `Creator` implements `Default`, which returns a `Creator` with the
@@ -96,35 +254,140 @@ pub trait CreateFilter: Send + 'static {
 ///
 /// * Appenders
 ///     * "file" -> `FileAppenderCreator`
+///     * "daily_rolling_file" -> `DailyRollingFileAppenderCreator`
 ///     * "console" -> `ConsoleAppenderCreator`
+///     * "async" -> `AsyncAppenderCreator`
+///     * "async_file" -> `AsyncFileAppenderCreator`
+///     * "syslog" -> `SyslogAppenderCreator`
+///     * "tcp" -> `TcpAppenderCreator`
+///     * "udp" -> `UdpAppenderCreator`
+///     * "smtp" -> `SmtpAppenderCreator`
+///     * "fifo" -> `FifoAppenderCreator` (Unix only)
+///     * "dedup" -> `DedupAppenderCreator`
+///     * "rolling_file" -> `RollingFileAppenderCreator`
+///     * "leveled_file" -> `LeveledFileAppenderCreator`
+///     * "process" -> `ProcessAppenderCreator`
+///     * "windows_event_log" -> `EventLogAppenderCreator`
+///     * "journald" -> `JournaldAppenderCreator` (Linux only)
 /// * Filters
 ///     * "threshold" -> `ThresholdFilterCreator`
+///     * "level_range" -> `LevelRangeFilterCreator`
+///     * "sampling" -> `SamplingFilterCreator`
+///     * "message" -> `MessageFilterCreator`
+///
+/// The "json" and "raw" encoders are always available, but may be replaced
+/// by registering an encoder creator under the same kind.
+///
+/// # Custom appenders
+///
+/// Appenders defined outside of log4rs are made available to config files
+/// by registering a creator for them under a new kind with `add_appender`.
+/// The creator is passed the appender's table with the `kind` and `filter`
+/// keys removed, and any key it does not recognize should be reported as an
+/// error. Closures taking the table and a `Context` can be used as creators
+/// directly:
+///
+/// ```
+/// # extern crate log;
+/// # extern crate log4rs;
+/// # extern crate toml;
+/// use std::error::Error;
+/// use log::LogRecord;
+/// use log4rs::Append;
+/// use log4rs::toml::{Config, Context, Creator};
+///
+/// struct KafkaAppender {
+///     topic: String,
+/// }
+///
+/// impl Append for KafkaAppender {
+///     fn append(&mut self, record: &LogRecord) -> Result<(), Box<Error>> {
+///         // send the record to `self.topic`
+/// #       let _ = (record, &self.topic);
+///         Ok(())
+///     }
+/// }
+///
+/// # fn main() {
+/// let mut creator = Creator::default();
+/// creator.add_appender("kafka", Box::new(|mut config: toml::Table, _: &Context| {
+///     let topic = match config.remove("topic") {
+///         Some(toml::Value::String(topic)) => topic,
+///         _ => return Err("`topic` must be a string".into()),
+///     };
+///     Ok(Box::new(KafkaAppender { topic: topic }) as Box<Append>)
+/// }));
+///
+/// let source = "[appender.events]\nkind = \"kafka\"\ntopic = \"logs\"";
+/// let (config, errors) = Config::parse(source, &creator).unwrap();
+/// errors.unwrap();
+/// assert_eq!(config.config().appenders().len(), 1);
+/// # }
+/// ```
+///
+/// Filters and encoders are registered in the same way with `add_filter` and
+/// `add_encoder`. A registered encoder is available to every appender which
+/// accepts the `encoder` key, including custom appenders whose creators read
+/// their encoder with `Context::create_encoder`.
 pub struct Creator {
     appenders: HashMap<String, Box<CreateAppender>>,
     filters: HashMap<String, Box<CreateFilter>>,
+    encoders: HashMap<String, Box<CreateEncoder>>,
+    reusable: HashMap<String, String>,
+    // set once the creator loads configs which replace a running one
+    reloading: bool,
+    // the async pools started for configs loaded by this creator, by name,
+    // along with their number of threads
+    pools: Mutex<HashMap<String, (usize, AsyncPool)>>,
 }
 
 impl Default for Creator {
     fn default() -> Creator {
         let mut creator = Creator::new();
         creator.add_appender("file", Box::new(FileAppenderCreator));
+        creator.add_appender("daily_rolling_file", Box::new(DailyRollingFileAppenderCreator));
         creator.add_appender("console", Box::new(ConsoleAppenderCreator));
+        creator.add_appender("async", Box::new(AsyncAppenderCreator));
+        creator.add_appender("async_file", Box::new(AsyncFileAppenderCreator));
+        creator.add_appender("syslog", Box::new(SyslogAppenderCreator));
+        creator.add_appender("tcp", Box::new(TcpAppenderCreator));
+        creator.add_appender("udp", Box::new(UdpAppenderCreator));
+        creator.add_appender("smtp", Box::new(SmtpAppenderCreator));
+        #[cfg(unix)]
+        creator.add_appender("fifo", Box::new(FifoAppenderCreator));
+        creator.add_appender("dedup", Box::new(DedupAppenderCreator));
+        creator.add_appender("rolling_file", Box::new(RollingFileAppenderCreator));
+        creator.add_appender("leveled_file", Box::new(LeveledFileAppenderCreator));
+        creator.add_appender("process", Box::new(ProcessAppenderCreator));
+        creator.add_appender("windows_event_log", Box::new(EventLogAppenderCreator));
+        #[cfg(target_os = "linux")]
+        creator.add_appender("journald", Box::new(JournaldAppenderCreator));
         creator.add_filter("threshold", Box::new(ThresholdFilterCreator));
+        creator.add_filter("level_range", Box::new(LevelRangeFilterCreator));
+        creator.add_filter("sampling", Box::new(SamplingFilterCreator));
+        creator.add_filter("message", Box::new(MessageFilterCreator));
         creator
     }
 }
 
 impl Creator {
-    /// Creates a new `Creator` with no appender or filter mappings.
+    /// Creates a new `Creator` with no appender, filter or encoder mappings.
     pub fn new() -> Creator {
         Creator {
             appenders: HashMap::new(),
             filters: HashMap::new(),
+            encoders: HashMap::new(),
+            reusable: HashMap::new(),
+            reloading: false,
+            pools: Mutex::new(HashMap::new()),
         }
     }
 
     /// Adds a mapping from the specified `kind` to the specified appender
     /// creator.
+    ///
+    /// A mapping already registered for the `kind`, including one of the
+    /// defaults, is replaced.
     pub fn add_appender(&mut self, kind: &str, creator: Box<CreateAppender>) {
         self.appenders.insert(kind.to_string(), creator);
     }
@@ -135,10 +398,16 @@ impl Creator {
         self.filters.insert(kind.to_string(), creator);
     }
 
-    fn create_appender(&self, kind: &str, config: toml_parser::Table)
+    /// Adds a mapping from the specified `kind` to the specified encoder
+    /// creator.
+    pub fn add_encoder(&mut self, kind: &str, creator: Box<CreateEncoder>) {
+        self.encoders.insert(kind.to_string(), creator);
+    }
+
+    fn create_appender(&self, kind: &str, config: toml_parser::Table, context: &Context)
                        -> Result<Box<Append>, Box<error::Error>> {
         match self.appenders.get(kind) {
-            Some(creator) => creator.create_appender(config),
+            Some(creator) => creator.create_appender(config, context),
             None => Err(Box::new(StringError(format!("No creator registered for appender kind \"{}\"", kind))))
         }
     }
@@ -152,6 +421,97 @@ impl Creator {
     }
 }
 
+/// The state passed to appender creators while a config is loaded.
+pub struct Context<'a> {
+    creator: &'a Creator,
+    escape: Escape,
+    // the number of threads of each async pool defined by the config
+    pools: &'a HashMap<String, usize>,
+}
+
+impl<'a> Context<'a> {
+    // Returns the async pool with the specified name, starting it if the
+    // creator has no pool of that name with the configured number of threads.
+    fn pool(&self, name: &str) -> Result<AsyncPool, Box<error::Error>> {
+        let threads = match self.pools.get(name) {
+            Some(&threads) => threads,
+            None => {
+                return Err(Box::new(StringError(format!("No async pool named \"{}\"", name))));
+            }
+        };
+
+        let mut pools = self.creator.pools.lock().unwrap();
+        match pools.get(name) {
+            Some(&(started, ref pool)) if started == threads => return Ok(pool.clone()),
+            _ => {}
+        }
+        let pool = try!(AsyncPool::new(threads));
+        pools.insert(name.to_owned(), (threads, pool.clone()));
+        Ok(pool)
+    }
+
+    /// Determines if the config is being reloaded to replace a running one,
+    /// rather than loaded when the logger is initialized.
+    ///
+    /// Appenders should only take actions meant for the start of the program,
+    /// such as rolling the previous run's log file, when this is false.
+    pub fn reloading(&self) -> bool {
+        self.creator.reloading
+    }
+
+    /// Creates an appender of the specified kind with the `Creator` whose
+    /// config is being loaded.
+    ///
+    /// This allows an appender which wraps another appender to support every
+    /// kind registered with the `Creator`, including custom ones.
+    pub fn create_appender(&self, kind: &str, config: toml_parser::Table)
+                           -> Result<Box<Append>, Box<error::Error>> {
+        self.creator.create_appender(kind, config, self)
+    }
+
+    /// Creates an encoder from the `pattern`, `encoder`, `escape`,
+    /// `level_names`, `line_ending` and `max_len` keys of an appender's
+    /// config, removing them.
+    ///
+    /// The keys are interpreted as they are by the `FileAppenderCreator`, so
+    /// encoders registered with the `Creator` may be selected by kind, and
+    /// the top-level `escape` setting applies. `None` is returned if none of
+    /// the keys are present and the appender's default encoder should be
+    /// used.
+    pub fn create_encoder(&self, config: &mut toml_parser::Table)
+                          -> Result<Option<Box<Encode>>, Box<error::Error>> {
+        let line_ending = match config.remove("line_ending") {
+            Some(Value::String(line_ending)) => Some(try!(line_ending.parse::<LineEnding>())),
+            Some(_) => {
+                return Err(Box::new(StringError("`line_ending` must be a string".to_string())));
+            }
+            None => None,
+        };
+
+        let max_len = match config.remove("max_len") {
+            Some(Value::Integer(max_len)) if max_len >= 0 => Some(max_len as usize),
+            Some(_) => {
+                return Err(Box::new(StringError("`max_len` must be a non-negative integer"
+                                                    .to_string())));
+            }
+            None => None,
+        };
+
+        let mut encoder = try!(create_base_encoder(config, self));
+        if let Some(max_len) = max_len {
+            let inner = encoder.unwrap_or_else(|| Box::new(PatternLayout::default()));
+            encoder = Some(Box::new(TruncatingEncoder::new(inner, max_len)));
+        }
+        match line_ending {
+            Some(line_ending) => {
+                let encoder = encoder.unwrap_or_else(|| Box::new(PatternLayout::default()));
+                Ok(Some(Box::new(LineEndingEncoder::new(encoder, line_ending))))
+            }
+            None => Ok(encoder),
+        }
+    }
+}
+
 /// Errors encountered when parsing a log4rs TOML config.
 #[derive(Debug)]
 pub struct ParseErrors {
@@ -240,25 +600,69 @@ impl error::Error for Errors {
     }
 }
 
+/// The newest config format version supported by this build.
+///
+/// Config files may declare the version they were written for with a
+/// top-level `version` key.
+pub const CONFIG_VERSION: i64 = 1;
+
+/// Determines when a config file is considered to have changed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ReloadOn {
+    /// The file has changed when its modification time changes.
+    Mtime,
+    /// The file has changed when its contents change.
+    ///
+    /// The contents are only read when the modification time changes, or
+    /// when it is too recent to tell apart from that of an earlier write.
+    Content,
+}
+
 /// A deserialized TOML log4rs configuration.
 pub struct Config {
     refresh_rate: Option<Duration>,
+    watch: bool,
+    reload_on: ReloadOn,
     config: config::Config,
 }
 
 impl Config {
     /// Creates a log4rs `Config` from the specified TOML config string and `Creator`.
+    ///
+    /// Files named by an `include` key are resolved relative to the current
+    /// directory.
     pub fn parse(config: &str, creator: &Creator)
                  -> Result<(Config, Result<(), Errors>), ParseErrors> {
-        let mut errors = vec![];
+        Config::parse_inner(config, None, creator)
+    }
 
-        let config = match raw::parse(config) {
-            Ok(config) => config,
-            Err(errors) => return Err(ParseErrors { errors: errors }),
-        };
+    /// Creates a log4rs `Config` from a TOML config string which was read
+    /// from the file at the specified path.
+    ///
+    /// Files named by an `include` key are resolved relative to the directory
+    /// containing `path`.
+    pub fn parse_file(config: &str, path: &Path, creator: &Creator)
+                      -> Result<(Config, Result<(), Errors>), ParseErrors> {
+        Config::parse_inner(config, Some(path), creator)
+    }
+
+    fn parse_inner(config: &str, path: Option<&Path>, creator: &Creator)
+                   -> Result<(Config, Result<(), Errors>), ParseErrors> {
+        match raw::parse(config, path) {
+            Ok(config) => Ok(Config::from_raw(config, creator)),
+            Err(errors) => Err(ParseErrors { errors: errors }),
+        }
+    }
+
+    fn from_raw(config: raw::Config, creator: &Creator) -> (Config, Result<(), Errors>) {
+        let mut errors = vec![];
 
         let raw::Config {
             refresh_rate,
+            watch,
+            reload_on,
+            escape,
+            async_pools,
             root: raw_root,
             appenders: raw_appenders,
             loggers: raw_loggers,
@@ -275,14 +679,52 @@ impl Config {
             None => config::Root::builder(LogLevelFilter::Debug).build(),
         };
 
+        let context = Context {
+            creator: creator,
+            escape: escape.unwrap_or(Escape::Off),
+            pools: &async_pools,
+        };
         let mut config = config::Config::builder(root);
 
-        for (name, raw::Appender { kind, config: raw_config, filters }) in raw_appenders {
-            match creator.create_appender(&kind, raw_config) {
-                Ok(appender_obj) => {
-                    let mut builder = config::Appender::builder(name.clone(), appender_obj);
+        for (name, raw::Appender { kind, config: raw_config, filters, on_error, required })
+                in raw_appenders {
+            let created = expand_env_table(raw_config).and_then(|raw_config| {
+                let mut identity = format!("{}{:?}", kind, raw_config);
+                if let Some(escape) = escape {
+                    identity.push_str(&format!("{:?}", escape));
+                }
+                // the creator replaces a pool whose number of threads changes,
+                // so an appender using it must then be created again
+                if let Some(&Value::String(ref pool)) = raw_config.get("pool") {
+                    if let Some(threads) = async_pools.get(pool) {
+                        identity.push_str(&format!(" with {} pool threads", threads));
+                    }
+                }
+                // an unchanged appender is taken over from the current config
+                // rather than created again
+                if creator.reusable.get(&name) == Some(&identity) {
+                    Ok((None, identity))
+                } else {
+                    creator.create_appender(&kind, raw_config, &context)
+                        .map(|a| (Some(a), identity))
+                }
+            });
+            match created {
+                Ok((appender_obj, identity)) => {
+                    let builder = match appender_obj {
+                        Some(appender_obj) => config::Appender::builder(name.clone(), appender_obj),
+                        None => config::Appender::reused(name.clone()),
+                    };
+                    let mut builder = builder.identity(identity);
+                    if let Some(on_error) = on_error {
+                        builder = builder.on_error(on_error);
+                    }
+                    if let Some(required) = required {
+                        builder = builder.required(required);
+                    }
                     for raw::Filter { kind, config } in filters.unwrap_or(vec![]) {
-                        match creator.create_filter(&kind, config) {
+                        match expand_env_table(config)
+                                .and_then(|config| creator.create_filter(&kind, config)) {
                             Ok(filter) => builder = builder.filter(filter),
                             Err(err) => errors.push(Error::FilterCreation(name.clone(), err)),
                         }
@@ -314,6 +756,8 @@ impl Config {
 
         let config = Config {
             refresh_rate: refresh_rate,
+            watch: watch,
+            reload_on: reload_on.unwrap_or(ReloadOn::Content),
             config: config
         };
 
@@ -325,7 +769,7 @@ impl Config {
             })
         };
 
-        Ok((config, errors))
+        (config, errors)
     }
 
     /// Returns the requested refresh rate.
@@ -333,6 +777,16 @@ impl Config {
         self.refresh_rate
     }
 
+    /// Determines if the config file should be watched for changes.
+    pub fn watch(&self) -> bool {
+        self.watch
+    }
+
+    /// Returns what counts as a change to the config file.
+    pub fn reload_on(&self) -> ReloadOn {
+        self.reload_on
+    }
+
     /// Returns the log4rs `Config`.
     pub fn config(&self) -> &config::Config {
         &self.config
@@ -340,9 +794,33 @@ impl Config {
 }
 
 impl PrivateTomlConfigExt for Config {
-    fn unpack(self) -> (Option<Duration>, config::Config) {
-        let Config { refresh_rate, config } = self;
-        (refresh_rate, config)
+    fn unpack(self) -> (Option<Duration>, bool, config::Config) {
+        let Config { refresh_rate, watch, config, .. } = self;
+        (refresh_rate, watch, config)
+    }
+
+    fn parse_table(table: toml_parser::Table, creator: &Creator)
+                   -> Result<(Config, Result<(), Errors>), ParseErrors> {
+        match raw::finish_parse_config(table) {
+            Ok(config) => Ok(Config::from_raw(config, creator)),
+            Err(errors) => Err(ParseErrors { errors: errors }),
+        }
+    }
+}
+
+impl PrivateCreatorExt for Creator {
+    fn set_reusable(&mut self, reusable: HashMap<String, String>) {
+        self.reusable = reusable;
+    }
+
+    fn set_reloading(&mut self, reloading: bool) {
+        self.reloading = reloading;
+    }
+}
+
+impl PrivateTomlParseErrorsExt for ParseErrors {
+    fn new(errors: Vec<String>) -> ParseErrors {
+        ParseErrors { errors: errors }
     }
 }
 
@@ -367,6 +845,200 @@ impl From<String> for StringError {
     }
 }
 
+fn expand_env_table(table: toml_parser::Table)
+                    -> Result<toml_parser::Table, Box<error::Error>> {
+    let mut expanded = toml_parser::Table::new();
+    for (key, value) in table {
+        expanded.insert(key, try!(expand_env_value(value)));
+    }
+    Ok(expanded)
+}
+
+fn expand_env_value(value: Value) -> Result<Value, Box<error::Error>> {
+    match value {
+        Value::String(s) => Ok(Value::String(try!(expand_env(&s)))),
+        Value::Array(array) => {
+            let mut expanded = vec![];
+            for value in array {
+                expanded.push(try!(expand_env_value(value)));
+            }
+            Ok(Value::Array(expanded))
+        }
+        Value::Table(table) => Ok(Value::Table(try!(expand_env_table(table)))),
+        value => Ok(value),
+    }
+}
+
+fn expand_env(s: &str) -> Result<String, Box<error::Error>> {
+    let mut expanded = String::new();
+    let mut it = s.chars().peekable();
+
+    while let Some(ch) = it.next() {
+        if ch != '$' {
+            expanded.push(ch);
+            continue;
+        }
+
+        let (name, default) = match it.peek().cloned() {
+            Some('$') => {
+                it.next();
+                expanded.push('$');
+                continue;
+            }
+            Some('{') => {
+                it.next();
+                let mut body = String::new();
+                loop {
+                    match it.next() {
+                        Some('}') => break,
+                        Some(ch) => body.push(ch),
+                        None => {
+                            return Err(Box::new(StringError(
+                                format!("Unterminated environment variable in \"{}\"", s))));
+                        }
+                    }
+                }
+                match body.find(":-") {
+                    Some(idx) => (body[..idx].to_owned(), Some(body[idx + 2..].to_owned())),
+                    None => (body, None),
+                }
+            }
+            Some(ch) if ch == '_' || ch.is_alphabetic() => {
+                let mut name = String::new();
+                while let Some(&ch) = it.peek() {
+                    if ch == '_' || ch.is_alphanumeric() {
+                        name.push(ch);
+                        it.next();
+                    } else {
+                        break;
+                    }
+                }
+                (name, None)
+            }
+            _ => {
+                expanded.push('$');
+                continue;
+            }
+        };
+
+        match (env::var(&name), default) {
+            (Ok(value), _) => expanded.push_str(&value),
+            (Err(_), Some(default)) => expanded.push_str(&default),
+            (Err(_), None) => {
+                return Err(Box::new(StringError(
+                    format!("Environment variable `{}` is not set", name))));
+            }
+        }
+    }
+
+    Ok(expanded)
+}
+
+// Creates an encoder from the keys of an appender's config other than
+// `line_ending` and `max_len`, which only apply to some appenders.
+fn create_base_encoder(config: &mut toml_parser::Table, context: &Context)
+                       -> Result<Option<Box<Encode>>, Box<error::Error>> {
+    let level_names = config.remove("level_names");
+    if level_names.is_some() && !config.contains_key("pattern") {
+        return Err(Box::new(StringError("`level_names` requires `pattern`".to_string())));
+    }
+
+    let escape = match config.remove("escape") {
+        Some(Value::String(escape)) => {
+            if config.contains_key("encoder") {
+                return Err(Box::new(StringError("`escape` cannot be used with `encoder`"
+                                                    .to_string())));
+            }
+            try!(escape.parse::<Escape>())
+        }
+        Some(_) => return Err(Box::new(StringError("`escape` must be a string".to_string()))),
+        None => context.escape,
+    };
+
+    match (config.remove("pattern"), config.remove("encoder")) {
+        (Some(_), Some(_)) => {
+            Err(Box::new(StringError("`pattern` and `encoder` cannot both be specified"
+                                         .to_string())))
+        }
+        (Some(Value::String(pattern)), None) => {
+            let mut layout = try!(PatternLayout::new(&pattern)).escape(escape);
+            if let Some(level_names) = level_names {
+                layout = try!(apply_level_names(layout, level_names));
+            }
+            Ok(Some(Box::new(layout)))
+        }
+        (Some(_), None) => Err(Box::new(StringError("`pattern` must be a string".to_string()))),
+        (None, Some(Value::String(kind))) => {
+            create_encoder_kind(&kind, toml_parser::Table::new(), context).map(Some)
+        }
+        (None, Some(Value::Table(mut config))) => {
+            let kind = try!(required_string(&mut config, "kind")
+                                .map_err(|e| format!("Invalid `encoder`: {}", e)));
+            create_encoder_kind(&kind, config, context).map(Some)
+        }
+        (None, Some(_)) => {
+            Err(Box::new(StringError("`encoder` must be a string or a table".to_string())))
+        }
+        (None, None) if escape != Escape::Off => {
+            Ok(Some(Box::new(PatternLayout::default().escape(escape))))
+        }
+        (None, None) => Ok(None),
+    }
+}
+
+fn apply_level_names(mut layout: PatternLayout, level_names: Value)
+                     -> Result<PatternLayout, Box<error::Error>> {
+    let level_names = match level_names {
+        Value::Table(level_names) => level_names,
+        _ => return Err(Box::new(StringError("`level_names` must be a table".to_string()))),
+    };
+
+    for (level, name) in level_names {
+        let level = match config::parse_level(&level) {
+            Ok(level) => level,
+            Err(err) => {
+                return Err(Box::new(StringError(format!("Invalid `level_names`: {}", err))));
+            }
+        };
+        match name {
+            Value::String(name) => layout = layout.level_name(level, &name),
+            _ => {
+                return Err(Box::new(StringError("`level_names` values must be strings"
+                                                    .to_string())));
+            }
+        }
+    }
+
+    Ok(layout)
+}
+
+fn parse_pool(config: &mut toml_parser::Table, context: &Context)
+              -> Result<Option<AsyncPool>, Box<error::Error>> {
+    match config.remove("pool") {
+        Some(Value::String(name)) => context.pool(&name).map(Some),
+        Some(_) => Err(Box::new(StringError("`pool` must be a string".to_string()))),
+        None => Ok(None),
+    }
+}
+
+fn create_encoder_kind(kind: &str, config: toml_parser::Table, context: &Context)
+                       -> Result<Box<Encode>, Box<error::Error>> {
+    if let Some(creator) = context.creator.encoders.get(kind) {
+        return creator.create_encoder(config);
+    }
+
+    let encoder: Box<Encode> = match kind {
+        "json" => Box::new(JsonEncoder::new()),
+        "raw" => Box::new(RawEncoder::new()),
+        _ => {
+            return Err(Box::new(StringError(format!("No creator registered for encoder kind \"{}\"",
+                                                    kind))));
+        }
+    };
+    try!(ensure_empty(&config));
+    Ok(encoder)
+}
+
 fn ensure_empty(config: &toml_parser::Table) -> Result<(), Box<error::Error>> {
     let remaining_keys: Vec<_> = config.keys().collect();
     if remaining_keys.is_empty() {
@@ -376,16 +1048,39 @@ fn ensure_empty(config: &toml_parser::Table) -> Result<(), Box<error::Error>> {
     }
 }
 
+const DEFAULT_BUFFER_SIZE: usize = 8192;
+
 /// An appender creator for the `FileAppender`.
 ///
 /// The `path` key is required, and specifies the path to the log file. The
 /// `pattern` key is optional and specifies a `PatternLayout` pattern to be
-/// used for output. The `append` key is optional and specifies whether the
-/// output file should be truncated or appended to.
+/// used for output. Alternatively, the `encoder` key may be set to `"json"` to
+/// use a `JsonEncoder` instead, or to `"raw"` to use a `RawEncoder`. The
+/// `line_ending` key is optional and may be set to `"lf"` or `"crlf"` to
+/// select the terminator written after each log event. The `max_len` key is
+/// optional and specifies a length in bytes beyond which each log event is
+/// truncated and marked with `...`. The `append` key is optional and
+/// specifies whether the output file should be appended to or truncated when
+/// it is opened, defaulting to `true`. The `create_dirs` key is optional and
+/// specifies whether missing parent directories of the output file should be
+/// created, defaulting to `false`. The `mode` key is optional and specifies
+/// the permissions of the output file if it is created, as a string of octal
+/// digits such as `"0640"`. It is ignored, with a warning reported to the
+/// error handler, on platforms other than Unix.
+///
+/// The `buffered` key is optional and specifies whether output should be
+/// buffered rather than flushed after every log event, defaulting to `false`.
+/// When buffering, the `buffer_size` key specifies the size of the buffer in
+/// bytes, defaulting to 8192, the `flush_interval` key specifies a number of
+/// milliseconds after which buffered output is flushed, the `flush_records`
+/// key specifies a number of log events after which buffered output is
+/// flushed, and the `flush_level` key specifies a level, such as
+/// `"error"`, at or above which log events are flushed immediately. All four
+/// are optional.
 pub struct FileAppenderCreator;
 
 impl CreateAppender for FileAppenderCreator {
-    fn create_appender(&self, mut config: toml_parser::Table)
+    fn create_appender(&self, mut config: toml_parser::Table, context: &Context)
                        -> Result<Box<Append>, Box<error::Error>> {
         let path = match config.remove("path") {
             Some(Value::String(path)) => path,
@@ -394,12 +1089,8 @@ impl CreateAppender for FileAppenderCreator {
         };
 
         let mut appender = FileAppender::builder(&path);
-        match config.remove("pattern") {
-            Some(Value::String(pattern)) => {
-                appender = appender.pattern(try!(PatternLayout::new(&pattern)));
-            }
-            Some(_) => return Err(Box::new(StringError("`pattern` must be a string".to_string()))),
-            None => {}
+        if let Some(encoder) = try!(context.create_encoder(&mut config)) {
+            appender = appender.encoder(encoder);
         }
 
         match config.remove("append") {
@@ -408,6 +1099,138 @@ impl CreateAppender for FileAppenderCreator {
             Some(_) => return Err(Box::new(StringError("`append` must be a bool".to_string()))),
         }
 
+        match config.remove("create_dirs") {
+            Some(Value::Boolean(create_dirs)) => appender = appender.create_dirs(create_dirs),
+            None => {}
+            Some(_) => {
+                return Err(Box::new(StringError("`create_dirs` must be a bool".to_string())));
+            }
+        }
+
+        match config.remove("mode") {
+            Some(Value::String(ref mode)) => appender = appender.mode(try!(parse_mode(mode))),
+            None => {}
+            Some(_) => return Err(Box::new(StringError("`mode` must be a string".to_string()))),
+        }
+
+        let buffered = match config.remove("buffered") {
+            Some(Value::Boolean(buffered)) => buffered,
+            None => false,
+            Some(_) => return Err(Box::new(StringError("`buffered` must be a bool".to_string()))),
+        };
+        let buffer_size = match config.remove("buffer_size") {
+            Some(Value::Integer(size)) if size > 0 => size as usize,
+            None => DEFAULT_BUFFER_SIZE,
+            Some(_) => {
+                return Err(Box::new(StringError("`buffer_size` must be a positive integer"
+                                                    .to_string())));
+            }
+        };
+        match config.remove("flush_interval") {
+            Some(Value::Integer(ms)) if ms > 0 => {
+                appender = appender.flush_interval(StdDuration::from_millis(ms as u64));
+            }
+            None => {}
+            Some(_) => {
+                return Err(Box::new(StringError("`flush_interval` must be a positive integer"
+                                                    .to_string())));
+            }
+        }
+        match config.remove("flush_records") {
+            Some(Value::Integer(records)) if records > 0 => {
+                appender = appender.flush_records(records as u64);
+            }
+            None => {}
+            Some(_) => {
+                return Err(Box::new(StringError("`flush_records` must be a positive integer"
+                                                    .to_string())));
+            }
+        }
+        if config.contains_key("flush_level") {
+            appender = appender.flush_level(try!(parse_filter_level(&mut config, "flush_level")));
+        }
+        if buffered {
+            appender = appender.buffer_size(buffer_size);
+        }
+
+        try!(ensure_empty(&config));
+        match appender.build() {
+            Ok(appender) => Ok(Box::new(appender)),
+            Err(err) => Err(Box::new(err))
+        }
+    }
+}
+
+fn parse_mode(mode: &str) -> Result<u32, Box<error::Error>> {
+    let parsed = match u32::from_str_radix(mode, 8) {
+        Ok(parsed) if parsed <= 0o7777 => parsed,
+        _ => return Err(Box::new(StringError(format!("Invalid `mode` \"{}\"", mode)))),
+    };
+    if !cfg!(unix) {
+        handle_error(&StringError(format!("Ignoring `mode` \"{}\", which is only supported \
+                                           on Unix",
+                                          mode)));
+    }
+    Ok(parsed)
+}
+
+fn parse_capacity(config: &mut toml_parser::Table) -> Result<Option<usize>, Box<error::Error>> {
+    match config.remove("capacity") {
+        Some(Value::Integer(capacity)) if capacity > 0 => Ok(Some(capacity as usize)),
+        Some(_) => {
+            Err(Box::new(StringError("`capacity` must be a positive integer".to_string())))
+        }
+        None => Ok(None),
+    }
+}
+
+fn parse_overflow(config: &mut toml_parser::Table)
+                  -> Result<Option<OverflowPolicy>, Box<error::Error>> {
+    match config.remove("overflow") {
+        Some(Value::String(policy)) => {
+            match &*policy {
+                "block" => Ok(Some(OverflowPolicy::Block)),
+                "drop_oldest" => Ok(Some(OverflowPolicy::DropOldest)),
+                "drop_newest" => Ok(Some(OverflowPolicy::DropNewest)),
+                _ => Err(Box::new(StringError(format!("Invalid `overflow` \"{}\"", policy)))),
+            }
+        }
+        Some(_) => Err(Box::new(StringError("`overflow` must be a string".to_string()))),
+        None => Ok(None),
+    }
+}
+
+/// An appender creator for the `DailyRollingFileAppender`.
+///
+/// The `path` key is required, and specifies a `Tm::strftime` template for
+/// the path to the log file, for example `"log/app-%Y-%m-%d.log"`. The
+/// `pattern` key is optional and specifies a `PatternLayout` pattern to be
+/// used for output. Alternatively, the `encoder` key may be set to `"json"` or
+/// `"raw"`. The `line_ending` and `max_len` keys are handled as they are by
+/// the `FileAppenderCreator`. The `compress` key is optional and specifies
+/// whether the previous day's file should be gzipped after a roll.
+pub struct DailyRollingFileAppenderCreator;
+
+impl CreateAppender for DailyRollingFileAppenderCreator {
+    fn create_appender(&self, mut config: toml_parser::Table, context: &Context)
+                       -> Result<Box<Append>, Box<error::Error>> {
+        let path = match config.remove("path") {
+            Some(Value::String(path)) => path,
+            Some(_) => return Err(Box::new(StringError("`path` must be a string".to_string()))),
+            None => return Err(Box::new(StringError("`path` is required".to_string()))),
+        };
+
+        let mut appender = DailyRollingFileAppender::builder(&path);
+        if let Some(encoder) = try!(context.create_encoder(&mut config)) {
+            appender = appender.encoder(encoder);
+        }
+
+        match config.remove("compress") {
+            Some(Value::Boolean(compress)) => appender = appender.compress(compress),
+            None => {}
+            Some(_) => return Err(Box::new(StringError("`compress` must be a bool".to_string()))),
+        }
+
         try!(ensure_empty(&config));
         match appender.build() {
             Ok(appender) => Ok(Box::new(appender)),
@@ -419,18 +1242,51 @@ impl CreateAppender for FileAppenderCreator {
 /// An appender creator for the `ConsoleAppender`.
 ///
 /// The `pattern` key is optional and specifies a `PatternLayout` pattern to be
-/// used for output.
+/// used for output. Alternatively, the `encoder` key may be set to `"json"` or
+/// `"raw"`. The `line_ending` and `max_len` keys are handled as they are by
+/// the `FileAppenderCreator`. The `target` key is optional and specifies the
+/// stream to write to, either `"stdout"` or `"stderr"`. It defaults to
+/// `"stdout"`. The `color` key is optional and specifies when ANSI color codes
+/// are written: `"always"`, `"never"`, or `"auto"` (the default) to write them
+/// only when the stream is a terminal.
 pub struct ConsoleAppenderCreator;
 
 impl CreateAppender for ConsoleAppenderCreator {
-    fn create_appender(&self, mut config: toml_parser::Table)
+    fn create_appender(&self, mut config: toml_parser::Table, context: &Context)
                        -> Result<Box<Append>, Box<error::Error>> {
         let mut appender = ConsoleAppender::builder();
-        match config.remove("pattern") {
-            Some(Value::String(pattern)) => {
-                appender = appender.pattern(try!(PatternLayout::new(&pattern)));
+        if let Some(encoder) = try!(context.create_encoder(&mut config)) {
+            appender = appender.encoder(encoder);
+        }
+
+        match config.remove("target") {
+            Some(Value::String(ref target)) if target == "stdout" => {
+                appender = appender.target(Target::Stdout);
+            }
+            Some(Value::String(ref target)) if target == "stderr" => {
+                appender = appender.target(Target::Stderr);
             }
-            Some(_) => return Err(Box::new(StringError("`pattern` must be a string".to_string()))),
+            Some(Value::String(target)) => {
+                return Err(Box::new(StringError(format!("Invalid `target` \"{}\"", target))));
+            }
+            Some(_) => return Err(Box::new(StringError("`target` must be a string".to_string()))),
+            None => {}
+        }
+
+        match config.remove("color") {
+            Some(Value::String(ref color)) if color == "always" => {
+                appender = appender.color(ColorMode::Always);
+            }
+            Some(Value::String(ref color)) if color == "never" => {
+                appender = appender.color(ColorMode::Never);
+            }
+            Some(Value::String(ref color)) if color == "auto" => {
+                appender = appender.color(ColorMode::Auto);
+            }
+            Some(Value::String(color)) => {
+                return Err(Box::new(StringError(format!("Invalid `color` \"{}\"", color))));
+            }
+            Some(_) => return Err(Box::new(StringError("`color` must be a string".to_string()))),
             None => {}
         }
 
@@ -439,26 +1295,1850 @@ impl CreateAppender for ConsoleAppenderCreator {
     }
 }
 
-/// A filter creator for the `ThresholdFilter`.
+/// An appender creator for an `AsyncAppender` wrapping another appender.
 ///
-/// The `level` key is required and specifies the threshold for the filter.
-pub struct ThresholdFilterCreator;
+/// The `appender` key is required, and is a table configuring the wrapped
+/// appender in the same way as a top-level appender, including its `kind`,
+/// which may be any kind registered with the `Creator`. The `capacity` key is
+/// optional and specifies the maximum number of queued log events,
+/// defaulting to 1024. The `overflow` key is optional and specifies what
+/// happens when the queue is full: `"block"` (the default), `"drop_oldest"`,
+/// or `"drop_newest"`. The `report_interval` key is optional and specifies
+/// the number of seconds over which discarded log events are summarized on
+/// stderr, defaulting to 60. The `pool` key is optional and names a pool in
+/// the top-level `async_pool` table whose threads write out the queue,
+/// rather than a thread of the appender's own.
+///
+/// ```toml
+/// [appender.requests]
+/// kind = "async"
+/// capacity = 4096
+/// overflow = "drop_oldest"
+///
+/// [appender.requests.appender]
+/// kind = "rolling_file"
+/// path = "log/requests.log"
+///
+/// [[appender.requests.appender.policy]]
+/// kind = "size"
+/// limit = "100mb"
+/// ```
+pub struct AsyncAppenderCreator;
 
-impl CreateFilter for ThresholdFilterCreator {
-    fn create_filter(&self, mut config: toml_parser::Table)
-                     -> Result<Box<Filter>, Box<error::Error>> {
-        let level = match config.remove("level") {
-            Some(Value::String(level)) => level,
-            Some(_) => return Err(Box::new(StringError("`level` must be a string".to_string()))),
-            None => return Err(Box::new(StringError("`level` must be provided".to_string()))),
+impl CreateAppender for AsyncAppenderCreator {
+    fn create_appender(&self, mut config: toml_parser::Table, context: &Context)
+                       -> Result<Box<Append>, Box<error::Error>> {
+        let mut inner_config = match config.remove("appender") {
+            Some(Value::Table(inner_config)) => inner_config,
+            Some(_) => return Err(Box::new(StringError("`appender` must be a table".to_string()))),
+            None => return Err(Box::new(StringError("`appender` is required".to_string()))),
         };
-
-        let level = match level.parse() {
-            Ok(level) => level,
-            Err(_) => return Err(Box::new(StringError(format!("Invalid `level` \"{}\"", level)))),
+        let kind = match inner_config.remove("kind") {
+            Some(Value::String(kind)) => kind,
+            Some(_) => return Err(Box::new(StringError("`kind` must be a string".to_string()))),
+            None => return Err(Box::new(StringError("`appender` must contain a `kind`"
+                                                        .to_string()))),
         };
 
-        try!(ensure_empty(&config));
-        Ok(Box::new(ThresholdFilter::new(level)))
+        let inner = try!(context.create_appender(&kind, inner_config));
+        create_async(inner, config, context)
+    }
+}
+
+/// An appender creator for an `AsyncAppender` wrapping a `FileAppender`.
+///
+/// The `capacity`, `overflow`, `report_interval` and `pool` keys are handled
+/// as they are by the `AsyncAppenderCreator`. The remaining keys configure
+/// the file appender, as they do for the `FileAppenderCreator`.
+pub struct AsyncFileAppenderCreator;
+
+impl CreateAppender for AsyncFileAppenderCreator {
+    fn create_appender(&self, mut config: toml_parser::Table, context: &Context)
+                       -> Result<Box<Append>, Box<error::Error>> {
+        let mut async_config = toml_parser::Table::new();
+        for key in &["capacity", "overflow", "report_interval", "pool"] {
+            if let Some(value) = config.remove(*key) {
+                async_config.insert(key.to_string(), value);
+            }
+        }
+
+        let inner = try!(FileAppenderCreator.create_appender(config, context));
+        create_async(inner, async_config, context)
+    }
+}
+
+// Creates an `AsyncAppender` wrapping `appender`, configured by the keys
+// shared by the async appender creators.
+fn create_async(appender: Box<Append>, mut config: toml_parser::Table, context: &Context)
+                -> Result<Box<Append>, Box<error::Error>> {
+    let mut appender = AsyncAppender::builder(appender);
+    if let Some(capacity) = try!(parse_capacity(&mut config)) {
+        appender = appender.capacity(capacity);
+    }
+    if let Some(policy) = try!(parse_overflow(&mut config)) {
+        appender = appender.policy(policy);
+    }
+    if let Some(pool) = try!(parse_pool(&mut config, context)) {
+        appender = appender.pool(pool);
+    }
+    match config.remove("report_interval") {
+        Some(Value::Integer(secs)) if secs > 0 => {
+            appender = appender.report_interval(StdDuration::from_secs(secs as u64));
+        }
+        Some(_) => {
+            return Err(Box::new(StringError("`report_interval` must be a positive integer"
+                                                .to_string())));
+        }
+        None => {}
+    }
+
+    try!(ensure_empty(&config));
+    Ok(Box::new(try!(appender.build())))
+}
+
+/// An appender creator for the `SyslogAppender`.
+///
+/// The `facility` key is optional and specifies the syslog facility by name,
+/// for example `"daemon"` or `"local0"`. It defaults to `"user"`. The
+/// `transport` key is optional and is either `"unix"` or `"udp"`. It defaults
+/// to `"unix"` on Unix platforms and `"udp"` elsewhere. The `address` key is
+/// optional and specifies the socket path or `host:port` of the syslog daemon,
+/// defaulting to `"/dev/log"` or `"127.0.0.1:514"` respectively. The
+/// `app_name` key is optional. The `pattern` and `encoder` keys are handled as
+/// they are by the `FileAppenderCreator`, and format the message part of each
+/// syslog message.
+pub struct SyslogAppenderCreator;
+
+impl CreateAppender for SyslogAppenderCreator {
+    fn create_appender(&self, mut config: toml_parser::Table, context: &Context)
+                       -> Result<Box<Append>, Box<error::Error>> {
+        let mut appender = SyslogAppender::builder();
+        if let Some(encoder) = try!(create_base_encoder(&mut config, context)) {
+            appender = appender.encoder(encoder);
+        }
+
+        match config.remove("facility") {
+            Some(Value::String(facility)) => appender = appender.facility(try!(facility.parse())),
+            Some(_) => return Err(Box::new(StringError("`facility` must be a string".to_string()))),
+            None => {}
+        }
+
+        match config.remove("app_name") {
+            Some(Value::String(app_name)) => appender = appender.app_name(app_name),
+            Some(_) => return Err(Box::new(StringError("`app_name` must be a string".to_string()))),
+            None => {}
+        }
+
+        let transport = match config.remove("transport") {
+            Some(Value::String(transport)) => Some(transport),
+            Some(_) => return Err(Box::new(StringError("`transport` must be a string".to_string()))),
+            None => None,
+        };
+
+        let address = match config.remove("address") {
+            Some(Value::String(address)) => Some(address),
+            Some(_) => return Err(Box::new(StringError("`address` must be a string".to_string()))),
+            None => None,
+        };
+
+        let transport = match (transport.as_ref().map(|s| &**s), address) {
+            (Some("unix"), address) => {
+                Some(SyslogTransport::Unix(address.unwrap_or_else(|| "/dev/log".to_owned()).into()))
+            }
+            (Some("udp"), address) => {
+                Some(SyslogTransport::Udp(address.unwrap_or_else(|| "127.0.0.1:514".to_owned())))
+            }
+            (Some(transport), _) => {
+                return Err(Box::new(StringError(format!("Invalid `transport` \"{}\"",
+                                                        transport))));
+            }
+            (None, Some(address)) => {
+                if cfg!(unix) {
+                    Some(SyslogTransport::Unix(address.into()))
+                } else {
+                    Some(SyslogTransport::Udp(address))
+                }
+            }
+            (None, None) => None,
+        };
+        if let Some(transport) = transport {
+            appender = appender.transport(transport);
+        }
+
+        try!(ensure_empty(&config));
+        Ok(Box::new(try!(appender.build())))
+    }
+}
+
+/// An appender creator for the `TcpAppender`.
+///
+/// The `address` key is required, and specifies the `host:port` address of
+/// the collector. The `pattern`, `encoder`, `line_ending` and `max_len` keys
+/// are handled as they are by the `FileAppenderCreator`. The `buffer` key is optional and
+/// specifies the maximum number of log events held while disconnected,
+/// defaulting to 1024. A value of 0 drops log events while disconnected.
+pub struct TcpAppenderCreator;
+
+impl CreateAppender for TcpAppenderCreator {
+    fn create_appender(&self, mut config: toml_parser::Table, context: &Context)
+                       -> Result<Box<Append>, Box<error::Error>> {
+        let address = match config.remove("address") {
+            Some(Value::String(address)) => address,
+            Some(_) => return Err(Box::new(StringError("`address` must be a string".to_string()))),
+            None => return Err(Box::new(StringError("`address` is required".to_string()))),
+        };
+
+        let mut appender = TcpAppender::builder(&address);
+        if let Some(encoder) = try!(context.create_encoder(&mut config)) {
+            appender = appender.encoder(encoder);
+        }
+
+        match config.remove("buffer") {
+            Some(Value::Integer(0)) => appender = appender.policy(OutagePolicy::Drop),
+            Some(Value::Integer(buffer)) if buffer > 0 => {
+                appender = appender.policy(OutagePolicy::Buffer(buffer as usize));
+            }
+            Some(_) => {
+                return Err(Box::new(StringError("`buffer` must be a non-negative integer"
+                                                    .to_string())));
+            }
+            None => {}
+        }
+
+        try!(ensure_empty(&config));
+        Ok(Box::new(appender.build()))
+    }
+}
+
+/// An appender creator for the `UdpAppender`.
+///
+/// The `address` key is required, and specifies the `host:port` address of
+/// the collector. The `pattern` and `encoder` keys are handled as they are by
+/// the `FileAppenderCreator`. The `mtu` key is optional and specifies the
+/// maximum size of a datagram in bytes, defaulting to 1472. The `oversize` key
+/// is optional and specifies what happens to larger log events: `"truncate"`
+/// (the default) or `"split"`.
+pub struct UdpAppenderCreator;
+
+impl CreateAppender for UdpAppenderCreator {
+    fn create_appender(&self, mut config: toml_parser::Table, context: &Context)
+                       -> Result<Box<Append>, Box<error::Error>> {
+        let address = match config.remove("address") {
+            Some(Value::String(address)) => address,
+            Some(_) => return Err(Box::new(StringError("`address` must be a string".to_string()))),
+            None => return Err(Box::new(StringError("`address` is required".to_string()))),
+        };
+
+        let mut appender = UdpAppender::builder(&address);
+        if let Some(encoder) = try!(create_base_encoder(&mut config, context)) {
+            appender = appender.encoder(encoder);
+        }
+
+        match config.remove("mtu") {
+            Some(Value::Integer(mtu)) if mtu > 0 => appender = appender.mtu(mtu as usize),
+            Some(_) => {
+                return Err(Box::new(StringError("`mtu` must be a positive integer".to_string())));
+            }
+            None => {}
+        }
+
+        match config.remove("oversize") {
+            Some(Value::String(ref oversize)) if oversize == "truncate" => {
+                appender = appender.oversize(Oversize::Truncate);
+            }
+            Some(Value::String(ref oversize)) if oversize == "split" => {
+                appender = appender.oversize(Oversize::Split);
+            }
+            Some(Value::String(oversize)) => {
+                return Err(Box::new(StringError(format!("Invalid `oversize` \"{}\"", oversize))));
+            }
+            Some(_) => {
+                return Err(Box::new(StringError("`oversize` must be a string".to_string())));
+            }
+            None => {}
+        }
+
+        try!(ensure_empty(&config));
+        Ok(Box::new(try!(appender.build())))
+    }
+}
+
+/// An appender creator for the `SmtpAppender`.
+///
+/// The `server` key is required, and specifies the `host:port` address of the
+/// mail server. The `from` key is required and is the sender's address. The
+/// `to` key is required and is an array of recipient addresses. The `username`
+/// and `password` keys are optional and must be given together. The `tls` key
+/// is optional and is `"none"` (the default), `"starttls"` or `"tls"`. The
+/// `subject` key is optional. The `batch_window` key is optional and
+/// specifies, in milliseconds, how long log events are collected into a
+/// single email. The `pattern` and `encoder` keys are handled as they are by
+/// the `FileAppenderCreator`.
+///
+/// ```toml
+/// [appender.alerts]
+/// kind = "smtp"
+/// server = "smtp.example.com:587"
+/// from = "app@example.com"
+/// to = ["oncall@example.com"]
+/// username = "app"
+/// password = "${SMTP_PASSWORD}"
+/// tls = "starttls"
+/// batch_window = 10000
+///
+/// [[appender.alerts.filter]]
+/// kind = "threshold"
+/// level = "error"
+/// ```
+pub struct SmtpAppenderCreator;
+
+impl CreateAppender for SmtpAppenderCreator {
+    fn create_appender(&self, mut config: toml_parser::Table, context: &Context)
+                       -> Result<Box<Append>, Box<error::Error>> {
+        let server = try!(required_string(&mut config, "server"));
+        let from = try!(required_string(&mut config, "from"));
+        let mut appender = SmtpAppender::builder(&server, &from);
+
+        match config.remove("to") {
+            Some(Value::Array(to)) => {
+                for to in to {
+                    match to {
+                        Value::String(to) => appender = appender.to(&to),
+                        _ => {
+                            return Err(Box::new(StringError("`to` must be an array of strings"
+                                                                .to_string())));
+                        }
+                    }
+                }
+            }
+            Some(_) => {
+                return Err(Box::new(StringError("`to` must be an array of strings".to_string())));
+            }
+            None => return Err(Box::new(StringError("`to` is required".to_string()))),
+        }
+
+        match (config.remove("username"), config.remove("password")) {
+            (Some(Value::String(username)), Some(Value::String(password))) => {
+                appender = appender.credentials(&username, &password);
+            }
+            (None, None) => {}
+            (Some(_), Some(_)) => {
+                return Err(Box::new(StringError("`username` and `password` must be strings"
+                                                    .to_string())));
+            }
+            _ => {
+                return Err(Box::new(StringError("`username` and `password` must be specified \
+                                                 together"
+                                                    .to_string())));
+            }
+        }
+
+        match config.remove("tls") {
+            Some(Value::String(ref tls)) if tls == "none" => appender = appender.tls(Tls::None),
+            Some(Value::String(ref tls)) if tls == "starttls" => {
+                appender = appender.tls(Tls::StartTls);
+            }
+            Some(Value::String(ref tls)) if tls == "tls" => appender = appender.tls(Tls::Wrapper),
+            Some(Value::String(tls)) => {
+                return Err(Box::new(StringError(format!("Invalid `tls` \"{}\"", tls))));
+            }
+            Some(_) => return Err(Box::new(StringError("`tls` must be a string".to_string()))),
+            None => {}
+        }
+
+        match config.remove("subject") {
+            Some(Value::String(subject)) => appender = appender.subject(&subject),
+            Some(_) => {
+                return Err(Box::new(StringError("`subject` must be a string".to_string())));
+            }
+            None => {}
+        }
+
+        match config.remove("batch_window") {
+            Some(Value::Integer(window)) if window >= 0 => {
+                appender = appender.batch_window(StdDuration::from_millis(window as u64));
+            }
+            Some(_) => {
+                return Err(Box::new(StringError("`batch_window` must be a non-negative integer"
+                                                    .to_string())));
+            }
+            None => {}
+        }
+
+        if let Some(encoder) = try!(create_base_encoder(&mut config, context)) {
+            appender = appender.encoder(encoder);
+        }
+
+        try!(ensure_empty(&config));
+        Ok(Box::new(try!(appender.build())))
+    }
+}
+
+fn required_string(config: &mut toml_parser::Table, key: &str)
+                   -> Result<String, Box<error::Error>> {
+    match config.remove(key) {
+        Some(Value::String(value)) => Ok(value),
+        Some(_) => Err(Box::new(StringError(format!("`{}` must be a string", key)))),
+        None => Err(Box::new(StringError(format!("`{}` is required", key)))),
+    }
+}
+
+/// An appender creator for the `FifoAppender`.
+///
+/// The `path` key is required, and specifies the path to an existing named
+/// pipe. The `pattern` and `encoder` keys are handled as they are by the
+/// `FileAppenderCreator`.
+#[cfg(unix)]
+pub struct FifoAppenderCreator;
+
+#[cfg(unix)]
+impl CreateAppender for FifoAppenderCreator {
+    fn create_appender(&self, mut config: toml_parser::Table, context: &Context)
+                       -> Result<Box<Append>, Box<error::Error>> {
+        let path = try!(required_string(&mut config, "path"));
+        let mut appender = FifoAppender::builder(&path);
+        if let Some(encoder) = try!(create_base_encoder(&mut config, context)) {
+            appender = appender.encoder(encoder);
+        }
+
+        try!(ensure_empty(&config));
+        Ok(Box::new(try!(appender.build())))
+    }
+}
+
+/// An appender creator for the `EventLogAppender`.
+///
+/// The `source` key is required, and specifies the name of the event source
+/// the events are reported under. The `pattern` and `encoder` keys are
+/// handled as they are by the `FileAppenderCreator`, and format the message
+/// of each event. On platforms other than Windows a warning is reported and
+/// the appender discards all log events, so that a config shared between
+/// platforms still loads.
+pub struct EventLogAppenderCreator;
+
+impl CreateAppender for EventLogAppenderCreator {
+    fn create_appender(&self, mut config: toml_parser::Table, context: &Context)
+                       -> Result<Box<Append>, Box<error::Error>> {
+        let source = try!(required_string(&mut config, "source"));
+        let mut appender = EventLogAppender::builder(&source);
+        if let Some(encoder) = try!(create_base_encoder(&mut config, context)) {
+            appender = appender.encoder(encoder);
+        }
+
+        try!(ensure_empty(&config));
+        if !cfg!(windows) {
+            handle_error(&StringError("The Windows Event Log is not available on this \
+                                       platform; log events will be discarded"
+                                          .to_string()));
+        }
+        Ok(Box::new(try!(appender.build())))
+    }
+}
+
+/// An appender creator for the `JournaldAppender`.
+///
+/// The `syslog_identifier` key is optional and defaults to the file name of
+/// the current executable. The `path` key is optional and specifies the path
+/// of the journal socket, defaulting to `"/run/systemd/journal/socket"`. The
+/// `pattern` and `encoder` keys are handled as they are by the
+/// `FileAppenderCreator`, and format the `MESSAGE` field of each log event.
+#[cfg(target_os = "linux")]
+pub struct JournaldAppenderCreator;
+
+#[cfg(target_os = "linux")]
+impl CreateAppender for JournaldAppenderCreator {
+    fn create_appender(&self, mut config: toml_parser::Table, context: &Context)
+                       -> Result<Box<Append>, Box<error::Error>> {
+        let mut appender = JournaldAppender::builder();
+        if let Some(encoder) = try!(create_base_encoder(&mut config, context)) {
+            appender = appender.encoder(encoder);
+        }
+
+        match config.remove("syslog_identifier") {
+            Some(Value::String(identifier)) => appender = appender.syslog_identifier(&identifier),
+            Some(_) => {
+                return Err(Box::new(StringError("`syslog_identifier` must be a string"
+                                                    .to_string())));
+            }
+            None => {}
+        }
+
+        match config.remove("path") {
+            Some(Value::String(path)) => appender = appender.path(path),
+            Some(_) => return Err(Box::new(StringError("`path` must be a string".to_string()))),
+            None => {}
+        }
+
+        try!(ensure_empty(&config));
+        Ok(Box::new(try!(appender.build())))
+    }
+}
+
+/// An appender creator for the `DedupAppender`.
+///
+/// The `appender` key is required, and is a table configuring the wrapped
+/// appender in the same way as a top-level appender, including its `kind`,
+/// which may be any kind registered with the `Creator`. The `threshold` key
+/// is optional and specifies the number of times a log event is written
+/// before repeats are suppressed, defaulting to 1. The `timeout` key is
+/// optional and specifies, in seconds, how long repeats are suppressed before
+/// a summary is written. The `dedup_window` key is optional and specifies, in
+/// seconds, a window within which repeats are suppressed even if other log
+/// events arrive in between them.
+///
+/// ```toml
+/// [appender.errors]
+/// kind = "dedup"
+/// threshold = 3
+/// timeout = 60
+/// dedup_window = 300
+///
+/// [appender.errors.appender]
+/// kind = "file"
+/// path = "log/errors.log"
+/// ```
+pub struct DedupAppenderCreator;
+
+impl CreateAppender for DedupAppenderCreator {
+    fn create_appender(&self, mut config: toml_parser::Table, context: &Context)
+                       -> Result<Box<Append>, Box<error::Error>> {
+        let mut inner_config = match config.remove("appender") {
+            Some(Value::Table(inner_config)) => inner_config,
+            Some(_) => return Err(Box::new(StringError("`appender` must be a table".to_string()))),
+            None => return Err(Box::new(StringError("`appender` is required".to_string()))),
+        };
+        let kind = match inner_config.remove("kind") {
+            Some(Value::String(kind)) => kind,
+            Some(_) => return Err(Box::new(StringError("`kind` must be a string".to_string()))),
+            None => return Err(Box::new(StringError("`appender` must contain a `kind`"
+                                                        .to_string()))),
+        };
+
+        let inner = try!(context.create_appender(&kind, inner_config));
+        let mut appender = DedupAppender::builder(inner);
+
+        match config.remove("threshold") {
+            Some(Value::Integer(threshold)) if threshold > 0 => {
+                appender = appender.threshold(threshold as u64);
+            }
+            Some(_) => {
+                return Err(Box::new(StringError("`threshold` must be a positive integer"
+                                                    .to_string())));
+            }
+            None => {}
+        }
+
+        match config.remove("timeout") {
+            Some(Value::Integer(timeout)) if timeout > 0 => {
+                appender = appender.timeout(StdDuration::from_secs(timeout as u64));
+            }
+            Some(_) => {
+                return Err(Box::new(StringError("`timeout` must be a positive integer"
+                                                    .to_string())));
+            }
+            None => {}
+        }
+
+        match config.remove("dedup_window") {
+            Some(Value::Integer(window)) if window > 0 => {
+                appender = appender.window(StdDuration::from_secs(window as u64));
+            }
+            Some(_) => {
+                return Err(Box::new(StringError("`dedup_window` must be a positive integer"
+                                                    .to_string())));
+            }
+            None => {}
+        }
+
+        try!(ensure_empty(&config));
+        Ok(Box::new(appender.build()))
+    }
+}
+
+/// An appender creator for the `RollingFileAppender`.
+///
+/// The `path` key is required, and specifies the path to the log file. The
+/// `pattern`, `encoder`, `line_ending` and `max_len` keys are handled as they
+/// are by the `FileAppenderCreator`. Rolling policies are configured by the `policy`
+/// array of tables, each of which must specify a `kind`:
+///
+/// * `"size"` rolls the file once it reaches the size given by the `limit`
+///     key, either as a number of bytes or as a string with a `kb`, `mb` or
+///     `gb` suffix, such as `"100mb"`.
+/// * `"daily"` rolls the file when the date changes.
+///
+/// By default, rolled files are renamed by appending `.1` to their name. The
+/// appender may instead specify a `roller` table with a `kind` of
+/// `"fixed_window"`, which keeps the number of files given by the `count` key
+/// (defaulting to 5), named by the `pattern` key. The pattern must contain a
+/// single `{}` token which is replaced by the index of the file. Either kind
+/// of roller accepts an optional `compress` key, which if true gzips rolled
+/// files and appends `.gz` to their names. It defaults to false.
+///
+/// The `roll_on_startup` key is optional, and if true an existing, non-empty
+/// log file is rolled before the appender first writes to it, so that each
+/// run of the program starts a new file. It is ignored when the config file is
+/// reloaded, so that an appender created again by a reload continues the
+/// current file. It defaults to false.
+///
+/// ```toml
+/// [appender.foo]
+/// kind = "rolling_file"
+/// path = "log/app.log"
+/// roll_on_startup = true
+///
+/// [[appender.foo.policy]]
+/// kind = "size"
+/// limit = "100mb"
+///
+/// [[appender.foo.policy]]
+/// kind = "daily"
+///
+/// [appender.foo.roller]
+/// kind = "fixed_window"
+/// pattern = "log/app.{}.log"
+/// count = 5
+/// compress = true
+/// ```
+pub struct RollingFileAppenderCreator;
+
+impl CreateAppender for RollingFileAppenderCreator {
+    fn create_appender(&self, mut config: toml_parser::Table, context: &Context)
+                       -> Result<Box<Append>, Box<error::Error>> {
+        let path = match config.remove("path") {
+            Some(Value::String(path)) => path,
+            Some(_) => return Err(Box::new(StringError("`path` must be a string".to_string()))),
+            None => return Err(Box::new(StringError("`path` is required".to_string()))),
+        };
+
+        let mut appender = RollingFileAppender::builder(&path);
+        if let Some(encoder) = try!(context.create_encoder(&mut config)) {
+            appender = appender.encoder(encoder);
+        }
+
+        match config.remove("policy") {
+            Some(Value::Array(policies)) => {
+                for policy in policies {
+                    match policy {
+                        Value::Table(policy) => {
+                            appender = appender.policy(try!(create_policy(policy)));
+                        }
+                        _ => {
+                            return Err(Box::new(StringError("`policy` must be an array of tables"
+                                                                .to_string())));
+                        }
+                    }
+                }
+            }
+            Some(_) => {
+                return Err(Box::new(StringError("`policy` must be an array of tables"
+                                                    .to_string())));
+            }
+            None => {}
+        }
+
+        match config.remove("roller") {
+            Some(Value::Table(roller)) => appender = appender.roller(try!(create_roller(roller))),
+            Some(_) => return Err(Box::new(StringError("`roller` must be a table".to_string()))),
+            None => {}
+        }
+
+        match config.remove("roll_on_startup") {
+            Some(Value::Boolean(roll)) => {
+                appender = appender.roll_on_startup(roll && !context.reloading());
+            }
+            Some(_) => {
+                return Err(Box::new(StringError("`roll_on_startup` must be a bool".to_string())));
+            }
+            None => {}
+        }
+
+        try!(ensure_empty(&config));
+        Ok(Box::new(try!(appender.build())))
+    }
+}
+
+fn create_policy(mut config: toml_parser::Table) -> Result<Box<Policy>, Box<error::Error>> {
+    let kind = match config.remove("kind") {
+        Some(Value::String(kind)) => kind,
+        Some(_) => return Err(Box::new(StringError("`kind` must be a string".to_string()))),
+        None => return Err(Box::new(StringError("`policy` must contain a `kind`".to_string()))),
+    };
+
+    let policy: Box<Policy> = match &*kind {
+        "size" => {
+            let limit = match config.remove("limit") {
+                Some(Value::Integer(limit)) if limit >= 0 => limit as u64,
+                Some(Value::String(limit)) => try!(parse_size(&limit)),
+                Some(_) => {
+                    return Err(Box::new(StringError("`limit` must be a size".to_string())));
+                }
+                None => return Err(Box::new(StringError("`limit` is required".to_string()))),
+            };
+            Box::new(SizePolicy::new(limit))
+        }
+        "daily" => Box::new(DailyPolicy::new()),
+        _ => return Err(Box::new(StringError(format!("Invalid policy `kind` \"{}\"", kind)))),
+    };
+
+    try!(ensure_empty(&config));
+    Ok(policy)
+}
+
+fn create_roller(mut config: toml_parser::Table) -> Result<Box<Roll>, Box<error::Error>> {
+    let kind = match config.remove("kind") {
+        Some(Value::String(kind)) => kind,
+        Some(_) => return Err(Box::new(StringError("`kind` must be a string".to_string()))),
+        None => return Err(Box::new(StringError("`roller` must contain a `kind`".to_string()))),
+    };
+
+    let compress = match config.remove("compress") {
+        Some(Value::Boolean(compress)) => compress,
+        Some(_) => return Err(Box::new(StringError("`compress` must be a bool".to_string()))),
+        None => false,
+    };
+
+    let roller: Box<Roll> = match &*kind {
+        "rename" => Box::new(RenameRoller::new().compress(compress)),
+        "fixed_window" => {
+            let pattern = match config.remove("pattern") {
+                Some(Value::String(pattern)) => pattern,
+                Some(_) => {
+                    return Err(Box::new(StringError("`pattern` must be a string".to_string())));
+                }
+                None => return Err(Box::new(StringError("`pattern` is required".to_string()))),
+            };
+            let count = match config.remove("count") {
+                Some(Value::Integer(count)) if count >= 0 => count as u32,
+                Some(_) => {
+                    return Err(Box::new(StringError("`count` must be a non-negative integer"
+                                                        .to_string())));
+                }
+                None => 5,
+            };
+            Box::new(try!(FixedWindowRoller::new(&pattern, count)).compress(compress))
+        }
+        _ => return Err(Box::new(StringError(format!("Invalid roller `kind` \"{}\"", kind)))),
+    };
+
+    try!(ensure_empty(&config));
+    Ok(roller)
+}
+
+fn parse_size(size: &str) -> Result<u64, Box<error::Error>> {
+    let lower = size.trim().to_lowercase();
+    let (number, multiplier) = if lower.ends_with("kb") {
+        (&lower[..lower.len() - 2], 1 << 10)
+    } else if lower.ends_with("mb") {
+        (&lower[..lower.len() - 2], 1 << 20)
+    } else if lower.ends_with("gb") {
+        (&lower[..lower.len() - 2], 1 << 30)
+    } else if lower.ends_with('b') {
+        (&lower[..lower.len() - 1], 1)
+    } else {
+        (&*lower, 1)
+    };
+
+    match number.trim().parse::<u64>().ok().and_then(|n| n.checked_mul(multiplier)) {
+        Some(size) => Ok(size),
+        None => Err(Box::new(StringError(format!("Invalid size \"{}\"", size)))),
+    }
+}
+
+/// An appender creator for the `LeveledFileAppender`.
+///
+/// The `path` key is required, and specifies a template for the paths of the
+/// log files which must contain a `%level` token, for example
+/// `"log/app-%level.log"`. The `pattern`, `encoder`, `line_ending` and
+/// `max_len` keys are handled as they are by the `FileAppenderCreator`.
+///
+/// ```toml
+/// [appender.levels]
+/// kind = "leveled_file"
+/// path = "log/app-%level.log"
+/// ```
+pub struct LeveledFileAppenderCreator;
+
+impl CreateAppender for LeveledFileAppenderCreator {
+    fn create_appender(&self, mut config: toml_parser::Table, context: &Context)
+                       -> Result<Box<Append>, Box<error::Error>> {
+        let path = try!(required_string(&mut config, "path"));
+        let mut appender = LeveledFileAppender::builder(&path);
+        if let Some(encoder) = try!(context.create_encoder(&mut config)) {
+            appender = appender.encoder(encoder);
+        }
+
+        try!(ensure_empty(&config));
+        Ok(Box::new(try!(appender.build())))
+    }
+}
+
+/// An appender creator for the `ProcessAppender`.
+///
+/// The `command` key is required, and specifies the program to start. The
+/// `args` key is optional and specifies an array of arguments to pass to it.
+/// The `restart` key is optional and specifies what happens when the program
+/// exits: `"always"` (the default) starts it again, and `"never"` discards
+/// further log events. The `capacity`, `overflow` and `pool` keys are handled
+/// as they are by the `AsyncAppenderCreator`, except that `overflow` defaults
+/// to `"drop_newest"`. The `pattern`, `encoder`, `line_ending` and `max_len`
+/// keys are handled as they are by the `FileAppenderCreator`.
+///
+/// ```toml
+/// [appender.sidecar]
+/// kind = "process"
+/// command = "logger"
+/// args = ["-t", "my_app"]
+/// ```
+pub struct ProcessAppenderCreator;
+
+impl CreateAppender for ProcessAppenderCreator {
+    fn create_appender(&self, mut config: toml_parser::Table, context: &Context)
+                       -> Result<Box<Append>, Box<error::Error>> {
+        let command = try!(required_string(&mut config, "command"));
+        let mut appender = ProcessAppender::builder(&command);
+
+        match config.remove("args") {
+            Some(Value::Array(args)) => {
+                for arg in args {
+                    match arg {
+                        Value::String(arg) => appender = appender.arg(&arg),
+                        _ => {
+                            return Err(Box::new(StringError("`args` must be an array of strings"
+                                                                .to_string())));
+                        }
+                    }
+                }
+            }
+            Some(_) => {
+                return Err(Box::new(StringError("`args` must be an array of strings"
+                                                    .to_string())));
+            }
+            None => {}
+        }
+
+        match config.remove("restart") {
+            Some(Value::String(ref restart)) if restart == "always" => {
+                appender = appender.restart(RestartPolicy::Always);
+            }
+            Some(Value::String(ref restart)) if restart == "never" => {
+                appender = appender.restart(RestartPolicy::Never);
+            }
+            Some(Value::String(restart)) => {
+                return Err(Box::new(StringError(format!("Invalid `restart` \"{}\"", restart))));
+            }
+            Some(_) => return Err(Box::new(StringError("`restart` must be a string".to_string()))),
+            None => {}
+        }
+
+        if let Some(capacity) = try!(parse_capacity(&mut config)) {
+            appender = appender.capacity(capacity);
+        }
+        if let Some(policy) = try!(parse_overflow(&mut config)) {
+            appender = appender.policy(policy);
+        }
+        if let Some(pool) = try!(parse_pool(&mut config, context)) {
+            appender = appender.pool(pool);
+        }
+        if let Some(encoder) = try!(context.create_encoder(&mut config)) {
+            appender = appender.encoder(encoder);
+        }
+
+        try!(ensure_empty(&config));
+        Ok(Box::new(try!(appender.build())))
+    }
+}
+
+/// A filter creator for the `ThresholdFilter`.
+///
+/// The `level` key is required and specifies the threshold for the filter.
+pub struct ThresholdFilterCreator;
+
+impl CreateFilter for ThresholdFilterCreator {
+    fn create_filter(&self, mut config: toml_parser::Table)
+                     -> Result<Box<Filter>, Box<error::Error>> {
+        let level = match config.remove("level") {
+            Some(Value::String(level)) => level,
+            Some(_) => return Err(Box::new(StringError("`level` must be a string".to_string()))),
+            None => return Err(Box::new(StringError("`level` must be provided".to_string()))),
+        };
+
+        let level = match config::parse_level_filter(&level) {
+            Ok(level) => level,
+            Err(err) => return Err(Box::new(StringError(format!("Invalid `level`: {}", err)))),
+        };
+
+        try!(ensure_empty(&config));
+        Ok(Box::new(ThresholdFilter::new(level)))
+    }
+}
+
+/// A filter creator for the `LevelRangeFilter`.
+///
+/// The `min` and `max` keys are required and specify the inclusive bounds of
+/// the accepted levels, for example `"warn"` and `"error"`.
+pub struct LevelRangeFilterCreator;
+
+impl CreateFilter for LevelRangeFilterCreator {
+    fn create_filter(&self, mut config: toml_parser::Table)
+                     -> Result<Box<Filter>, Box<error::Error>> {
+        let min = try!(parse_filter_level(&mut config, "min"));
+        let max = try!(parse_filter_level(&mut config, "max"));
+        try!(ensure_empty(&config));
+        Ok(Box::new(LevelRangeFilter::new(min, max)))
+    }
+}
+
+/// A filter creator for the `SamplingFilter`.
+///
+/// The `rate` key is required, and specifies that one in every `rate` log
+/// events is passed.
+///
+/// ```toml
+/// [[appender.debug.filter]]
+/// kind = "sampling"
+/// rate = 100
+/// ```
+pub struct SamplingFilterCreator;
+
+impl CreateFilter for SamplingFilterCreator {
+    fn create_filter(&self, mut config: toml_parser::Table)
+                     -> Result<Box<Filter>, Box<error::Error>> {
+        let rate = match config.remove("rate") {
+            Some(Value::Integer(rate)) if rate > 0 => rate as usize,
+            Some(_) => return Err(Box::new(StringError("`rate` must be a positive integer"
+                                                           .to_string()))),
+            None => return Err(Box::new(StringError("`rate` must be provided".to_string()))),
+        };
+
+        try!(ensure_empty(&config));
+        Ok(Box::new(SamplingFilter::new(rate)))
+    }
+}
+
+/// A filter creator for the `MessageFilter`.
+///
+/// The `contains` key is required, and specifies the substring to look for in
+/// log messages. It may not be empty. The `on_match` and `on_mismatch` keys
+/// are optional, and specify the response to log events whose message does
+/// and does not contain the substring as one of `"accept"`, `"neutral"` or
+/// `"reject"`. They default to `"reject"` and `"neutral"` respectively.
+///
+/// ```toml
+/// [[appender.foo.filter]]
+/// kind = "message"
+/// contains = "connection reset by peer"
+/// ```
+pub struct MessageFilterCreator;
+
+impl CreateFilter for MessageFilterCreator {
+    fn create_filter(&self, mut config: toml_parser::Table)
+                     -> Result<Box<Filter>, Box<error::Error>> {
+        let substring = try!(required_string(&mut config, "contains"));
+        if substring.is_empty() {
+            return Err(Box::new(StringError("`contains` may not be empty".to_string())));
+        }
+        let on_match = try!(parse_filter_response(&mut config, "on_match", FilterResponse::Reject));
+        let on_mismatch = try!(parse_filter_response(&mut config,
+                                                     "on_mismatch",
+                                                     FilterResponse::Neutral));
+
+        try!(ensure_empty(&config));
+        Ok(Box::new(MessageFilter::new(&substring, on_match).on_mismatch(on_mismatch)))
+    }
+}
+
+fn parse_filter_response(config: &mut toml_parser::Table, key: &str, default: FilterResponse)
+                         -> Result<FilterResponse, Box<error::Error>> {
+    match config.remove(key) {
+        Some(Value::String(response)) => {
+            match &*response.to_lowercase() {
+                "accept" => Ok(FilterResponse::Accept),
+                "neutral" => Ok(FilterResponse::Neutral),
+                "reject" => Ok(FilterResponse::Reject),
+                _ => Err(Box::new(StringError(format!("Invalid `{}` \"{}\"", key, response)))),
+            }
+        }
+        Some(_) => Err(Box::new(StringError(format!("`{}` must be a string", key)))),
+        None => Ok(default),
+    }
+}
+
+fn parse_filter_level(config: &mut toml_parser::Table, key: &str)
+                      -> Result<LogLevel, Box<error::Error>> {
+    let level = match config.remove(key) {
+        Some(Value::String(level)) => level,
+        Some(_) => return Err(Box::new(StringError(format!("`{}` must be a string", key)))),
+        None => return Err(Box::new(StringError(format!("`{}` must be provided", key)))),
+    };
+
+    match config::parse_level(&level) {
+        Ok(level) => Ok(level),
+        Err(err) => Err(Box::new(StringError(format!("Invalid `{}`: {}", key, err)))),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::error;
+    use std::fs;
+    use std::io::{self, Write};
+    use std::sync::{Arc, Mutex};
+    use log::LogRecord;
+    use toml_parser::{self, Value};
+
+    use {Append, Encode, Filter, FilterResponse};
+    use super::*;
+
+    struct NullAppender;
+
+    impl Append for NullAppender {
+        fn append(&mut self, _: &LogRecord) -> Result<(), Box<error::Error>> {
+            Ok(())
+        }
+    }
+
+    struct RecordingCreator(Arc<Mutex<Vec<Option<Value>>>>);
+
+    impl CreateAppender for RecordingCreator {
+        fn create_appender(&self, mut config: toml_parser::Table, _: &Context)
+                           -> Result<Box<Append>, Box<error::Error>> {
+            self.0.lock().unwrap().push(config.remove("pattern"));
+            Ok(Box::new(NullAppender))
+        }
+    }
+
+    #[test]
+    fn escape() {
+        let creator = Creator::new();
+        let pools = HashMap::new();
+        let mut context = Context {
+            creator: &creator,
+            escape: Escape::Off,
+            pools: &pools,
+        };
+        let mut config = toml_parser::Table::new();
+        assert!(context.create_encoder(&mut config).unwrap().is_none());
+        context.escape = Escape::All;
+        assert!(context.create_encoder(&mut config).unwrap().is_some());
+
+        let cfg = r#"
+escape = "control"
+
+[appender.console]
+kind = "console"
+pattern = "%m"
+
+[appender.raw]
+kind = "console"
+pattern = "%m"
+escape = "off"
+"#;
+        let (config, errors) = Config::parse(cfg, &Creator::default()).unwrap();
+        errors.unwrap();
+        assert_eq!(config.config().appenders().len(), 2);
+
+        let cfg = r#"
+[appender.console]
+kind = "console"
+encoder = "json"
+escape = "all"
+"#;
+        let (config, errors) = Config::parse(cfg, &Creator::default()).unwrap();
+        assert!(config.config().appenders().is_empty());
+        assert!(errors.unwrap_err().to_string().contains("`escape` cannot be used with `encoder`"));
+
+        assert!(Config::parse("escape = \"newlines\"", &Creator::default()).is_err());
+    }
+
+    #[test]
+    fn level_names() {
+        let cfg = r#"
+[appender.console]
+kind = "console"
+pattern = "%l %m"
+level_names = { warn = "WARNING", error = "FATAL" }
+"#;
+        let (config, errors) = Config::parse(cfg, &Creator::default()).unwrap();
+        errors.unwrap();
+        assert_eq!(config.config().appenders().len(), 1);
+
+        let cfg = r#"
+[appender.console]
+kind = "console"
+level_names = { warn = "WARNING" }
+"#;
+        let (config, errors) = Config::parse(cfg, &Creator::default()).unwrap();
+        assert!(config.config().appenders().is_empty());
+        assert!(errors.unwrap_err().to_string().contains("`level_names` requires `pattern`"));
+
+        let cfg = r#"
+[appender.console]
+kind = "console"
+pattern = "%l %m"
+level_names = { fatal = "F" }
+"#;
+        let (config, errors) = Config::parse(cfg, &Creator::default()).unwrap();
+        assert!(config.config().appenders().is_empty());
+        assert!(errors.unwrap_err().to_string().contains("Invalid `level_names`"));
+    }
+
+    #[test]
+    fn message_filter() {
+        let cfg = r#"
+[appender.console]
+kind = "console"
+
+[[appender.console.filter]]
+kind = "message"
+contains = "connection reset"
+on_match = "reject"
+on_mismatch = "neutral"
+"#;
+        let (config, errors) = Config::parse(cfg, &Creator::default()).unwrap();
+        errors.unwrap();
+        assert_eq!(config.config().appenders().len(), 1);
+
+        let cfg = r#"
+[appender.console]
+kind = "console"
+
+[[appender.console.filter]]
+kind = "message"
+contains = ""
+"#;
+        let (_, errors) = Config::parse(cfg, &Creator::default()).unwrap();
+        assert!(errors.unwrap_err().to_string().contains("`contains` may not be empty"));
+    }
+
+    #[test]
+    fn per_appender_patterns() {
+        let cfg = r#"
+[appender.console]
+kind = "recording"
+pattern = "%H{%l} %m"
+
+[appender.file]
+kind = "recording"
+pattern = "%d %f:%L - %m"
+
+[appender.plain]
+kind = "recording"
+"#;
+        let patterns = Arc::new(Mutex::new(vec![]));
+        let mut creator = Creator::new();
+        creator.add_appender("recording", Box::new(RecordingCreator(patterns.clone())));
+        let (_, errors) = Config::parse(cfg, &creator).unwrap();
+        errors.unwrap();
+
+        let mut patterns = patterns.lock().unwrap().clone();
+        patterns.sort_by_key(|p| format!("{:?}", p));
+        assert_eq!(patterns,
+                   [None,
+                    Some(Value::String("%H{%l} %m".to_owned())),
+                    Some(Value::String("%d %f:%L - %m".to_owned()))]);
+
+        let cfg = r#"
+[appender.console]
+kind = "console"
+pattern = "%H{%l} %m"
+
+[appender.stderr]
+kind = "console"
+target = "stderr"
+pattern = "%d %f:%L - %m"
+"#;
+        let (config, errors) = Config::parse(cfg, &Creator::default()).unwrap();
+        errors.unwrap();
+        assert_eq!(config.config().appenders().len(), 2);
+    }
+
+    #[test]
+    fn custom_appender() {
+        let cfg = r#"
+[appender.console]
+kind = "console"
+target = "stderr"
+
+[appender.custom]
+kind = "custom"
+"#;
+        let kinds = Arc::new(Mutex::new(vec![]));
+        let mut creator = Creator::default();
+        let custom_kinds = kinds.clone();
+        creator.add_appender("custom", Box::new(move |config: toml_parser::Table, _: &Context| {
+            try!(ensure_empty(&config));
+            custom_kinds.lock().unwrap().push("custom");
+            Ok(Box::new(NullAppender) as Box<Append>)
+        }));
+        let (_, errors) = Config::parse(cfg, &creator).unwrap();
+        errors.unwrap();
+        assert_eq!(*kinds.lock().unwrap(), ["custom"]);
+
+        // a default kind can be replaced
+        let console_kinds = kinds.clone();
+        creator.add_appender("console", Box::new(move |_: toml_parser::Table, _: &Context| {
+            console_kinds.lock().unwrap().push("console");
+            Ok(Box::new(NullAppender) as Box<Append>)
+        }));
+        let (_, errors) = Config::parse(cfg, &creator).unwrap();
+        errors.unwrap();
+        let mut kinds = kinds.lock().unwrap().clone();
+        kinds.sort();
+        assert_eq!(kinds, ["console", "custom", "custom"]);
+
+        let (_, errors) = Config::parse(cfg, &Creator::default()).unwrap();
+        let err = errors.unwrap_err().to_string();
+        assert!(err.contains("No creator registered for appender kind \"custom\""), "{}", err);
+
+        // wrapped appenders are created by the same creator
+        let cfg = r#"
+[appender.async]
+kind = "async"
+
+[appender.async.appender]
+kind = "custom"
+
+[appender.dedup]
+kind = "dedup"
+
+[appender.dedup.appender]
+kind = "custom"
+"#;
+        let (_, errors) = Config::parse(cfg, &creator).unwrap();
+        errors.unwrap();
+    }
+
+    #[test]
+    fn custom_filters_and_encoders() {
+        struct NullEncoder;
+
+        impl Encode for NullEncoder {
+            fn encode(&self, _: &mut io::Write, _: &LogRecord) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        struct NullFilter;
+
+        impl Filter for NullFilter {
+            fn filter(&mut self, _: &LogRecord) -> FilterResponse {
+                FilterResponse::Neutral
+            }
+        }
+
+        let seen = Arc::new(Mutex::new(vec![]));
+        let mut creator = Creator::new();
+        let encoded = |mut config: toml_parser::Table, context: &Context| {
+            try!(context.create_encoder(&mut config));
+            try!(ensure_empty(&config));
+            Ok(Box::new(NullAppender) as Box<Append>)
+        };
+        creator.add_appender("encoded", Box::new(encoded));
+        let encoder_seen = seen.clone();
+        creator.add_encoder("custom", Box::new(move |mut config: toml_parser::Table| {
+            let prefix = config.remove("prefix").map(|p| p.to_string());
+            try!(ensure_empty(&config));
+            encoder_seen.lock().unwrap().push(prefix);
+            Ok(Box::new(NullEncoder) as Box<Encode>)
+        }));
+        let filter_seen = seen.clone();
+        creator.add_filter("custom", Box::new(move |_: toml_parser::Table| {
+            filter_seen.lock().unwrap().push(Some("filter".to_owned()));
+            Ok(Box::new(NullFilter) as Box<Filter>)
+        }));
+
+        let cfg = r#"
+[appender.plain]
+kind = "encoded"
+encoder = "custom"
+
+[[appender.plain.filter]]
+kind = "custom"
+
+[appender.table]
+kind = "encoded"
+encoder = { kind = "custom", prefix = "> " }
+
+[appender.json]
+kind = "encoded"
+encoder = { kind = "json" }
+"#;
+        let (_, errors) = Config::parse(cfg, &creator).unwrap();
+        errors.unwrap();
+        let mut seen = seen.lock().unwrap().clone();
+        seen.sort();
+        assert_eq!(seen,
+                   [None, Some("\"> \"".to_owned()), Some("filter".to_owned())]);
+
+        // encoders registered with one creator are not available to another
+        let other = Creator::new();
+        let pools = HashMap::new();
+        let context = Context {
+            creator: &other,
+            escape: Escape::Off,
+            pools: &pools,
+        };
+        let mut config = toml_parser::Table::new();
+        config.insert("encoder".to_owned(), Value::String("custom".to_owned()));
+        assert!(context.create_encoder(&mut config).is_err());
+
+        let cfg = r#"
+[appender.foo]
+kind = "encoded"
+encoder = "nonexistent"
+
+[[appender.foo.filter]]
+kind = "nonexistent"
+"#;
+        let (_, errors) = Config::parse(cfg, &creator).unwrap();
+        let err = errors.unwrap_err().to_string();
+        assert!(err.contains("No creator registered for encoder kind \"nonexistent\""),
+                "{}",
+                err);
+
+        let cfg = r#"
+[appender.foo]
+kind = "encoded"
+
+[[appender.foo.filter]]
+kind = "nonexistent"
+"#;
+        let (_, errors) = Config::parse(cfg, &creator).unwrap();
+        let err = errors.unwrap_err().to_string();
+        assert!(err.contains("No creator registered for filter kind \"nonexistent\""),
+                "{}",
+                err);
+    }
+
+    #[test]
+    fn windows_event_log() {
+        let cfg = r#"
+[appender.events]
+kind = "windows_event_log"
+source = "log4rs"
+pattern = "[%t] %m"
+"#;
+        let (config, errors) = Config::parse(cfg, &Creator::default()).unwrap();
+        errors.unwrap();
+        assert_eq!(config.config().appenders().len(), 1);
+
+        let cfg = r#"
+[appender.events]
+kind = "windows_event_log"
+"#;
+        let (_, errors) = Config::parse(cfg, &Creator::default()).unwrap();
+        assert!(errors.is_err());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn journald() {
+        let cfg = r#"
+[appender.journal]
+kind = "journald"
+syslog_identifier = "app"
+pattern = "[%t] %m"
+"#;
+        let (config, errors) = Config::parse(cfg, &Creator::default()).unwrap();
+        errors.unwrap();
+        assert_eq!(config.config().appenders().len(), 1);
+
+        let cfg = r#"
+[appender.journal]
+kind = "journald"
+syslog_identifier = 1
+"#;
+        let (_, errors) = Config::parse(cfg, &Creator::default()).unwrap();
+        assert!(errors.is_err());
+    }
+
+    #[test]
+    fn annotations() {
+        let cfg = r#"
+refresh_rate = 30
+
+[meta]
+owner = "ops"
+deployed = 2016-01-01T00:00:00Z
+
+[_]
+note = "ignored"
+
+[root]
+level = "warn"
+"#;
+        let (config, errors) = Config::parse(cfg, &Creator::default()).unwrap();
+        errors.unwrap();
+        assert_eq!(config.config().root().level(), LogLevelFilter::Warn);
+
+        assert!(Config::parse("refresh_rat = 30", &Creator::default()).is_err());
+        assert!(Config::parse("[appenders.foo]\nkind = \"console\"", &Creator::default())
+                    .is_err());
+        assert!(Config::parse("meta = \"ops\"", &Creator::default()).is_err());
+        assert!(Config::parse("[root]\nlevel = \"warn\"\nlevle = \"info\"",
+                              &Creator::default())
+                    .is_err());
+    }
+
+    #[test]
+    fn rolling_file() {
+        let dir = env::temp_dir().join("log4rs-toml-rolling-file-test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let cfg = format!(r#"
+[appender.foo]
+kind = "rolling_file"
+path = "{0}/app.log"
+roll_on_startup = true
+
+[[appender.foo.policy]]
+kind = "size"
+limit = "100mb"
+
+[[appender.foo.policy]]
+kind = "daily"
+
+[appender.foo.roller]
+kind = "fixed_window"
+pattern = "{0}/app.{{}}.log"
+compress = true
+"#,
+                          dir.display());
+        let (config, errors) = Config::parse(&cfg, &Creator::default()).unwrap();
+        errors.unwrap();
+        assert_eq!(config.config().appenders().len(), 1);
+
+        let cfg = format!(r#"
+[appender.foo]
+kind = "rolling_file"
+path = "{0}/app.log"
+
+[[appender.foo.policy]]
+kind = "daily"
+
+[appender.foo.roller]
+kind = "fixed_window"
+pattern = "{0}/app.log"
+"#,
+                          dir.display());
+        let (_, errors) = Config::parse(&cfg, &Creator::default()).unwrap();
+        assert!(errors.is_err());
+
+        let cfg = format!(r#"
+[appender.foo]
+kind = "rolling_file"
+path = "{0}/app.log"
+
+[[appender.foo.policy]]
+kind = "daily"
+
+[appender.foo.roller]
+kind = "rename"
+compress = "yes"
+"#,
+                          dir.display());
+        let (_, errors) = Config::parse(&cfg, &Creator::default()).unwrap();
+        assert!(errors.is_err());
+    }
+
+    #[test]
+    fn size() {
+        assert_eq!(parse_size("100").unwrap(), 100);
+        assert_eq!(parse_size("10b").unwrap(), 10);
+        assert_eq!(parse_size("2KB").unwrap(), 2048);
+        assert_eq!(parse_size("100 mb").unwrap(), 100 * 1024 * 1024);
+        assert_eq!(parse_size("1gb").unwrap(), 1024 * 1024 * 1024);
+        assert!(parse_size("mb").is_err());
+        assert!(parse_size("1tb").is_err());
+        assert!(parse_size("99999999999gb").is_err());
+    }
+
+    #[test]
+    fn line_ending() {
+        let cfg = r#"
+[appender.console]
+kind = "console"
+line_ending = "crlf"
+"#;
+        let (config, errors) = Config::parse(cfg, &Creator::default()).unwrap();
+        errors.unwrap();
+        assert_eq!(config.config().appenders().len(), 1);
+
+        let cfg = r#"
+[appender.console]
+kind = "console"
+line_ending = "cr"
+"#;
+        let (_, errors) = Config::parse(cfg, &Creator::default()).unwrap();
+        assert!(errors.is_err());
+    }
+
+    #[test]
+    fn max_len() {
+        let cfg = r#"
+[appender.console]
+kind = "console"
+max_len = 1024
+"#;
+        let (config, errors) = Config::parse(cfg, &Creator::default()).unwrap();
+        errors.unwrap();
+        assert_eq!(config.config().appenders().len(), 1);
+
+        let cfg = r#"
+[appender.console]
+kind = "console"
+max_len = -1
+"#;
+        let (_, errors) = Config::parse(cfg, &Creator::default()).unwrap();
+        assert!(errors.is_err());
+    }
+
+    #[test]
+    fn console_color() {
+        for color in &["always", "never", "auto"] {
+            let cfg = format!("[appender.console]\nkind = \"console\"\ncolor = \"{}\"\n", color);
+            let (config, errors) = Config::parse(&cfg, &Creator::default()).unwrap();
+            errors.unwrap();
+            assert_eq!(config.config().appenders().len(), 1);
+        }
+
+        let cfg = r#"
+[appender.console]
+kind = "console"
+color = "sometimes"
+"#;
+        let (_, errors) = Config::parse(cfg, &Creator::default()).unwrap();
+        assert!(errors.is_err());
+    }
+
+    #[test]
+    fn file_mode() {
+        let path = env::temp_dir().join("log4rs-toml-mode-test.log");
+        let cfg = |mode| {
+            format!("[appender.file]\nkind = \"file\"\npath = \"{}\"\nmode = \"{}\"\n",
+                    path.display(),
+                    mode)
+        };
+        let (_, errors) = Config::parse(&cfg("0640"), &Creator::default()).unwrap();
+        errors.unwrap();
+
+        for mode in &["999", "17777", ""] {
+            let (_, errors) = Config::parse(&cfg(mode), &Creator::default()).unwrap();
+            assert!(errors.is_err(), "{}", mode);
+        }
+    }
+
+    #[test]
+    fn async_file() {
+        let path = env::temp_dir().join("log4rs-toml-async-test.log");
+        let cfg = |report_interval| {
+            format!(r#"
+[appender.async]
+kind = "async_file"
+path = "{}"
+overflow = "drop_newest"
+report_interval = {}
+"#,
+                    path.display(),
+                    report_interval)
+        };
+        let (config, errors) = Config::parse(&cfg("30"), &Creator::default()).unwrap();
+        errors.unwrap();
+        assert_eq!(config.config().appenders().len(), 1);
+
+        let (_, errors) = Config::parse(&cfg("0"), &Creator::default()).unwrap();
+        assert!(errors.is_err());
+    }
+
+    #[test]
+    fn async_appender() {
+        let cfg = r#"
+[appender.async]
+kind = "async"
+capacity = 16
+overflow = "drop_oldest"
+
+[appender.async.appender]
+kind = "console"
+target = "stderr"
+"#;
+        let (config, errors) = Config::parse(cfg, &Creator::default()).unwrap();
+        errors.unwrap();
+        assert_eq!(config.config().appenders().len(), 1);
+
+        let (_, errors) = Config::parse("[appender.async]\nkind = \"async\"",
+                                        &Creator::default())
+            .unwrap();
+        assert!(errors.unwrap_err().to_string().contains("`appender` is required"));
+    }
+
+    #[test]
+    fn async_pool() {
+        let dir = env::temp_dir().join("log4rs-toml-async-pool-test");
+        let cfg = |pool| {
+            format!(r#"
+[async_pool.shared]
+threads = 2
+
+[appender.a]
+kind = "async_file"
+path = "{0}/a.log"
+pool = "{1}"
+
+[appender.b]
+kind = "async_file"
+path = "{0}/b.log"
+pool = "{1}"
+"#,
+                    dir.display(),
+                    pool)
+        };
+        let identities = |config: &Config| {
+            let mut identities = config.config()
+                                       .appenders()
+                                       .iter()
+                                       .map(|a| a.identity().unwrap().to_owned())
+                                       .collect::<Vec<_>>();
+            identities.sort();
+            identities
+        };
+        fs::create_dir_all(&dir).unwrap();
+        let creator = Creator::default();
+        let (config, errors) = Config::parse(&cfg("shared"), &creator).unwrap();
+        errors.unwrap();
+        assert_eq!(config.config().appenders().len(), 2);
+        assert_eq!(creator.pools.lock().unwrap()["shared"].0, 2);
+
+        // a reload keeps using the pool the creator already started
+        let (first, errors) = Config::parse(&cfg("shared"), &creator).unwrap();
+        errors.unwrap();
+        let (second, errors) = Config::parse(&cfg("shared"), &creator).unwrap();
+        errors.unwrap();
+        assert_eq!(identities(&first), identities(&second));
+        assert_eq!(creator.pools.lock().unwrap().len(), 1);
+
+        let resized = cfg("shared").replace("threads = 2", "threads = 3");
+        let (third, errors) = Config::parse(&resized, &creator).unwrap();
+        errors.unwrap();
+        assert!(identities(&first) != identities(&third));
+        assert_eq!(creator.pools.lock().unwrap()["shared"].0, 3);
+
+        let (_, errors) = Config::parse(&cfg("missing"), &Creator::default()).unwrap();
+        assert!(errors.unwrap_err().to_string().contains("No async pool named \"missing\""));
+
+        assert!(Config::parse("[async_pool.shared]\nthreads = 0", &Creator::default()).is_err());
+        assert!(Config::parse("[async_pool.shared]", &Creator::default()).is_err());
+    }
+
+    #[test]
+    fn buffered_file() {
+        let path = env::temp_dir().join("log4rs-toml-buffered-test.log");
+        let cfg = format!(r#"
+[appender.file]
+kind = "file"
+path = "{}"
+buffered = true
+flush_interval = 1000
+flush_records = 100
+flush_level = "error"
+"#,
+                          path.display());
+        let (config, errors) = Config::parse(&cfg, &Creator::default()).unwrap();
+        errors.unwrap();
+        assert_eq!(config.config().appenders().len(), 1);
+    }
+
+    #[test]
+    fn appender_open_error() {
+        let path = env::temp_dir().join("log4rs-toml-missing-dir/app.log");
+        let cfg = format!(r#"
+[appender.file]
+kind = "file"
+path = "{}"
+"#,
+                          path.display());
+        let (_, errors) = Config::parse(&cfg, &Creator::default()).unwrap();
+        let errors = errors.unwrap_err().to_string();
+        let expected = format!("Error creating appender `file`: Unable to open `{}`: ",
+                               path.display());
+        assert!(errors.starts_with(&expected), "{}", errors);
+    }
+
+    #[test]
+    fn invalid_loggers() {
+        let cfg = r#"
+[[logger]]
+name = "foo"
+level = "info"
+
+[[logger]]
+name = "foo"
+level = "trace"
+
+[[logger]]
+name = ""
+level = "info"
+"#;
+        let (config, errors) = Config::parse(cfg, &Creator::default()).unwrap();
+        assert_eq!(errors.unwrap_err().to_string(),
+                   "Error creating config: Duplicate logger name `foo`\n\
+                    Error creating config: Logger names must not be empty\n");
+        let loggers = config.config().loggers();
+        assert_eq!(loggers.len(), 1);
+        assert_eq!(loggers[0].level(), LogLevelFilter::Info);
+    }
+
+    #[test]
+    fn include() {
+        let dir = env::temp_dir().join("log4rs-toml-include-test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("shared")).unwrap();
+        fs::File::create(dir.join("shared/appenders.toml"))
+            .unwrap()
+            .write_all(b"include = [\"levels.toml\"]\n\
+                         [appender.console]\n\
+                         kind = \"console\"\n\
+                         [root]\n\
+                         level = \"error\"\n\
+                         [[logger]]\n\
+                         name = \"a\"\n\
+                         level = \"info\"\n")
+            .unwrap();
+        fs::File::create(dir.join("shared/levels.toml"))
+            .unwrap()
+            .write_all(b"refresh_rate = 60\n")
+            .unwrap();
+
+        let cfg = r#"
+include = ["shared/appenders.toml"]
+refresh_rate = 30
+
+[appender.stderr]
+kind = "console"
+target = "stderr"
+
+[root]
+level = "warn"
+
+[[logger]]
+name = "b"
+level = "debug"
+"#;
+        let path = dir.join("log4rs.toml");
+        let (config, errors) = Config::parse_file(cfg, &path, &Creator::default()).unwrap();
+        errors.unwrap();
+        assert_eq!(config.refresh_rate(), Some(Duration::seconds(30)));
+        assert_eq!(config.config().appenders().len(), 2);
+        assert_eq!(config.config().root().level(), LogLevelFilter::Warn);
+        let loggers = config.config().loggers().iter().map(|l| l.name()).collect::<Vec<_>>();
+        assert_eq!(loggers, ["a", "b"]);
+
+        let cfg = r#"
+include = ["shared/appenders.toml"]
+
+[appender.console]
+kind = "console"
+"#;
+        assert!(Config::parse_file(cfg, &path, &Creator::default()).is_err());
+
+        fs::File::create(dir.join("shared/levels.toml"))
+            .unwrap()
+            .write_all(b"include = [\"appenders.toml\"]\n")
+            .unwrap();
+        let cfg = "include = [\"shared/appenders.toml\"]";
+        assert!(Config::parse_file(cfg, &path, &Creator::default()).is_err());
+    }
+
+    #[test]
+    fn dedup() {
+        let cfg = r#"
+[appender.errors]
+kind = "dedup"
+threshold = 3
+timeout = 60
+dedup_window = 300
+
+[appender.errors.appender]
+kind = "console"
+target = "stderr"
+"#;
+        let (config, errors) = Config::parse(cfg, &Creator::default()).unwrap();
+        errors.unwrap();
+        assert_eq!(config.config().appenders().len(), 1);
+
+        let cfg = r#"
+[appender.errors]
+kind = "dedup"
+
+[appender.errors.appender]
+target = "stderr"
+"#;
+        let (_, errors) = Config::parse(cfg, &Creator::default()).unwrap();
+        assert!(errors.is_err());
+    }
+
+    #[test]
+    fn leveled_file() {
+        let cfg = r#"
+[appender.levels]
+kind = "leveled_file"
+path = "log/app-%level.log"
+pattern = "%m"
+"#;
+        let (config, errors) = Config::parse(cfg, &Creator::default()).unwrap();
+        errors.unwrap();
+        assert_eq!(config.config().appenders().len(), 1);
+
+        let cfg = r#"
+[appender.levels]
+kind = "leveled_file"
+path = "log/app.log"
+"#;
+        let (_, errors) = Config::parse(cfg, &Creator::default()).unwrap();
+        assert!(errors.is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn process() {
+        let cfg = r#"
+[appender.sidecar]
+kind = "process"
+command = "cat"
+args = ["-u"]
+restart = "never"
+capacity = 16
+overflow = "drop_oldest"
+pattern = "%m"
+"#;
+        let (config, errors) = Config::parse(cfg, &Creator::default()).unwrap();
+        errors.unwrap();
+        assert_eq!(config.config().appenders().len(), 1);
+
+        let cfg = r#"
+[appender.sidecar]
+kind = "process"
+command = "cat"
+args = [1]
+"#;
+        let (_, errors) = Config::parse(cfg, &Creator::default()).unwrap();
+        assert!(errors.is_err());
+    }
+
+    #[test]
+    fn env_expansion() {
+        env::set_var("LOG4RS_TEST_LOG_DIR", "/var/log");
+        env::remove_var("LOG4RS_TEST_UNSET");
+
+        assert_eq!(expand_env("$LOG4RS_TEST_LOG_DIR/app.log").unwrap(), "/var/log/app.log");
+        assert_eq!(expand_env("${LOG4RS_TEST_LOG_DIR}app.log").unwrap(), "/var/logapp.log");
+        assert_eq!(expand_env("${LOG4RS_TEST_UNSET:-log}/app.log").unwrap(), "log/app.log");
+        assert_eq!(expand_env("${LOG4RS_TEST_LOG_DIR:-log}").unwrap(), "/var/log");
+        assert_eq!(expand_env("cost: $$5, $ 1").unwrap(), "cost: $5, $ 1");
+        assert!(expand_env("$LOG4RS_TEST_UNSET/app.log").is_err());
+        assert!(expand_env("${LOG4RS_TEST_LOG_DIR").is_err());
     }
 }
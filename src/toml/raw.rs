@@ -1,14 +1,25 @@
 use std::borrow::ToOwned;
 use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
 use time::Duration;
 
 use toml_parser::Value;
 use toml_parser as toml;
 use log::LogLevelFilter;
 
+use config;
+use pattern::Escape;
+use super::{ReloadOn, CONFIG_VERSION};
+
 #[cfg_attr(test, derive(PartialEq, Debug))]
 pub struct Config {
     pub refresh_rate: Option<Duration>,
+    pub watch: bool,
+    pub reload_on: Option<ReloadOn>,
+    pub escape: Option<Escape>,
+    pub async_pools: HashMap<String, usize>,
     pub root: Option<Root>,
     pub appenders: HashMap<String, Appender>,
     pub loggers: Vec<Logger>,
@@ -24,6 +35,8 @@ pub struct Root {
 pub struct Appender {
     pub kind: String,
     pub filters: Option<Vec<Filter>>,
+    pub on_error: Option<config::ErrorPolicy>,
+    pub required: Option<bool>,
     pub config: toml::Table,
 }
 
@@ -41,12 +54,24 @@ pub struct Logger {
     pub additive: Option<bool>,
 }
 
-pub fn parse(config: &str) -> Result<Config, Vec<String>> {
+// Parses a config, merging in any files it includes. `path` is the location
+// of the config, if it was read from a file, which includes are resolved
+// relative to.
+pub fn parse(config: &str, path: Option<&Path>) -> Result<Config, Vec<String>> {
+    let mut table = try!(parse_table(config));
+    try!(check_version(&mut table));
+    let dir = path.and_then(|p| p.parent()).unwrap_or(Path::new(""));
+    let mut stack = path.map(|p| vec![canonicalize(p)]).unwrap_or(vec![]);
+    let table = try!(resolve_includes(table, dir, &mut stack));
+    finish_parse_config(table)
+}
+
+fn parse_table(config: &str) -> Result<toml::Table, Vec<String>> {
     let mut parser = toml::Parser::new(config);
     match parser.parse() {
         Some(table) => {
             if parser.errors.is_empty() {
-                finish_parse_config(table)
+                Ok(table)
             } else {
                 Err(make_errors(&parser))
             }
@@ -55,13 +80,120 @@ pub fn parse(config: &str) -> Result<Config, Vec<String>> {
     }
 }
 
+// Removes the `version` key of `table`, failing if it names a format this
+// build does not support. This is checked before anything else so that a
+// newer config is not reported as a list of unrecognized keys.
+fn check_version(table: &mut toml::Table) -> Result<(), Vec<String>> {
+    match table.remove("version") {
+        Some(Value::Integer(version)) if version > CONFIG_VERSION => {
+            Err(vec![format!("this config uses format version {}, but this build of log4rs \
+                              supports version {}",
+                             version,
+                             CONFIG_VERSION)])
+        }
+        Some(Value::Integer(version)) if version < 1 => {
+            Err(vec![format!("Invalid `version` {}", version)])
+        }
+        Some(Value::Integer(_)) | None => Ok(()),
+        Some(_) => Err(vec!["`version` must be an integer".to_owned()]),
+    }
+}
+
+fn canonicalize(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_owned())
+}
+
+// Replaces the `include` key of `table` with the contents of the files it
+// names. `stack` holds the files currently being included, to catch cycles.
+fn resolve_includes(mut table: toml::Table, dir: &Path, stack: &mut Vec<PathBuf>)
+                    -> Result<toml::Table, Vec<String>> {
+    let includes = match table.remove("include") {
+        Some(Value::Array(includes)) => includes,
+        Some(_) => return Err(vec!["`include` must be an array of strings".to_owned()]),
+        None => return Ok(table),
+    };
+
+    let mut errors = vec![];
+    let mut merged = toml::Table::new();
+    for include in includes {
+        let path = match include {
+            Value::String(include) => dir.join(include),
+            _ => return Err(vec!["`include` must be an array of strings".to_owned()]),
+        };
+
+        let canonical = canonicalize(&path);
+        if stack.contains(&canonical) {
+            errors.push(format!("`{}` is included recursively", path.display()));
+            continue;
+        }
+
+        let mut source = String::new();
+        if let Err(err) = File::open(&path).and_then(|mut f| f.read_to_string(&mut source)) {
+            errors.push(format!("Error reading included file `{}`: {}", path.display(), err));
+            continue;
+        }
+
+        let included = match parse_table(&source).and_then(|mut included| {
+            try!(check_version(&mut included));
+            Ok(included)
+        }) {
+            Ok(included) => included,
+            Err(errs) => {
+                errors.extend(errs.into_iter().map(|e| format!("{}: {}", path.display(), e)));
+                continue;
+            }
+        };
+
+        stack.push(canonical);
+        let included = resolve_includes(included,
+                                        path.parent().unwrap_or(Path::new("")),
+                                        stack);
+        stack.pop();
+        match included {
+            Ok(included) => merge(&mut merged, included, &mut errors),
+            Err(errs) => errors.extend(errs),
+        }
+    }
+    merge(&mut merged, table, &mut errors);
+
+    if errors.is_empty() {
+        Ok(merged)
+    } else {
+        Err(errors)
+    }
+}
+
+// Merges `table` into `base`. Appenders and loggers are combined, while any
+// other key in `table` replaces the one in `base`.
+fn merge(base: &mut toml::Table, table: toml::Table, errors: &mut Vec<String>) {
+    for (key, value) in table {
+        let value = match (&*key, base.remove(&key), value) {
+            ("appender", Some(Value::Table(mut appenders)), Value::Table(more)) => {
+                for (name, appender) in more {
+                    if appenders.contains_key(&name) {
+                        errors.push(format!("Appender {} is defined in multiple files", name));
+                    } else {
+                        appenders.insert(name, appender);
+                    }
+                }
+                Value::Table(appenders)
+            }
+            ("logger", Some(Value::Array(mut loggers)), Value::Array(more)) => {
+                loggers.extend(more);
+                Value::Array(loggers)
+            }
+            (_, _, value) => value,
+        };
+        base.insert(key, value);
+    }
+}
+
 fn parse_level(level: toml::Value) -> Result<LogLevelFilter, Vec<String>> {
     match level {
         Value::String(level) => {
-            if let Ok(level) = level.parse() {
-                Ok(level)
-            } else {
-                Err(vec![format!("Invalid `level` \"{}\"", level)])
+            match config::parse_level_filter(&level) {
+                Ok(level) => Ok(level),
+                Err(err) => Err(vec![format!("Invalid `level`: {}", err)]),
             }
         }
         _ => {
@@ -177,7 +309,8 @@ fn parse_filters(appender: &str, filters: toml::Value) -> Result<Vec<Filter>, Ve
     }
 }
 
-fn finish_parse_config(mut table: toml::Table) -> Result<Config, Vec<String>> {
+pub fn finish_parse_config(mut table: toml::Table) -> Result<Config, Vec<String>> {
+    try!(check_version(&mut table));
     let mut errors = vec![];
 
     let refresh_rate = match table.remove("refresh_rate") {
@@ -189,6 +322,84 @@ fn finish_parse_config(mut table: toml::Table) -> Result<Config, Vec<String>> {
         None => None
     };
 
+    let watch = match table.remove("watch") {
+        Some(Value::Boolean(watch)) => watch,
+        Some(_) => {
+            errors.push("`watch` must be a boolean".to_owned());
+            false
+        }
+        None => false
+    };
+
+    let reload_on = match table.remove("reload_on") {
+        Some(Value::String(ref reload_on)) if reload_on == "mtime" => Some(ReloadOn::Mtime),
+        Some(Value::String(ref reload_on)) if reload_on == "content" => Some(ReloadOn::Content),
+        Some(Value::String(reload_on)) => {
+            errors.push(format!("Invalid `reload_on` \"{}\"", reload_on));
+            None
+        }
+        Some(_) => {
+            errors.push("`reload_on` must be a string".to_owned());
+            None
+        }
+        None => None
+    };
+
+    let escape = match table.remove("escape") {
+        Some(Value::String(escape)) => {
+            match escape.parse() {
+                Ok(escape) => Some(escape),
+                Err(_) => {
+                    errors.push(format!("Invalid `escape` \"{}\"", escape));
+                    None
+                }
+            }
+        }
+        Some(_) => {
+            errors.push("`escape` must be a string".to_owned());
+            None
+        }
+        None => None
+    };
+
+    let async_pools = match table.remove("async_pool") {
+        Some(Value::Table(table)) => {
+            table.into_iter().filter_map(|(name, spec)| {
+                let mut spec = match spec {
+                    Value::Table(spec) => spec,
+                    _ => {
+                        errors.push(format!("async_pool {} should be a table", name));
+                        return None;
+                    }
+                };
+
+                let threads = match spec.remove("threads") {
+                    Some(Value::Integer(threads)) if threads > 0 => threads as usize,
+                    Some(_) => {
+                        errors.push(format!("`threads` must be a positive integer in async_pool {}",
+                                            name));
+                        return None;
+                    }
+                    None => {
+                        errors.push(format!("`threads` must be present in async_pool {}", name));
+                        return None;
+                    }
+                };
+
+                for key in spec.keys() {
+                    errors.push(format!("unrecognized `async_pool` key: {}", key));
+                }
+
+                Some((name, threads))
+            }).collect()
+        }
+        None => HashMap::new(),
+        _ => {
+            errors.push("`async_pool` should be a table".to_owned());
+            HashMap::new()
+        }
+    };
+
     let root = match table.remove("root") {
         Some(root) => match parse_root(root) {
             Ok(root) => Some(root),
@@ -236,10 +447,42 @@ fn finish_parse_config(mut table: toml::Table) -> Result<Config, Vec<String>> {
                     None => None,
                 };
 
+                let on_error = match spec.remove("on_error") {
+                    Some(Value::String(on_error)) => {
+                        match &*on_error {
+                            "ignore" => Some(config::ErrorPolicy::Ignore),
+                            "backoff" => Some(config::ErrorPolicy::Backoff),
+                            "disable" => Some(config::ErrorPolicy::Disable),
+                            _ => {
+                                errors.push(format!("Invalid `on_error` \"{}\" in appender {}",
+                                                    on_error,
+                                                    name));
+                                None
+                            }
+                        }
+                    }
+                    Some(_) => {
+                        errors.push(format!("`on_error` must be a string in appender {}", name));
+                        None
+                    }
+                    None => None,
+                };
+
+                let required = match spec.remove("required") {
+                    Some(Value::Boolean(required)) => Some(required),
+                    Some(_) => {
+                        errors.push(format!("`required` must be a bool in appender {}", name));
+                        None
+                    }
+                    None => None,
+                };
+
                 let spec = Appender {
                     kind: kind,
                     config: spec,
                     filters: filters,
+                    on_error: on_error,
+                    required: required,
                 };
 
                 Some((name, spec))
@@ -322,9 +565,22 @@ fn finish_parse_config(mut table: toml::Table) -> Result<Config, Vec<String>> {
         }
     };
 
+    // the reserved `[meta]` and `[_]` tables may carry annotations for other
+    // tools
+    for (key, value) in &table {
+        match (&**key, value) {
+            ("meta", &Value::Table(_)) | ("_", &Value::Table(_)) => {}
+            _ => errors.push(format!("unrecognized top-level key: {}", key)),
+        }
+    }
+
     if errors.is_empty() {
         Ok(Config {
             refresh_rate: refresh_rate,
+            watch: watch,
+            reload_on: reload_on,
+            escape: escape,
+            async_pools: async_pools,
             appenders: appenders,
             root: root,
             loggers: loggers,
@@ -350,12 +606,19 @@ mod test {
     use toml_parser::Value;
     use log::LogLevelFilter;
 
+    use config;
     use super::*;
 
     #[test]
     fn test_basic() {
         let cfg = r#"
+version = 1
 refresh_rate = 60
+reload_on = "mtime"
+escape = "control"
+
+[async_pool.shared]
+threads = 2
 
 [appender.console]
 kind = "console"
@@ -367,6 +630,8 @@ level = "debug"
 [appender.baz]
 kind = "file"
 file = "log/baz.log"
+on_error = "disable"
+required = true
 
 [root]
 appenders = ["console"]
@@ -379,16 +644,26 @@ appenders = ["baz"]
 additive = false
 "#;
 
-        let actual = parse(cfg).unwrap();
+        let actual = parse(cfg, None).unwrap();
 
         let expected = Config {
             refresh_rate: Some(Duration::seconds(60)),
+            watch: false,
+            reload_on: Some(ReloadOn::Mtime),
+            escape: Some(Escape::Control),
+            async_pools: {
+                let mut m = HashMap::new();
+                m.insert("shared".to_owned(), 2);
+                m
+            },
             appenders: {
                 let mut m = HashMap::new();
                 m.insert("console".to_owned(),
                          Appender {
                              kind: "console".to_owned(),
                              config: BTreeMap::new(),
+                             on_error: None,
+                             required: None,
                              filters: Some(vec![Filter {
                                 kind: "threshold".to_string(),
                                 config: {
@@ -409,6 +684,8 @@ additive = false
                                  m
                              },
                              filters: None,
+                             on_error: Some(config::ErrorPolicy::Disable),
+                             required: Some(true),
                          });
                 m
             },
@@ -428,4 +705,17 @@ additive = false
 
         assert_eq!(expected, actual);
     }
+
+    #[test]
+    fn version() {
+        let errors = parse("version = 2\nfuture_key = true", None).unwrap_err();
+        assert_eq!(errors,
+                   ["this config uses format version 2, but this build of log4rs supports \
+                     version 1"]);
+
+        assert_eq!(parse("version = 0", None).unwrap_err(), ["Invalid `version` 0"]);
+        assert_eq!(parse("version = \"1\"", None).unwrap_err(),
+                   ["`version` must be an integer"]);
+        assert!(parse("", None).is_ok());
+    }
 }
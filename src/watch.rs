@@ -0,0 +1,489 @@
+//! Change notification for config files.
+
+use std::cmp;
+use std::path::Path;
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration as StdDuration, Instant};
+use time::Duration;
+
+/// The polling interval used when file notifications are unavailable and no
+/// refresh rate is configured.
+const DEFAULT_POLL_SECS: i64 = 5;
+
+/// The longest time a wait for file notifications goes without checking for
+/// a stop signal.
+const STOP_POLL_MS: i64 = 100;
+
+/// A signal which ends the waits of a `Watcher` early.
+pub struct Stop {
+    stopped: Mutex<bool>,
+    cond: Condvar,
+}
+
+impl Stop {
+    pub fn new() -> Stop {
+        Stop {
+            stopped: Mutex::new(false),
+            cond: Condvar::new(),
+        }
+    }
+
+    pub fn stop(&self) {
+        *self.stopped.lock().unwrap() = true;
+        self.cond.notify_all();
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        *self.stopped.lock().unwrap()
+    }
+
+    /// Blocks until the timeout elapses or the signal is raised, returning
+    /// true in the latter case.
+    pub fn sleep(&self, timeout: Duration) -> bool {
+        let timeout = StdDuration::from_millis(timeout.num_milliseconds() as u64);
+        let deadline = Instant::now() + timeout;
+        let mut stopped = self.stopped.lock().unwrap();
+        loop {
+            let now = Instant::now();
+            if *stopped || now >= deadline {
+                return *stopped;
+            }
+            stopped = self.cond.wait_timeout(stopped, deadline - now).unwrap().0;
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+use self::inotify::Inotify as Backend;
+#[cfg(any(target_os = "macos", target_os = "freebsd", target_os = "dragonfly",
+          target_os = "openbsd", target_os = "netbsd"))]
+use self::kqueue::Kqueue as Backend;
+
+/// Waits for changes to a file.
+///
+/// On Linux this uses inotify, and on macOS and the BSDs it uses kqueue.
+/// Elsewhere, or if notifications cannot be set up, it falls back to sleeping
+/// for the timeout.
+pub struct Watcher {
+    #[cfg(any(target_os = "linux", target_os = "macos", target_os = "freebsd",
+              target_os = "dragonfly", target_os = "openbsd", target_os = "netbsd"))]
+    backend: Option<Backend>,
+}
+
+impl Watcher {
+    pub fn new(path: &Path) -> Watcher {
+        Watcher::new_inner(path)
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "macos", target_os = "freebsd",
+              target_os = "dragonfly", target_os = "openbsd", target_os = "netbsd"))]
+    fn new_inner(path: &Path) -> Watcher {
+        Watcher { backend: Backend::new(path).ok() }
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "freebsd",
+                  target_os = "dragonfly", target_os = "openbsd", target_os = "netbsd")))]
+    fn new_inner(_: &Path) -> Watcher {
+        Watcher {}
+    }
+
+    /// Blocks until the file may have changed, the timeout elapses, or `stop`
+    /// is raised, returning true in the last case.
+    pub fn wait(&mut self, timeout: Option<Duration>, stop: &Stop) -> bool {
+        if self.wait_inner(timeout, stop) {
+            stop.is_stopped()
+        } else {
+            stop.sleep(timeout.unwrap_or(Duration::seconds(DEFAULT_POLL_SECS)))
+        }
+    }
+
+    // Returns false if notifications are unavailable.
+    #[cfg(any(target_os = "linux", target_os = "macos", target_os = "freebsd",
+              target_os = "dragonfly", target_os = "openbsd", target_os = "netbsd"))]
+    fn wait_inner(&mut self, timeout: Option<Duration>, stop: &Stop) -> bool {
+        let backend = match self.backend {
+            Some(ref mut backend) => backend,
+            None => return false,
+        };
+
+        // poll in slices so that a stop signal is noticed promptly, measuring
+        // the timeout from the start so that events for other files in the
+        // directory do not extend it
+        let start = Instant::now();
+        loop {
+            if stop.is_stopped() {
+                return true;
+            }
+            let slice = match timeout {
+                Some(timeout) => {
+                    let elapsed = start.elapsed().as_millis() as i64;
+                    let remaining = timeout.num_milliseconds() - elapsed;
+                    if remaining <= 0 {
+                        return true;
+                    }
+                    cmp::min(remaining, STOP_POLL_MS)
+                }
+                None => STOP_POLL_MS,
+            };
+            match backend.wait(slice as i32) {
+                Ok(true) => return true,
+                Ok(false) => {}
+                Err(_) => return false,
+            }
+        }
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "freebsd",
+                  target_os = "dragonfly", target_os = "openbsd", target_os = "netbsd")))]
+    fn wait_inner(&mut self, _: Option<Duration>, _: &Stop) -> bool {
+        false
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod inotify {
+    use libc;
+    use std::ffi::{CString, OsString};
+    use std::io;
+    use std::mem;
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+    use std::path::Path;
+    use std::ptr;
+
+    pub struct Inotify {
+        fd: libc::c_int,
+        name: OsString,
+    }
+
+    impl Drop for Inotify {
+        fn drop(&mut self) {
+            unsafe {
+                libc::close(self.fd);
+            }
+        }
+    }
+
+    impl Inotify {
+        // The parent directory is watched rather than the file itself, since
+        // editors commonly replace files rather than writing to them in place.
+        pub fn new(path: &Path) -> io::Result<Inotify> {
+            let name = match path.file_name() {
+                Some(name) => name.to_owned(),
+                None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "not a file")),
+            };
+            let dir = match path.parent() {
+                Some(dir) if dir.as_os_str().is_empty() => Path::new("."),
+                Some(dir) => dir,
+                None => Path::new("/"),
+            };
+            let dir = match CString::new(dir.as_os_str().as_bytes()) {
+                Ok(dir) => dir,
+                Err(_) => return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid path")),
+            };
+
+            let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let inotify = Inotify {
+                fd: fd,
+                name: name,
+            };
+
+            let mask = libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO | libc::IN_CREATE;
+            if unsafe { libc::inotify_add_watch(fd, dir.as_ptr(), mask) } < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(inotify)
+        }
+
+        // Returns true if an event for the file is received within the
+        // timeout, in milliseconds. Events for other files end the wait early
+        // with false, so that the caller can account for the time already
+        // waited. A negative timeout waits indefinitely.
+        pub fn wait(&mut self, timeout: i32) -> io::Result<bool> {
+            let mut fds = libc::pollfd {
+                fd: self.fd,
+                events: libc::POLLIN,
+                revents: 0,
+            };
+
+            match unsafe { libc::poll(&mut fds, 1, timeout) } {
+                -1 => {
+                    let err = io::Error::last_os_error();
+                    if err.kind() == io::ErrorKind::Interrupted {
+                        return Ok(false);
+                    }
+                    Err(err)
+                }
+                0 => Ok(false),
+                _ => self.read_events(),
+            }
+        }
+
+        // Drains pending events, returning true if any concern the file.
+        fn read_events(&mut self) -> io::Result<bool> {
+            let mut buf = [0u8; 4096];
+            let len = unsafe {
+                libc::read(self.fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len())
+            };
+            if len < 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            let header = mem::size_of::<libc::inotify_event>();
+            let mut found = false;
+            let mut offset = 0;
+            while offset + header <= len as usize {
+                let event = unsafe {
+                    ptr::read_unaligned(buf[offset..].as_ptr() as *const libc::inotify_event)
+                };
+                let name = &buf[offset + header..offset + header + event.len as usize];
+                let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())];
+                if OsString::from_vec(name.to_vec()) == self.name {
+                    found = true;
+                }
+                offset += header + event.len as usize;
+            }
+            Ok(found)
+        }
+    }
+}
+
+#[cfg(any(target_os = "macos", target_os = "freebsd", target_os = "dragonfly",
+          target_os = "openbsd", target_os = "netbsd"))]
+mod kqueue {
+    use libc;
+    use std::ffi::CString;
+    use std::fs;
+    use std::io;
+    use std::mem;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::MetadataExt;
+    use std::path::{Path, PathBuf};
+    use std::ptr;
+
+    pub struct Kqueue {
+        kq: libc::c_int,
+        dir: libc::c_int,
+        file: libc::c_int,
+        path: PathBuf,
+        state: Option<(u64, i64, i64, u64)>,
+    }
+
+    impl Drop for Kqueue {
+        fn drop(&mut self) {
+            unsafe {
+                for &fd in &[self.file, self.dir, self.kq] {
+                    if fd >= 0 {
+                        libc::close(fd);
+                    }
+                }
+            }
+        }
+    }
+
+    impl Kqueue {
+        // kqueue does not report which directory entry changed, so both the
+        // parent directory and the file are watched, and events are filtered
+        // by checking whether the file at the path has changed.
+        pub fn new(path: &Path) -> io::Result<Kqueue> {
+            if path.file_name().is_none() {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "not a file"));
+            }
+            let dir = match path.parent() {
+                Some(dir) if dir.as_os_str().is_empty() => Path::new("."),
+                Some(dir) => dir,
+                None => Path::new("/"),
+            };
+
+            let kq = unsafe { libc::kqueue() };
+            if kq < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let mut kqueue = Kqueue {
+                kq: kq,
+                dir: -1,
+                file: -1,
+                path: path.to_owned(),
+                state: state(path),
+            };
+
+            kqueue.dir = try!(open(dir));
+            try!(kqueue.register(kqueue.dir, libc::NOTE_WRITE));
+            kqueue.watch_file();
+            Ok(kqueue)
+        }
+
+        // Returns true if the file has changed when an event is received
+        // within the timeout, in milliseconds. A negative timeout waits
+        // indefinitely.
+        pub fn wait(&mut self, timeout: i32) -> io::Result<bool> {
+            let ts = libc::timespec {
+                tv_sec: (timeout / 1000) as libc::time_t,
+                tv_nsec: ((timeout % 1000) * 1000000) as libc::c_long,
+            };
+            let ts = if timeout < 0 { ptr::null() } else { &ts as *const _ };
+            let mut event: libc::kevent = unsafe { mem::zeroed() };
+
+            match unsafe { libc::kevent(self.kq, ptr::null(), 0, &mut event, 1, ts) } {
+                -1 => {
+                    let err = io::Error::last_os_error();
+                    if err.kind() == io::ErrorKind::Interrupted {
+                        return Ok(false);
+                    }
+                    Err(err)
+                }
+                0 => Ok(false),
+                _ => Ok(self.changed()),
+            }
+        }
+
+        fn changed(&mut self) -> bool {
+            let state = state(&self.path);
+            if state == self.state {
+                return false;
+            }
+
+            // the file was replaced, so the old one is no longer of interest
+            let replaced = match (state, self.state) {
+                (Some(new), Some(old)) => new.0 != old.0,
+                _ => true,
+            };
+            self.state = state;
+            if replaced {
+                self.watch_file();
+            }
+            state.is_some()
+        }
+
+        fn watch_file(&mut self) {
+            if self.file >= 0 {
+                unsafe {
+                    libc::close(self.file);
+                }
+                self.file = -1;
+            }
+            if let Ok(file) = open(&self.path) {
+                self.file = file;
+                let flags = libc::NOTE_WRITE | libc::NOTE_EXTEND | libc::NOTE_ATTRIB |
+                            libc::NOTE_DELETE | libc::NOTE_RENAME;
+                let _ = self.register(file, flags);
+            }
+        }
+
+        fn register(&self, fd: libc::c_int, fflags: u32) -> io::Result<()> {
+            let mut event: libc::kevent = unsafe { mem::zeroed() };
+            event.ident = fd as libc::uintptr_t;
+            event.filter = libc::EVFILT_VNODE;
+            event.flags = libc::EV_ADD | libc::EV_CLEAR;
+            event.fflags = fflags;
+            if unsafe { libc::kevent(self.kq, &event, 1, ptr::null_mut(), 0, ptr::null()) } < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        }
+    }
+
+    fn open(path: &Path) -> io::Result<libc::c_int> {
+        let path = match CString::new(path.as_os_str().as_bytes()) {
+            Ok(path) => path,
+            Err(_) => return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid path")),
+        };
+        let fd = unsafe { libc::open(path.as_ptr(), libc::O_RDONLY | libc::O_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(fd)
+    }
+
+    // The identity, modification time and size of the file, if it exists.
+    fn state(path: &Path) -> Option<(u64, i64, i64, u64)> {
+        fs::metadata(path).ok().map(|m| (m.ino(), m.mtime(), m.mtime_nsec(), m.len()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::Write;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Duration as StdDuration, Instant};
+    use time::Duration;
+
+    use super::*;
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn wakes_on_change() {
+        let dir = env::temp_dir().join("log4rs-watch-test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("log4rs.toml");
+        File::create(&path).unwrap();
+
+        let mut watcher = Watcher::new(&path);
+        let writer = {
+            let path = path.clone();
+            thread::spawn(move || {
+                thread::sleep(StdDuration::from_millis(100));
+                File::create(dir.join("other.toml")).unwrap();
+                File::create(&path).unwrap().write_all(b"refresh_rate = 1").unwrap();
+            })
+        };
+
+        let start = Instant::now();
+        assert!(!watcher.wait(Some(Duration::seconds(10)), &Stop::new()));
+        assert!(start.elapsed().as_secs() < 5);
+        writer.join().unwrap();
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn ignores_other_files() {
+        let dir = env::temp_dir().join("log4rs-watch-other-test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("log4rs.toml");
+        File::create(&path).unwrap();
+
+        let mut watcher = Watcher::new(&path);
+        let stop = Arc::new(Stop::new());
+        let writer = {
+            let stop = stop.clone();
+            thread::spawn(move || {
+                while !stop.sleep(Duration::milliseconds(20)) {
+                    File::create(dir.join("other.toml")).unwrap();
+                }
+            })
+        };
+
+        // a steady stream of events for another file does not extend the wait
+        let start = Instant::now();
+        assert!(!watcher.wait(Some(Duration::milliseconds(300)), &Stop::new()));
+        assert!(start.elapsed() < StdDuration::from_secs(2));
+        stop.stop();
+        writer.join().unwrap();
+    }
+
+    #[test]
+    fn stops_promptly() {
+        let path = env::temp_dir().join("log4rs-watch-stop-test.toml");
+        let stop = Arc::new(Stop::new());
+        let stopper = {
+            let stop = stop.clone();
+            thread::spawn(move || {
+                thread::sleep(StdDuration::from_millis(100));
+                stop.stop();
+            })
+        };
+
+        let start = Instant::now();
+        assert!(Watcher::new(&path).wait(None, &stop));
+        assert!(start.elapsed().as_secs() < 5);
+        assert!(stop.sleep(Duration::seconds(10)));
+        stopper.join().unwrap();
+    }
+}
@@ -0,0 +1,158 @@
+//! Support for log4rs configuration from YAML files.
+//!
+//! YAML configuration files have the same structure as TOML configuration
+//! files, which are documented in the `toml` module, and are processed by
+//! the same `Creator`. The appender and filter tables passed to
+//! `CreateAppender` and `CreateFilter` implementations are converted to
+//! TOML tables.
+//!
+//! # Syntax
+//!
+//! ```yaml
+//! refresh_rate: 30
+//!
+//! appender:
+//!   foo:
+//!     kind: console
+//!     pattern: "%d [%t] %m"
+//!     filter:
+//!       - kind: threshold
+//!         level: error
+//!
+//! root:
+//!   level: warn
+//!   appenders:
+//!     - foo
+//!
+//! logger:
+//!   - name: foo::bar::baz
+//!     level: trace
+//!     appenders:
+//!       - foo
+//!     additive: false
+//! ```
+use std::collections::BTreeMap;
+use toml_parser::{self, Value};
+use yaml_rust::{Yaml, YamlLoader};
+
+use toml::{Config, Creator, Errors, ParseErrors};
+use {PrivateTomlConfigExt, PrivateTomlParseErrorsExt};
+
+/// Creates a log4rs `Config` from the specified YAML config string and
+/// `Creator`.
+pub fn parse(config: &str, creator: &Creator)
+             -> Result<(Config, Result<(), Errors>), ParseErrors> {
+    let mut docs = match YamlLoader::load_from_str(config) {
+        Ok(docs) => docs,
+        Err(err) => return Err(ParseErrors::new(vec![err.to_string()])),
+    };
+
+    let table = match docs.len() {
+        0 => BTreeMap::new(),
+        1 => {
+            match convert(docs.remove(0)) {
+                Ok(Value::Table(table)) => table,
+                Ok(_) => return Err(ParseErrors::new(vec!["config must be a mapping".to_owned()])),
+                Err(err) => return Err(ParseErrors::new(vec![err])),
+            }
+        }
+        _ => {
+            return Err(ParseErrors::new(vec!["config must contain a single document"
+                                                 .to_owned()]));
+        }
+    };
+
+    Config::parse_table(table, creator)
+}
+
+fn convert(yaml: Yaml) -> Result<Value, String> {
+    match yaml {
+        Yaml::String(s) => Ok(Value::String(s)),
+        Yaml::Integer(i) => Ok(Value::Integer(i)),
+        Yaml::Real(s) => {
+            match s.parse() {
+                Ok(f) => Ok(Value::Float(f)),
+                Err(_) => Err(format!("invalid float `{}`", s)),
+            }
+        }
+        Yaml::Boolean(b) => Ok(Value::Boolean(b)),
+        Yaml::Array(array) => {
+            let mut values = vec![];
+            for yaml in array {
+                values.push(try!(convert(yaml)));
+            }
+            Ok(Value::Array(values))
+        }
+        Yaml::Hash(hash) => {
+            let mut table = toml_parser::Table::new();
+            for (key, value) in hash {
+                let key = match key {
+                    Yaml::String(key) => key,
+                    key => return Err(format!("mapping keys must be strings, found {:?}", key)),
+                };
+                table.insert(key, try!(convert(value)));
+            }
+            Ok(Value::Table(table))
+        }
+        Yaml::Null => Err("null values are not supported".to_owned()),
+        Yaml::Alias(_) => Err("aliases are not supported".to_owned()),
+        Yaml::BadValue => Err("invalid value".to_owned()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use log::LogLevelFilter;
+    use time::Duration;
+
+    use toml::Creator;
+    use super::*;
+
+    #[test]
+    fn basic() {
+        let cfg = r#"
+refresh_rate: 60
+
+appender:
+  console:
+    kind: console
+    filter:
+      - kind: threshold
+        level: debug
+
+root:
+  appenders:
+    - console
+  level: info
+
+logger:
+  - name: foo::bar::baz
+    level: warn
+    appenders:
+      - console
+    additive: false
+"#;
+
+        let (config, errors) = parse(cfg, &Creator::default()).unwrap();
+        errors.unwrap();
+
+        assert_eq!(config.refresh_rate(), Some(Duration::seconds(60)));
+        let config = config.config();
+        assert_eq!(config.appenders().len(), 1);
+        assert_eq!(config.appenders()[0].name(), "console");
+        assert_eq!(config.appenders()[0].filters().len(), 1);
+        assert_eq!(config.root().level(), LogLevelFilter::Info);
+        assert_eq!(config.root().appenders(), &["console".to_owned()]);
+        assert_eq!(config.loggers().len(), 1);
+        assert_eq!(config.loggers()[0].name(), "foo::bar::baz");
+        assert_eq!(config.loggers()[0].level(), LogLevelFilter::Warn);
+        assert!(!config.loggers()[0].additive());
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(parse("refresh_rate: [", &Creator::default()).is_err());
+        assert!(parse("- 1\n- 2", &Creator::default()).is_err());
+        assert!(parse("root:\n  level: ~", &Creator::default()).is_err());
+    }
+}